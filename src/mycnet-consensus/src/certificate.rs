@@ -0,0 +1,228 @@
+//! Compact quorum certificates proving a decision was reached
+
+use crate::decision_log::signed_decision;
+use crate::{DecisionSignature, LogEntry, ParticipantSet};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// Signer bitmap plus signatures over an operation's decision hash
+///
+/// Bit `i` of the bitmap refers to `members[i]` of the participant set for
+/// `epoch`, and signatures are stored in bitmap order. Signatures cover the
+/// operation ID together with the decision hash. Any node holding the
+/// participant set and public keys can check the certificate offline.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuorumCertificate {
+    pub operation_id: Uuid,
+    pub decision_hash: [u8; 32],
+    pub epoch: u64,
+    pub participant_set_digest: [u8; 32],
+    pub signer_bitmap: Vec<u8>,
+    pub signatures: Vec<Vec<u8>>,
+}
+
+impl QuorumCertificate {
+    /// Bundle decision signatures from members of a participant set
+    pub fn from_signatures(
+        operation_id: Uuid,
+        decision_hash: [u8; 32],
+        participant_set: &ParticipantSet,
+        signatures: &[DecisionSignature],
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut signer_bitmap = vec![0u8; participant_set.len().div_ceil(8)];
+        let mut by_index = Vec::new();
+
+        for signature in signatures {
+            let index = participant_set.members
+                .iter()
+                .position(|member| member.node_id == signature.node_id)
+                .ok_or_else(|| format!("Signer {} is not in participant set", signature.node_id))?;
+
+            if signer_bitmap[index / 8] & (1 << (index % 8)) == 0 {
+                signer_bitmap[index / 8] |= 1 << (index % 8);
+                by_index.push((index, signature.signature.clone()));
+            }
+        }
+        by_index.sort_by_key(|(index, _)| *index);
+
+        Ok(Self {
+            operation_id,
+            decision_hash,
+            epoch: participant_set.epoch,
+            participant_set_digest: participant_set.digest(),
+            signer_bitmap,
+            signatures: by_index.into_iter().map(|(_, signature)| signature).collect(),
+        })
+    }
+
+    /// Build a certificate from the signatures recorded with a logged decision
+    pub fn from_log_entry(entry: &LogEntry) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::from_signatures(
+            entry.result.operation_id,
+            entry.decision_digest(),
+            &entry.participant_set,
+            &entry.signatures,
+        )
+    }
+
+    /// Nodes that signed, resolved against the participant set
+    pub fn signers(&self, participant_set: &ParticipantSet) -> Vec<Uuid> {
+        participant_set.members
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| {
+                self.signer_bitmap
+                    .get(index / 8)
+                    .map(|byte| byte & (1 << (index % 8)) != 0)
+                    .unwrap_or(false)
+            })
+            .map(|(_, member)| member.node_id)
+            .collect()
+    }
+
+    /// Verify every signature and that signers hold the set's approval threshold of the voting weight
+    pub fn verify(
+        &self,
+        participant_set: &ParticipantSet,
+        public_keys: &HashMap<Uuid, ed25519_dalek::VerifyingKey>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        use ed25519_dalek::Verifier;
+
+        if participant_set.epoch != self.epoch || participant_set.digest() != self.participant_set_digest {
+            return Err("Certificate was issued for a different participant set".into());
+        }
+        participant_set.check_threshold()?;
+        if self.signer_bitmap.len() != participant_set.len().div_ceil(8) {
+            return Err("Signer bitmap does not match participant set size".into());
+        }
+        // Padding bits in the last byte would otherwise be silently ignored
+        let padding = (participant_set.len()..self.signer_bitmap.len() * 8)
+            .any(|index| self.signer_bitmap[index / 8] & (1 << (index % 8)) != 0);
+        if padding {
+            return Err("Signer bitmap marks signers beyond the participant set".into());
+        }
+
+        let signers = self.signers(participant_set);
+        if signers.len() != self.signatures.len() {
+            return Err("Signature count does not match signer bitmap".into());
+        }
+
+        let message = signed_decision(&self.operation_id, &self.decision_hash);
+        for (node_id, signature) in signers.iter().zip(&self.signatures) {
+            let public_key = public_keys
+                .get(node_id)
+                .ok_or_else(|| format!("No public key for signer {}", node_id))?;
+            let signature = ed25519_dalek::Signature::from_slice(signature)
+                .map_err(|_| format!("Malformed signature from {}", node_id))?;
+            public_key
+                .verify(&message, &signature)
+                .map_err(|_| format!("Invalid signature from {}", node_id))?;
+        }
+
+        let signed_weight = participant_set.weight_ratio(&signers);
+        if signed_weight < participant_set.approval_threshold {
+            return Err(format!(
+                "Signers hold {:.2} of voting weight, {:.2} required",
+                signed_weight, participant_set.approval_threshold
+            )
+            .into());
+        }
+
+        Ok(())
+    }
+
+    /// Encode for embedding in spores, revocation lists or updates
+    pub fn to_bytes(&self) -> Vec<u8> {
+        bincode::serialize(self).expect("Certificate serialization cannot fail")
+    }
+
+    /// Decode a certificate carried by another message
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(bincode::deserialize(bytes)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{NodeTier, WeightedParticipant};
+
+    fn setup(size: usize) -> (ParticipantSet, Vec<ed25519_dalek::SigningKey>, HashMap<Uuid, ed25519_dalek::VerifyingKey>) {
+        let signing_keys: Vec<_> = (0..size)
+            .map(|_| ed25519_dalek::SigningKey::generate(&mut rand::rngs::OsRng))
+            .collect();
+        let members: Vec<_> = (0..size)
+            .map(|_| WeightedParticipant {
+                node_id: Uuid::new_v4(),
+                tier: NodeTier::Sclerotia,
+                weight: 1.0,
+            })
+            .collect();
+        let public_keys = members.iter().zip(&signing_keys).map(|(m, k)| (m.node_id, k.verifying_key())).collect();
+
+        (ParticipantSet { epoch: 3, members, approval_threshold: 0.67 }, signing_keys, public_keys)
+    }
+
+    #[test]
+    fn test_certificate_round_trip_and_threshold() {
+        let (set, signing_keys, public_keys) = setup(10);
+        let (operation_id, decision_hash) = (Uuid::new_v4(), [5u8; 32]);
+
+        let signatures: Vec<_> = set.members
+            .iter()
+            .zip(&signing_keys)
+            .take(7)
+            .map(|(member, signing_key)| DecisionSignature::sign(member.node_id, &operation_id, &decision_hash, signing_key))
+            .collect();
+
+        let certificate = QuorumCertificate::from_signatures(operation_id, decision_hash, &set, &signatures).unwrap();
+        assert_eq!(certificate.signer_bitmap.len(), 2);
+        assert_eq!(certificate.signers(&set).len(), 7);
+
+        let decoded = QuorumCertificate::from_bytes(&certificate.to_bytes()).unwrap();
+        assert!(decoded.verify(&set, &public_keys).is_ok());
+
+        // Seven of ten is below a set that requires 0.8
+        let strict = ParticipantSet { approval_threshold: 0.8, ..set.clone() };
+        let certificate = QuorumCertificate::from_signatures(operation_id, decision_hash, &strict, &signatures).unwrap();
+        assert!(certificate.verify(&strict, &public_keys).is_err());
+
+        // A set with an unreachable threshold verifies nothing, even with no signers
+        let empty = QuorumCertificate::from_signatures(operation_id, decision_hash, &set, &[]).unwrap();
+        let lax = ParticipantSet { approval_threshold: 0.0, ..set };
+        let empty = QuorumCertificate { participant_set_digest: lax.digest(), ..empty };
+        assert!(empty.verify(&lax, &public_keys).is_err());
+    }
+
+    #[test]
+    fn test_forged_certificate_rejected() {
+        let (set, signing_keys, public_keys) = setup(3);
+        let (operation_id, decision_hash) = (Uuid::new_v4(), [1u8; 32]);
+
+        let signatures: Vec<_> = set.members
+            .iter()
+            .zip(&signing_keys)
+            .map(|(member, signing_key)| DecisionSignature::sign(member.node_id, &operation_id, &decision_hash, signing_key))
+            .collect();
+        let certificate = QuorumCertificate::from_signatures(operation_id, decision_hash, &set, &signatures).unwrap();
+        assert!(certificate.verify(&set, &public_keys).is_ok());
+
+        let mut forged = certificate.clone();
+        forged.decision_hash = [2u8; 32];
+        assert!(forged.verify(&set, &public_keys).is_err());
+
+        // The same signatures do not vouch for another operation
+        let mut relabelled = certificate.clone();
+        relabelled.operation_id = Uuid::new_v4();
+        assert!(relabelled.verify(&set, &public_keys).is_err());
+
+        // Bits past the last member are rejected
+        let mut padded = certificate.clone();
+        padded.signer_bitmap[0] |= 1 << 5;
+        assert!(padded.verify(&set, &public_keys).is_err());
+
+        let other_epoch = ParticipantSet { epoch: 4, ..set };
+        assert!(certificate.verify(&other_epoch, &public_keys).is_err());
+    }
+}
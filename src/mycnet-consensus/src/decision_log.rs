@@ -0,0 +1,657 @@
+//! Durable, hash-chained log of consensus decisions
+
+use crate::{
+    AdmissionCriteria, ConsensusOperation, ConsensusOutcome, ConsensusResult, NodeTier, ParticipantSet, QuorumCertificate,
+    QuorumManager, TrustScoring,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use uuid::Uuid;
+
+/// Participant signature over a decision digest
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DecisionSignature {
+    pub node_id: Uuid,
+    pub signature: Vec<u8>,
+}
+
+/// One decision in the log
+///
+/// Signatures are not part of the entry hash so that participant signatures
+/// arriving after the decision can be attached without rewriting the chain.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogEntry {
+    pub sequence: u64,
+    pub previous_hash: [u8; 32],
+    pub operation: ConsensusOperation,
+    pub result: ConsensusResult,
+    pub participant_set: ParticipantSet,
+    pub signatures: Vec<DecisionSignature>,
+    pub entry_hash: [u8; 32],
+}
+
+/// Request for decisions a lagging node is missing
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CatchUpRequest {
+    pub from_sequence: u64,
+    pub max_entries: usize,
+}
+
+/// Decisions returned to a lagging node
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CatchUpResponse {
+    pub entries: Vec<LogEntry>,
+    pub head_sequence: Option<u64>,
+}
+
+/// State rebuilt by replaying the log from the beginning
+///
+/// The engine keeps the same state live and commits each decision through the same methods,
+/// so a running node and one that restarts from its log agree.
+#[derive(Default)]
+pub struct ReplayedState {
+    pub trust_scoring: TrustScoring,
+    pub configuration: BTreeMap<String, Vec<u8>>,
+    pub admitted_nodes: BTreeSet<Uuid>,
+    /// Tier each admitted node declared in its admission criteria
+    pub admitted_tiers: BTreeMap<Uuid, NodeTier>,
+    pub last_sequence: Option<u64>,
+}
+
+/// Append-only decision log persisted in sled
+pub struct DecisionLog {
+    tree: sled::Tree,
+}
+
+/// Hash that participants sign to attest to a decision
+///
+/// Covers the operation, its outcome, the trust adjustments replay will apply and
+/// the participant set that decided it; local details such as timestamps are
+/// excluded so every participant computes the same digest.
+pub fn decision_digest(operation: &ConsensusOperation, result: &ConsensusResult, participant_set: &ParticipantSet) -> [u8; 32] {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(b"mycnet-consensus-decision");
+    hasher.update(result.operation_id.as_bytes());
+    hasher.update(&bincode::serialize(operation).expect("Operation serialization cannot fail"));
+    hasher.update(&bincode::serialize(&result.result).expect("Outcome serialization cannot fail"));
+    hasher.update(&result.participant_epoch.to_be_bytes());
+    hasher.update(&participant_set.digest());
+
+    // Adjustments are hashed in node order so the digest does not depend on map iteration
+    let adjustments: BTreeMap<Uuid, f32> = result.trust_adjustments.iter().map(|(k, v)| (*k, *v)).collect();
+    for (node_id, adjustment) in adjustments {
+        hasher.update(node_id.as_bytes());
+        hasher.update(&adjustment.to_be_bytes());
+    }
+    *hasher.finalize().as_bytes()
+}
+
+/// Bytes a decision signature covers: the operation ID followed by the decision digest
+///
+/// Binding the operation ID means a certificate cannot be relabelled for another operation.
+pub(crate) fn signed_decision(operation_id: &Uuid, digest: &[u8; 32]) -> Vec<u8> {
+    let mut message = Vec::with_capacity(48);
+    message.extend_from_slice(operation_id.as_bytes());
+    message.extend_from_slice(digest);
+    message
+}
+
+impl DecisionSignature {
+    /// Sign the decision digest of an operation
+    pub fn sign(node_id: Uuid, operation_id: &Uuid, digest: &[u8; 32], signing_key: &ed25519_dalek::SigningKey) -> Self {
+        use ed25519_dalek::Signer;
+
+        Self {
+            node_id,
+            signature: signing_key.sign(&signed_decision(operation_id, digest)).to_bytes().to_vec(),
+        }
+    }
+
+    /// Verify the signature over the decision digest of an operation
+    pub fn verify(&self, operation_id: &Uuid, digest: &[u8; 32], public_key: &ed25519_dalek::VerifyingKey) -> bool {
+        use ed25519_dalek::Verifier;
+
+        match ed25519_dalek::Signature::from_slice(&self.signature) {
+            Ok(signature) => public_key.verify(&signed_decision(operation_id, digest), &signature).is_ok(),
+            Err(_) => false,
+        }
+    }
+}
+
+impl LogEntry {
+    /// Digest participants sign for this entry's decision
+    pub fn decision_digest(&self) -> [u8; 32] {
+        decision_digest(&self.operation, &self.result, &self.participant_set)
+    }
+
+    /// Recompute the chained hash of this entry
+    pub fn compute_hash(&self) -> [u8; 32] {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(&self.sequence.to_be_bytes());
+        hasher.update(&self.previous_hash);
+        hasher.update(&self.decision_digest());
+        *hasher.finalize().as_bytes()
+    }
+
+    /// Whether replaying this entry changes trust, configuration or membership
+    pub fn changes_state(&self) -> bool {
+        matches!(self.result.result, ConsensusOutcome::Approved) || !self.result.trust_adjustments.is_empty()
+    }
+
+    /// Check signatures against the participant set the verifier holds for the entry's epoch
+    ///
+    /// The recorded set must be that set, every signature must be valid and from a
+    /// participant, and decisions that change state need signatures carrying the set's
+    /// approval threshold of the voting weight.
+    pub fn verify_signatures(
+        &self,
+        participant_set: &ParticipantSet,
+        public_keys: &HashMap<Uuid, ed25519_dalek::VerifyingKey>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if self.result.participant_epoch != participant_set.epoch || self.participant_set != *participant_set {
+            return Err(format!("Decision {} was not taken by the epoch {} participant set", self.sequence, participant_set.epoch).into());
+        }
+        participant_set.check_threshold()?;
+        let digest = self.decision_digest();
+
+        for signature in &self.signatures {
+            if !self.participant_set.contains(&signature.node_id) {
+                return Err(format!("Signer {} is not in participant set", signature.node_id).into());
+            }
+            let public_key = public_keys
+                .get(&signature.node_id)
+                .ok_or_else(|| format!("No public key for signer {}", signature.node_id))?;
+            if !signature.verify(&self.result.operation_id, &digest, public_key) {
+                return Err(format!("Invalid decision signature from {}", signature.node_id).into());
+            }
+        }
+
+        if self.changes_state() {
+            let signers: BTreeSet<Uuid> = self.signatures.iter().map(|signature| signature.node_id).collect();
+            if self.participant_set.weight_ratio(&signers) < participant_set.approval_threshold {
+                return Err(format!("Decision {} lacks quorum signatures", self.sequence).into());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl DecisionLog {
+    /// Open or create a log at a path
+    pub fn open(path: impl AsRef<std::path::Path>) -> Result<Self, Box<dyn std::error::Error>> {
+        let db = sled::open(path)?;
+        Ok(Self {
+            tree: db.open_tree("consensus-decisions")?,
+        })
+    }
+
+    /// Create a log that is deleted when dropped
+    pub fn temporary() -> Result<Self, Box<dyn std::error::Error>> {
+        let db = sled::Config::new().temporary(true).open()?;
+        Ok(Self {
+            tree: db.open_tree("consensus-decisions")?,
+        })
+    }
+
+    /// Last entry in the log
+    pub fn head(&self) -> Result<Option<LogEntry>, Box<dyn std::error::Error>> {
+        match self.tree.last()? {
+            Some((_, value)) => Ok(Some(bincode::deserialize(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Get the entry at a sequence number
+    pub fn get(&self, sequence: u64) -> Result<Option<LogEntry>, Box<dyn std::error::Error>> {
+        match self.tree.get(sequence.to_be_bytes())? {
+            Some(value) => Ok(Some(bincode::deserialize(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Get up to `limit` entries starting at a sequence number
+    pub fn entries_from(&self, sequence: u64, limit: usize) -> Result<Vec<LogEntry>, Box<dyn std::error::Error>> {
+        self.tree
+            .range(sequence.to_be_bytes()..)
+            .take(limit)
+            .map(|item| {
+                let (_, value) = item?;
+                Ok(bincode::deserialize(&value)?)
+            })
+            .collect()
+    }
+
+    /// Number of entries in the log
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    /// Whether the log has no entries
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    /// Append a new decision after the current head
+    ///
+    /// If another append takes the same sequence first, the entry is re-chained onto the new head.
+    pub fn append(
+        &self,
+        operation: ConsensusOperation,
+        result: ConsensusResult,
+        participant_set: ParticipantSet,
+        signatures: Vec<DecisionSignature>,
+    ) -> Result<LogEntry, Box<dyn std::error::Error>> {
+        let mut entry = LogEntry {
+            sequence: 0,
+            previous_hash: [0u8; 32],
+            operation,
+            result,
+            participant_set,
+            signatures,
+            entry_hash: [0u8; 32],
+        };
+
+        loop {
+            (entry.sequence, entry.previous_hash) = match self.head()? {
+                Some(head) => (head.sequence + 1, head.entry_hash),
+                None => (0, [0u8; 32]),
+            };
+            entry.entry_hash = entry.compute_hash();
+
+            if self.insert_new_entry(&entry)? {
+                return Ok(entry);
+            }
+        }
+    }
+
+    /// Append an entry received from another node, checking that it extends the chain
+    pub fn append_entry(&self, entry: LogEntry) -> Result<(), Box<dyn std::error::Error>> {
+        let (expected_sequence, expected_previous) = match self.head()? {
+            Some(head) => (head.sequence + 1, head.entry_hash),
+            None => (0, [0u8; 32]),
+        };
+
+        if entry.sequence != expected_sequence {
+            return Err(format!("Expected sequence {}, got {}", expected_sequence, entry.sequence).into());
+        }
+        if entry.previous_hash != expected_previous {
+            return Err(format!("Entry {} does not extend the local chain", entry.sequence).into());
+        }
+        if entry.compute_hash() != entry.entry_hash {
+            return Err(format!("Entry {} hash mismatch", entry.sequence).into());
+        }
+
+        if !self.insert_new_entry(&entry)? {
+            // A concurrent append took the sequence; only the identical entry is acceptable
+            let existing = self.get(entry.sequence)?.ok_or("Log entry vanished during append")?;
+            if existing.entry_hash != entry.entry_hash {
+                return Err(format!("Sequence {} was appended concurrently", entry.sequence).into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Attach a participant signature to an existing entry
+    pub fn add_signature(&self, sequence: u64, signature: DecisionSignature, public_key: &ed25519_dalek::VerifyingKey) -> Result<(), Box<dyn std::error::Error>> {
+        let mut entry = self.get(sequence)?.ok_or("Unknown log sequence")?;
+
+        if !entry.participant_set.contains(&signature.node_id) {
+            return Err("Signer is not in the decision's participant set".into());
+        }
+        if !signature.verify(&entry.result.operation_id, &entry.decision_digest(), public_key) {
+            return Err("Invalid decision signature".into());
+        }
+        if entry.signatures.iter().any(|existing| existing.node_id == signature.node_id) {
+            return Ok(());
+        }
+
+        entry.signatures.push(signature);
+        self.write_entry(&entry)
+    }
+
+    /// Insert an entry only if its sequence is still free, returning whether it was inserted
+    fn insert_new_entry(&self, entry: &LogEntry) -> Result<bool, Box<dyn std::error::Error>> {
+        let inserted = self.tree
+            .compare_and_swap(entry.sequence.to_be_bytes(), None as Option<&[u8]>, Some(bincode::serialize(entry)?))?
+            .is_ok();
+        if inserted {
+            self.tree.flush()?;
+        }
+        Ok(inserted)
+    }
+
+    fn write_entry(&self, entry: &LogEntry) -> Result<(), Box<dyn std::error::Error>> {
+        self.tree.insert(entry.sequence.to_be_bytes(), bincode::serialize(entry)?)?;
+        self.tree.flush()?;
+        Ok(())
+    }
+
+    /// Build a quorum certificate for a logged decision
+    pub fn certificate(&self, sequence: u64) -> Result<Option<QuorumCertificate>, Box<dyn std::error::Error>> {
+        match self.get(sequence)? {
+            Some(entry) => Ok(Some(QuorumCertificate::from_log_entry(&entry)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Check hash links for the whole log
+    pub fn verify_chain(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let mut previous_hash = [0u8; 32];
+
+        for (expected_sequence, item) in self.tree.iter().enumerate() {
+            let (_, value) = item?;
+            let entry: LogEntry = bincode::deserialize(&value)?;
+
+            if entry.sequence != expected_sequence as u64 {
+                return Err(format!("Gap in log at sequence {}", expected_sequence).into());
+            }
+            if entry.previous_hash != previous_hash || entry.compute_hash() != entry.entry_hash {
+                return Err(format!("Hash chain broken at sequence {}", entry.sequence).into());
+            }
+            previous_hash = entry.entry_hash;
+        }
+
+        Ok(())
+    }
+
+    /// Answer a catch-up request from a lagging node
+    pub fn handle_catch_up(&self, request: &CatchUpRequest) -> Result<CatchUpResponse, Box<dyn std::error::Error>> {
+        Ok(CatchUpResponse {
+            entries: self.entries_from(request.from_sequence, request.max_entries)?,
+            head_sequence: self.head()?.map(|head| head.sequence),
+        })
+    }
+
+    /// Build the request this node should send to catch up
+    pub fn catch_up_request(&self, max_entries: usize) -> Result<CatchUpRequest, Box<dyn std::error::Error>> {
+        Ok(CatchUpRequest {
+            from_sequence: self.head()?.map(|head| head.sequence + 1).unwrap_or(0),
+            max_entries,
+        })
+    }
+
+    /// Verify and append entries from a catch-up response, returning how many were applied
+    ///
+    /// Each entry is checked against the participant set `quorum` holds for its epoch.
+    pub fn apply_catch_up(
+        &self,
+        response: CatchUpResponse,
+        quorum: &QuorumManager,
+        public_keys: &HashMap<Uuid, ed25519_dalek::VerifyingKey>,
+    ) -> Result<usize, Box<dyn std::error::Error>> {
+        let mut applied = 0;
+
+        for entry in response.entries {
+            // Entries we already have are skipped, anything else must extend the chain
+            if let Some(existing) = self.get(entry.sequence)? {
+                if existing.entry_hash != entry.entry_hash {
+                    return Err(format!("Divergent decision at sequence {}", entry.sequence).into());
+                }
+                continue;
+            }
+
+            let participant_set = quorum
+                .participant_set(entry.result.participant_epoch)
+                .ok_or_else(|| format!("Unknown participant epoch {}", entry.result.participant_epoch))?;
+            entry.verify_signatures(participant_set, public_keys)?;
+            self.append_entry(entry)?;
+            applied += 1;
+        }
+
+        Ok(applied)
+    }
+
+    /// Rebuild trust and configuration state by replaying every decision in order
+    pub fn replay(&self) -> Result<ReplayedState, Box<dyn std::error::Error>> {
+        let mut state = ReplayedState::default();
+
+        for item in self.tree.iter() {
+            let (_, value) = item?;
+            let entry: LogEntry = bincode::deserialize(&value)?;
+
+            state.apply_adjustments(&entry.result);
+            state.apply_outcome(&entry.operation, &entry.result.result);
+            state.last_sequence = Some(entry.sequence);
+        }
+
+        Ok(state)
+    }
+}
+
+impl ReplayedState {
+    /// Apply the trust adjustments recorded with a decision, in node order
+    pub fn apply_adjustments(&mut self, result: &ConsensusResult) {
+        let adjustments: BTreeMap<Uuid, f32> = result.trust_adjustments.iter().map(|(k, v)| (*k, *v)).collect();
+        for (node_id, adjustment) in adjustments {
+            self.trust_scoring.apply_penalty(node_id, adjustment);
+        }
+    }
+
+    /// Apply the effect of a decided operation; only approvals change state
+    pub fn apply_outcome(&mut self, operation: &ConsensusOperation, outcome: &ConsensusOutcome) {
+        if !matches!(outcome, ConsensusOutcome::Approved) {
+            return;
+        }
+        match operation {
+            ConsensusOperation::NetworkConfiguration { config_type, proposed_change } => {
+                self.configuration.insert(config_type.clone(), proposed_change.clone());
+            },
+            ConsensusOperation::TrustScoreModification { target_node, score_change, evidence, .. } => {
+                match evidence {
+                    Some(evidence) => self.trust_scoring.apply_misbehavior(evidence),
+                    None => self.trust_scoring.apply_penalty(*target_node, *score_change),
+                };
+            },
+            ConsensusOperation::NodeAdmission { candidate_node, admission_criteria } => {
+                self.admitted_nodes.insert(*candidate_node);
+                let tier = AdmissionCriteria::decode(admission_criteria)
+                    .ok()
+                    .and_then(|criteria| criteria.node_type.parse().ok());
+                if let Some(tier) = tier {
+                    self.admitted_tiers.insert(*candidate_node, tier);
+                }
+            },
+            ConsensusOperation::ServiceDeployment { .. } => {},
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{NodeTier, WeightedParticipant};
+
+    fn participant_set(node_id: Uuid) -> ParticipantSet {
+        ParticipantSet {
+            epoch: 1,
+            members: vec![WeightedParticipant {
+                node_id,
+                tier: NodeTier::Sclerotia,
+                weight: 0.5,
+            }],
+            approval_threshold: 0.67,
+        }
+    }
+
+    fn approved(operation_id: Uuid) -> ConsensusResult {
+        ConsensusResult {
+            operation_id,
+            result: ConsensusOutcome::Approved,
+            participating_nodes: Vec::new(),
+            trust_adjustments: HashMap::new(),
+            timestamp: chrono::Utc::now(),
+            participant_epoch: 1,
+        }
+    }
+
+    fn config_change(value: u8) -> ConsensusOperation {
+        ConsensusOperation::NetworkConfiguration {
+            config_type: "gossip".to_string(),
+            proposed_change: vec![value],
+        }
+    }
+
+    #[test]
+    fn test_append_chain_and_replay() {
+        let log = DecisionLog::temporary().unwrap();
+        let node_id = Uuid::new_v4();
+
+        log.append(config_change(1), approved(Uuid::new_v4()), participant_set(node_id), Vec::new()).unwrap();
+        let admitted = Uuid::new_v4();
+        let admission = ConsensusOperation::NodeAdmission {
+            candidate_node: admitted,
+            admission_criteria: Vec::new(),
+        };
+        log.append(admission, approved(Uuid::new_v4()), participant_set(node_id), Vec::new()).unwrap();
+        log.append(config_change(2), approved(Uuid::new_v4()), participant_set(node_id), Vec::new()).unwrap();
+
+        assert_eq!(log.len(), 3);
+        assert!(log.verify_chain().is_ok());
+
+        let state = log.replay().unwrap();
+        assert_eq!(state.configuration["gossip"], vec![2]);
+        assert!(state.admitted_nodes.contains(&admitted));
+        assert_eq!(state.last_sequence, Some(2));
+    }
+
+    #[test]
+    fn test_concurrent_appends_keep_one_chain() {
+        let log = std::sync::Arc::new(DecisionLog::temporary().unwrap());
+        let node_id = Uuid::new_v4();
+
+        let writers: Vec<_> = (0..4u8)
+            .map(|writer| {
+                let log = log.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..10 {
+                        log.append(config_change(writer), approved(Uuid::new_v4()), participant_set(node_id), Vec::new()).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        assert_eq!(log.len(), 40);
+        assert!(log.verify_chain().is_ok());
+    }
+
+    fn quorum_of(node_id: Uuid) -> QuorumManager {
+        let mut quorum = QuorumManager::new(crate::QuorumPolicy::default());
+        quorum.add_member(node_id, NodeTier::Sclerotia);
+        quorum.reconfigure(&TrustScoring::new()).unwrap();
+        quorum
+    }
+
+    fn unsigned_catch_up(operation: ConsensusOperation, result: ConsensusResult, participant_set: ParticipantSet) -> CatchUpResponse {
+        let log = DecisionLog::temporary().unwrap();
+        log.append(operation, result, participant_set, Vec::new()).unwrap();
+        log.handle_catch_up(&CatchUpRequest { from_sequence: 0, max_entries: 1 }).unwrap()
+    }
+
+    #[test]
+    fn test_catch_up_verifies_signatures() {
+        let signing_key = ed25519_dalek::SigningKey::generate(&mut rand::rngs::OsRng);
+        let node_id = Uuid::new_v4();
+        let public_keys: HashMap<Uuid, ed25519_dalek::VerifyingKey> = [(node_id, signing_key.verifying_key())].into_iter().collect();
+        let quorum = quorum_of(node_id);
+        let set = quorum.current().clone();
+
+        let leader = DecisionLog::temporary().unwrap();
+        for value in 0..3 {
+            let (operation, result) = (config_change(value), approved(Uuid::new_v4()));
+            let digest = decision_digest(&operation, &result, &set);
+            let signature = DecisionSignature::sign(node_id, &result.operation_id, &digest, &signing_key);
+            leader.append(operation, result, set.clone(), vec![signature]).unwrap();
+        }
+
+        let lagging = DecisionLog::temporary().unwrap();
+        let request = lagging.catch_up_request(2).unwrap();
+        let applied = lagging.apply_catch_up(leader.handle_catch_up(&request).unwrap(), &quorum, &public_keys).unwrap();
+        assert_eq!(applied, 2);
+
+        let request = lagging.catch_up_request(10).unwrap();
+        assert_eq!(request.from_sequence, 2);
+        lagging.apply_catch_up(leader.handle_catch_up(&request).unwrap(), &quorum, &public_keys).unwrap();
+        assert_eq!(lagging.head().unwrap().unwrap().entry_hash, leader.head().unwrap().unwrap().entry_hash);
+
+        // Unsigned approvals are refused
+        let response = unsigned_catch_up(config_change(9), approved(Uuid::new_v4()), set.clone());
+        assert!(DecisionLog::temporary().unwrap().apply_catch_up(response, &quorum, &public_keys).is_err());
+
+        // So are unsigned penalties, whatever the outcome
+        let mut timeout = approved(Uuid::new_v4());
+        timeout.result = ConsensusOutcome::Timeout;
+        timeout.trust_adjustments.insert(node_id, -0.1);
+        let response = unsigned_catch_up(config_change(9), timeout, set);
+        assert!(DecisionLog::temporary().unwrap().apply_catch_up(response, &quorum, &public_keys).is_err());
+    }
+
+    #[test]
+    fn test_catch_up_uses_the_set_threshold() {
+        let signing_keys: Vec<_> = (0..3).map(|_| ed25519_dalek::SigningKey::generate(&mut rand::rngs::OsRng)).collect();
+        let node_ids: Vec<_> = (0..3).map(|_| Uuid::new_v4()).collect();
+        let public_keys: HashMap<Uuid, ed25519_dalek::VerifyingKey> =
+            node_ids.iter().zip(&signing_keys).map(|(node_id, key)| (*node_id, key.verifying_key())).collect();
+
+        // Two of three equally weighted signers meet a 0.6 threshold but not 0.8
+        for (approval_threshold, accepted) in [(0.6, true), (0.8, false)] {
+            let mut quorum = QuorumManager::new(crate::QuorumPolicy {
+                approval_threshold,
+                ..crate::QuorumPolicy::default()
+            });
+            for node_id in &node_ids {
+                quorum.add_member(*node_id, NodeTier::Sclerotia);
+            }
+            quorum.reconfigure(&TrustScoring::new()).unwrap();
+            let set = quorum.current().clone();
+
+            let (operation, result) = (config_change(1), approved(Uuid::new_v4()));
+            let digest = decision_digest(&operation, &result, &set);
+            let signatures = node_ids
+                .iter()
+                .zip(&signing_keys)
+                .take(2)
+                .map(|(node_id, key)| DecisionSignature::sign(*node_id, &result.operation_id, &digest, key))
+                .collect();
+            let leader = DecisionLog::temporary().unwrap();
+            leader.append(operation, result, set, signatures).unwrap();
+            let response = leader.handle_catch_up(&CatchUpRequest { from_sequence: 0, max_entries: 1 }).unwrap();
+            assert_eq!(DecisionLog::temporary().unwrap().apply_catch_up(response, &quorum, &public_keys).is_ok(), accepted);
+        }
+    }
+
+    #[test]
+    fn test_catch_up_rejects_self_declared_participants() {
+        let node_id = Uuid::new_v4();
+        let quorum = quorum_of(node_id);
+
+        // An attacker records a set of its own in place of the epoch's membership
+        let attacker_key = ed25519_dalek::SigningKey::generate(&mut rand::rngs::OsRng);
+        let attacker = Uuid::new_v4();
+        let public_keys: HashMap<Uuid, ed25519_dalek::VerifyingKey> = [(attacker, attacker_key.verifying_key())].into_iter().collect();
+        let forged_set = participant_set(attacker);
+        let (operation, result) = (config_change(1), approved(Uuid::new_v4()));
+        let digest = decision_digest(&operation, &result, &forged_set);
+        let signature = DecisionSignature::sign(attacker, &result.operation_id, &digest, &attacker_key);
+
+        let log = DecisionLog::temporary().unwrap();
+        log.append(operation, result, forged_set, vec![signature]).unwrap();
+        let response = log.handle_catch_up(&CatchUpRequest { from_sequence: 0, max_entries: 1 }).unwrap();
+        assert!(DecisionLog::temporary().unwrap().apply_catch_up(response, &quorum, &public_keys).is_err());
+    }
+
+    #[test]
+    fn test_digest_covers_adjustments_and_participants() {
+        let node_id = Uuid::new_v4();
+        let (operation, result) = (config_change(1), approved(Uuid::new_v4()));
+        let digest = decision_digest(&operation, &result, &participant_set(node_id));
+
+        let mut adjusted = result.clone();
+        adjusted.trust_adjustments.insert(node_id, 0.1);
+        assert_ne!(digest, decision_digest(&operation, &adjusted, &participant_set(node_id)));
+        assert_ne!(digest, decision_digest(&operation, &result, &participant_set(Uuid::new_v4())));
+    }
+}
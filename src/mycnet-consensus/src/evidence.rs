@@ -0,0 +1,306 @@
+//! Signed protocol messages and portable evidence of participant misbehavior
+
+use crate::{ConsensusOperation, Vote};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Commitment to a vote, signed by the participant that made it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedCommitment {
+    pub operation_id: Uuid,
+    pub node_id: Uuid,
+    pub commitment: [u8; 32],
+    pub signature: Vec<u8>,
+}
+
+/// Revealed vote, signed by the participant that cast it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedReveal {
+    pub operation_id: Uuid,
+    pub node_id: Uuid,
+    pub vote: Vote,
+    pub nonce: [u8; 32],
+    pub signature: Vec<u8>,
+}
+
+/// Types of provable misbehavior
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MisbehaviorKind {
+    /// Two different commitments for the same operation
+    ConflictingCommitments {
+        first: SignedCommitment,
+        second: SignedCommitment,
+    },
+    /// A revealed vote that does not hash to the node's commitment
+    RevealMismatch {
+        commitment: SignedCommitment,
+        reveal: SignedReveal,
+    },
+}
+
+/// Misbehavior record that any node can verify with the offender's and reporter's public keys
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MisbehaviorEvidence {
+    pub evidence_id: Uuid,
+    pub kind: MisbehaviorKind,
+    pub reporter: Uuid,
+    pub detected_at: chrono::DateTime<chrono::Utc>,
+    pub reporter_signature: Vec<u8>,
+}
+
+fn verify_signature(public_key: &ed25519_dalek::VerifyingKey, message: &[u8], signature: &[u8]) -> bool {
+    use ed25519_dalek::Verifier;
+
+    match ed25519_dalek::Signature::from_slice(signature) {
+        Ok(signature) => public_key.verify(message, &signature).is_ok(),
+        Err(_) => false,
+    }
+}
+
+impl SignedCommitment {
+    /// Sign a commitment with the participant's signing key
+    pub fn new(operation_id: Uuid, node_id: Uuid, commitment: [u8; 32], signing_key: &ed25519_dalek::SigningKey) -> Self {
+        use ed25519_dalek::Signer;
+
+        let mut signed = Self {
+            operation_id,
+            node_id,
+            commitment,
+            signature: Vec::new(),
+        };
+        signed.signature = signing_key.sign(&signed.signing_bytes()).to_bytes().to_vec();
+        signed
+    }
+
+    fn signing_bytes(&self) -> Vec<u8> {
+        let mut bytes = b"mycnet-consensus-commit".to_vec();
+        bytes.extend_from_slice(self.operation_id.as_bytes());
+        bytes.extend_from_slice(self.node_id.as_bytes());
+        bytes.extend_from_slice(&self.commitment);
+        bytes
+    }
+
+    /// Verify the participant's signature
+    pub fn verify(&self, public_key: &ed25519_dalek::VerifyingKey) -> bool {
+        verify_signature(public_key, &self.signing_bytes(), &self.signature)
+    }
+}
+
+impl SignedReveal {
+    /// Sign a revealed vote with the participant's signing key
+    pub fn new(operation_id: Uuid, node_id: Uuid, vote: Vote, nonce: [u8; 32], signing_key: &ed25519_dalek::SigningKey) -> Self {
+        use ed25519_dalek::Signer;
+
+        let mut signed = Self {
+            operation_id,
+            node_id,
+            vote,
+            nonce,
+            signature: Vec::new(),
+        };
+        signed.signature = signing_key.sign(&signed.signing_bytes()).to_bytes().to_vec();
+        signed
+    }
+
+    fn signing_bytes(&self) -> Vec<u8> {
+        let mut bytes = b"mycnet-consensus-reveal".to_vec();
+        bytes.extend_from_slice(self.operation_id.as_bytes());
+        bytes.extend_from_slice(self.node_id.as_bytes());
+        bytes.push(self.vote as u8);
+        bytes.extend_from_slice(&self.nonce);
+        bytes
+    }
+
+    /// Verify the participant's signature
+    pub fn verify(&self, public_key: &ed25519_dalek::VerifyingKey) -> bool {
+        verify_signature(public_key, &self.signing_bytes(), &self.signature)
+    }
+
+    /// Whether this reveal opens the given commitment
+    pub fn matches(&self, commitment: &SignedCommitment) -> bool {
+        self.vote.commitment(self.operation_id, self.node_id, &self.nonce) == commitment.commitment
+    }
+}
+
+impl MisbehaviorKind {
+    /// Node that misbehaved
+    pub fn offender(&self) -> Uuid {
+        match self {
+            MisbehaviorKind::ConflictingCommitments { first, .. } => first.node_id,
+            MisbehaviorKind::RevealMismatch { commitment, .. } => commitment.node_id,
+        }
+    }
+
+    /// Operation during which the misbehavior happened
+    pub fn operation_id(&self) -> Uuid {
+        match self {
+            MisbehaviorKind::ConflictingCommitments { first, .. } => first.operation_id,
+            MisbehaviorKind::RevealMismatch { commitment, .. } => commitment.operation_id,
+        }
+    }
+
+    /// Trust penalty applied when this misbehavior is proven
+    pub fn penalty(&self) -> f32 {
+        match self {
+            MisbehaviorKind::ConflictingCommitments { .. } => -0.5, // Equivocation is never honest
+            MisbehaviorKind::RevealMismatch { .. } => -0.3,
+        }
+    }
+
+    /// Check that the signed messages prove the misbehavior
+    pub fn verify_proof(&self, offender_key: &ed25519_dalek::VerifyingKey) -> bool {
+        match self {
+            MisbehaviorKind::ConflictingCommitments { first, second } => {
+                first.operation_id == second.operation_id
+                    && first.node_id == second.node_id
+                    && first.commitment != second.commitment
+                    && first.verify(offender_key)
+                    && second.verify(offender_key)
+            },
+            MisbehaviorKind::RevealMismatch { commitment, reveal } => {
+                commitment.operation_id == reveal.operation_id
+                    && commitment.node_id == reveal.node_id
+                    && !reveal.matches(commitment)
+                    && commitment.verify(offender_key)
+                    && reveal.verify(offender_key)
+            },
+        }
+    }
+
+    fn digest_into(&self, hasher: &mut blake3::Hasher) {
+        match self {
+            MisbehaviorKind::ConflictingCommitments { first, second } => {
+                hasher.update(b"conflicting-commitments");
+                hasher.update(&first.signing_bytes());
+                hasher.update(&first.signature);
+                hasher.update(&second.signing_bytes());
+                hasher.update(&second.signature);
+            },
+            MisbehaviorKind::RevealMismatch { commitment, reveal } => {
+                hasher.update(b"reveal-mismatch");
+                hasher.update(&commitment.signing_bytes());
+                hasher.update(&commitment.signature);
+                hasher.update(&reveal.signing_bytes());
+                hasher.update(&reveal.signature);
+            },
+        }
+    }
+}
+
+impl MisbehaviorEvidence {
+    /// Create evidence signed by the reporting node
+    pub fn new(kind: MisbehaviorKind, reporter: Uuid, signing_key: &ed25519_dalek::SigningKey) -> Self {
+        use ed25519_dalek::Signer;
+
+        let mut evidence = Self {
+            evidence_id: Uuid::new_v4(),
+            kind,
+            reporter,
+            detected_at: chrono::Utc::now(),
+            reporter_signature: Vec::new(),
+        };
+        evidence.reporter_signature = signing_key.sign(&evidence.digest()).to_bytes().to_vec();
+        evidence
+    }
+
+    /// Node that misbehaved
+    pub fn offender(&self) -> Uuid {
+        self.kind.offender()
+    }
+
+    fn digest(&self) -> [u8; 32] {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(self.evidence_id.as_bytes());
+        hasher.update(self.reporter.as_bytes());
+        hasher.update(&self.detected_at.timestamp_millis().to_be_bytes());
+        self.kind.digest_into(&mut hasher);
+        *hasher.finalize().as_bytes()
+    }
+
+    /// Verify both the misbehavior proof and the reporter's signature
+    pub fn verify(&self, offender_key: &ed25519_dalek::VerifyingKey, reporter_key: &ed25519_dalek::VerifyingKey) -> bool {
+        self.kind.verify_proof(offender_key)
+            && verify_signature(reporter_key, &self.digest(), &self.reporter_signature)
+    }
+
+    /// Turn the evidence into a trust modification proposal carrying its penalty
+    pub fn into_operation(self) -> ConsensusOperation {
+        let justification = match &self.kind {
+            MisbehaviorKind::ConflictingCommitments { .. } => "Conflicting commitments",
+            MisbehaviorKind::RevealMismatch { .. } => "Reveal does not match commitment",
+        };
+
+        ConsensusOperation::TrustScoreModification {
+            target_node: self.offender(),
+            score_change: self.kind.penalty(),
+            justification: format!("{} in operation {}", justification, self.kind.operation_id()),
+            evidence: Some(Box::new(self)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signing_key() -> ed25519_dalek::SigningKey {
+        ed25519_dalek::SigningKey::generate(&mut rand::rngs::OsRng)
+    }
+
+    #[test]
+    fn test_conflicting_commitments_evidence_verifies() {
+        let offender_keys = signing_key();
+        let reporter_keys = signing_key();
+        let (operation_id, offender, reporter) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+
+        let first = SignedCommitment::new(operation_id, offender, [1u8; 32], &offender_keys);
+        let second = SignedCommitment::new(operation_id, offender, [2u8; 32], &offender_keys);
+        let evidence = MisbehaviorEvidence::new(
+            MisbehaviorKind::ConflictingCommitments { first, second },
+            reporter,
+            &reporter_keys,
+        );
+
+        assert_eq!(evidence.offender(), offender);
+        assert!(evidence.verify(&offender_keys.verifying_key(), &reporter_keys.verifying_key()));
+        assert!(!evidence.verify(&reporter_keys.verifying_key(), &reporter_keys.verifying_key()));
+    }
+
+    #[test]
+    fn test_honest_reveal_is_not_evidence() {
+        let offender_keys = signing_key();
+        let (operation_id, node_id) = (Uuid::new_v4(), Uuid::new_v4());
+        let nonce = [3u8; 32];
+
+        let commitment = SignedCommitment::new(
+            operation_id,
+            node_id,
+            Vote::Reject.commitment(operation_id, node_id, &nonce),
+            &offender_keys,
+        );
+        let reveal = SignedReveal::new(operation_id, node_id, Vote::Reject, nonce, &offender_keys);
+        assert!(reveal.matches(&commitment));
+
+        let kind = MisbehaviorKind::RevealMismatch { commitment, reveal };
+        assert!(!kind.verify_proof(&offender_keys.verifying_key()));
+    }
+
+    #[test]
+    fn test_tampered_evidence_fails_verification() {
+        let offender_keys = signing_key();
+        let reporter_keys = signing_key();
+        let (operation_id, offender) = (Uuid::new_v4(), Uuid::new_v4());
+
+        let commitment = SignedCommitment::new(operation_id, offender, [9u8; 32], &offender_keys);
+        let reveal = SignedReveal::new(operation_id, offender, Vote::Approve, [0u8; 32], &offender_keys);
+        let mut evidence = MisbehaviorEvidence::new(
+            MisbehaviorKind::RevealMismatch { commitment, reveal },
+            Uuid::new_v4(),
+            &reporter_keys,
+        );
+        assert!(evidence.verify(&offender_keys.verifying_key(), &reporter_keys.verifying_key()));
+
+        evidence.reporter = Uuid::new_v4();
+        assert!(!evidence.verify(&offender_keys.verifying_key(), &reporter_keys.verifying_key()));
+    }
+}
@@ -1,9 +1,28 @@
 //! Mycnet Consensus - Byzantine Fault Tolerant consensus system
 
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use uuid::Uuid;
 
+pub mod certificate;
+pub mod decision_log;
+pub mod evidence;
+pub mod quorum;
+pub mod routing;
+pub mod timeout;
+pub mod validation;
+
+pub use certificate::QuorumCertificate;
+pub use decision_log::{decision_digest, CatchUpRequest, CatchUpResponse, DecisionLog, DecisionSignature, LogEntry, ReplayedState};
+pub use evidence::{MisbehaviorEvidence, MisbehaviorKind, SignedCommitment, SignedReveal};
+pub use quorum::{NodeTier, ParticipantSet, QuorumManager, QuorumPolicy, WeightedParticipant};
+pub use routing::{AuthorityResolver, ConsensusMechanism, OperationRouter, RoutingMetrics};
+pub use timeout::{PhaseDeadlines, TimeoutConfig};
+pub use validation::{
+    AdmissionCriteria, ConfigSchema, ConfigurationSchemaValidator, NodeAdmissionValidator, OperationValidator,
+    TrustModificationBoundsValidator, ValidationContext, ValidationVerdict, ValidatorRegistry,
+};
+
 /// BFT consensus operation types
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ConsensusOperation {
@@ -17,6 +36,8 @@ pub enum ConsensusOperation {
         target_node: Uuid,
         score_change: f32,
         justification: String,
+        /// Proof of misbehavior backing an automatic penalty
+        evidence: Option<Box<MisbehaviorEvidence>>,
     },
     /// Node admission to network
     NodeAdmission {
@@ -30,6 +51,30 @@ pub enum ConsensusOperation {
     },
 }
 
+/// `deployment_strategy` of a service deployment confined to the proposing node
+pub const LOCAL_DEPLOYMENT_STRATEGY: &str = "local";
+
+/// Operation type without its payload, used to key per-operation configuration
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum OperationKind {
+    NetworkConfiguration,
+    TrustScoreModification,
+    NodeAdmission,
+    ServiceDeployment,
+}
+
+impl ConsensusOperation {
+    /// Get the kind of this operation
+    pub fn kind(&self) -> OperationKind {
+        match self {
+            ConsensusOperation::NetworkConfiguration { .. } => OperationKind::NetworkConfiguration,
+            ConsensusOperation::TrustScoreModification { .. } => OperationKind::TrustScoreModification,
+            ConsensusOperation::NodeAdmission { .. } => OperationKind::NodeAdmission,
+            ConsensusOperation::ServiceDeployment { .. } => OperationKind::ServiceDeployment,
+        }
+    }
+}
+
 /// Consensus result with trust implications
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ConsensusResult {
@@ -38,6 +83,8 @@ pub struct ConsensusResult {
     pub participating_nodes: Vec<Uuid>,
     pub trust_adjustments: HashMap<Uuid, f32>,
     pub timestamp: chrono::DateTime<chrono::Utc>,
+    /// Epoch of the participant set that decided the operation
+    pub participant_epoch: u64,
 }
 
 /// Outcome of consensus operation
@@ -46,12 +93,61 @@ pub enum ConsensusOutcome {
     Approved,
     Rejected { reason: String },
     Timeout,
+    Cancelled,
+}
+
+/// Phase of the commit-reveal protocol
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ConsensusPhase {
+    Commit,
+    Reveal,
+}
+
+/// Vote cast by a participant
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Vote {
+    Approve,
+    Reject,
+}
+
+impl Vote {
+    /// Compute the commitment a participant publishes before revealing this vote
+    pub fn commitment(&self, operation_id: Uuid, node_id: Uuid, nonce: &[u8; 32]) -> [u8; 32] {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(operation_id.as_bytes());
+        hasher.update(node_id.as_bytes());
+        hasher.update(&[*self as u8]);
+        hasher.update(nonce);
+        *hasher.finalize().as_bytes()
+    }
+}
+
+/// State of an operation that has not reached a decision yet
+#[derive(Debug, Clone)]
+pub struct ActiveOperation {
+    pub operation: ConsensusOperation,
+    pub proposer: Uuid,
+    pub phase: ConsensusPhase,
+    pub participant_set: ParticipantSet,
+    pub commitments: HashMap<Uuid, SignedCommitment>,
+    pub reveals: HashMap<Uuid, Vote>,
+    pub attempt: u32,
+    pub phase_deadline: chrono::DateTime<chrono::Utc>,
+}
+
+impl ActiveOperation {
+    /// Whether every participant has revealed its vote
+    pub fn all_revealed(&self) -> bool {
+        self.phase == ConsensusPhase::Reveal && self.reveals.len() == self.participant_set.len()
+    }
 }
 
 /// Trust scoring system for consensus participants
 pub struct TrustScoring {
     trust_scores: HashMap<Uuid, f32>,
     participation_history: HashMap<Uuid, Vec<ParticipationRecord>>,
+    /// Offender and operation pairs already penalized, so duplicate reports are not applied twice
+    applied_evidence: HashSet<(Uuid, Uuid)>,
 }
 
 /// Record of node participation in consensus
@@ -66,30 +162,244 @@ pub struct ParticipationRecord {
 /// BFT consensus engine using commit-reveal protocol
 pub struct BFTConsensusEngine {
     node_id: Uuid,
-    trust_scoring: TrustScoring,
-    active_operations: HashMap<Uuid, ConsensusOperation>,
-    quorum_threshold: f32,
+    /// Trust, configuration and admissions, kept as `DecisionLog::replay` would rebuild them
+    state: ReplayedState,
+    active_operations: HashMap<Uuid, ActiveOperation>,
+    timed_out_operations: HashMap<Uuid, ActiveOperation>,
+    quorum: QuorumManager,
+    participant_keys: HashMap<Uuid, ed25519_dalek::VerifyingKey>,
+    signing_key: Option<ed25519_dalek::SigningKey>,
+    detected_evidence: Vec<MisbehaviorEvidence>,
+    decided_operations: HashMap<Uuid, ConsensusResult>,
+    router: OperationRouter,
+    authority_resolver: Option<Box<dyn AuthorityResolver>>,
+    validators: ValidatorRegistry,
+    decision_log: Option<DecisionLog>,
+    timeout_config: TimeoutConfig,
 }
 
 impl BFTConsensusEngine {
     /// Create new consensus engine
     pub fn new(node_id: Uuid) -> Self {
+        // A fresh engine is the sole genesis participant until membership is configured
+        let state = ReplayedState::default();
+        let mut quorum = QuorumManager::new(QuorumPolicy::default());
+        quorum.add_member(node_id, NodeTier::Sclerotia);
+        quorum
+            .reconfigure(&state.trust_scoring)
+            .expect("Genesis participant satisfies the default quorum policy");
+        
         Self {
             node_id,
-            trust_scoring: TrustScoring::new(),
+            state,
             active_operations: HashMap::new(),
-            quorum_threshold: 0.67, // 2/3 majority
+            timed_out_operations: HashMap::new(),
+            quorum,
+            participant_keys: HashMap::new(),
+            signing_key: None,
+            detected_evidence: Vec::new(),
+            decided_operations: HashMap::new(),
+            router: OperationRouter::new(),
+            authority_resolver: None,
+            validators: ValidatorRegistry::new(),
+            decision_log: None,
+            timeout_config: TimeoutConfig::default(),
+        }
+    }
+    
+    /// Get the quorum manager to update membership and policy
+    pub fn quorum_mut(&mut self) -> &mut QuorumManager {
+        &mut self.quorum
+    }
+    
+    /// Get the quorum manager
+    pub fn quorum(&self) -> &QuorumManager {
+        &self.quorum
+    }
+    
+    /// Derive a new participant set from membership and the trust scores committed to the decision log
+    ///
+    /// The new epoch applies only to operations proposed after this call.
+    pub fn reconfigure_quorum(&mut self) -> Result<u64, Box<dyn std::error::Error>> {
+        // Every node derives the set from the same committed decisions; without a log the
+        // local state is the only state there is
+        let replayed = match &self.decision_log {
+            Some(decision_log) => Some(decision_log.replay()?),
+            None => None,
+        };
+        let committed = replayed.as_ref().unwrap_or(&self.state);
+        for (node_id, tier) in &committed.admitted_tiers {
+            self.quorum.add_member(*node_id, *tier);
+        }
+        self.quorum.reconfigure(&committed.trust_scoring)
+    }
+    
+    /// Register the public key used to verify a participant's messages
+    pub fn register_participant_key(&mut self, node_id: Uuid, public_key: ed25519_dalek::VerifyingKey) {
+        self.participant_keys.insert(node_id, public_key);
+    }
+    
+    /// Set the key this node uses to sign evidence and its own messages
+    pub fn set_signing_key(&mut self, signing_key: ed25519_dalek::SigningKey) {
+        self.participant_keys.insert(self.node_id, signing_key.verifying_key());
+        self.signing_key = Some(signing_key);
+    }
+    
+    /// Take the misbehavior evidence detected since the last call
+    pub fn drain_evidence(&mut self) -> Vec<MisbehaviorEvidence> {
+        std::mem::take(&mut self.detected_evidence)
+    }
+    
+    /// Propose every piece of detected evidence to the network as a trust modification
+    pub async fn propose_detected_evidence(&mut self) -> Result<Vec<Uuid>, Box<dyn std::error::Error>> {
+        let mut operation_ids = Vec::new();
+        for evidence in self.drain_evidence() {
+            operation_ids.push(self.propose_operation(evidence.into_operation()).await?);
         }
+        Ok(operation_ids)
+    }
+    
+    /// Set the authority that decides operations routed through the hierarchy path
+    pub fn set_authority_resolver(&mut self, resolver: Box<dyn AuthorityResolver>) {
+        self.authority_resolver = Some(resolver);
+    }
+    
+    /// Add a validator run against every operation before this node votes
+    pub fn register_validator(&mut self, validator: Box<dyn OperationValidator>) {
+        self.validators.register(validator);
+    }
+    
+    /// Validate an in-progress operation and return the vote this node should commit to
+    pub fn evaluate_operation(&self, operation_id: &Uuid) -> Result<Vote, Box<dyn std::error::Error>> {
+        let active = self.active_operations
+            .get(operation_id)
+            .ok_or("Unknown consensus operation")?;
+        
+        Ok(self.validators.vote(&active.operation, self.validation_context()))
+    }
+    
+    /// View of this engine's state passed to validators, so they check evidence against the same keys
+    fn validation_context(&self) -> ValidationContext<'_> {
+        ValidationContext {
+            participant_keys: &self.participant_keys,
+        }
+    }
+    
+    /// Persist every decision this engine produces to a decision log
+    pub fn attach_decision_log(&mut self, decision_log: DecisionLog) {
+        self.decision_log = Some(decision_log);
+    }
+    
+    /// Get the attached decision log
+    pub fn decision_log(&self) -> Option<&DecisionLog> {
+        self.decision_log.as_ref()
+    }
+    
+    /// Append a decision to the log, signed by this node when it has a signing key, and apply its effect
+    ///
+    /// Trust adjustments in `result` are already applied as participation is scored; the
+    /// operation's effect goes through the same `ReplayedState` method `replay` uses.
+    fn record_decision(&mut self, operation: &ConsensusOperation, result: &ConsensusResult, participant_set: &ParticipantSet) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(decision_log) = &self.decision_log {
+            let signatures = match &self.signing_key {
+                Some(signing_key) if participant_set.contains(&self.node_id) => {
+                    let digest = decision_digest(operation, result, participant_set);
+                    vec![DecisionSignature::sign(self.node_id, &result.operation_id, &digest, signing_key)]
+                },
+                _ => Vec::new(),
+            };
+            
+            let entry = decision_log.append(operation.clone(), result.clone(), participant_set.clone(), signatures)?;
+            self.state.last_sequence = Some(entry.sequence);
+        }
+        
+        self.state.apply_outcome(operation, &result.result);
+        Ok(())
+    }
+    
+    /// Get the operation router to configure overrides
+    pub fn router_mut(&mut self) -> &mut OperationRouter {
+        &mut self.router
+    }
+    
+    /// Get per-mechanism routing metrics
+    pub fn routing_metrics(&self) -> &RoutingMetrics {
+        self.router.metrics()
+    }
+    
+    /// Replace the phase deadline configuration
+    pub fn set_timeout_config(&mut self, timeout_config: TimeoutConfig) -> Result<(), Box<dyn std::error::Error>> {
+        timeout_config.validate()?;
+        self.timeout_config = timeout_config;
+        Ok(())
+    }
+    
+    /// Get the trust scoring state used by this engine
+    pub fn trust_scoring(&self) -> &TrustScoring {
+        &self.state.trust_scoring
+    }
+    
+    /// Get the state committed decisions have produced on this node
+    pub fn committed_state(&self) -> &ReplayedState {
+        &self.state
+    }
+    
+    /// Get the state of an operation that is still in progress
+    pub fn active_operation(&self, operation_id: &Uuid) -> Option<&ActiveOperation> {
+        self.active_operations.get(operation_id)
     }
     
     /// Propose a new consensus operation
     pub async fn propose_operation(&mut self, operation: ConsensusOperation) -> Result<Uuid, Box<dyn std::error::Error>> {
         let operation_id = Uuid::new_v4();
+        let mechanism = self.router.route(&operation);
+        
+        tracing::info!("Proposing consensus operation: {:?} via {:?}", operation_id, mechanism);
         
-        tracing::info!("Proposing consensus operation: {:?}", operation_id);
+        // Operations off the BFT path are decided immediately
+        let outcome = match mechanism {
+            ConsensusMechanism::LocalAuthority => Some(match self.validators.validate(&operation, self.validation_context()) {
+                ValidationVerdict::Valid => ConsensusOutcome::Approved,
+                ValidationVerdict::Invalid { validator, reason } => ConsensusOutcome::Rejected {
+                    reason: format!("{}: {}", validator, reason),
+                },
+            }),
+            ConsensusMechanism::AuthorityHierarchy => self.decide_by_authority(operation_id, &operation),
+            ConsensusMechanism::FullBFT => None,
+        };
+        if let Some(outcome) = outcome {
+            self.router.record_outcome(mechanism, &outcome);
+            let result = ConsensusResult {
+                operation_id,
+                result: outcome,
+                participating_nodes: vec![self.node_id],
+                trust_adjustments: HashMap::new(),
+                timestamp: chrono::Utc::now(),
+                participant_epoch: self.quorum.current().epoch,
+            };
+            let participant_set = self.quorum.current().clone();
+            self.record_decision(&operation, &result, &participant_set)?;
+            self.decided_operations.insert(operation_id, result);
+            return Ok(operation_id);
+        }
+        
+        let participant_set = self.quorum.current().clone();
+        if participant_set.len() < self.quorum.policy().min_participants {
+            return Err("Not enough eligible participants for full BFT".into());
+        }
         
         // Store operation
-        self.active_operations.insert(operation_id, operation);
+        let active = ActiveOperation {
+            operation,
+            proposer: self.node_id,
+            phase: ConsensusPhase::Commit,
+            participant_set,
+            commitments: HashMap::new(),
+            reveals: HashMap::new(),
+            attempt: 1,
+            phase_deadline: chrono::Utc::now(),
+        };
+        self.active_operations.insert(operation_id, active);
         
         // Begin commit-reveal protocol
         self.begin_commit_phase(operation_id).await?;
@@ -97,50 +407,398 @@ impl BFTConsensusEngine {
         Ok(operation_id)
     }
     
+    /// Ask the authority to decide, returning `None` when the operation must fall back to full BFT
+    fn decide_by_authority(&mut self, operation_id: Uuid, operation: &ConsensusOperation) -> Option<ConsensusOutcome> {
+        let decision = match &self.authority_resolver {
+            Some(resolver) => resolver
+                .decide(operation_id, operation)
+                .map_err(|e| tracing::warn!("Authority {} failed to decide {:?}: {}", resolver.authority_name(), operation_id, e))
+                .ok(),
+            None => {
+                tracing::warn!("No authority available for {:?}, escalating to full BFT", operation_id);
+                None
+            },
+        };
+        
+        if decision.is_none() {
+            self.router.record_fallback();
+        }
+        decision
+    }
+    
     /// Begin commit phase of commit-reveal protocol
-    async fn begin_commit_phase(&self, operation_id: Uuid) -> Result<(), Box<dyn std::error::Error>> {
+    async fn begin_commit_phase(&mut self, operation_id: Uuid) -> Result<(), Box<dyn std::error::Error>> {
         tracing::debug!("Beginning commit phase for operation: {:?}", operation_id);
         
-        // In real implementation:
-        // 1. Nodes submit cryptographic hashes of their results
-        // 2. Wait for all commits to be received
-        // 3. Move to reveal phase
+        let active = self.active_operations
+            .get_mut(&operation_id)
+            .ok_or("Unknown consensus operation")?;
+        
+        let deadlines = self.timeout_config.escalated_deadlines(active.operation.kind(), active.attempt);
+        active.phase = ConsensusPhase::Commit;
+        active.phase_deadline = chrono::Utc::now() + chrono::Duration::from_std(deadlines.commit_timeout)?;
+        
+        Ok(())
+    }
+    
+    /// Record a participant's signed commitment to its vote
+    pub fn submit_commitment(&mut self, commitment: SignedCommitment) -> Result<(), Box<dyn std::error::Error>> {
+        let (operation_id, node_id) = (commitment.operation_id, commitment.node_id);
+        self.verify_participant_message(node_id, |key| commitment.verify(key))?;
+        
+        let active = self.active_operations
+            .get_mut(&operation_id)
+            .ok_or("Unknown consensus operation")?;
+        
+        // Equivocation is checked before phase so late conflicting commitments are caught too
+        if let Some(existing) = active.commitments.get(&node_id) {
+            if *existing == commitment {
+                return Ok(());
+            }
+            let kind = MisbehaviorKind::ConflictingCommitments {
+                first: existing.clone(),
+                second: commitment,
+            };
+            self.report_misbehavior(kind);
+            return Err("Conflicting commitment submitted for operation".into());
+        }
+        
+        if active.phase != ConsensusPhase::Commit {
+            return Err("Commit phase has ended".into());
+        }
+        if chrono::Utc::now() > active.phase_deadline {
+            return Err("Commit deadline has passed".into());
+        }
+        if !active.participant_set.contains(&node_id) {
+            return Err("Node is not a participant in this operation".into());
+        }
+        
+        active.commitments.insert(node_id, commitment);
+        
+        // Move to reveal phase once every participant has committed
+        if active.commitments.len() == active.participant_set.len() {
+            let deadlines = self.timeout_config.escalated_deadlines(active.operation.kind(), active.attempt);
+            active.phase = ConsensusPhase::Reveal;
+            active.phase_deadline = chrono::Utc::now() + chrono::Duration::from_std(deadlines.reveal_timeout)?;
+        }
+        
+        Ok(())
+    }
+    
+    /// Reveal a participant's signed vote, checked against its earlier commitment
+    pub fn submit_reveal(&mut self, reveal: SignedReveal) -> Result<(), Box<dyn std::error::Error>> {
+        let (operation_id, node_id) = (reveal.operation_id, reveal.node_id);
+        self.verify_participant_message(node_id, |key| reveal.verify(key))?;
+        
+        let active = self.active_operations
+            .get_mut(&operation_id)
+            .ok_or("Unknown consensus operation")?;
+        
+        if active.phase != ConsensusPhase::Reveal {
+            return Err("Operation is not in reveal phase".into());
+        }
+        if chrono::Utc::now() > active.phase_deadline {
+            return Err("Reveal deadline has passed".into());
+        }
+        
+        let commitment = active.commitments
+            .get(&node_id)
+            .ok_or("Node did not commit to a vote")?;
+        if !reveal.matches(commitment) {
+            let kind = MisbehaviorKind::RevealMismatch {
+                commitment: commitment.clone(),
+                reveal,
+            };
+            self.report_misbehavior(kind);
+            return Err("Revealed vote does not match commitment".into());
+        }
+        
+        active.reveals.insert(node_id, reveal.vote);
+        Ok(())
+    }
+    
+    fn verify_participant_message(&self, node_id: Uuid, verify: impl Fn(&ed25519_dalek::VerifyingKey) -> bool) -> Result<(), Box<dyn std::error::Error>> {
+        let public_key = self.participant_keys
+            .get(&node_id)
+            .ok_or("No public key registered for participant")?;
         
+        if !verify(public_key) {
+            return Err("Invalid participant signature".into());
+        }
         Ok(())
     }
     
+    /// Penalize a proven offender and keep signed evidence for the rest of the network
+    fn report_misbehavior(&mut self, kind: MisbehaviorKind) {
+        let offender = kind.offender();
+        tracing::warn!("Detected misbehavior by node {} in operation {:?}", offender, kind.operation_id());
+        
+        let adjustment = match &self.signing_key {
+            Some(signing_key) => {
+                let evidence = MisbehaviorEvidence::new(kind, self.node_id, signing_key);
+                let adjustment = self.state.trust_scoring.apply_misbehavior(&evidence);
+                self.detected_evidence.push(evidence);
+                adjustment
+            },
+            None => {
+                tracing::warn!("No signing key set; evidence against {} cannot be signed", offender);
+                self.state.trust_scoring.apply_penalty(offender, kind.penalty())
+            },
+        };
+        tracing::info!(
+            "Trust score of node {} adjusted by {:.3} to {:.3}",
+            offender,
+            adjustment,
+            self.state.trust_scoring.get_trust_score(&offender)
+        );
+    }
+    
+    /// Verify evidence reported by another node and apply its penalty locally
+    pub fn apply_evidence(&mut self, evidence: &MisbehaviorEvidence) -> Result<f32, Box<dyn std::error::Error>> {
+        let offender_key = self.participant_keys
+            .get(&evidence.offender())
+            .ok_or("No public key registered for offender")?;
+        let reporter_key = self.participant_keys
+            .get(&evidence.reporter)
+            .ok_or("No public key registered for reporter")?;
+        
+        if !evidence.verify(offender_key, reporter_key) {
+            return Err("Misbehavior evidence failed verification".into());
+        }
+        
+        Ok(self.state.trust_scoring.apply_misbehavior(evidence))
+    }
+    
     /// Execute reveal phase and determine consensus
     pub async fn execute_reveal_phase(&mut self, operation_id: Uuid) -> Result<ConsensusResult, Box<dyn std::error::Error>> {
         tracing::debug!("Executing reveal phase for operation: {:?}", operation_id);
         
-        // In real implementation:
-        // 1. Nodes reveal their actual results
-        // 2. Majority result is accepted as canonical
-        // 3. Trust scores are updated based on participation
+        // Operations decided outside the commit-reveal path already have a result
+        if let Some(result) = self.decided_operations.remove(&operation_id) {
+            return Ok(result);
+        }
+        
+        let active = self.active_operations
+            .get(&operation_id)
+            .ok_or("Unknown consensus operation")?;
+        
+        // Reveals are only accepted before the deadline, so a complete set is decided even
+        // when it is collected late; same rule as expire_stalled_operations
+        if !active.all_revealed() {
+            if chrono::Utc::now() > active.phase_deadline {
+                return Ok(self.expire_operation(operation_id));
+            }
+            return Err("Reveal phase is still in progress".into());
+        }
+        
+        self.decide_operation(operation_id)
+    }
+    
+    /// Tally the reveals of an operation every participant revealed for and record the decision
+    fn decide_operation(&mut self, operation_id: Uuid) -> Result<ConsensusResult, Box<dyn std::error::Error>> {
+        let active = self.active_operations.remove(&operation_id).ok_or("Unknown consensus operation")?;
+        
+        // Trust-weighted majority result is accepted as canonical
+        let approving = active.reveals
+            .iter()
+            .filter(|(_, vote)| **vote == Vote::Approve)
+            .map(|(node_id, _)| node_id);
+        let approval_ratio = active.participant_set.weight_ratio(approving);
+        let approval_threshold = active.participant_set.approval_threshold;
+        let (outcome, majority_vote) = if approval_ratio >= approval_threshold {
+            (ConsensusOutcome::Approved, Vote::Approve)
+        } else {
+            let reason = format!("Approval ratio {:.2} below quorum threshold {:.2}", approval_ratio, approval_threshold);
+            (ConsensusOutcome::Rejected { reason }, Vote::Reject)
+        };
         
+        // Trust scores are updated based on participation
+        let mut trust_adjustments = HashMap::new();
+        for node_id in active.participant_set.node_ids() {
+            let vote = active.reveals.get(&node_id);
+            let record = ParticipationRecord {
+                operation_id,
+                participated: vote.is_some(),
+                correct_vote: vote == Some(&majority_vote),
+                timestamp: chrono::Utc::now(),
+            };
+            let adjustment = self.state.trust_scoring.apply_participation(node_id, record);
+            trust_adjustments.insert(node_id, adjustment);
+        }
+        
+        self.router.record_outcome(ConsensusMechanism::FullBFT, &outcome);
         let result = ConsensusResult {
             operation_id,
-            result: ConsensusOutcome::Approved,
-            participating_nodes: vec![self.node_id],
+            result: outcome,
+            participating_nodes: active.reveals.keys().copied().collect(),
+            trust_adjustments,
+            timestamp: chrono::Utc::now(),
+            participant_epoch: active.participant_set.epoch,
+        };
+        self.record_decision(&active.operation, &result, &active.participant_set)?;
+        
+        Ok(result)
+    }
+    
+    /// Time out every operation whose current phase deadline has passed
+    pub fn expire_stalled_operations(&mut self) -> Vec<ConsensusResult> {
+        self.expire_stalled_operations_at(chrono::Utc::now())
+    }
+    
+    /// Time out every operation whose current phase deadline is before `now`
+    ///
+    /// Operations every participant revealed for are decided instead. Timed out operations left
+    /// unretried for longer than the retry window are dropped, as are routed decisions left
+    /// uncollected for longer than the decided retention.
+    pub fn expire_stalled_operations_at(&mut self, now: chrono::DateTime<chrono::Utc>) -> Vec<ConsensusResult> {
+        let retry_window = chrono::Duration::from_std(self.timeout_config.retry_window).unwrap_or(chrono::Duration::MAX);
+        self.timed_out_operations
+            .retain(|_, timed_out| now.signed_duration_since(timed_out.phase_deadline) <= retry_window);
+        let decided_retention = chrono::Duration::from_std(self.timeout_config.decided_retention).unwrap_or(chrono::Duration::MAX);
+        self.decided_operations
+            .retain(|_, result| now.signed_duration_since(result.timestamp) <= decided_retention);
+        
+        let stalled: Vec<Uuid> = self.active_operations
+            .iter()
+            .filter(|(_, active)| now > active.phase_deadline)
+            .map(|(operation_id, _)| *operation_id)
+            .collect();
+        
+        stalled
+            .into_iter()
+            .filter_map(|operation_id| {
+                let all_revealed = self.active_operations.get(&operation_id).is_some_and(ActiveOperation::all_revealed);
+                if !all_revealed {
+                    return Some(self.expire_operation(operation_id));
+                }
+                self.decide_operation(operation_id)
+                    .map_err(|e| tracing::error!("Failed to decide {:?}: {}", operation_id, e))
+                    .ok()
+            })
+            .collect()
+    }
+    
+    /// Remove a stalled operation and penalize participants that did not respond
+    fn expire_operation(&mut self, operation_id: Uuid) -> ConsensusResult {
+        let mut result = ConsensusResult {
+            operation_id,
+            result: ConsensusOutcome::Timeout,
+            participating_nodes: Vec::new(),
+            trust_adjustments: HashMap::new(),
+            timestamp: chrono::Utc::now(),
+            participant_epoch: self.quorum.current().epoch,
+        };
+        
+        if let Some(active) = self.active_operations.remove(&operation_id) {
+            result.participant_epoch = active.participant_set.epoch;
+            tracing::warn!("Consensus operation {:?} timed out in {:?} phase", operation_id, active.phase);
+            
+            for node_id in active.participant_set.node_ids() {
+                let responded = match active.phase {
+                    ConsensusPhase::Commit => active.commitments.contains_key(&node_id),
+                    ConsensusPhase::Reveal => active.reveals.contains_key(&node_id),
+                };
+                
+                if responded {
+                    result.participating_nodes.push(node_id);
+                } else {
+                    let record = ParticipationRecord {
+                        operation_id,
+                        participated: false,
+                        correct_vote: false,
+                        timestamp: chrono::Utc::now(),
+                    };
+                    let adjustment = self.state.trust_scoring.apply_participation(node_id, record);
+                    result.trust_adjustments.insert(node_id, adjustment);
+                }
+            }
+            
+            self.router.record_outcome(ConsensusMechanism::FullBFT, &ConsensusOutcome::Timeout);
+            if let Err(e) = self.record_decision(&active.operation, &result, &active.participant_set) {
+                tracing::error!("Failed to log timeout of {:?}: {}", operation_id, e);
+            }
+            if self.timeout_config.can_retry(active.attempt) {
+                self.timed_out_operations.insert(operation_id, active);
+            } else {
+                tracing::warn!("Consensus operation {:?} abandoned after {} attempts", operation_id, active.attempt);
+            }
+        }
+        
+        result
+    }
+    
+    /// Retry a timed out operation with escalated deadlines
+    pub async fn retry_operation(&mut self, operation_id: Uuid) -> Result<(), Box<dyn std::error::Error>> {
+        let timed_out = self.timed_out_operations
+            .get(&operation_id)
+            .ok_or("Operation has not timed out")?;
+        
+        if !self.timeout_config.can_retry(timed_out.attempt) {
+            return Err(format!("Operation exhausted {} attempts", timed_out.attempt).into());
+        }
+        // Same guard as a new proposal; the operation stays timed out so it can be retried later
+        if self.quorum.current().len() < self.quorum.policy().min_participants {
+            return Err("Not enough eligible participants for full BFT".into());
+        }
+        
+        let mut active = self.timed_out_operations.remove(&operation_id).ok_or("Operation has not timed out")?;
+        tracing::info!("Retrying consensus operation {:?} (attempt {})", operation_id, active.attempt + 1);
+        
+        active.attempt += 1;
+        active.participant_set = self.quorum.current().clone();
+        active.commitments.clear();
+        active.reveals.clear();
+        self.active_operations.insert(operation_id, active);
+        
+        self.begin_commit_phase(operation_id).await
+    }
+    
+    /// Cancel an operation that has not reached a decision
+    pub fn cancel_operation(&mut self, operation_id: Uuid) -> Result<ConsensusResult, Box<dyn std::error::Error>> {
+        let active = self.active_operations
+            .remove(&operation_id)
+            .or_else(|| self.timed_out_operations.remove(&operation_id))
+            .ok_or("Unknown consensus operation")?;
+        
+        tracing::info!("Cancelled consensus operation: {:?}", operation_id);
+        
+        let result = ConsensusResult {
+            operation_id,
+            result: ConsensusOutcome::Cancelled,
+            participating_nodes: active.commitments.keys().copied().collect(),
             trust_adjustments: HashMap::new(),
             timestamp: chrono::Utc::now(),
+            participant_epoch: active.participant_set.epoch,
         };
+        self.record_decision(&active.operation, &result, &active.participant_set)?;
         
         Ok(result)
     }
 }
 
+impl Default for TrustScoring {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl TrustScoring {
     /// Create new trust scoring system
     pub fn new() -> Self {
         Self {
             trust_scores: HashMap::new(),
             participation_history: HashMap::new(),
+            applied_evidence: HashSet::new(),
         }
     }
     
     /// Update trust score based on consensus participation
     pub fn update_trust_score(&mut self, node_id: Uuid, participation: ParticipationRecord) {
+        self.apply_participation(node_id, participation);
+    }
+    
+    /// Update trust score and return the applied adjustment
+    pub fn apply_participation(&mut self, node_id: Uuid, participation: ParticipationRecord) -> f32 {
         let current_score = self.trust_scores.get(&node_id).copied().unwrap_or(0.5);
         
         let adjustment = if participation.participated {
@@ -159,8 +817,26 @@ impl TrustScoring {
         // Record participation history
         self.participation_history
             .entry(node_id)
-            .or_insert_with(Vec::new)
+            .or_default()
             .push(participation);
+        
+        new_score - current_score
+    }
+    
+    /// Apply the penalty for verified misbehavior once per offender and operation
+    pub fn apply_misbehavior(&mut self, evidence: &MisbehaviorEvidence) -> f32 {
+        if !self.applied_evidence.insert((evidence.offender(), evidence.kind.operation_id())) {
+            return 0.0;
+        }
+        self.apply_penalty(evidence.offender(), evidence.kind.penalty())
+    }
+    
+    /// Apply a direct score change and return the effective adjustment
+    pub fn apply_penalty(&mut self, node_id: Uuid, adjustment: f32) -> f32 {
+        let current_score = self.get_trust_score(&node_id);
+        let new_score = (current_score + adjustment).clamp(0.0, 1.0);
+        self.trust_scores.insert(node_id, new_score);
+        new_score - current_score
     }
     
     /// Get current trust score for a node
@@ -178,7 +854,7 @@ mod tests {
         let node_id = Uuid::new_v4();
         let engine = BFTConsensusEngine::new(node_id);
         assert_eq!(engine.node_id, node_id);
-        assert_eq!(engine.quorum_threshold, 0.67);
+        assert_eq!(engine.quorum().current().approval_threshold, 0.67);
     }
     
     #[test]
@@ -196,4 +872,471 @@ mod tests {
         trust_scoring.update_trust_score(node_id, participation);
         assert!(trust_scoring.get_trust_score(&node_id) > 0.5);
     }
-}
\ No newline at end of file
+    
+    fn signing_key() -> ed25519_dalek::SigningKey {
+        ed25519_dalek::SigningKey::generate(&mut rand::rngs::OsRng)
+    }
+    
+    fn commit(operation_id: Uuid, node_id: Uuid, vote: Vote, nonce: [u8; 32], signing_key: &ed25519_dalek::SigningKey) -> SignedCommitment {
+        SignedCommitment::new(operation_id, node_id, vote.commitment(operation_id, node_id, &nonce), signing_key)
+    }
+    
+    #[tokio::test]
+    async fn test_commit_reveal_approval() {
+        let node_id = Uuid::new_v4();
+        let keys = signing_key();
+        let mut engine = BFTConsensusEngine::new(node_id);
+        engine.register_participant_key(node_id, keys.verifying_key());
+        
+        let operation = ConsensusOperation::TrustScoreModification {
+            target_node: Uuid::new_v4(),
+            score_change: 0.1,
+            justification: "sustained uptime".to_string(),
+            evidence: None,
+        };
+        let operation_id = engine.propose_operation(operation).await.unwrap();
+        
+        let nonce = [7u8; 32];
+        engine.submit_commitment(commit(operation_id, node_id, Vote::Approve, nonce, &keys)).unwrap();
+        engine.submit_reveal(SignedReveal::new(operation_id, node_id, Vote::Approve, nonce, &keys)).unwrap();
+        
+        let result = engine.execute_reveal_phase(operation_id).await.unwrap();
+        assert!(matches!(result.result, ConsensusOutcome::Approved));
+        assert!(engine.active_operation(&operation_id).is_none());
+    }
+    
+    #[tokio::test]
+    async fn test_stalled_operation_times_out_and_penalizes() {
+        let node_id = Uuid::new_v4();
+        let silent_node = Uuid::new_v4();
+        let keys = signing_key();
+        let mut engine = BFTConsensusEngine::new(node_id);
+        engine.quorum_mut().add_member(silent_node, NodeTier::Rhizomorph);
+        engine.reconfigure_quorum().unwrap();
+        engine.register_participant_key(node_id, keys.verifying_key());
+        
+        let operation = ConsensusOperation::NodeAdmission {
+            candidate_node: Uuid::new_v4(),
+            admission_criteria: Vec::new(),
+        };
+        let operation_id = engine.propose_operation(operation).await.unwrap();
+        engine.submit_commitment(commit(operation_id, node_id, Vote::Approve, [0u8; 32], &keys)).unwrap();
+        
+        let later = chrono::Utc::now() + chrono::Duration::minutes(5);
+        let results = engine.expire_stalled_operations_at(later);
+        
+        assert_eq!(results.len(), 1);
+        assert!(matches!(results[0].result, ConsensusOutcome::Timeout));
+        assert!(results[0].trust_adjustments[&silent_node] < 0.0);
+        assert!(!results[0].trust_adjustments.contains_key(&node_id));
+        assert!(engine.trust_scoring().get_trust_score(&silent_node) < 0.5);
+        
+        // Retry escalates and the operation becomes active again
+        engine.retry_operation(operation_id).await.unwrap();
+        assert_eq!(engine.active_operation(&operation_id).unwrap().attempt, 2);
+    }
+    
+    #[tokio::test]
+    async fn test_timed_out_operations_are_pruned() {
+        let node_id = Uuid::new_v4();
+        let keys = signing_key();
+        let mut engine = BFTConsensusEngine::new(node_id);
+        engine.register_participant_key(node_id, keys.verifying_key());
+        
+        let operation = ConsensusOperation::NetworkConfiguration {
+            config_type: "routing".to_string(),
+            proposed_change: Vec::new(),
+        };
+        let operation_id = engine.propose_operation(operation).await.unwrap();
+        let later = chrono::Utc::now() + chrono::Duration::minutes(5);
+        assert_eq!(engine.expire_stalled_operations_at(later).len(), 1);
+        
+        // Left unretried past the retry window, the operation is forgotten
+        engine.expire_stalled_operations_at(later + chrono::Duration::hours(1));
+        assert!(engine.retry_operation(operation_id).await.is_err());
+        assert!(engine.timed_out_operations.is_empty());
+    }
+    
+    #[tokio::test]
+    async fn test_uncollected_decisions_are_pruned() {
+        let mut engine = BFTConsensusEngine::new(Uuid::new_v4());
+        
+        let local = ConsensusOperation::ServiceDeployment {
+            service_spec: Vec::new(),
+            deployment_strategy: LOCAL_DEPLOYMENT_STRATEGY.to_string(),
+        };
+        let operation_id = engine.propose_operation(local).await.unwrap();
+        engine.expire_stalled_operations_at(chrono::Utc::now() + chrono::Duration::minutes(5));
+        assert!(engine.decided_operations.contains_key(&operation_id));
+        
+        // Nobody collected the result within the retention, so it is forgotten
+        engine.expire_stalled_operations_at(chrono::Utc::now() + chrono::Duration::hours(1));
+        assert!(engine.decided_operations.is_empty());
+        assert!(engine.execute_reveal_phase(operation_id).await.is_err());
+    }
+    
+    #[tokio::test]
+    async fn test_reveal_after_deadline_times_out() {
+        let node_id = Uuid::new_v4();
+        let keys = signing_key();
+        let mut engine = BFTConsensusEngine::new(node_id);
+        engine.register_participant_key(node_id, keys.verifying_key());
+        
+        let operation = ConsensusOperation::NetworkConfiguration {
+            config_type: "routing".to_string(),
+            proposed_change: Vec::new(),
+        };
+        let operation_id = engine.propose_operation(operation).await.unwrap();
+        let nonce = [3u8; 32];
+        engine.submit_commitment(commit(operation_id, node_id, Vote::Approve, nonce, &keys)).unwrap();
+        engine.submit_reveal(SignedReveal::new(operation_id, node_id, Vote::Approve, nonce, &keys)).unwrap();
+        
+        // Every vote was revealed in time, so collecting after the deadline still decides
+        engine.active_operations.get_mut(&operation_id).unwrap().phase_deadline = chrono::Utc::now() - chrono::Duration::seconds(1);
+        let result = engine.execute_reveal_phase(operation_id).await.unwrap();
+        assert!(matches!(result.result, ConsensusOutcome::Approved));
+        
+        let silent_node = Uuid::new_v4();
+        engine.quorum_mut().add_member(silent_node, NodeTier::Rhizomorph);
+        engine.reconfigure_quorum().unwrap();
+        let operation = ConsensusOperation::NetworkConfiguration {
+            config_type: "routing".to_string(),
+            proposed_change: Vec::new(),
+        };
+        let operation_id = engine.propose_operation(operation).await.unwrap();
+        engine.submit_commitment(commit(operation_id, node_id, Vote::Approve, nonce, &keys)).unwrap();
+        engine.active_operations.get_mut(&operation_id).unwrap().phase_deadline = chrono::Utc::now() - chrono::Duration::seconds(1);
+        let result = engine.execute_reveal_phase(operation_id).await.unwrap();
+        assert!(matches!(result.result, ConsensusOutcome::Timeout));
+    }
+    
+    #[tokio::test]
+    async fn test_revealed_operations_are_decided_by_the_sweep() {
+        let node_id = Uuid::new_v4();
+        let keys = signing_key();
+        let mut engine = BFTConsensusEngine::new(node_id);
+        engine.register_participant_key(node_id, keys.verifying_key());
+        
+        let operation = ConsensusOperation::NetworkConfiguration {
+            config_type: "routing".to_string(),
+            proposed_change: Vec::new(),
+        };
+        let operation_id = engine.propose_operation(operation).await.unwrap();
+        let nonce = [4u8; 32];
+        engine.submit_commitment(commit(operation_id, node_id, Vote::Approve, nonce, &keys)).unwrap();
+        engine.submit_reveal(SignedReveal::new(operation_id, node_id, Vote::Approve, nonce, &keys)).unwrap();
+        
+        let results = engine.expire_stalled_operations_at(chrono::Utc::now() + chrono::Duration::minutes(5));
+        assert_eq!(results.len(), 1);
+        assert!(matches!(results[0].result, ConsensusOutcome::Approved));
+        assert!(engine.retry_operation(operation_id).await.is_err());
+    }
+    
+    #[tokio::test]
+    async fn test_retry_requires_enough_participants() {
+        let node_id = Uuid::new_v4();
+        let mut engine = BFTConsensusEngine::new(node_id);
+        
+        let operation = ConsensusOperation::NetworkConfiguration {
+            config_type: "routing".to_string(),
+            proposed_change: Vec::new(),
+        };
+        let operation_id = engine.propose_operation(operation).await.unwrap();
+        engine.expire_stalled_operations_at(chrono::Utc::now() + chrono::Duration::minutes(5));
+        
+        engine.quorum_mut().set_policy(QuorumPolicy {
+            min_participants: 2,
+            ..QuorumPolicy::default()
+        });
+        assert!(engine.retry_operation(operation_id).await.is_err());
+        assert!(engine.active_operation(&operation_id).is_none());
+        
+        // Once the quorum is large enough again the same operation can be retried
+        engine.quorum_mut().add_member(Uuid::new_v4(), NodeTier::Sclerotia);
+        engine.reconfigure_quorum().unwrap();
+        engine.retry_operation(operation_id).await.unwrap();
+        assert_eq!(engine.active_operation(&operation_id).unwrap().attempt, 2);
+    }
+    
+    #[test]
+    fn test_invalid_timeout_config_is_rejected() {
+        let mut engine = BFTConsensusEngine::new(Uuid::new_v4());
+        let config = TimeoutConfig {
+            escalation_factor: f32::NAN,
+            ..TimeoutConfig::default()
+        };
+        assert!(engine.set_timeout_config(config).is_err());
+    }
+    
+    #[tokio::test]
+    async fn test_cancel_operation() {
+        let mut engine = BFTConsensusEngine::new(Uuid::new_v4());
+        let operation = ConsensusOperation::NetworkConfiguration {
+            config_type: "security_policy".to_string(),
+            proposed_change: Vec::new(),
+        };
+        let operation_id = engine.propose_operation(operation).await.unwrap();
+        
+        let result = engine.cancel_operation(operation_id).unwrap();
+        assert!(matches!(result.result, ConsensusOutcome::Cancelled));
+        assert!(engine.cancel_operation(operation_id).is_err());
+    }
+    
+    #[tokio::test]
+    async fn test_equivocation_produces_evidence() {
+        let (node_id, byzantine_node) = (Uuid::new_v4(), Uuid::new_v4());
+        let (keys, byzantine_keys) = (signing_key(), signing_key());
+        let byzantine_public = byzantine_keys.verifying_key();
+        
+        let mut engine = BFTConsensusEngine::new(node_id);
+        engine.set_signing_key(keys);
+        engine.register_participant_key(byzantine_node, byzantine_public);
+        engine.quorum_mut().add_member(byzantine_node, NodeTier::Sclerotia);
+        engine.reconfigure_quorum().unwrap();
+        
+        let operation = ConsensusOperation::NetworkConfiguration {
+            config_type: "routing".to_string(),
+            proposed_change: Vec::new(),
+        };
+        let operation_id = engine.propose_operation(operation).await.unwrap();
+        
+        engine.submit_commitment(commit(operation_id, byzantine_node, Vote::Approve, [1u8; 32], &byzantine_keys)).unwrap();
+        assert!(engine.submit_commitment(commit(operation_id, byzantine_node, Vote::Reject, [1u8; 32], &byzantine_keys)).is_err());
+        
+        let evidence = engine.drain_evidence();
+        assert_eq!(evidence.len(), 1);
+        assert_eq!(evidence[0].offender(), byzantine_node);
+        assert!(engine.trust_scoring().get_trust_score(&byzantine_node) <= 0.0);
+        
+        // Another node verifies the same evidence independently, and it is only applied once
+        let mut observer = BFTConsensusEngine::new(Uuid::new_v4());
+        observer.register_participant_key(byzantine_node, byzantine_public);
+        observer.register_participant_key(node_id, engine.participant_keys[&node_id]);
+        assert!(observer.apply_evidence(&evidence[0]).unwrap() < 0.0);
+        assert_eq!(observer.apply_evidence(&evidence[0]).unwrap(), 0.0);
+        
+        let operation = evidence[0].clone().into_operation();
+        assert_eq!(operation.kind(), OperationKind::TrustScoreModification);
+        
+        // The detecting node puts the evidence to a vote
+        assert!(engine.submit_commitment(commit(operation_id, byzantine_node, Vote::Reject, [2u8; 32], &byzantine_keys)).is_err());
+        let proposals = engine.propose_detected_evidence().await.unwrap();
+        assert_eq!(proposals.len(), 1);
+        assert!(matches!(
+            engine.active_operation(&proposals[0]).unwrap().operation,
+            ConsensusOperation::TrustScoreModification { evidence: Some(_), .. }
+        ));
+        assert!(engine.drain_evidence().is_empty());
+    }
+    
+    struct RejectingAuthority;
+    
+    impl AuthorityResolver for RejectingAuthority {
+        fn authority_name(&self) -> &str {
+            "primary-spore"
+        }
+        
+        fn decide(&self, _operation_id: Uuid, _operation: &ConsensusOperation) -> Result<ConsensusOutcome, Box<dyn std::error::Error>> {
+            Ok(ConsensusOutcome::Rejected { reason: "insufficient resources".to_string() })
+        }
+    }
+    
+    #[tokio::test]
+    async fn test_operations_routed_off_bft_path() {
+        let mut engine = BFTConsensusEngine::new(Uuid::new_v4());
+        
+        // Without an authority, hierarchy operations escalate to full BFT
+        let deployment = ConsensusOperation::ServiceDeployment {
+            service_spec: Vec::new(),
+            deployment_strategy: "rolling".to_string(),
+        };
+        let operation_id = engine.propose_operation(deployment.clone()).await.unwrap();
+        assert!(engine.active_operation(&operation_id).is_some());
+        assert_eq!(engine.routing_metrics().authority_fallbacks, 1);
+        
+        engine.set_authority_resolver(Box::new(RejectingAuthority));
+        let operation_id = engine.propose_operation(deployment).await.unwrap();
+        let result = engine.execute_reveal_phase(operation_id).await.unwrap();
+        assert!(matches!(result.result, ConsensusOutcome::Rejected { .. }));
+        
+        let local = ConsensusOperation::ServiceDeployment {
+            service_spec: Vec::new(),
+            deployment_strategy: LOCAL_DEPLOYMENT_STRATEGY.to_string(),
+        };
+        let operation_id = engine.propose_operation(local).await.unwrap();
+        let result = engine.execute_reveal_phase(operation_id).await.unwrap();
+        assert!(matches!(result.result, ConsensusOutcome::Approved));
+        assert_eq!(engine.routing_metrics().routed[&ConsensusMechanism::LocalAuthority], 1);
+    }
+    
+    #[test]
+    fn test_quorum_follows_committed_state() {
+        let mut engine = BFTConsensusEngine::new(Uuid::new_v4());
+        engine.attach_decision_log(DecisionLog::temporary().unwrap());
+        let other = Uuid::new_v4();
+        engine.quorum_mut().add_member(other, NodeTier::Rhizomorph);
+        let epoch = engine.reconfigure_quorum().unwrap();
+        
+        // A penalty only this node has applied does not move the voting set
+        let (reporter, offender_keys, reporter_keys) = (Uuid::new_v4(), signing_key(), signing_key());
+        engine.register_participant_key(other, offender_keys.verifying_key());
+        engine.register_participant_key(reporter, reporter_keys.verifying_key());
+        let operation_id = Uuid::new_v4();
+        let kind = MisbehaviorKind::ConflictingCommitments {
+            first: SignedCommitment::new(operation_id, other, [1u8; 32], &offender_keys),
+            second: SignedCommitment::new(operation_id, other, [2u8; 32], &offender_keys),
+        };
+        engine.apply_evidence(&MisbehaviorEvidence::new(kind, reporter, &reporter_keys)).unwrap();
+        assert_eq!(engine.reconfigure_quorum().unwrap(), epoch);
+        assert!(engine.quorum().current().contains(&other));
+        
+        // A committed admission brings the node in with its declared tier
+        let admitted = Uuid::new_v4();
+        let criteria = AdmissionCriteria::new(&[0u8; 32], admitted, 0, &signing_key(), "Hyphae".to_string(), Vec::new());
+        let admission = ConsensusOperation::NodeAdmission {
+            candidate_node: admitted,
+            admission_criteria: criteria.encode(),
+        };
+        let result = ConsensusResult {
+            operation_id: Uuid::new_v4(),
+            result: ConsensusOutcome::Approved,
+            participating_nodes: vec![other],
+            trust_adjustments: HashMap::new(),
+            timestamp: chrono::Utc::now(),
+            participant_epoch: epoch,
+        };
+        let participant_set = engine.quorum().current().clone();
+        engine.decision_log().unwrap().append(admission, result, participant_set, Vec::new()).unwrap();
+        assert_eq!(engine.reconfigure_quorum().unwrap(), epoch + 1);
+        assert_eq!(engine.quorum().current().members.iter().find(|m| m.node_id == admitted).unwrap().tier, NodeTier::Hyphae);
+    }
+    
+    #[tokio::test]
+    async fn test_participant_votes_on_substance() {
+        let mut engine = BFTConsensusEngine::new(Uuid::new_v4());
+        engine.register_validator(Box::new(TrustModificationBoundsValidator::default()));
+        
+        let excessive = ConsensusOperation::TrustScoreModification {
+            target_node: Uuid::new_v4(),
+            score_change: 0.8,
+            justification: "promotion".to_string(),
+            evidence: None,
+        };
+        let operation_id = engine.propose_operation(excessive).await.unwrap();
+        assert_eq!(engine.evaluate_operation(&operation_id).unwrap(), Vote::Reject);
+    }
+    
+    #[tokio::test]
+    async fn test_validators_use_engine_participant_keys() {
+        let (offender, reporter) = (Uuid::new_v4(), Uuid::new_v4());
+        let (offender_keys, reporter_keys) = (signing_key(), signing_key());
+        let operation_id = Uuid::new_v4();
+        let kind = MisbehaviorKind::ConflictingCommitments {
+            first: SignedCommitment::new(operation_id, offender, [1u8; 32], &offender_keys),
+            second: SignedCommitment::new(operation_id, offender, [2u8; 32], &offender_keys),
+        };
+        let evidence = MisbehaviorEvidence::new(kind, reporter, &reporter_keys);
+        
+        let mut engine = BFTConsensusEngine::new(Uuid::new_v4());
+        engine.register_validator(Box::new(TrustModificationBoundsValidator::default()));
+        engine.register_participant_key(offender, offender_keys.verifying_key());
+        let operation_id = engine.propose_operation(evidence.clone().into_operation()).await.unwrap();
+        assert_eq!(engine.evaluate_operation(&operation_id).unwrap(), Vote::Reject);
+        
+        // Keys registered once with the engine are all the validator needs
+        engine.register_participant_key(reporter, reporter_keys.verifying_key());
+        let operation_id = engine.propose_operation(evidence.into_operation()).await.unwrap();
+        assert_eq!(engine.evaluate_operation(&operation_id).unwrap(), Vote::Approve);
+    }
+    
+    #[tokio::test]
+    async fn test_result_records_participant_epoch() {
+        let node_id = Uuid::new_v4();
+        let keys = signing_key();
+        let mut engine = BFTConsensusEngine::new(node_id);
+        engine.register_participant_key(node_id, keys.verifying_key());
+        
+        let operation = ConsensusOperation::NodeAdmission {
+            candidate_node: Uuid::new_v4(),
+            admission_criteria: Vec::new(),
+        };
+        let operation_id = engine.propose_operation(operation).await.unwrap();
+        
+        // Reconfiguration mid-flight does not change the pinned set
+        engine.quorum_mut().add_member(Uuid::new_v4(), NodeTier::Hyphae);
+        assert_eq!(engine.reconfigure_quorum().unwrap(), 2);
+        
+        let nonce = [2u8; 32];
+        engine.submit_commitment(commit(operation_id, node_id, Vote::Approve, nonce, &keys)).unwrap();
+        engine.submit_reveal(SignedReveal::new(operation_id, node_id, Vote::Approve, nonce, &keys)).unwrap();
+        
+        let result = engine.execute_reveal_phase(operation_id).await.unwrap();
+        assert!(matches!(result.result, ConsensusOutcome::Approved));
+        assert_eq!(result.participant_epoch, 1);
+    }
+    
+    #[tokio::test]
+    async fn test_live_state_matches_replay() {
+        let node_id = Uuid::new_v4();
+        let keys = signing_key();
+        let mut engine = BFTConsensusEngine::new(node_id);
+        engine.set_signing_key(keys.clone());
+        engine.attach_decision_log(DecisionLog::temporary().unwrap());
+        
+        let target = Uuid::new_v4();
+        let operations = [
+            ConsensusOperation::NetworkConfiguration {
+                config_type: "gossip".to_string(),
+                proposed_change: vec![3],
+            },
+            ConsensusOperation::TrustScoreModification {
+                target_node: target,
+                score_change: -0.1,
+                justification: "missed heartbeats".to_string(),
+                evidence: None,
+            },
+        ];
+        for (index, operation) in operations.into_iter().enumerate() {
+            let operation_id = engine.propose_operation(operation).await.unwrap();
+            let nonce = [index as u8; 32];
+            engine.submit_commitment(commit(operation_id, node_id, Vote::Approve, nonce, &keys)).unwrap();
+            engine.submit_reveal(SignedReveal::new(operation_id, node_id, Vote::Approve, nonce, &keys)).unwrap();
+            assert!(matches!(engine.execute_reveal_phase(operation_id).await.unwrap().result, ConsensusOutcome::Approved));
+        }
+        
+        let live = engine.committed_state();
+        let replayed = engine.decision_log().unwrap().replay().unwrap();
+        assert_eq!(live.configuration, replayed.configuration);
+        assert_eq!(live.configuration["gossip"], vec![3]);
+        assert_eq!(live.last_sequence, replayed.last_sequence);
+        for node in [node_id, target] {
+            assert_eq!(live.trust_scoring.get_trust_score(&node), replayed.trust_scoring.get_trust_score(&node));
+        }
+        assert!(live.trust_scoring.get_trust_score(&target) < 0.5);
+    }
+    
+    #[tokio::test]
+    async fn test_decisions_are_logged() {
+        let node_id = Uuid::new_v4();
+        let mut engine = BFTConsensusEngine::new(node_id);
+        engine.set_signing_key(signing_key());
+        engine.attach_decision_log(DecisionLog::temporary().unwrap());
+        
+        let local = ConsensusOperation::ServiceDeployment {
+            service_spec: Vec::new(),
+            deployment_strategy: LOCAL_DEPLOYMENT_STRATEGY.to_string(),
+        };
+        engine.propose_operation(local).await.unwrap();
+        let operation = ConsensusOperation::NetworkConfiguration {
+            config_type: "security_policy".to_string(),
+            proposed_change: Vec::new(),
+        };
+        let operation_id = engine.propose_operation(operation).await.unwrap();
+        engine.cancel_operation(operation_id).unwrap();
+        
+        let decision_log = engine.decision_log().unwrap();
+        assert_eq!(decision_log.len(), 2);
+        assert!(decision_log.verify_chain().is_ok());
+        assert_eq!(decision_log.get(0).unwrap().unwrap().signatures.len(), 1);
+    }
+}
@@ -0,0 +1,412 @@
+//! Trust-weighted quorum membership with epoch-based reconfiguration
+
+use crate::TrustScoring;
+pub use mycnet_core::NodeTier;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use uuid::Uuid;
+
+/// Participant with its voting weight
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct WeightedParticipant {
+    pub node_id: Uuid,
+    pub tier: NodeTier,
+    pub weight: f32,
+}
+
+/// Versioned voting set used for every operation proposed during an epoch
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ParticipantSet {
+    pub epoch: u64,
+    pub members: Vec<WeightedParticipant>,
+    /// Fraction of the total weight that must approve an operation proposed in this epoch
+    pub approval_threshold: f32,
+}
+
+/// Rules for deriving the voting set
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuorumPolicy {
+    /// Nodes below this trust score are excluded from voting
+    pub minimum_trust_score: f32,
+    pub min_participants: usize,
+    pub max_participants: usize,
+    /// Minimum number of Sclerotia that must be in the set
+    pub min_sclerotia: usize,
+    /// Current participants stay until their trust falls this far below `minimum_trust_score`
+    pub trust_hysteresis: f32,
+    /// Trust scores are rounded to this step before weighting, so small changes keep the epoch
+    pub weight_step: f32,
+    /// Fraction of the total weight that must approve an operation, above one half
+    pub approval_threshold: f32,
+}
+
+/// Derives and versions the participant set from membership and trust
+pub struct QuorumManager {
+    policy: QuorumPolicy,
+    members: HashMap<Uuid, NodeTier>,
+    current: ParticipantSet,
+    history: BTreeMap<u64, ParticipantSet>,
+}
+
+/// Voting weight multiplier for a tier
+pub fn tier_weight_multiplier(tier: NodeTier) -> f32 {
+    match tier {
+        NodeTier::Sclerotia => 1.0,
+        NodeTier::Rhizomorph => 0.75,
+        NodeTier::Hyphae => 0.5,
+    }
+}
+
+/// Check that an approval threshold is a finite majority of the voting weight
+fn check_approval_threshold(approval_threshold: f32) -> Result<(), Box<dyn std::error::Error>> {
+    if !(approval_threshold > 0.5 && approval_threshold <= 1.0) {
+        return Err(format!("Approval threshold {} must be above 0.5 and at most 1", approval_threshold).into());
+    }
+    Ok(())
+}
+
+impl ParticipantSet {
+    /// Build the set for an epoch, rejecting thresholds a minority or nobody could meet
+    pub fn new(epoch: u64, members: Vec<WeightedParticipant>, approval_threshold: f32) -> Result<Self, Box<dyn std::error::Error>> {
+        check_approval_threshold(approval_threshold)?;
+        Ok(Self {
+            epoch,
+            members,
+            approval_threshold,
+        })
+    }
+
+    /// Check the set's own threshold, for sets that did not come from `new`
+    pub fn check_threshold(&self) -> Result<(), Box<dyn std::error::Error>> {
+        check_approval_threshold(self.approval_threshold)
+    }
+
+    /// Whether a node votes in this set
+    pub fn contains(&self, node_id: &Uuid) -> bool {
+        self.members.iter().any(|member| member.node_id == *node_id)
+    }
+
+    /// Voting weight of a node, zero if it is not a member
+    pub fn weight_of(&self, node_id: &Uuid) -> f32 {
+        self.members
+            .iter()
+            .find(|member| member.node_id == *node_id)
+            .map(|member| member.weight)
+            .unwrap_or(0.0)
+    }
+
+    /// Sum of all voting weights
+    pub fn total_weight(&self) -> f32 {
+        self.members.iter().map(|member| member.weight).sum()
+    }
+
+    /// Fraction of the total weight held by the given nodes
+    pub fn weight_ratio<'a>(&self, nodes: impl IntoIterator<Item = &'a Uuid>) -> f32 {
+        let total = self.total_weight();
+        if total <= 0.0 {
+            return 0.0;
+        }
+        nodes.into_iter().map(|node_id| self.weight_of(node_id)).sum::<f32>() / total
+    }
+
+    /// Node IDs in set order
+    pub fn node_ids(&self) -> Vec<Uuid> {
+        self.members.iter().map(|member| member.node_id).collect()
+    }
+
+    /// Number of voting members
+    pub fn len(&self) -> usize {
+        self.members.len()
+    }
+
+    /// Whether the set has no voting members
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+
+    /// Hash identifying this exact set and its weights
+    pub fn digest(&self) -> [u8; 32] {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(&self.epoch.to_be_bytes());
+        hasher.update(&self.approval_threshold.to_be_bytes());
+        for member in &self.members {
+            hasher.update(member.node_id.as_bytes());
+            hasher.update(&[member.tier as u8]);
+            hasher.update(&member.weight.to_be_bytes());
+        }
+        *hasher.finalize().as_bytes()
+    }
+}
+
+impl Default for QuorumPolicy {
+    fn default() -> Self {
+        Self {
+            minimum_trust_score: 0.3,
+            min_participants: 1,
+            max_participants: 21,
+            min_sclerotia: 0,
+            trust_hysteresis: 0.05,
+            weight_step: 0.05,
+            approval_threshold: 0.67, // 2/3 majority
+        }
+    }
+}
+
+impl QuorumManager {
+    /// Create a manager with an empty epoch-zero set
+    pub fn new(policy: QuorumPolicy) -> Self {
+        let current = ParticipantSet {
+            epoch: 0,
+            members: Vec::new(),
+            approval_threshold: policy.approval_threshold,
+        };
+        let mut history = BTreeMap::new();
+        history.insert(0, current.clone());
+
+        Self {
+            policy,
+            members: HashMap::new(),
+            current,
+            history,
+        }
+    }
+
+    /// Add a node to the membership, or change its tier
+    pub fn add_member(&mut self, node_id: Uuid, tier: NodeTier) {
+        self.members.insert(node_id, tier);
+    }
+
+    /// Remove a node that left the network
+    pub fn remove_member(&mut self, node_id: &Uuid) {
+        self.members.remove(node_id);
+    }
+
+    /// Get the policy
+    pub fn policy(&self) -> &QuorumPolicy {
+        &self.policy
+    }
+
+    /// Replace the policy; takes effect at the next reconfiguration
+    pub fn set_policy(&mut self, policy: QuorumPolicy) {
+        self.policy = policy;
+    }
+
+    /// Participant set for new operations
+    pub fn current(&self) -> &ParticipantSet {
+        &self.current
+    }
+
+    /// Participant set that was active during an epoch
+    pub fn participant_set(&self, epoch: u64) -> Option<&ParticipantSet> {
+        self.history.get(&epoch)
+    }
+
+    /// Derive the voting set from membership, tiers and trust scores
+    ///
+    /// `trust_scoring` must be state every node agrees on, such as the scores replayed from
+    /// the decision log; locally observed penalties would give each node a different set.
+    pub fn derive_members(&self, trust_scoring: &TrustScoring) -> Vec<WeightedParticipant> {
+        let mut eligible: Vec<(Uuid, NodeTier, f32)> = self.members
+            .iter()
+            .map(|(node_id, tier)| (*node_id, *tier, self.rounded_trust(trust_scoring.get_trust_score(node_id))))
+            .filter(|(node_id, _, trust)| {
+                // Incumbents get some slack so a score hovering at the threshold does not churn epochs
+                let minimum = if self.current.contains(node_id) {
+                    self.policy.minimum_trust_score - self.policy.trust_hysteresis
+                } else {
+                    self.policy.minimum_trust_score
+                };
+                *trust >= minimum
+            })
+            .collect();
+
+        // Sclerotia first, then highest trust; node ID breaks ties deterministically
+        eligible.sort_by(|a, b| {
+            a.1.cmp(&b.1)
+                .then(b.2.total_cmp(&a.2))
+                .then(a.0.cmp(&b.0))
+        });
+        eligible.truncate(self.policy.max_participants);
+
+        let mut members: Vec<WeightedParticipant> = eligible
+            .into_iter()
+            .map(|(node_id, tier, trust)| WeightedParticipant {
+                node_id,
+                tier,
+                weight: trust * tier_weight_multiplier(tier),
+            })
+            .collect();
+        members.sort_by_key(|member| member.node_id);
+        members
+    }
+
+    fn rounded_trust(&self, trust: f32) -> f32 {
+        if self.policy.weight_step > 0.0 {
+            (trust / self.policy.weight_step).round() * self.policy.weight_step
+        } else {
+            trust
+        }
+    }
+
+    /// Start a new epoch if membership or trust changed the voting set
+    ///
+    /// Operations already in flight keep the set they were proposed with; the
+    /// new set only applies to operations proposed afterwards.
+    pub fn reconfigure(&mut self, trust_scoring: &TrustScoring) -> Result<u64, Box<dyn std::error::Error>> {
+        let approval_threshold = self.policy.approval_threshold;
+        check_approval_threshold(approval_threshold)?;
+        let members = self.derive_members(trust_scoring);
+
+        if members.len() < self.policy.min_participants {
+            return Err(format!(
+                "Only {} eligible participants, policy requires {}",
+                members.len(),
+                self.policy.min_participants
+            )
+            .into());
+        }
+        let sclerotia = members.iter().filter(|member| member.tier == NodeTier::Sclerotia).count();
+        if sclerotia < self.policy.min_sclerotia {
+            return Err(format!("Only {} eligible Sclerotia, policy requires {}", sclerotia, self.policy.min_sclerotia).into());
+        }
+
+        if members == self.current.members && approval_threshold == self.current.approval_threshold {
+            return Ok(self.current.epoch);
+        }
+
+        let next = ParticipantSet::new(self.current.epoch + 1, members, approval_threshold)?;
+        tracing::info!("Quorum reconfigured to epoch {} with {} participants", next.epoch, next.len());
+
+        self.history.insert(next.epoch, next.clone());
+        self.current = next;
+        Ok(self.current.epoch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ParticipationRecord;
+
+    #[test]
+    fn test_sclerotia_first_and_trust_filtering() {
+        let mut trust = TrustScoring::new();
+        let mut quorum = QuorumManager::new(QuorumPolicy {
+            max_participants: 2,
+            ..QuorumPolicy::default()
+        });
+
+        let (sclerotia, rhizomorph, hyphae, untrusted) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        quorum.add_member(sclerotia, NodeTier::Sclerotia);
+        quorum.add_member(rhizomorph, NodeTier::Rhizomorph);
+        quorum.add_member(hyphae, NodeTier::Hyphae);
+        quorum.add_member(untrusted, NodeTier::Sclerotia);
+        trust.apply_penalty(untrusted, -0.4);
+
+        assert_eq!(quorum.reconfigure(&trust).unwrap(), 1);
+        let set = quorum.current();
+        assert!(set.contains(&sclerotia));
+        assert!(set.contains(&rhizomorph));
+        assert!(!set.contains(&hyphae));
+        assert!(!set.contains(&untrusted));
+        assert!(set.weight_of(&sclerotia) > set.weight_of(&rhizomorph));
+    }
+
+    #[test]
+    fn test_epochs_only_advance_on_change() {
+        let mut trust = TrustScoring::new();
+        let mut quorum = QuorumManager::new(QuorumPolicy::default());
+        let (first, second) = (Uuid::new_v4(), Uuid::new_v4());
+
+        quorum.add_member(first, NodeTier::Sclerotia);
+        quorum.add_member(second, NodeTier::Rhizomorph);
+        assert_eq!(quorum.reconfigure(&trust).unwrap(), 1);
+        assert_eq!(quorum.reconfigure(&trust).unwrap(), 1);
+
+        // Demotion through lost trust removes the node at the next epoch
+        for _ in 0..15 {
+            trust.update_trust_score(second, ParticipationRecord {
+                operation_id: Uuid::new_v4(),
+                participated: false,
+                correct_vote: false,
+                timestamp: chrono::Utc::now(),
+            });
+        }
+        assert_eq!(quorum.reconfigure(&trust).unwrap(), 2);
+        assert!(!quorum.current().contains(&second));
+        assert!(quorum.participant_set(1).unwrap().contains(&second));
+    }
+
+    #[test]
+    fn test_minimum_participants_enforced() {
+        let trust = TrustScoring::new();
+        let mut quorum = QuorumManager::new(QuorumPolicy {
+            min_participants: 3,
+            ..QuorumPolicy::default()
+        });
+        quorum.add_member(Uuid::new_v4(), NodeTier::Sclerotia);
+
+        assert!(quorum.reconfigure(&trust).is_err());
+        assert_eq!(quorum.current().epoch, 0);
+    }
+
+    #[test]
+    fn test_small_trust_changes_keep_the_epoch() {
+        let mut trust = TrustScoring::new();
+        let mut quorum = QuorumManager::new(QuorumPolicy {
+            minimum_trust_score: 0.5,
+            ..QuorumPolicy::default()
+        });
+        let node_id = Uuid::new_v4();
+        quorum.add_member(node_id, NodeTier::Sclerotia);
+        assert_eq!(quorum.reconfigure(&trust).unwrap(), 1);
+
+        // Below the rounding step the weight is unchanged
+        trust.apply_penalty(node_id, 0.01);
+        assert_eq!(quorum.reconfigure(&trust).unwrap(), 1);
+
+        // Just under the threshold an incumbent stays, a newcomer is not admitted
+        trust.apply_penalty(node_id, -0.05);
+        let newcomer = Uuid::new_v4();
+        quorum.add_member(newcomer, NodeTier::Sclerotia);
+        trust.apply_penalty(newcomer, -0.04);
+        assert_eq!(quorum.reconfigure(&trust).unwrap(), 2);
+        assert!(quorum.current().contains(&node_id));
+        assert!(!quorum.current().contains(&newcomer));
+    }
+
+    #[test]
+    fn test_threshold_change_starts_an_epoch() {
+        let trust = TrustScoring::new();
+        let mut quorum = QuorumManager::new(QuorumPolicy::default());
+        quorum.add_member(Uuid::new_v4(), NodeTier::Sclerotia);
+        assert_eq!(quorum.reconfigure(&trust).unwrap(), 1);
+
+        quorum.set_policy(QuorumPolicy {
+            approval_threshold: 0.4,
+            ..QuorumPolicy::default()
+        });
+        assert!(quorum.reconfigure(&trust).is_err());
+
+        quorum.set_policy(QuorumPolicy {
+            approval_threshold: 0.8,
+            ..QuorumPolicy::default()
+        });
+        assert_eq!(quorum.reconfigure(&trust).unwrap(), 2);
+        assert_eq!(quorum.current().approval_threshold, 0.8);
+        assert_eq!(quorum.participant_set(1).unwrap().approval_threshold, 0.67);
+    }
+
+    #[test]
+    fn test_sets_reject_unreachable_thresholds() {
+        let member = WeightedParticipant {
+            node_id: Uuid::new_v4(),
+            tier: NodeTier::Sclerotia,
+            weight: 1.0,
+        };
+        for threshold in [0.0, -1.0, 0.5, 1.5, f32::NAN, f32::INFINITY] {
+            assert!(ParticipantSet::new(1, vec![member], threshold).is_err());
+        }
+        assert!(ParticipantSet::new(1, vec![member], 0.67).is_ok());
+    }
+}
@@ -0,0 +1,215 @@
+//! Operation routing following the consensus decision matrix
+
+use crate::{ConsensusOperation, ConsensusOutcome, OperationKind, LOCAL_DEPLOYMENT_STRATEGY};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+
+/// Mechanism used to reach a decision on an operation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ConsensusMechanism {
+    /// Full commit-reveal BFT among all participants
+    FullBFT,
+    /// Decision delegated to the Raft leader or Primary Spore authority
+    AuthorityHierarchy,
+    /// Decision taken by the proposing node alone
+    LocalAuthority,
+}
+
+/// Authority that decides operations routed through the hierarchy path
+pub trait AuthorityResolver: Send + Sync {
+    /// Name of the authority, used in logs and rejection reasons
+    fn authority_name(&self) -> &str;
+
+    /// Decide an operation on behalf of the network
+    fn decide(&self, operation_id: Uuid, operation: &ConsensusOperation) -> Result<ConsensusOutcome, Box<dyn std::error::Error>>;
+}
+
+/// Per-mechanism routing counters
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RoutingMetrics {
+    pub routed: HashMap<ConsensusMechanism, u64>,
+    pub approved: HashMap<ConsensusMechanism, u64>,
+    pub rejected: HashMap<ConsensusMechanism, u64>,
+    pub timed_out: HashMap<ConsensusMechanism, u64>,
+    /// Operations that were routed with an override instead of the decision matrix
+    pub overrides_applied: u64,
+    /// Authority-path operations escalated to full BFT because no authority was available
+    pub authority_fallbacks: u64,
+}
+
+/// Classifies operations and tracks where they were routed
+pub struct OperationRouter {
+    kind_overrides: HashMap<OperationKind, ConsensusMechanism>,
+    config_type_overrides: HashMap<String, ConsensusMechanism>,
+    security_config_types: HashSet<String>,
+    metrics: RoutingMetrics,
+}
+
+impl OperationRouter {
+    /// Create a router using the default decision matrix
+    pub fn new() -> Self {
+        let security_config_types = ["security_policy", "network_identity", "trust_policy", "admission_policy"]
+            .iter()
+            .map(|config_type| config_type.to_string())
+            .collect();
+
+        Self {
+            kind_overrides: HashMap::new(),
+            config_type_overrides: HashMap::new(),
+            security_config_types,
+            metrics: RoutingMetrics::default(),
+        }
+    }
+
+    /// Classify an operation with the decision matrix, ignoring overrides
+    pub fn classify(&self, operation: &ConsensusOperation) -> ConsensusMechanism {
+        match operation {
+            // Security-critical operations always need BFT
+            ConsensusOperation::TrustScoreModification { .. } | ConsensusOperation::NodeAdmission { .. } => {
+                ConsensusMechanism::FullBFT
+            },
+            // Configuration changes need BFT only when they affect security
+            ConsensusOperation::NetworkConfiguration { config_type, .. } => {
+                if self.security_config_types.contains(config_type) {
+                    ConsensusMechanism::FullBFT
+                } else {
+                    ConsensusMechanism::AuthorityHierarchy
+                }
+            },
+            // Deployments confined to the proposing node need no coordination
+            ConsensusOperation::ServiceDeployment { deployment_strategy, .. } => {
+                if deployment_strategy == LOCAL_DEPLOYMENT_STRATEGY {
+                    ConsensusMechanism::LocalAuthority
+                } else {
+                    ConsensusMechanism::AuthorityHierarchy
+                }
+            },
+        }
+    }
+
+    /// Route an operation, applying overrides and recording metrics
+    pub fn route(&mut self, operation: &ConsensusOperation) -> ConsensusMechanism {
+        let override_mechanism = match operation {
+            ConsensusOperation::NetworkConfiguration { config_type, .. } => self.config_type_overrides.get(config_type),
+            _ => None,
+        }
+        .or_else(|| self.kind_overrides.get(&operation.kind()))
+        .copied();
+
+        let mechanism = match override_mechanism {
+            Some(mechanism) => {
+                self.metrics.overrides_applied += 1;
+                mechanism
+            },
+            None => self.classify(operation),
+        };
+
+        *self.metrics.routed.entry(mechanism).or_insert(0) += 1;
+        mechanism
+    }
+
+    /// Force every operation of a kind onto a mechanism
+    pub fn set_override(&mut self, kind: OperationKind, mechanism: ConsensusMechanism) {
+        self.kind_overrides.insert(kind, mechanism);
+    }
+
+    /// Force network configuration changes of one type onto a mechanism
+    pub fn set_config_type_override(&mut self, config_type: String, mechanism: ConsensusMechanism) {
+        self.config_type_overrides.insert(config_type, mechanism);
+    }
+
+    /// Remove the override for an operation kind
+    pub fn clear_override(&mut self, kind: OperationKind) {
+        self.kind_overrides.remove(&kind);
+    }
+
+    /// Mark a configuration type as security-relevant so it requires full BFT
+    pub fn add_security_config_type(&mut self, config_type: String) {
+        self.security_config_types.insert(config_type);
+    }
+
+    /// Record that an operation escalated from the authority path to full BFT
+    pub fn record_fallback(&mut self) {
+        self.metrics.authority_fallbacks += 1;
+    }
+
+    /// Record the outcome of an operation decided through a mechanism
+    pub fn record_outcome(&mut self, mechanism: ConsensusMechanism, outcome: &ConsensusOutcome) {
+        let counter = match outcome {
+            ConsensusOutcome::Approved => &mut self.metrics.approved,
+            ConsensusOutcome::Rejected { .. } => &mut self.metrics.rejected,
+            ConsensusOutcome::Timeout => &mut self.metrics.timed_out,
+            ConsensusOutcome::Cancelled => return,
+        };
+        *counter.entry(mechanism).or_insert(0) += 1;
+    }
+
+    /// Get routing metrics
+    pub fn metrics(&self) -> &RoutingMetrics {
+        &self.metrics
+    }
+}
+
+impl Default for OperationRouter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decision_matrix() {
+        let router = OperationRouter::new();
+
+        let admission = ConsensusOperation::NodeAdmission {
+            candidate_node: Uuid::new_v4(),
+            admission_criteria: Vec::new(),
+        };
+        let security_config = ConsensusOperation::NetworkConfiguration {
+            config_type: "security_policy".to_string(),
+            proposed_change: Vec::new(),
+        };
+        let routine_config = ConsensusOperation::NetworkConfiguration {
+            config_type: "log_level".to_string(),
+            proposed_change: Vec::new(),
+        };
+        let deployment = ConsensusOperation::ServiceDeployment {
+            service_spec: Vec::new(),
+            deployment_strategy: "rolling".to_string(),
+        };
+
+        assert_eq!(router.classify(&admission), ConsensusMechanism::FullBFT);
+        assert_eq!(router.classify(&security_config), ConsensusMechanism::FullBFT);
+        assert_eq!(router.classify(&routine_config), ConsensusMechanism::AuthorityHierarchy);
+        assert_eq!(router.classify(&deployment), ConsensusMechanism::AuthorityHierarchy);
+    }
+
+    #[test]
+    fn test_overrides_and_metrics() {
+        let mut router = OperationRouter::new();
+        router.set_override(OperationKind::ServiceDeployment, ConsensusMechanism::FullBFT);
+        router.set_config_type_override("log_level".to_string(), ConsensusMechanism::LocalAuthority);
+
+        let deployment = ConsensusOperation::ServiceDeployment {
+            service_spec: Vec::new(),
+            deployment_strategy: "rolling".to_string(),
+        };
+        let config = ConsensusOperation::NetworkConfiguration {
+            config_type: "log_level".to_string(),
+            proposed_change: Vec::new(),
+        };
+
+        assert_eq!(router.route(&deployment), ConsensusMechanism::FullBFT);
+        assert_eq!(router.route(&config), ConsensusMechanism::LocalAuthority);
+        router.record_outcome(ConsensusMechanism::FullBFT, &ConsensusOutcome::Approved);
+
+        let metrics = router.metrics();
+        assert_eq!(metrics.overrides_applied, 2);
+        assert_eq!(metrics.routed[&ConsensusMechanism::FullBFT], 1);
+        assert_eq!(metrics.approved[&ConsensusMechanism::FullBFT], 1);
+    }
+}
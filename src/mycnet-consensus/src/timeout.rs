@@ -0,0 +1,173 @@
+//! Phase deadlines and retry escalation for consensus operations
+
+use crate::OperationKind;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Longest a single phase may run, however far its deadline has been escalated
+pub const MAX_PHASE_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+
+/// Deadlines for the commit and reveal phases of a single attempt
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PhaseDeadlines {
+    pub commit_timeout: Duration,
+    pub reveal_timeout: Duration,
+}
+
+/// Timeout configuration with per-operation overrides
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimeoutConfig {
+    pub default_deadlines: PhaseDeadlines,
+    pub overrides: HashMap<OperationKind, PhaseDeadlines>,
+    /// Maximum number of attempts (including the first) before an operation is abandoned
+    pub max_attempts: u32,
+    /// Multiplier applied to both deadlines on every retry
+    pub escalation_factor: f32,
+    /// How long a timed out operation can still be retried before it is dropped
+    pub retry_window: Duration,
+    /// How long a routed decision nobody has collected is kept before it is dropped
+    pub decided_retention: Duration,
+}
+
+impl PhaseDeadlines {
+    /// Create deadlines for both phases
+    pub fn new(commit_timeout: Duration, reveal_timeout: Duration) -> Self {
+        Self {
+            commit_timeout,
+            reveal_timeout,
+        }
+    }
+
+    /// Scale both deadlines by a factor, capped at `MAX_PHASE_TIMEOUT`
+    ///
+    /// Negative or NaN factors leave the deadlines unchanged.
+    pub fn scaled(&self, factor: f32) -> Self {
+        let scale = |timeout: Duration| {
+            if factor.is_nan() || factor < 0.0 {
+                return timeout;
+            }
+            Duration::try_from_secs_f64(timeout.as_secs_f64() * factor as f64)
+                .unwrap_or(MAX_PHASE_TIMEOUT)
+                .min(MAX_PHASE_TIMEOUT)
+        };
+        Self {
+            commit_timeout: scale(self.commit_timeout),
+            reveal_timeout: scale(self.reveal_timeout),
+        }
+    }
+}
+
+impl TimeoutConfig {
+    /// Create a configuration using the same deadlines for every operation
+    pub fn new(default_deadlines: PhaseDeadlines) -> Self {
+        Self {
+            default_deadlines,
+            overrides: HashMap::new(),
+            max_attempts: 3,
+            escalation_factor: 2.0,
+            retry_window: Duration::from_secs(10 * 60),
+            decided_retention: Duration::from_secs(10 * 60),
+        }
+    }
+
+    /// Check the retry settings before the configuration is used
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_attempts == 0 {
+            return Err("max_attempts must allow at least one attempt".to_string());
+        }
+        if !self.escalation_factor.is_finite() || self.escalation_factor < 1.0 {
+            return Err(format!("escalation_factor {} must be a finite value of at least 1", self.escalation_factor));
+        }
+        Ok(())
+    }
+
+    /// Override the deadlines used for one operation type
+    pub fn set_deadlines(&mut self, kind: OperationKind, deadlines: PhaseDeadlines) {
+        self.overrides.insert(kind, deadlines);
+    }
+
+    /// Deadlines for the first attempt of an operation type
+    pub fn deadlines_for(&self, kind: OperationKind) -> PhaseDeadlines {
+        self.overrides
+            .get(&kind)
+            .copied()
+            .unwrap_or(self.default_deadlines)
+    }
+
+    /// Deadlines for a given attempt, escalated for each retry
+    pub fn escalated_deadlines(&self, kind: OperationKind, attempt: u32) -> PhaseDeadlines {
+        let retries = attempt.saturating_sub(1).min(i32::MAX as u32) as i32;
+        self.deadlines_for(kind).scaled(self.escalation_factor.powi(retries))
+    }
+
+    /// Whether another attempt is allowed after `attempt` timed out
+    pub fn can_retry(&self, attempt: u32) -> bool {
+        attempt < self.max_attempts
+    }
+}
+
+impl Default for TimeoutConfig {
+    fn default() -> Self {
+        let mut config = Self::new(PhaseDeadlines::new(Duration::from_secs(30), Duration::from_secs(30)));
+
+        // Admission needs time for candidates to be vetted; deployments should fail fast
+        config.set_deadlines(
+            OperationKind::NodeAdmission,
+            PhaseDeadlines::new(Duration::from_secs(60), Duration::from_secs(60)),
+        );
+        config.set_deadlines(
+            OperationKind::TrustScoreModification,
+            PhaseDeadlines::new(Duration::from_secs(15), Duration::from_secs(15)),
+        );
+        config.set_deadlines(
+            OperationKind::ServiceDeployment,
+            PhaseDeadlines::new(Duration::from_secs(10), Duration::from_secs(10)),
+        );
+
+        config
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_per_operation_overrides() {
+        let mut config = TimeoutConfig::new(PhaseDeadlines::new(Duration::from_secs(5), Duration::from_secs(5)));
+        config.set_deadlines(
+            OperationKind::NodeAdmission,
+            PhaseDeadlines::new(Duration::from_secs(20), Duration::from_secs(10)),
+        );
+
+        assert_eq!(config.deadlines_for(OperationKind::NodeAdmission).commit_timeout, Duration::from_secs(20));
+        assert_eq!(config.deadlines_for(OperationKind::ServiceDeployment).commit_timeout, Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_escalation_and_retry_limit() {
+        let config = TimeoutConfig::new(PhaseDeadlines::new(Duration::from_secs(10), Duration::from_secs(10)));
+
+        assert_eq!(config.escalated_deadlines(OperationKind::NetworkConfiguration, 1).commit_timeout, Duration::from_secs(10));
+        assert_eq!(config.escalated_deadlines(OperationKind::NetworkConfiguration, 3).commit_timeout, Duration::from_secs(40));
+        assert!(config.can_retry(2));
+        assert!(!config.can_retry(3));
+    }
+
+    #[test]
+    fn test_escalation_is_capped_and_validated() {
+        let mut config = TimeoutConfig::new(PhaseDeadlines::new(Duration::from_secs(10), Duration::from_secs(10)));
+        assert_eq!(config.escalated_deadlines(OperationKind::NodeAdmission, u32::MAX).commit_timeout, MAX_PHASE_TIMEOUT);
+
+        for factor in [f32::NAN, -2.0, f32::INFINITY, 0.5] {
+            config.escalation_factor = factor;
+            assert!(config.validate().is_err());
+            // Bad factors never panic even when the configuration was not validated
+            config.escalated_deadlines(OperationKind::NodeAdmission, 3);
+        }
+        config.escalation_factor = 2.0;
+        config.max_attempts = 0;
+        assert!(config.validate().is_err());
+    }
+}
@@ -0,0 +1,513 @@
+//! Operation validators run by each participant before voting
+
+use crate::{ConsensusOperation, OperationKind, Vote};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use uuid::Uuid;
+
+/// Result of validating an operation's content
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidationVerdict {
+    Valid,
+    Invalid { validator: String, reason: String },
+}
+
+/// Engine state validators may consult
+#[derive(Clone, Copy)]
+pub struct ValidationContext<'a> {
+    /// Keys registered with the engine, used to verify signatures carried in operations
+    pub participant_keys: &'a HashMap<Uuid, ed25519_dalek::VerifyingKey>,
+}
+
+/// Inspects the content of consensus operations
+pub trait OperationValidator: Send + Sync {
+    /// Name used when reporting rejections
+    fn name(&self) -> &str;
+
+    /// Whether this validator inspects operations of the given kind
+    fn applies_to(&self, kind: OperationKind) -> bool;
+
+    /// Check the operation, returning the reason it is invalid
+    fn validate(&self, operation: &ConsensusOperation, context: ValidationContext<'_>) -> Result<(), String>;
+}
+
+/// Set of validators run against every proposal
+#[derive(Default)]
+pub struct ValidatorRegistry {
+    validators: Vec<Box<dyn OperationValidator>>,
+}
+
+/// Admission payload carried in `NodeAdmission::admission_criteria`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AdmissionCriteria {
+    /// Admission epoch the membership proof was issued for
+    pub epoch: u64,
+    pub public_key: [u8; 32],
+    pub membership_proof: Vec<u8>,
+    /// Signature by `public_key` over the candidate and epoch, proving the candidate holds the key
+    pub possession_signature: Vec<u8>,
+    pub node_type: String,
+    pub capabilities: Vec<String>,
+}
+
+/// Checks membership proofs, node types and capabilities of admission candidates
+pub struct NodeAdmissionValidator {
+    isolation_key: [u8; 32],
+    admission_epoch: u64,
+    allowed_node_types: Vec<String>,
+    required_capabilities: Vec<String>,
+}
+
+/// Bounds how far a single trust modification may move a score
+///
+/// Evidence signatures are checked against the engine's participant keys.
+pub struct TrustModificationBoundsValidator {
+    pub max_increase: f32,
+    pub max_decrease: f32,
+}
+
+/// Schema for one network configuration type
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ConfigSchema {
+    pub required_keys: Vec<String>,
+    pub allowed_keys: Vec<String>,
+    pub max_payload_bytes: usize,
+}
+
+/// Validates `NetworkConfiguration::proposed_change` against registered schemas
+pub struct ConfigurationSchemaValidator {
+    schemas: HashMap<String, ConfigSchema>,
+}
+
+impl ValidatorRegistry {
+    /// Create an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a validator
+    pub fn register(&mut self, validator: Box<dyn OperationValidator>) {
+        self.validators.push(validator);
+    }
+
+    /// Run every applicable validator, stopping at the first rejection
+    pub fn validate(&self, operation: &ConsensusOperation, context: ValidationContext<'_>) -> ValidationVerdict {
+        let kind = operation.kind();
+
+        for validator in self.validators.iter().filter(|validator| validator.applies_to(kind)) {
+            if let Err(reason) = validator.validate(operation, context) {
+                tracing::debug!("Validator {} rejected {:?} operation: {}", validator.name(), kind, reason);
+                return ValidationVerdict::Invalid {
+                    validator: validator.name().to_string(),
+                    reason,
+                };
+            }
+        }
+
+        ValidationVerdict::Valid
+    }
+
+    /// Vote this node should cast for an operation
+    pub fn vote(&self, operation: &ConsensusOperation, context: ValidationContext<'_>) -> Vote {
+        match self.validate(operation, context) {
+            ValidationVerdict::Valid => Vote::Approve,
+            ValidationVerdict::Invalid { .. } => Vote::Reject,
+        }
+    }
+}
+
+impl AdmissionCriteria {
+    /// Build criteria with a membership proof derived from the network isolation key
+    ///
+    /// The proof binds the candidate and the admission epoch, so it cannot be replayed for
+    /// another node or in a later epoch. The candidate signs the same challenge with its key.
+    pub fn new(
+        isolation_key: &[u8; 32],
+        candidate_node: Uuid,
+        epoch: u64,
+        signing_key: &ed25519_dalek::SigningKey,
+        node_type: String,
+        capabilities: Vec<String>,
+    ) -> Self {
+        use ed25519_dalek::Signer;
+
+        let public_key = signing_key.verifying_key().to_bytes();
+        let challenge = Self::challenge(&candidate_node, epoch);
+        Self {
+            epoch,
+            public_key,
+            membership_proof: Self::expected_proof(isolation_key, &candidate_node, epoch, &public_key).to_vec(),
+            possession_signature: signing_key.sign(&challenge).to_bytes().to_vec(),
+            node_type,
+            capabilities,
+        }
+    }
+
+    fn challenge(candidate_node: &Uuid, epoch: u64) -> Vec<u8> {
+        let mut challenge = b"mycnet-admission-challenge".to_vec();
+        challenge.extend_from_slice(candidate_node.as_bytes());
+        challenge.extend_from_slice(&epoch.to_le_bytes());
+        challenge
+    }
+
+    fn expected_proof(isolation_key: &[u8; 32], candidate_node: &Uuid, epoch: u64, public_key: &[u8; 32]) -> [u8; 32] {
+        let mut proof_data = Vec::new();
+        proof_data.extend_from_slice(candidate_node.as_bytes());
+        proof_data.extend_from_slice(&epoch.to_le_bytes());
+        proof_data.extend_from_slice(public_key);
+        *blake3::keyed_hash(isolation_key, &proof_data).as_bytes()
+    }
+
+    /// Check that the candidate's public key signed the admission challenge
+    fn verify_possession(&self, candidate_node: &Uuid) -> bool {
+        use ed25519_dalek::Verifier;
+
+        let Ok(public_key) = ed25519_dalek::VerifyingKey::from_bytes(&self.public_key) else {
+            return false;
+        };
+        let Ok(signature) = ed25519_dalek::Signature::from_slice(&self.possession_signature) else {
+            return false;
+        };
+        public_key
+            .verify(&Self::challenge(candidate_node, self.epoch), &signature)
+            .is_ok()
+    }
+
+    /// Encode for use as `admission_criteria`
+    pub fn encode(&self) -> Vec<u8> {
+        bincode::serialize(self).expect("Admission criteria serialization cannot fail")
+    }
+
+    /// Decode from `admission_criteria`
+    pub fn decode(bytes: &[u8]) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(bincode::deserialize(bytes)?)
+    }
+}
+
+impl NodeAdmissionValidator {
+    /// Create a validator for candidates of the network with the given isolation key
+    ///
+    /// Only proofs issued for `admission_epoch` and candidates of an allowed node type pass.
+    pub fn new(
+        isolation_key: [u8; 32],
+        admission_epoch: u64,
+        allowed_node_types: Vec<String>,
+        required_capabilities: Vec<String>,
+    ) -> Self {
+        Self {
+            isolation_key,
+            admission_epoch,
+            allowed_node_types,
+            required_capabilities,
+        }
+    }
+
+    /// Move to a new admission epoch, invalidating proofs issued for earlier ones
+    pub fn set_admission_epoch(&mut self, admission_epoch: u64) {
+        self.admission_epoch = admission_epoch;
+    }
+}
+
+impl OperationValidator for NodeAdmissionValidator {
+    fn name(&self) -> &str {
+        "node-admission"
+    }
+
+    fn applies_to(&self, kind: OperationKind) -> bool {
+        kind == OperationKind::NodeAdmission
+    }
+
+    fn validate(&self, operation: &ConsensusOperation, _context: ValidationContext<'_>) -> Result<(), String> {
+        let ConsensusOperation::NodeAdmission { candidate_node, admission_criteria } = operation else {
+            return Ok(());
+        };
+
+        let criteria = AdmissionCriteria::decode(admission_criteria)
+            .map_err(|e| format!("Malformed admission criteria: {}", e))?;
+
+        if criteria.epoch != self.admission_epoch {
+            return Err(format!("Membership proof is for epoch {}, not {}", criteria.epoch, self.admission_epoch));
+        }
+        let expected_proof = AdmissionCriteria::expected_proof(
+            &self.isolation_key,
+            candidate_node,
+            criteria.epoch,
+            &criteria.public_key,
+        );
+        if !constant_time_eq::constant_time_eq(&criteria.membership_proof, &expected_proof) {
+            return Err("Membership proof does not match network isolation key and candidate".to_string());
+        }
+        if !criteria.verify_possession(candidate_node) {
+            return Err("Candidate did not prove possession of its public key".to_string());
+        }
+
+        if !self.allowed_node_types.contains(&criteria.node_type) {
+            return Err(format!("Node type not allowed: {}", criteria.node_type));
+        }
+
+        if let Some(missing) = self.required_capabilities
+            .iter()
+            .find(|capability| !criteria.capabilities.contains(capability))
+        {
+            return Err(format!("Candidate lacks required capability: {}", missing));
+        }
+
+        Ok(())
+    }
+}
+
+impl OperationValidator for TrustModificationBoundsValidator {
+    fn name(&self) -> &str {
+        "trust-modification-bounds"
+    }
+
+    fn applies_to(&self, kind: OperationKind) -> bool {
+        kind == OperationKind::TrustScoreModification
+    }
+
+    fn validate(&self, operation: &ConsensusOperation, context: ValidationContext<'_>) -> Result<(), String> {
+        let ConsensusOperation::TrustScoreModification { target_node, score_change, justification, evidence } = operation else {
+            return Ok(());
+        };
+
+        if justification.trim().is_empty() {
+            return Err("Trust modification requires a justification".to_string());
+        }
+        if !score_change.is_finite() {
+            return Err("Score change is not a finite number".to_string());
+        }
+
+        // Evidence-backed penalties carry exactly the penalty defined for the misbehavior
+        if let Some(evidence) = evidence {
+            if evidence.offender() != *target_node {
+                return Err("Evidence does not concern the target node".to_string());
+            }
+            let (Some(offender_key), Some(reporter_key)) = (
+                context.participant_keys.get(&evidence.offender()),
+                context.participant_keys.get(&evidence.reporter),
+            ) else {
+                return Err("Evidence signer keys are unknown".to_string());
+            };
+            if !evidence.verify(offender_key, reporter_key) {
+                return Err("Evidence signatures do not verify".to_string());
+            }
+            if *score_change != evidence.kind.penalty() {
+                return Err("Score change does not match the evidence penalty".to_string());
+            }
+            return Ok(());
+        }
+
+        if *score_change > self.max_increase || *score_change < -self.max_decrease {
+            return Err(format!(
+                "Score change {:.2} outside bounds [-{:.2}, {:.2}]",
+                score_change, self.max_decrease, self.max_increase
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for TrustModificationBoundsValidator {
+    fn default() -> Self {
+        Self {
+            max_increase: 0.1,
+            max_decrease: 0.2,
+        }
+    }
+}
+
+impl ConfigurationSchemaValidator {
+    /// Create a validator with no registered schemas
+    pub fn new() -> Self {
+        Self {
+            schemas: HashMap::new(),
+        }
+    }
+
+    /// Register the schema for a configuration type
+    pub fn register_schema(&mut self, config_type: String, schema: ConfigSchema) {
+        self.schemas.insert(config_type, schema);
+    }
+
+    /// Encode configuration values for use as `proposed_change`
+    pub fn encode_change(values: &BTreeMap<String, String>) -> Vec<u8> {
+        bincode::serialize(values).expect("Configuration serialization cannot fail")
+    }
+}
+
+impl Default for ConfigurationSchemaValidator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OperationValidator for ConfigurationSchemaValidator {
+    fn name(&self) -> &str {
+        "configuration-schema"
+    }
+
+    fn applies_to(&self, kind: OperationKind) -> bool {
+        kind == OperationKind::NetworkConfiguration
+    }
+
+    fn validate(&self, operation: &ConsensusOperation, _context: ValidationContext<'_>) -> Result<(), String> {
+        let ConsensusOperation::NetworkConfiguration { config_type, proposed_change } = operation else {
+            return Ok(());
+        };
+
+        let schema = self.schemas
+            .get(config_type)
+            .ok_or_else(|| format!("Unknown configuration type: {}", config_type))?;
+
+        if schema.max_payload_bytes > 0 && proposed_change.len() > schema.max_payload_bytes {
+            return Err(format!("Configuration payload exceeds {} bytes", schema.max_payload_bytes));
+        }
+
+        let values: BTreeMap<String, String> = bincode::deserialize(proposed_change)
+            .map_err(|e| format!("Malformed configuration payload: {}", e))?;
+
+        if let Some(missing) = schema.required_keys.iter().find(|key| !values.contains_key(*key)) {
+            return Err(format!("Missing required key: {}", missing));
+        }
+        if !schema.allowed_keys.is_empty() {
+            if let Some(unknown) = values
+                .keys()
+                .find(|key| !schema.allowed_keys.contains(key) && !schema.required_keys.contains(key))
+            {
+                return Err(format!("Key not allowed by schema: {}", unknown));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    fn no_keys() -> HashMap<Uuid, ed25519_dalek::VerifyingKey> {
+        HashMap::new()
+    }
+
+    fn context(participant_keys: &HashMap<Uuid, ed25519_dalek::VerifyingKey>) -> ValidationContext<'_> {
+        ValidationContext { participant_keys }
+    }
+
+    #[test]
+    fn test_node_admission_validator() {
+        let isolation_key = [4u8; 32];
+        let candidate = Uuid::new_v4();
+        let mut validator = NodeAdmissionValidator::new(
+            isolation_key,
+            1,
+            vec!["Rhizomorph".to_string(), "Hyphae".to_string()],
+            vec!["storage".to_string()],
+        );
+
+        let candidate_keys = ed25519_dalek::SigningKey::generate(&mut rand::rngs::OsRng);
+        let criteria = |key: &[u8; 32], epoch: u64, node_type: &str, capabilities: &[&str]| {
+            AdmissionCriteria::new(
+                key,
+                candidate,
+                epoch,
+                &candidate_keys,
+                node_type.to_string(),
+                capabilities.iter().map(|c| c.to_string()).collect(),
+            )
+        };
+        let admission = |candidate_node: Uuid, criteria: &AdmissionCriteria| ConsensusOperation::NodeAdmission {
+            candidate_node,
+            admission_criteria: criteria.encode(),
+        };
+
+        let keys = no_keys();
+        let valid = criteria(&isolation_key, 1, "Hyphae", &["storage"]);
+        assert!(validator.validate(&admission(candidate, &valid), context(&keys)).is_ok());
+        assert!(validator.validate(&admission(candidate, &criteria(&[5u8; 32], 1, "Hyphae", &["storage"])), context(&keys)).is_err());
+        assert!(validator.validate(&admission(candidate, &criteria(&isolation_key, 1, "Hyphae", &[])), context(&keys)).is_err());
+        assert!(validator.validate(&admission(candidate, &criteria(&isolation_key, 1, "Sclerotia", &["storage"])), context(&keys)).is_err());
+
+        // The proof cannot be replayed for another candidate or in a later epoch
+        assert!(validator.validate(&admission(Uuid::new_v4(), &valid), context(&keys)).is_err());
+        validator.set_admission_epoch(2);
+        assert!(validator.validate(&admission(candidate, &valid), context(&keys)).is_err());
+
+        // A proof for someone else's public key fails without a signature from that key
+        validator.set_admission_epoch(1);
+        let mut borrowed = valid.clone();
+        borrowed.possession_signature = criteria(&isolation_key, 2, "Hyphae", &["storage"]).possession_signature;
+        assert!(validator.validate(&admission(candidate, &borrowed), context(&keys)).is_err());
+    }
+
+    #[test]
+    fn test_trust_modification_bounds() {
+        let mut registry = ValidatorRegistry::new();
+        registry.register(Box::new(TrustModificationBoundsValidator::default()));
+
+        let modification = |score_change: f32| ConsensusOperation::TrustScoreModification {
+            target_node: Uuid::new_v4(),
+            score_change,
+            justification: "manual review".to_string(),
+            evidence: None,
+        };
+
+        let keys = no_keys();
+        assert_eq!(registry.vote(&modification(0.05), context(&keys)), Vote::Approve);
+        assert_eq!(registry.vote(&modification(-0.9), context(&keys)), Vote::Reject);
+    }
+
+    #[test]
+    fn test_evidence_signatures_are_verified() {
+        use crate::evidence::{MisbehaviorEvidence, MisbehaviorKind, SignedCommitment};
+
+        let (offender, reporter) = (Uuid::new_v4(), Uuid::new_v4());
+        let offender_keys = ed25519_dalek::SigningKey::generate(&mut rand::rngs::OsRng);
+        let reporter_keys = ed25519_dalek::SigningKey::generate(&mut rand::rngs::OsRng);
+        let operation_id = Uuid::new_v4();
+        let kind = MisbehaviorKind::ConflictingCommitments {
+            first: SignedCommitment::new(operation_id, offender, [1u8; 32], &offender_keys),
+            second: SignedCommitment::new(operation_id, offender, [2u8; 32], &offender_keys),
+        };
+        let operation = MisbehaviorEvidence::new(kind, reporter, &reporter_keys).into_operation();
+
+        let validator = TrustModificationBoundsValidator::default();
+        let mut keys = HashMap::from([(offender, offender_keys.verifying_key())]);
+        assert!(validator.validate(&operation, context(&keys)).is_err());
+
+        // A forged reporter key fails verification
+        let forged = ed25519_dalek::SigningKey::generate(&mut rand::rngs::OsRng);
+        keys.insert(reporter, forged.verifying_key());
+        assert!(validator.validate(&operation, context(&keys)).is_err());
+
+        keys.insert(reporter, reporter_keys.verifying_key());
+        assert!(validator.validate(&operation, context(&keys)).is_ok());
+    }
+
+    #[test]
+    fn test_configuration_schema_validator() {
+        let mut validator = ConfigurationSchemaValidator::new();
+        validator.register_schema(
+            "gossip".to_string(),
+            ConfigSchema {
+                required_keys: vec!["fanout".to_string()],
+                allowed_keys: vec!["interval_ms".to_string()],
+                max_payload_bytes: 1024,
+            },
+        );
+
+        let keys = no_keys();
+        let change = |pairs: &[(&str, &str)]| ConsensusOperation::NetworkConfiguration {
+            config_type: "gossip".to_string(),
+            proposed_change: ConfigurationSchemaValidator::encode_change(
+                &pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+            ),
+        };
+
+        assert!(validator.validate(&change(&[("fanout", "3"), ("interval_ms", "500")]), context(&keys)).is_ok());
+        assert!(validator.validate(&change(&[("interval_ms", "500")]), context(&keys)).is_err());
+        assert!(validator.validate(&change(&[("fanout", "3"), ("debug", "true")]), context(&keys)).is_err());
+    }
+}
@@ -16,11 +16,11 @@ pub struct NetworkIdentity {
     pub isolation_key: [u8; 32],
 }
 
-/// Node identity with cryptographic keypair
+/// Node identity with cryptographic signing key
 #[derive(Debug, Clone)]
 pub struct NodeIdentity {
     pub node_id: Uuid,
-    pub keypair: ed25519_dalek::Keypair,
+    pub signing_key: ed25519_dalek::SigningKey,
     pub node_type: NodeType,
 }
 
@@ -37,6 +37,16 @@ pub enum NodeType {
     Hyphae,
 }
 
+/// Position of a node in the network hierarchy, without the runtime details of `NodeType`
+///
+/// Ordered from the most to the least capable tier.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum NodeTier {
+    Sclerotia,
+    Rhizomorph,
+    Hyphae,
+}
+
 /// Bootstrap agent responsible for network initialization
 pub struct BootstrapAgent {
     network_identity: NetworkIdentity,
@@ -47,7 +57,6 @@ pub struct BootstrapAgent {
 /// Basic spore client for read-only operations during bootstrap
 pub struct BasicSporeClient {
     spore_endpoints: Vec<String>,
-    network_identity: NetworkIdentity,
 }
 
 /// Basic networking for initial connectivity
@@ -59,10 +68,10 @@ pub struct BasicNetworking {
 /// Peer connection information
 #[derive(Debug, Clone)]
 pub struct PeerConnection {
-    peer_id: Uuid,
-    address: std::net::SocketAddr,
-    last_seen: chrono::DateTime<chrono::Utc>,
-    trust_score: f32,
+    pub peer_id: Uuid,
+    pub address: std::net::SocketAddr,
+    pub last_seen: chrono::DateTime<chrono::Utc>,
+    pub trust_score: f32,
 }
 
 impl NetworkIdentity {
@@ -87,28 +96,52 @@ impl NetworkIdentity {
     }
 }
 
+impl NodeType {
+    /// Hierarchy tier this node type belongs to
+    pub fn tier(&self) -> NodeTier {
+        match self {
+            NodeType::DedicatedSclerotia | NodeType::DynamicSclerotia { .. } => NodeTier::Sclerotia,
+            NodeType::Rhizomorph { .. } => NodeTier::Rhizomorph,
+            NodeType::Hyphae => NodeTier::Hyphae,
+        }
+    }
+}
+
+impl std::str::FromStr for NodeTier {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "Sclerotia" => Ok(NodeTier::Sclerotia),
+            "Rhizomorph" => Ok(NodeTier::Rhizomorph),
+            "Hyphae" => Ok(NodeTier::Hyphae),
+            other => Err(format!("Unknown node tier: {}", other)),
+        }
+    }
+}
+
 impl NodeIdentity {
     /// Create a new node identity
     pub fn new(node_type: NodeType) -> Self {
         let mut csprng = rand::rngs::OsRng;
-        let keypair = ed25519_dalek::Keypair::generate(&mut csprng);
+        let signing_key = ed25519_dalek::SigningKey::generate(&mut csprng);
         
         Self {
             node_id: Uuid::new_v4(),
-            keypair,
+            signing_key,
             node_type,
         }
     }
     
     /// Get the public key for this node
-    pub fn public_key(&self) -> ed25519_dalek::PublicKey {
-        self.keypair.public
+    pub fn public_key(&self) -> ed25519_dalek::VerifyingKey {
+        self.signing_key.verifying_key()
     }
     
     /// Sign a message with this node's private key
     pub fn sign_message(&self, message: &[u8]) -> ed25519_dalek::Signature {
         use ed25519_dalek::Signer;
-        self.keypair.sign(message)
+        self.signing_key.sign(message)
     }
 }
 
@@ -117,7 +150,6 @@ impl BootstrapAgent {
     pub fn new(network_identity: NetworkIdentity, node_identity: NodeIdentity) -> Self {
         let spore_client = BasicSporeClient {
             spore_endpoints: vec![], // Will be populated from network discovery
-            network_identity: network_identity.clone(),
         };
         
         Self {
@@ -127,6 +159,11 @@ impl BootstrapAgent {
         }
     }
     
+    /// Spore endpoints discovered so far
+    pub fn spore_endpoints(&self) -> &[String] {
+        &self.spore_client.spore_endpoints
+    }
+    
     /// Initialize the node and join the network
     pub async fn initialize_and_join(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         tracing::info!("Starting bootstrap process for node {}", self.node_identity.node_id);
@@ -172,6 +209,31 @@ impl BootstrapAgent {
     }
 }
 
+impl BasicNetworking {
+    /// Create networking for a node listening on the given addresses
+    pub fn new(local_addresses: Vec<std::net::SocketAddr>) -> Self {
+        Self {
+            local_addresses,
+            peer_connections: HashMap::new(),
+        }
+    }
+    
+    /// Addresses this node listens on
+    pub fn local_addresses(&self) -> &[std::net::SocketAddr] {
+        &self.local_addresses
+    }
+    
+    /// Record a peer, replacing what was known about it
+    pub fn record_peer(&mut self, connection: PeerConnection) {
+        self.peer_connections.insert(connection.peer_id, connection);
+    }
+    
+    /// Last known connection information for a peer
+    pub fn peer(&self, peer_id: &Uuid) -> Option<&PeerConnection> {
+        self.peer_connections.get(peer_id)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -189,6 +251,15 @@ mod tests {
         assert_ne!(node.node_id, Uuid::nil());
     }
     
+    #[test]
+    fn test_node_type_tiers() {
+        assert_eq!(NodeType::DynamicSclerotia { current_load: 0.4 }.tier(), NodeTier::Sclerotia);
+        assert_eq!(NodeType::Rhizomorph { promotion_eligible: false }.tier(), NodeTier::Rhizomorph);
+        assert_eq!("Hyphae".parse::<NodeTier>().unwrap(), NodeType::Hyphae.tier());
+        assert!("Spore".parse::<NodeTier>().is_err());
+        assert!(NodeTier::Sclerotia < NodeTier::Hyphae);
+    }
+    
     #[test]
     fn test_signature_verification() {
         let node = NodeIdentity::new(NodeType::Rhizomorph { promotion_eligible: true });
@@ -0,0 +1,78 @@
+//! Typed errors for network transport
+
+use crate::wire::VersionRange;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Errors returned by connection establishment and request routing
+#[derive(Debug, thiserror::Error)]
+pub enum NetworkError {
+    #[error("No address is known for node {0}")]
+    UnknownNode(Uuid),
+
+    #[error("Node {node_id} is unreachable: {reason}")]
+    Unreachable { node_id: Uuid, reason: String },
+
+    #[error("Node {node_id} did not answer within {timeout:?}")]
+    Timeout { node_id: Uuid, timeout: Duration },
+
+    #[error("No transport configured")]
+    NoTransport,
+
+    #[error("No healthy connections available")]
+    NoConnections,
+
+    #[error("Message of {size} bytes exceeds the {limit} byte limit")]
+    MessageTooLarge { size: usize, limit: usize },
+
+    #[error("Transport is shut down")]
+    Shutdown,
+
+    #[error("Protocol version {0} is not supported")]
+    UnsupportedVersion(u16),
+
+    #[error("No common protocol version: local {local}, remote {remote}")]
+    VersionMismatch { local: VersionRange, remote: VersionRange },
+
+    #[error("Malformed frame: {0}")]
+    MalformedFrame(String),
+
+    #[error("Missing or invalid signature from node {sender}")]
+    InvalidSignature { sender: Uuid },
+
+    #[error("Node {actual} sent a message claiming to be from {claimed}")]
+    SenderMismatch { claimed: Uuid, actual: Uuid },
+
+    #[error("Payload error: {0}")]
+    Payload(String),
+
+    #[error("Remote error: {0}")]
+    Remote(String),
+
+    #[error("Invalid transport configuration: {0}")]
+    InvalidConfig(String),
+
+    #[error("Connection error: {0}")]
+    Connection(#[from] quinn::ConnectionError),
+
+    #[error("Datagram error: {0}")]
+    Datagram(#[from] quinn::SendDatagramError),
+
+    #[error("Stream write error: {0}")]
+    Write(#[from] quinn::WriteError),
+
+    #[error("Stream read error: {0}")]
+    Read(#[from] quinn::ReadToEndError),
+
+    #[error("TLS error: {0}")]
+    Tls(#[from] rustls::Error),
+
+    #[error("Certificate generation failed: {0}")]
+    Certificate(#[from] rcgen::RcgenError),
+
+    #[error("Encoding error: {0}")]
+    Encoding(#[from] bincode::Error),
+
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+}
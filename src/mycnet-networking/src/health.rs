@@ -0,0 +1,397 @@
+//! Active health probing of connections to other nodes
+//!
+//! The monitor probes every watched node on a fixed interval with datagrams
+//! the remote transport echoes. Round-trip times feed a moving average and a
+//! jitter estimate, unanswered probes count towards packet loss, and the
+//! congestion window gives a throughput estimate. A node is only marked
+//! unhealthy after several probes in a row fail, and only healthy again after
+//! several succeed, so a single lost datagram does not flap it. Each change
+//! is broadcast to subscribers.
+
+use crate::profile::LinkConditions;
+use crate::quic::{ProbeSample, QuicTransport};
+use crate::NetworkError;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+/// Weight of each new sample in the jitter estimate, as in RFC 3550
+const JITTER_GAIN: f64 = 1.0 / 16.0;
+
+/// Health events kept for subscribers that fall behind
+const EVENT_CAPACITY: usize = 64;
+
+#[derive(Debug, Clone)]
+pub struct HealthConfig {
+    pub probe_interval: Duration,
+    /// Probes unanswered for this long count as lost
+    pub probe_timeout: Duration,
+    /// Consecutive lost probes before a node is marked unhealthy
+    pub failure_threshold: u32,
+    /// Consecutive answered probes before an unhealthy node is healthy again
+    pub recovery_threshold: u32,
+    /// Weight of each new sample in the round-trip time average
+    pub rtt_smoothing: f64,
+    /// Number of recent probes packet loss is measured over
+    pub loss_window: usize,
+}
+
+/// Measured health of the connection to one node
+#[derive(Debug, Clone)]
+pub struct ConnectionHealth {
+    pub is_healthy: bool,
+    /// Smoothed round-trip time
+    pub latency_ms: u32,
+    pub jitter_ms: u32,
+    pub packet_loss_percent: f32,
+    /// 0 until a probe has been answered
+    pub throughput_mbps: u32,
+    pub last_check: chrono::DateTime<chrono::Utc>,
+}
+
+/// Change in a node's health
+#[derive(Debug, Clone)]
+pub enum HealthEvent {
+    Unhealthy { node_id: Uuid, health: ConnectionHealth },
+    Recovered { node_id: Uuid, health: ConnectionHealth },
+}
+
+/// Connection health monitoring
+pub struct ConnectionHealthMonitor {
+    config: HealthConfig,
+    transport: Option<Arc<QuicTransport>>,
+    health_checks: Mutex<HashMap<Uuid, HealthTracker>>,
+    watched: Mutex<HashSet<Uuid>>,
+    events: broadcast::Sender<HealthEvent>,
+    probing: AtomicBool,
+    /// Cancels the probing loop that is currently running
+    stop: Mutex<CancellationToken>,
+}
+
+/// Running estimates for one node
+struct HealthTracker {
+    health: ConnectionHealth,
+    smoothed_rtt_ms: Option<f64>,
+    last_rtt_ms: f64,
+    jitter_ms: f64,
+    /// Whether each recent probe was answered, oldest first
+    outcomes: VecDeque<bool>,
+    consecutive_failures: u32,
+    consecutive_successes: u32,
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        Self {
+            probe_interval: Duration::from_secs(5),
+            probe_timeout: Duration::from_secs(1),
+            failure_threshold: 3,
+            recovery_threshold: 3,
+            rtt_smoothing: 0.125,
+            loss_window: 20,
+        }
+    }
+}
+
+impl HealthEvent {
+    pub fn node_id(&self) -> Uuid {
+        match self {
+            HealthEvent::Unhealthy { node_id, .. } | HealthEvent::Recovered { node_id, .. } => *node_id,
+        }
+    }
+}
+
+impl HealthTracker {
+    /// Nodes start out healthy so one lost probe does not mark a new node down
+    fn new() -> Self {
+        Self {
+            health: ConnectionHealth {
+                is_healthy: true,
+                latency_ms: 0,
+                jitter_ms: 0,
+                packet_loss_percent: 0.0,
+                throughput_mbps: 0,
+                last_check: chrono::Utc::now(),
+            },
+            smoothed_rtt_ms: None,
+            last_rtt_ms: 0.0,
+            jitter_ms: 0.0,
+            outcomes: VecDeque::new(),
+            consecutive_failures: 0,
+            consecutive_successes: 0,
+        }
+    }
+
+    /// Fold in one probe result; returns true if the node's health flipped
+    fn record(&mut self, sample: Option<&ProbeSample>, config: &HealthConfig) -> bool {
+        self.outcomes.push_back(sample.is_some());
+        while self.outcomes.len() > config.loss_window.max(1) {
+            self.outcomes.pop_front();
+        }
+        let lost = self.outcomes.iter().filter(|answered| !**answered).count();
+        self.health.packet_loss_percent = lost as f32 * 100.0 / self.outcomes.len() as f32;
+        self.health.last_check = chrono::Utc::now();
+
+        let was_healthy = self.health.is_healthy;
+        match sample {
+            Some(sample) => {
+                let rtt_ms = sample.rtt.as_secs_f64() * 1000.0;
+                let smoothed = match self.smoothed_rtt_ms {
+                    Some(smoothed) => {
+                        self.jitter_ms += ((rtt_ms - self.last_rtt_ms).abs() - self.jitter_ms) * JITTER_GAIN;
+                        smoothed + (rtt_ms - smoothed) * config.rtt_smoothing
+                    },
+                    None => rtt_ms,
+                };
+                self.smoothed_rtt_ms = Some(smoothed);
+                self.last_rtt_ms = rtt_ms;
+                self.health.latency_ms = smoothed.round() as u32;
+                self.health.jitter_ms = self.jitter_ms.round() as u32;
+                self.health.throughput_mbps = sample.throughput_mbps;
+
+                self.consecutive_failures = 0;
+                self.consecutive_successes += 1;
+                if self.consecutive_successes >= config.recovery_threshold {
+                    self.health.is_healthy = true;
+                }
+            },
+            None => {
+                self.consecutive_successes = 0;
+                self.consecutive_failures += 1;
+                if self.consecutive_failures >= config.failure_threshold {
+                    self.health.is_healthy = false;
+                }
+            },
+        }
+        self.health.is_healthy != was_healthy
+    }
+}
+
+impl ConnectionHealthMonitor {
+    pub fn new() -> Self {
+        Self::build(None, HealthConfig::default())
+    }
+
+    /// Create a monitor that probes nodes over `transport`
+    pub fn with_transport(transport: Arc<QuicTransport>, config: HealthConfig) -> Self {
+        Self::build(Some(transport), config)
+    }
+
+    fn build(transport: Option<Arc<QuicTransport>>, config: HealthConfig) -> Self {
+        Self {
+            config,
+            transport,
+            health_checks: Mutex::new(HashMap::new()),
+            watched: Mutex::new(HashSet::new()),
+            events: broadcast::channel(EVENT_CAPACITY).0,
+            probing: AtomicBool::new(false),
+            stop: Mutex::new(CancellationToken::new()),
+        }
+    }
+
+    /// Include a node in periodic probing
+    pub fn watch(&self, node_id: Uuid) {
+        self.watched.lock().unwrap().insert(node_id);
+    }
+
+    /// Stop probing a node and forget what was measured
+    pub fn unwatch(&self, node_id: &Uuid) {
+        self.watched.lock().unwrap().remove(node_id);
+        self.health_checks.lock().unwrap().remove(node_id);
+    }
+
+    /// Receive an event each time a node becomes unhealthy or recovers
+    pub fn subscribe(&self) -> broadcast::Receiver<HealthEvent> {
+        self.events.subscribe()
+    }
+
+    /// Probe watched nodes every `probe_interval` until `stop` is called
+    ///
+    /// Calling it again while probing is running has no effect; after
+    /// `stop`, it starts probing again.
+    pub fn start(self: &Arc<Self>) {
+        let mut current = self.stop.lock().unwrap();
+        if self.probing.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let stop = CancellationToken::new();
+        *current = stop.clone();
+        drop(current);
+        let monitor = Arc::downgrade(self);
+        let mut interval = tokio::time::interval(self.config.probe_interval);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    _ = stop.cancelled() => break,
+                    _ = interval.tick() => {},
+                }
+                // The monitor stops probing once nothing else holds it
+                let Some(monitor) = monitor.upgrade() else { break };
+                let nodes: Vec<Uuid> = monitor.watched.lock().unwrap().iter().copied().collect();
+                let mut probes = JoinSet::new();
+                for node_id in nodes {
+                    let monitor = monitor.clone();
+                    probes.spawn(async move { monitor.check_connection_health(node_id).await });
+                }
+                while probes.join_next().await.is_some() {}
+            }
+        });
+    }
+
+    pub fn stop(&self) {
+        let current = self.stop.lock().unwrap();
+        current.cancel();
+        self.probing.store(false, Ordering::SeqCst);
+    }
+
+    /// Probe a node now and record the result
+    pub async fn check_connection_health(&self, node_id: Uuid) -> ConnectionHealth {
+        let result = match &self.transport {
+            Some(transport) => transport.probe(node_id, self.config.probe_timeout).await,
+            None => Err(NetworkError::NoTransport),
+        };
+        if let Err(e) = &result {
+            tracing::debug!("Health probe to node {} failed: {}", node_id, e);
+        }
+
+        let mut health_checks = self.health_checks.lock().unwrap();
+        let tracker = health_checks.entry(node_id).or_insert_with(HealthTracker::new);
+        let changed = tracker.record(result.as_ref().ok(), &self.config);
+        let health = tracker.health.clone();
+        drop(health_checks);
+
+        if changed {
+            let event = if health.is_healthy {
+                tracing::info!("Node {} recovered ({} ms round trip)", node_id, health.latency_ms);
+                HealthEvent::Recovered {
+                    node_id,
+                    health: health.clone(),
+                }
+            } else {
+                tracing::warn!("Node {} is unhealthy ({:.0}% probe loss)", node_id, health.packet_loss_percent);
+                HealthEvent::Unhealthy {
+                    node_id,
+                    health: health.clone(),
+                }
+            };
+            // No subscribers is fine
+            let _ = self.events.send(event);
+        }
+        health
+    }
+
+    pub fn is_healthy(&self, node_id: &Uuid) -> bool {
+        self.health_checks
+            .lock()
+            .unwrap()
+            .get(node_id)
+            .map(|tracker| tracker.health.is_healthy)
+            .unwrap_or(false)
+    }
+
+    /// Latest measurements for a node, if it has been probed
+    pub fn health(&self, node_id: &Uuid) -> Option<ConnectionHealth> {
+        self.health_checks.lock().unwrap().get(node_id).map(|tracker| tracker.health.clone())
+    }
+
+    /// Measured link to a node, for `RpcClient::set_link_conditions`
+    pub fn link_conditions(&self, node_id: &Uuid) -> Option<LinkConditions> {
+        let health_checks = self.health_checks.lock().unwrap();
+        let tracker = health_checks.get(node_id)?;
+        tracker.smoothed_rtt_ms?;
+        Some(LinkConditions {
+            // Never report a measured link as unmeasured
+            latency_ms: tracker.health.latency_ms.max(1),
+            bandwidth_mbps: tracker.health.throughput_mbps,
+        })
+    }
+}
+
+impl Default for ConnectionHealthMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for ConnectionHealthMonitor {
+    fn drop(&mut self) {
+        if let Ok(stop) = self.stop.get_mut() {
+            stop.cancel();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn answered(rtt_ms: u64) -> ProbeSample {
+        ProbeSample {
+            rtt: Duration::from_millis(rtt_ms),
+            throughput_mbps: 100,
+        }
+    }
+
+    #[test]
+    fn test_rtt_average_and_jitter() {
+        let config = HealthConfig::default();
+        let mut tracker = HealthTracker::new();
+        tracker.record(Some(&answered(40)), &config);
+        assert_eq!(tracker.health.latency_ms, 40);
+        assert_eq!(tracker.health.jitter_ms, 0);
+
+        // One slow sample moves the average by an eighth of the difference
+        tracker.record(Some(&answered(120)), &config);
+        assert_eq!(tracker.health.latency_ms, 50);
+        assert_eq!(tracker.health.jitter_ms, 5);
+        assert_eq!(tracker.health.throughput_mbps, 100);
+    }
+
+    #[test]
+    fn test_failures_and_recovery_use_hysteresis() {
+        let config = HealthConfig {
+            failure_threshold: 2,
+            recovery_threshold: 3,
+            loss_window: 4,
+            ..HealthConfig::default()
+        };
+        let mut tracker = HealthTracker::new();
+
+        assert!(!tracker.record(None, &config));
+        assert!(tracker.health.is_healthy);
+        assert!(tracker.record(None, &config));
+        assert!(!tracker.health.is_healthy);
+        assert_eq!(tracker.health.packet_loss_percent, 100.0);
+
+        assert!(!tracker.record(Some(&answered(10)), &config));
+        assert!(!tracker.record(Some(&answered(10)), &config));
+        assert!(!tracker.health.is_healthy);
+        assert!(tracker.record(Some(&answered(10)), &config));
+        assert!(tracker.health.is_healthy);
+        // Only the last four probes count towards loss
+        assert_eq!(tracker.health.packet_loss_percent, 25.0);
+    }
+
+    #[tokio::test]
+    async fn test_probe_without_transport_counts_as_lost() {
+        let config = HealthConfig {
+            failure_threshold: 1,
+            ..HealthConfig::default()
+        };
+        let monitor = ConnectionHealthMonitor::build(None, config);
+        let mut events = monitor.subscribe();
+        let node_id = Uuid::new_v4();
+
+        let health = monitor.check_connection_health(node_id).await;
+        assert!(!health.is_healthy);
+        assert!(!monitor.is_healthy(&node_id));
+        assert!(monitor.link_conditions(&node_id).is_none());
+        assert!(matches!(events.try_recv(), Ok(HealthEvent::Unhealthy { node_id: id, .. }) if id == node_id));
+    }
+}
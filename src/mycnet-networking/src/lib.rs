@@ -1,12 +1,32 @@
 //! Mycnet Networking - Multi-homing and adaptive protocols
 
+pub mod error;
+pub mod health;
+pub mod profile;
+pub mod quic;
+pub mod rpc;
+pub mod wire;
+
+pub use error::NetworkError;
+pub use health::{ConnectionHealth, ConnectionHealthMonitor, HealthConfig, HealthEvent};
+pub use profile::{LinkConditions, NodeCapabilities, NodeTier, ProtocolSettings};
+pub use quic::{
+    AddressResolver, NodeAddress, NodeIdentity, ProbeSample, QuicConfig, QuicTransport, RequestHandler, StaticResolver,
+    StreamHandler,
+};
+pub use rpc::{ResponseSink, ResponseStream, RpcClient, RpcConfig, RpcServer, RpcService};
+pub use wire::{Envelope, MessageType, VersionRange, PROTOCOL_VERSION};
+
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast::error::RecvError;
 use uuid::Uuid;
 
 /// Network communication protocols for different node types
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum CommunicationProtocol {
     /// High-performance protocol for Sclerotia-to-Sclerotia
     HighPerformance {
@@ -30,34 +50,34 @@ pub enum CommunicationProtocol {
     },
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum EncryptionLevel {
     High,
     Standard,
     Minimal,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum CompressionLevel {
     High,
     Medium,
     Low,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum BaseProtocol {
     Standard,
     Minimal,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum AdaptationStrategy {
     CapabilityBased,
     LatencyBased,
     BandwidthBased,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum ReliabilityLevel {
     High,
     BestEffort,
@@ -66,8 +86,12 @@ pub enum ReliabilityLevel {
 /// Multi-homing connection manager
 pub struct MultiHomingManager {
     active_connections: HashMap<Uuid, NodeConnection>,
-    health_monitor: ConnectionHealthMonitor,
+    health_monitor: Arc<ConnectionHealthMonitor>,
     selection_strategy: ConnectionSelectionStrategy,
+    transport: Option<Arc<QuicTransport>>,
+    /// Negotiates the profile recorded for each connection
+    rpc: Option<Arc<RpcClient>>,
+    next_connection: AtomicUsize,
 }
 
 /// Connection to a network node
@@ -81,24 +105,12 @@ pub struct NodeConnection {
     pub bandwidth_mbps: u32,
 }
 
-/// Connection health monitoring
-pub struct ConnectionHealthMonitor {
-    health_checks: HashMap<Uuid, ConnectionHealth>,
-}
-
-#[derive(Debug, Clone)]
-pub struct ConnectionHealth {
-    pub is_healthy: bool,
-    pub latency_ms: u32,
-    pub packet_loss_percent: f32,
-    pub last_check: chrono::DateTime<chrono::Utc>,
-}
-
 /// Connection selection strategies for multi-homing
 #[derive(Debug, Clone)]
 pub enum ConnectionSelectionStrategy {
     RoundRobin,
     LatencyBased,
+    /// Fewest requests awaiting a response, then lowest latency
     LoadBased,
     OperationSpecific,
 }
@@ -109,6 +121,10 @@ pub struct VirtualEndpoint {
     pub physical_connections: Vec<NodeConnection>,
     pub load_balancing: LoadBalancingStrategy,
     pub failover_config: FailoverConfiguration,
+    transport: Arc<QuicTransport>,
+    next_connection: AtomicUsize,
+    /// Nodes the health monitor has reported down, tried only as a last resort
+    unhealthy: Arc<Mutex<HashSet<Uuid>>>,
 }
 
 #[derive(Debug, Clone)]
@@ -131,13 +147,44 @@ impl MultiHomingManager {
     pub fn new(strategy: ConnectionSelectionStrategy) -> Self {
         Self {
             active_connections: HashMap::new(),
-            health_monitor: ConnectionHealthMonitor::new(),
+            health_monitor: Arc::new(ConnectionHealthMonitor::new()),
             selection_strategy: strategy,
+            transport: None,
+            rpc: None,
+            next_connection: AtomicUsize::new(0),
+        }
+    }
+
+    /// Create a manager that connects to nodes over `transport`
+    pub fn with_transport(strategy: ConnectionSelectionStrategy, transport: Arc<QuicTransport>) -> Self {
+        Self {
+            health_monitor: Arc::new(ConnectionHealthMonitor::with_transport(transport.clone(), HealthConfig::default())),
+            transport: Some(transport),
+            ..Self::new(strategy)
         }
     }
+
+    /// Probe connected nodes with `config` instead of the defaults
+    pub fn with_health_config(mut self, config: HealthConfig) -> Self {
+        if let Some(transport) = &self.transport {
+            self.health_monitor = Arc::new(ConnectionHealthMonitor::with_transport(transport.clone(), config));
+        }
+        self
+    }
+
+    /// Negotiate each connection's protocol profile through `client`
+    pub fn with_rpc_client(mut self, client: Arc<RpcClient>) -> Self {
+        self.rpc = Some(client);
+        self
+    }
+
+    /// Monitor probing every connected node; subscribe to it for health changes
+    pub fn health_monitor(&self) -> &Arc<ConnectionHealthMonitor> {
+        &self.health_monitor
+    }
     
     /// Establish connection to multiple nodes for multi-homing
-    pub async fn establish_multi_homed_connections(&mut self, target_nodes: Vec<Uuid>) -> Result<VirtualEndpoint, Box<dyn std::error::Error>> {
+    pub async fn establish_multi_homed_connections(&mut self, target_nodes: Vec<Uuid>) -> Result<VirtualEndpoint, NetworkError> {
         tracing::info!("Establishing multi-homed connections to {} nodes", target_nodes.len());
         
         let mut connections = Vec::new();
@@ -154,8 +201,13 @@ impl MultiHomingManager {
         }
         
         if connections.is_empty() {
-            return Err("No connections established".into());
+            return Err(NetworkError::NoConnections);
+        }
+        let transport = self.transport.clone().ok_or(NetworkError::NoTransport)?;
+        for connection in &connections {
+            self.health_monitor.watch(connection.node_id);
         }
+        self.health_monitor.start();
         
         let virtual_endpoint = VirtualEndpoint {
             endpoint_id: Uuid::new_v4(),
@@ -166,28 +218,32 @@ impl MultiHomingManager {
                 retry_delay_ms: 1000,
                 health_check_interval_ms: 5000,
             },
+            transport,
+            next_connection: AtomicUsize::new(0),
+            unhealthy: Arc::new(Mutex::new(HashSet::new())),
         };
+        virtual_endpoint.follow_health(&self.health_monitor);
         
         Ok(virtual_endpoint)
     }
     
-    async fn establish_connection(&mut self, node_id: Uuid) -> Result<NodeConnection, Box<dyn std::error::Error>> {
-        // In real implementation, this would:
-        // 1. Resolve node addresses
-        // 2. Establish QUIC connection
-        // 3. Perform authentication
-        // 4. Negotiate protocol parameters
+    async fn establish_connection(&mut self, node_id: Uuid) -> Result<NodeConnection, NetworkError> {
+        let transport = self.transport.as_ref().ok_or(NetworkError::NoTransport)?;
+        let quic_connection = transport.connect(node_id).await?;
+        // Without an RPC client nothing is negotiated and the node gets the baseline
+        let settings = match &self.rpc {
+            Some(rpc) => rpc.protocol(node_id).await?,
+            None => ProtocolSettings::baseline(),
+        };
         
         let connection = NodeConnection {
             node_id,
-            addresses: vec![SocketAddr::from(([127, 0, 0, 1], 8080))], // Placeholder
-            protocol: CommunicationProtocol::Standard {
-                negotiation_enabled: true,
-                encryption_level: EncryptionLevel::Standard,
-            },
+            addresses: vec![quic_connection.remote_address()],
+            protocol: settings.protocol,
             last_activity: chrono::Utc::now(),
-            latency_ms: 50,
-            bandwidth_mbps: 100,
+            latency_ms: quic_connection.rtt().as_millis() as u32,
+            // Not measured until traffic has flowed
+            bandwidth_mbps: 0,
         };
         
         self.active_connections.insert(node_id, connection.clone());
@@ -195,68 +251,138 @@ impl MultiHomingManager {
     }
     
     /// Select best connection for an operation
+    ///
+    /// Latency comes from the health monitor's latest probes once it has
+    /// measured a node, and from the connection otherwise.
     pub fn select_connection(&self, _operation_type: &str) -> Option<&NodeConnection> {
+        let mut connections: Vec<&NodeConnection> = self.active_connections.values().collect();
+        connections.sort_by_key(|conn| conn.node_id);
         match self.selection_strategy {
-            ConnectionSelectionStrategy::LatencyBased => {
-                self.active_connections
-                    .values()
-                    .min_by_key(|conn| conn.latency_ms)
+            ConnectionSelectionStrategy::RoundRobin if !connections.is_empty() => {
+                let next = self.next_connection.fetch_add(1, Ordering::Relaxed) % connections.len();
+                Some(connections[next])
             },
+            ConnectionSelectionStrategy::RoundRobin => None,
+            ConnectionSelectionStrategy::LatencyBased => self.lowest_latency(connections),
             ConnectionSelectionStrategy::LoadBased => {
-                // Select connection with lowest load (simplified)
-                self.active_connections.values().next()
+                let in_flight = |conn: &NodeConnection| self.transport.as_ref().map_or(0, |transport| transport.in_flight(&conn.node_id));
+                let least = connections.iter().map(|conn| in_flight(conn)).min()?;
+                self.lowest_latency(connections.into_iter().filter(|conn| in_flight(conn) == least))
             },
-            _ => self.active_connections.values().next(),
+            ConnectionSelectionStrategy::OperationSpecific => connections.first().copied(),
         }
     }
-}
 
-impl ConnectionHealthMonitor {
-    pub fn new() -> Self {
-        Self {
-            health_checks: HashMap::new(),
-        }
-    }
-    
-    pub async fn check_connection_health(&mut self, node_id: Uuid) -> ConnectionHealth {
-        // In real implementation, this would perform actual health checks
-        let health = ConnectionHealth {
-            is_healthy: true,
-            latency_ms: 50,
-            packet_loss_percent: 0.1,
-            last_check: chrono::Utc::now(),
-        };
-        
-        self.health_checks.insert(node_id, health.clone());
-        health
-    }
-    
-    pub fn is_healthy(&self, node_id: &Uuid) -> bool {
-        self.health_checks
-            .get(node_id)
-            .map(|health| health.is_healthy)
-            .unwrap_or(false)
+    /// Connection with the lowest latency the health monitor last measured
+    fn lowest_latency<'a>(&self, connections: impl IntoIterator<Item = &'a NodeConnection>) -> Option<&'a NodeConnection> {
+        connections.into_iter().min_by_key(|conn| {
+            self.health_monitor
+                .link_conditions(&conn.node_id)
+                .map_or(conn.latency_ms, |link| link.latency_ms)
+        })
     }
 }
 
 impl VirtualEndpoint {
     /// Route request through virtual endpoint with failover
-    pub async fn route_request(&self, request: &[u8]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-        // Select healthy connection
-        let connection = self.select_healthy_connection()?;
+    ///
+    /// Each attempt goes to the next connection in load-balancing order;
+    /// the retry delay only applies once every connection has been tried.
+    pub async fn route_request(&self, request: &[u8]) -> Result<Vec<u8>, NetworkError> {
+        let connections = self.connection_order();
+        if connections.is_empty() {
+            return Err(NetworkError::NoConnections);
+        }
         
-        // In real implementation, send request through QUIC connection
-        tracing::debug!("Routing request through node: {}", connection.node_id);
+        let mut last_error = NetworkError::NoConnections;
+        for attempt in 0..=self.failover_config.max_retry_attempts as usize {
+            if attempt > 0 && attempt % connections.len() == 0 {
+                tokio::time::sleep(std::time::Duration::from_millis(self.failover_config.retry_delay_ms as u64)).await;
+            }
+            let connection = connections[attempt % connections.len()];
+            tracing::debug!("Routing request through node: {}", connection.node_id);
+            
+            match self.transport.request(connection.node_id, request).await {
+                Ok(response) => return Ok(response),
+                Err(NetworkError::Shutdown) => return Err(NetworkError::Shutdown),
+                Err(e) => {
+                    tracing::warn!("Request through node {} failed: {}", connection.node_id, e);
+                    last_error = e;
+                }
+            }
+        }
         
-        // Placeholder response
-        Ok(b"response".to_vec())
+        Err(last_error)
     }
     
-    fn select_healthy_connection(&self) -> Result<&NodeConnection, Box<dyn std::error::Error>> {
+    /// Connections the health monitor has not reported down
+    pub fn healthy_connections(&self) -> Vec<&NodeConnection> {
+        let unhealthy = self.unhealthy.lock().unwrap();
         self.physical_connections
-            .first()
-            .ok_or_else(|| "No healthy connections available".into())
+            .iter()
+            .filter(|conn| !unhealthy.contains(&conn.node_id))
+            .collect()
     }
+
+    /// Track health changes of this endpoint's nodes reported by `monitor`
+    pub fn follow_health(&self, monitor: &Arc<ConnectionHealthMonitor>) {
+        let nodes: HashSet<Uuid> = self.physical_connections.iter().map(|conn| conn.node_id).collect();
+        let mut events = monitor.subscribe();
+        sync_unhealthy(&mut self.unhealthy.lock().unwrap(), &nodes, monitor);
+
+        // Neither side is kept alive by the other
+        let unhealthy = Arc::downgrade(&self.unhealthy);
+        let monitor = Arc::downgrade(monitor);
+        tokio::spawn(async move {
+            loop {
+                let event = events.recv().await;
+                let Some(unhealthy) = unhealthy.upgrade() else { break };
+                let mut unhealthy = unhealthy.lock().unwrap();
+                match event {
+                    Ok(HealthEvent::Unhealthy { node_id, .. }) if nodes.contains(&node_id) => {
+                        unhealthy.insert(node_id);
+                    },
+                    Ok(HealthEvent::Recovered { node_id, .. }) => {
+                        unhealthy.remove(&node_id);
+                    },
+                    Ok(_) => {},
+                    Err(RecvError::Lagged(_)) => {
+                        let Some(monitor) = monitor.upgrade() else { break };
+                        sync_unhealthy(&mut unhealthy, &nodes, &monitor);
+                    },
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+    }
+
+    fn connection_order(&self) -> Vec<&NodeConnection> {
+        let mut connections: Vec<&NodeConnection> = self.physical_connections.iter().collect();
+        match self.load_balancing {
+            LoadBalancingStrategy::LatencyBased => {
+                connections.sort_by_key(|conn| conn.latency_ms);
+            },
+            _ if !connections.is_empty() => {
+                let start = self.next_connection.fetch_add(1, Ordering::Relaxed) % connections.len();
+                connections.rotate_left(start);
+            },
+            _ => {}
+        }
+        // Stable, so healthy connections keep their order
+        let unhealthy = self.unhealthy.lock().unwrap();
+        connections.sort_by_key(|conn| unhealthy.contains(&conn.node_id));
+        connections
+    }
+}
+
+/// Rebuild the set of unhealthy nodes from the monitor's current view
+fn sync_unhealthy(unhealthy: &mut HashSet<Uuid>, nodes: &HashSet<Uuid>, monitor: &ConnectionHealthMonitor) {
+    unhealthy.clear();
+    unhealthy.extend(
+        nodes
+            .iter()
+            .filter(|node_id| monitor.health(node_id).is_some_and(|health| !health.is_healthy)),
+    );
 }
 
 #[cfg(test)]
@@ -269,9 +395,49 @@ mod tests {
         assert!(manager.active_connections.is_empty());
     }
     
+    fn connection(latency_ms: u32, bandwidth_mbps: u32) -> NodeConnection {
+        NodeConnection {
+            node_id: Uuid::new_v4(),
+            addresses: Vec::new(),
+            protocol: CommunicationProtocol::Standard {
+                negotiation_enabled: true,
+                encryption_level: EncryptionLevel::Standard,
+            },
+            last_activity: chrono::Utc::now(),
+            latency_ms,
+            bandwidth_mbps,
+        }
+    }
+
+    #[test]
+    fn test_connection_selection_strategies() {
+        let (fast, wide) = (connection(5, 100), connection(40, 10_000));
+        let manager_with = |strategy| {
+            let mut manager = MultiHomingManager::new(strategy);
+            for conn in [&fast, &wide] {
+                manager.active_connections.insert(conn.node_id, conn.clone());
+            }
+            manager
+        };
+
+        let manager = manager_with(ConnectionSelectionStrategy::LatencyBased);
+        assert_eq!(manager.select_connection("consensus").unwrap().node_id, fast.node_id);
+
+        // Without a transport nothing is in flight, so load falls back to latency
+        let manager = manager_with(ConnectionSelectionStrategy::LoadBased);
+        assert_eq!(manager.select_connection("replication").unwrap().node_id, fast.node_id);
+
+        let manager = manager_with(ConnectionSelectionStrategy::RoundRobin);
+        let picks: HashSet<Uuid> = (0..2).map(|_| manager.select_connection("consensus").unwrap().node_id).collect();
+        assert_eq!(picks.len(), 2);
+        assert!(MultiHomingManager::new(ConnectionSelectionStrategy::RoundRobin)
+            .select_connection("consensus")
+            .is_none());
+    }
+
     #[test]
     fn test_connection_health_monitoring() {
-        let mut monitor = ConnectionHealthMonitor::new();
+        let monitor = ConnectionHealthMonitor::new();
         let node_id = Uuid::new_v4();
         
         // Initially no health data
@@ -0,0 +1,355 @@
+//! Protocol profile selection between node tiers
+//!
+//! In the profile handshake each side advertises its tier, what it supports
+//! and what it has measured about the link. The answering node picks a
+//! `CommunicationProtocol` from the two, and both sides resolve it into the
+//! `ProtocolSettings` that decide how payloads on the connection are
+//! compressed and framed. Payloads are not encrypted again on top of QUIC:
+//! every connection already runs TLS 1.3 between pinned certificates. The
+//! protocol's `EncryptionLevel` instead decides how much traffic a
+//! connection may carry before its TLS traffic keys are updated.
+
+use crate::wire::{Envelope, PayloadEncoding, DEFAULT_MAX_FRAME_SIZE};
+use crate::{
+    AdaptationStrategy, BaseProtocol, CommunicationProtocol, CompressionLevel, EncryptionLevel, NetworkError, ReliabilityLevel,
+};
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+pub use mycnet_core::NodeTier;
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+
+/// Frame size cap for protocols aimed at constrained nodes
+pub const LIGHTWEIGHT_MAX_FRAME_SIZE: usize = 1024 * 1024;
+
+/// Traffic between key updates at `EncryptionLevel::High`
+pub const HIGH_KEY_UPDATE_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Traffic between key updates at `EncryptionLevel::Standard`
+pub const STANDARD_KEY_UPDATE_BYTES: u64 = 1024 * 1024 * 1024;
+
+/// Measurements of a link; zero means not measured
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LinkConditions {
+    pub latency_ms: u32,
+    pub bandwidth_mbps: u32,
+}
+
+/// What a node advertises in the profile handshake
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NodeCapabilities {
+    pub tier: NodeTier,
+    pub compression: bool,
+    pub max_frame_size: usize,
+    /// The advertising node's view of the link
+    pub conditions: LinkConditions,
+}
+
+/// Settings agreed for a connection
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProtocolSettings {
+    pub protocol: CommunicationProtocol,
+    /// `None` leaves payloads uncompressed
+    pub compression: Option<CompressionLevel>,
+    pub max_frame_size: usize,
+    /// Bytes a connection carries before its traffic keys are updated;
+    /// `None` leaves updates to QUIC's own confidentiality limits
+    pub key_update_bytes: Option<u64>,
+}
+
+impl NodeCapabilities {
+    pub fn new(tier: NodeTier) -> Self {
+        Self {
+            tier,
+            compression: true,
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            conditions: LinkConditions::default(),
+        }
+    }
+}
+
+impl Default for NodeCapabilities {
+    fn default() -> Self {
+        Self::new(NodeTier::Rhizomorph)
+    }
+}
+
+impl LinkConditions {
+    /// Worst of two views of the same link, ignoring values not measured
+    pub fn combine(&self, other: &LinkConditions) -> LinkConditions {
+        fn worst(a: u32, b: u32, pick: fn(u32, u32) -> u32) -> u32 {
+            match (a, b) {
+                (0, b) => b,
+                (a, 0) => a,
+                (a, b) => pick(a, b),
+            }
+        }
+        LinkConditions {
+            latency_ms: worst(self.latency_ms, other.latency_ms, u32::max),
+            bandwidth_mbps: worst(self.bandwidth_mbps, other.bandwidth_mbps, u32::min),
+        }
+    }
+}
+
+/// Protocol for a link between two nodes
+///
+/// Sclerotia pairs get the high-performance protocol, any link to a Hyphae
+/// gets the lightweight one, Rhizomorph pairs use the standard protocol and
+/// Sclerotia-Rhizomorph links adapt to whatever has been measured about them.
+pub fn select_protocol(local: &NodeCapabilities, remote: &NodeCapabilities) -> CommunicationProtocol {
+    match (local.tier, remote.tier) {
+        (NodeTier::Hyphae, _) | (_, NodeTier::Hyphae) => CommunicationProtocol::Lightweight {
+            encryption_level: EncryptionLevel::Standard,
+            reliability_level: ReliabilityLevel::High,
+        },
+        (NodeTier::Sclerotia, NodeTier::Sclerotia) => CommunicationProtocol::HighPerformance {
+            encryption_level: EncryptionLevel::High,
+            compression_level: CompressionLevel::Low,
+        },
+        (NodeTier::Rhizomorph, NodeTier::Rhizomorph) => CommunicationProtocol::Standard {
+            negotiation_enabled: true,
+            encryption_level: EncryptionLevel::Standard,
+        },
+        _ => {
+            let conditions = local.conditions.combine(&remote.conditions);
+            let adaptation_strategy = if conditions.bandwidth_mbps > 0 {
+                AdaptationStrategy::BandwidthBased
+            } else if conditions.latency_ms > 0 {
+                AdaptationStrategy::LatencyBased
+            } else {
+                AdaptationStrategy::CapabilityBased
+            };
+            CommunicationProtocol::Adaptive {
+                base_protocol: BaseProtocol::Standard,
+                adaptation_strategy,
+            }
+        },
+    }
+}
+
+impl ProtocolSettings {
+    /// Settings used with peers too old for the profile handshake
+    pub fn baseline() -> Self {
+        Self {
+            protocol: CommunicationProtocol::Standard {
+                negotiation_enabled: false,
+                encryption_level: EncryptionLevel::Standard,
+            },
+            compression: None,
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            key_update_bytes: key_update_bytes(EncryptionLevel::Standard),
+        }
+    }
+
+    /// Settings for `protocol`, limited to what both nodes support
+    pub fn resolve(protocol: &CommunicationProtocol, local: &NodeCapabilities, remote: &NodeCapabilities) -> Self {
+        let conditions = local.conditions.combine(&remote.conditions);
+        let (compression, frame_cap) = match protocol {
+            CommunicationProtocol::HighPerformance { compression_level, .. } => (*compression_level, DEFAULT_MAX_FRAME_SIZE),
+            CommunicationProtocol::Standard { .. } => (CompressionLevel::Medium, DEFAULT_MAX_FRAME_SIZE),
+            // Edge links are short on bandwidth rather than CPU on the far side
+            CommunicationProtocol::Lightweight { .. } => (CompressionLevel::High, LIGHTWEIGHT_MAX_FRAME_SIZE),
+            CommunicationProtocol::Adaptive {
+                base_protocol,
+                adaptation_strategy,
+            } => {
+                let compression = match adaptation_strategy {
+                    AdaptationStrategy::BandwidthBased if conditions.bandwidth_mbps < 100 => CompressionLevel::High,
+                    AdaptationStrategy::BandwidthBased if conditions.bandwidth_mbps < 1000 => CompressionLevel::Medium,
+                    AdaptationStrategy::BandwidthBased => CompressionLevel::Low,
+                    AdaptationStrategy::LatencyBased if conditions.latency_ms >= 50 => CompressionLevel::High,
+                    AdaptationStrategy::LatencyBased if conditions.latency_ms >= 10 => CompressionLevel::Medium,
+                    AdaptationStrategy::LatencyBased => CompressionLevel::Low,
+                    AdaptationStrategy::CapabilityBased => CompressionLevel::Medium,
+                };
+                let frame_cap = match base_protocol {
+                    BaseProtocol::Standard => DEFAULT_MAX_FRAME_SIZE,
+                    BaseProtocol::Minimal => LIGHTWEIGHT_MAX_FRAME_SIZE,
+                };
+                (compression, frame_cap)
+            },
+        };
+
+        Self {
+            protocol: protocol.clone(),
+            compression: (local.compression && remote.compression).then_some(compression),
+            max_frame_size: frame_cap.min(local.max_frame_size).min(remote.max_frame_size),
+            key_update_bytes: key_update_bytes(encryption_level(protocol)),
+        }
+    }
+
+    pub fn encoding(&self) -> PayloadEncoding {
+        PayloadEncoding {
+            compression: self.compression,
+        }
+    }
+}
+
+/// Encryption a protocol asks for; adaptive links take it from their base
+fn encryption_level(protocol: &CommunicationProtocol) -> EncryptionLevel {
+    match protocol {
+        CommunicationProtocol::HighPerformance { encryption_level, .. }
+        | CommunicationProtocol::Standard { encryption_level, .. }
+        | CommunicationProtocol::Lightweight { encryption_level, .. } => *encryption_level,
+        CommunicationProtocol::Adaptive { base_protocol, .. } => match base_protocol {
+            BaseProtocol::Standard => EncryptionLevel::Standard,
+            BaseProtocol::Minimal => EncryptionLevel::Minimal,
+        },
+    }
+}
+
+fn key_update_bytes(level: EncryptionLevel) -> Option<u64> {
+    match level {
+        EncryptionLevel::High => Some(HIGH_KEY_UPDATE_BYTES),
+        EncryptionLevel::Standard => Some(STANDARD_KEY_UPDATE_BYTES),
+        EncryptionLevel::Minimal => None,
+    }
+}
+
+/// Compress an envelope's payload as `encoding` asks
+pub fn encode_payload(envelope: &mut Envelope, encoding: PayloadEncoding) -> Result<(), NetworkError> {
+    if let Some(level) = encoding.compression {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::new(compression_level(level)));
+        encoder.write_all(&envelope.payload)?;
+        envelope.payload = encoder.finish()?;
+    }
+    envelope.encoding = encoding;
+    Ok(())
+}
+
+/// Reverse `encode_payload`, refusing payloads that expand beyond `max_size`
+pub fn decode_payload(envelope: &mut Envelope, max_size: usize) -> Result<(), NetworkError> {
+    if envelope.encoding.compression.is_some() {
+        let mut payload = Vec::new();
+        ZlibDecoder::new(envelope.payload.as_slice())
+            .take(max_size as u64 + 1)
+            .read_to_end(&mut payload)?;
+        if payload.len() > max_size {
+            return Err(NetworkError::MessageTooLarge {
+                size: payload.len(),
+                limit: max_size,
+            });
+        }
+        envelope.payload = payload;
+    }
+    envelope.encoding = PayloadEncoding::default();
+    Ok(())
+}
+
+fn compression_level(level: CompressionLevel) -> u32 {
+    match level {
+        CompressionLevel::Low => 1,
+        CompressionLevel::Medium => 6,
+        CompressionLevel::High => 9,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wire::{MessageType, PROTOCOL_VERSION};
+    use uuid::Uuid;
+
+    fn capabilities(tier: NodeTier, bandwidth_mbps: u32) -> NodeCapabilities {
+        NodeCapabilities {
+            conditions: LinkConditions {
+                latency_ms: 0,
+                bandwidth_mbps,
+            },
+            ..NodeCapabilities::new(tier)
+        }
+    }
+
+    #[test]
+    fn test_protocol_follows_node_tiers() {
+        let sclerotia = NodeCapabilities::new(NodeTier::Sclerotia);
+        let rhizomorph = NodeCapabilities::new(NodeTier::Rhizomorph);
+        let hyphae = NodeCapabilities::new(NodeTier::Hyphae);
+
+        let high_performance = select_protocol(&sclerotia, &sclerotia);
+        assert!(matches!(high_performance, CommunicationProtocol::HighPerformance { .. }));
+        let settings = ProtocolSettings::resolve(&high_performance, &sclerotia, &sclerotia);
+        assert_eq!(settings.compression, Some(CompressionLevel::Low));
+        assert_eq!(settings.max_frame_size, DEFAULT_MAX_FRAME_SIZE);
+
+        assert!(matches!(select_protocol(&rhizomorph, &rhizomorph), CommunicationProtocol::Standard { .. }));
+        assert!(matches!(select_protocol(&sclerotia, &rhizomorph), CommunicationProtocol::Adaptive { .. }));
+        for other in [&sclerotia, &rhizomorph, &hyphae] {
+            let lightweight = select_protocol(other, &hyphae);
+            assert!(matches!(lightweight, CommunicationProtocol::Lightweight { .. }));
+            let settings = ProtocolSettings::resolve(&lightweight, other, &hyphae);
+            assert_eq!(settings.max_frame_size, LIGHTWEIGHT_MAX_FRAME_SIZE);
+        }
+
+        // Settings never exceed what either side supports
+        let plain = NodeCapabilities {
+            compression: false,
+            max_frame_size: 4096,
+            ..sclerotia.clone()
+        };
+        let settings = ProtocolSettings::resolve(&select_protocol(&sclerotia, &plain), &sclerotia, &plain);
+        assert_eq!(settings.compression, None);
+        assert_eq!(settings.max_frame_size, 4096);
+    }
+
+    #[test]
+    fn test_encryption_level_sets_key_updates() {
+        let sclerotia = NodeCapabilities::new(NodeTier::Sclerotia);
+        let rhizomorph = NodeCapabilities::new(NodeTier::Rhizomorph);
+
+        let high_performance = select_protocol(&sclerotia, &sclerotia);
+        let settings = ProtocolSettings::resolve(&high_performance, &sclerotia, &sclerotia);
+        assert_eq!(settings.key_update_bytes, Some(HIGH_KEY_UPDATE_BYTES));
+        let standard = select_protocol(&rhizomorph, &rhizomorph);
+        let settings = ProtocolSettings::resolve(&standard, &rhizomorph, &rhizomorph);
+        assert_eq!(settings.key_update_bytes, Some(STANDARD_KEY_UPDATE_BYTES));
+
+        let minimal = CommunicationProtocol::Adaptive {
+            base_protocol: BaseProtocol::Minimal,
+            adaptation_strategy: AdaptationStrategy::CapabilityBased,
+        };
+        assert_eq!(ProtocolSettings::resolve(&minimal, &rhizomorph, &rhizomorph).key_update_bytes, None);
+    }
+
+    #[test]
+    fn test_adaptive_protocol_follows_link_conditions() {
+        let sclerotia = NodeCapabilities::new(NodeTier::Sclerotia);
+        let resolve = |remote: &NodeCapabilities| {
+            ProtocolSettings::resolve(&select_protocol(&sclerotia, remote), &sclerotia, remote).compression
+        };
+
+        assert_eq!(resolve(&capabilities(NodeTier::Rhizomorph, 0)), Some(CompressionLevel::Medium));
+        assert_eq!(resolve(&capabilities(NodeTier::Rhizomorph, 50)), Some(CompressionLevel::High));
+        assert_eq!(resolve(&capabilities(NodeTier::Rhizomorph, 10_000)), Some(CompressionLevel::Low));
+
+        // The slower view of the link wins
+        let local = capabilities(NodeTier::Sclerotia, 10_000);
+        let remote = capabilities(NodeTier::Rhizomorph, 50);
+        let settings = ProtocolSettings::resolve(&select_protocol(&local, &remote), &local, &remote);
+        assert_eq!(settings.compression, Some(CompressionLevel::High));
+    }
+
+    #[test]
+    fn test_payload_encoding_round_trip() {
+        let original = Envelope::new(PROTOCOL_VERSION, MessageType::Response, 5, Uuid::new_v4(), vec![7u8; 4096]);
+        let encoding = PayloadEncoding {
+            compression: Some(CompressionLevel::High),
+        };
+
+        let mut envelope = original.clone();
+        encode_payload(&mut envelope, encoding).unwrap();
+        assert!(envelope.payload.len() < original.payload.len());
+        assert_eq!(envelope.encoding, encoding);
+        let mut decoded = Envelope::decode(&envelope.encode().unwrap()[4..]).unwrap();
+        decode_payload(&mut decoded, DEFAULT_MAX_FRAME_SIZE).unwrap();
+        assert_eq!(decoded, original);
+
+        // A payload that inflates past the limit fails
+        let mut inflated = envelope;
+        assert!(matches!(
+            decode_payload(&mut inflated, 1024),
+            Err(NetworkError::MessageTooLarge { limit: 1024, .. })
+        ));
+    }
+}
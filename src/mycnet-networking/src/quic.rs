@@ -0,0 +1,760 @@
+//! QUIC transport between nodes, addressed by node ID
+//!
+//! Every node holds a self-signed certificate and publishes its blake3
+//! fingerprint alongside its addresses in spores. Both sides of a connection
+//! present their certificate and check it against the fingerprint the
+//! `AddressResolver` knows for that node, so a connection is always bound to
+//! a known node ID in both directions. Each request runs on its own
+//! bidirectional stream, and one connection per peer is reused for requests
+//! in either direction. Health probes travel as unreliable datagrams that
+//! the transport answers itself, so they measure the path rather than the
+//! request handler.
+
+use crate::NetworkError;
+use rustls::client::{ServerCertVerified, ServerCertVerifier};
+use rustls::server::{ClientCertVerified, ClientCertVerifier};
+use rustls::{Certificate, CertificateError, DistinguishedName, PrivateKey, ServerName};
+use std::collections::HashMap;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::oneshot;
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
+use uuid::Uuid;
+
+/// ALPN protocol identifier for node-to-node traffic
+pub const ALPN_PROTOCOL: &[u8] = b"mycnet/1";
+
+/// First byte of a probe datagram
+const PROBE_REQUEST: u8 = 0;
+const PROBE_REPLY: u8 = 1;
+
+/// blake3 hash of a DER-encoded certificate
+pub type CertificateFingerprint = [u8; 32];
+
+/// Where a node can be reached and the certificate it must present
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeAddress {
+    pub node_id: Uuid,
+    /// Tried in order until one connects
+    pub addresses: Vec<SocketAddr>,
+    pub certificate_fingerprint: CertificateFingerprint,
+}
+
+/// Source of node addresses, typically kept current from spores
+pub trait AddressResolver: Send + Sync + 'static {
+    fn resolve(&self, node_id: &Uuid) -> Option<NodeAddress>;
+
+    /// Node presenting a certificate, if it is known
+    fn identify(&self, fingerprint: &CertificateFingerprint) -> Option<Uuid>;
+}
+
+/// In-memory resolver updated as spores announce or drop nodes
+#[derive(Default)]
+pub struct StaticResolver {
+    nodes: RwLock<HashMap<Uuid, NodeAddress>>,
+}
+
+/// Certificate and key a node authenticates with
+#[derive(Clone)]
+pub struct NodeIdentity {
+    pub node_id: Uuid,
+    pub certificate: Certificate,
+    pub private_key: PrivateKey,
+}
+
+/// Serves requests arriving from other nodes
+pub trait RequestHandler: Send + Sync + 'static {
+    fn handle(&self, from: Uuid, request: Vec<u8>) -> impl Future<Output = Vec<u8>> + Send;
+}
+
+/// Serves raw streams opened by other nodes, for protocols that exchange
+/// more than one message per stream
+pub trait StreamHandler: Send + Sync + 'static {
+    fn handle_stream(
+        &self,
+        from: Uuid,
+        connection: quinn::Connection,
+        send: quinn::SendStream,
+        recv: quinn::RecvStream,
+    ) -> impl Future<Output = ()> + Send;
+}
+
+#[derive(Debug, Clone)]
+pub struct QuicConfig {
+    /// Largest request or response accepted
+    pub max_message_size: usize,
+    pub idle_timeout: Duration,
+    /// Keeps idle connections open for reuse; must be below `idle_timeout`
+    pub keep_alive_interval: Duration,
+    /// How long shutdown waits for in-flight requests
+    pub shutdown_timeout: Duration,
+    /// How long a request may take, from dialing to the end of the response
+    pub request_timeout: Duration,
+}
+
+/// Result of one probe of the path to a node
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ProbeSample {
+    pub rtt: Duration,
+    /// What the congestion controller estimates the path carries, from its
+    /// window and round-trip time
+    pub throughput_mbps: u32,
+}
+
+/// Probes awaiting a reply, by the node probed and the probe's random token
+type PendingProbes = Arc<Mutex<HashMap<(Uuid, u64), oneshot::Sender<Instant>>>>;
+
+type DialLocks = Mutex<HashMap<Uuid, Arc<tokio::sync::Mutex<()>>>>;
+
+type ErasedHandler = Arc<
+    dyn Fn(Uuid, quinn::Connection, quinn::SendStream, quinn::RecvStream) -> Pin<Box<dyn Future<Output = ()> + Send>>
+        + Send
+        + Sync,
+>;
+
+/// QUIC endpoint that listens for and dials other nodes by ID
+pub struct QuicTransport {
+    identity: NodeIdentity,
+    endpoint: quinn::Endpoint,
+    resolver: Arc<dyn AddressResolver>,
+    config: QuicConfig,
+    transport_config: Arc<quinn::TransportConfig>,
+    handler: ErasedHandler,
+    connections: Arc<Mutex<HashMap<Uuid, quinn::Connection>>>,
+    dialing: DialLocks,
+    /// Requests awaiting a response, per node
+    in_flight: Mutex<HashMap<Uuid, usize>>,
+    probes: PendingProbes,
+    shutdown: CancellationToken,
+    tasks: TaskTracker,
+}
+
+/// Name a node's certificate is issued for
+pub fn server_name(node_id: &Uuid) -> String {
+    format!("{}.node.mycnet", node_id)
+}
+
+pub fn certificate_fingerprint(certificate: &Certificate) -> CertificateFingerprint {
+    *blake3::hash(&certificate.0).as_bytes()
+}
+
+impl Default for QuicConfig {
+    fn default() -> Self {
+        Self {
+            max_message_size: 16 * 1024 * 1024,
+            idle_timeout: Duration::from_secs(30),
+            keep_alive_interval: Duration::from_secs(10),
+            shutdown_timeout: Duration::from_secs(5),
+            request_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+impl NodeAddress {
+    /// Build an address from a spore node entry, skipping addresses that do not parse
+    pub fn from_spore_entry(node_id: Uuid, addresses: &[String], certificate_fingerprint: CertificateFingerprint) -> Self {
+        let addresses = addresses
+            .iter()
+            .filter_map(|address| match address.parse() {
+                Ok(address) => Some(address),
+                Err(_) => {
+                    tracing::warn!("Ignoring unparsable address {} for node {}", address, node_id);
+                    None
+                },
+            })
+            .collect();
+        Self {
+            node_id,
+            addresses,
+            certificate_fingerprint,
+        }
+    }
+}
+
+impl StaticResolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&self, address: NodeAddress) {
+        self.nodes.write().unwrap().insert(address.node_id, address);
+    }
+
+    pub fn remove(&self, node_id: &Uuid) -> Option<NodeAddress> {
+        self.nodes.write().unwrap().remove(node_id)
+    }
+}
+
+impl AddressResolver for StaticResolver {
+    fn resolve(&self, node_id: &Uuid) -> Option<NodeAddress> {
+        self.nodes.read().unwrap().get(node_id).cloned()
+    }
+
+    fn identify(&self, fingerprint: &CertificateFingerprint) -> Option<Uuid> {
+        self.nodes
+            .read()
+            .unwrap()
+            .values()
+            .find(|address| address.certificate_fingerprint == *fingerprint)
+            .map(|address| address.node_id)
+    }
+}
+
+impl NodeIdentity {
+    /// Generate a self-signed certificate for a node
+    pub fn generate(node_id: Uuid) -> Result<Self, NetworkError> {
+        let certificate = rcgen::generate_simple_self_signed(vec![server_name(&node_id)])?;
+        Ok(Self {
+            node_id,
+            certificate: Certificate(certificate.serialize_der()?),
+            private_key: PrivateKey(certificate.serialize_private_key_der()),
+        })
+    }
+
+    pub fn fingerprint(&self) -> CertificateFingerprint {
+        certificate_fingerprint(&self.certificate)
+    }
+}
+
+impl QuicTransport {
+    /// Listen on `address` and serve incoming requests with `handler`
+    pub fn bind<H: RequestHandler>(
+        identity: NodeIdentity,
+        address: SocketAddr,
+        resolver: Arc<dyn AddressResolver>,
+        handler: Arc<H>,
+        config: QuicConfig,
+    ) -> Result<Arc<Self>, NetworkError> {
+        let max_message_size = config.max_message_size;
+        let handler: ErasedHandler = Arc::new(move |from, _connection, mut send, mut recv| {
+            let handler = handler.clone();
+            Box::pin(async move {
+                let request = match recv.read_to_end(max_message_size).await {
+                    Ok(request) => request,
+                    Err(e) => {
+                        tracing::debug!("Dropping request from node {}: {}", from, e);
+                        return;
+                    },
+                };
+                let response = handler.handle(from, request).await;
+                if send.write_all(&response).await.is_ok() {
+                    let _ = send.finish().await;
+                }
+            })
+        });
+        Self::bind_erased(identity, address, resolver, handler, config)
+    }
+
+    /// Listen on `address` and hand each incoming stream to `handler`
+    pub fn bind_streams<H: StreamHandler>(
+        identity: NodeIdentity,
+        address: SocketAddr,
+        resolver: Arc<dyn AddressResolver>,
+        handler: Arc<H>,
+        config: QuicConfig,
+    ) -> Result<Arc<Self>, NetworkError> {
+        let handler: ErasedHandler = Arc::new(move |from, connection, send, recv| {
+            let handler = handler.clone();
+            Box::pin(async move { handler.handle_stream(from, connection, send, recv).await })
+        });
+        Self::bind_erased(identity, address, resolver, handler, config)
+    }
+
+    fn bind_erased(
+        identity: NodeIdentity,
+        address: SocketAddr,
+        resolver: Arc<dyn AddressResolver>,
+        handler: ErasedHandler,
+        config: QuicConfig,
+    ) -> Result<Arc<Self>, NetworkError> {
+        let mut transport_config = quinn::TransportConfig::default();
+        let idle_timeout = quinn::IdleTimeout::try_from(config.idle_timeout)
+            .map_err(|_| NetworkError::InvalidConfig(format!("Idle timeout {:?} is too large", config.idle_timeout)))?;
+        transport_config.max_idle_timeout(Some(idle_timeout));
+        transport_config.keep_alive_interval(Some(config.keep_alive_interval));
+        let transport_config = Arc::new(transport_config);
+
+        let mut tls = rustls::ServerConfig::builder()
+            .with_safe_defaults()
+            .with_client_cert_verifier(Arc::new(KnownNodeVerifier {
+                resolver: resolver.clone(),
+            }))
+            .with_single_cert(vec![identity.certificate.clone()], identity.private_key.clone())?;
+        tls.alpn_protocols = vec![ALPN_PROTOCOL.to_vec()];
+        let mut server_config = quinn::ServerConfig::with_crypto(Arc::new(tls));
+        server_config.transport_config(transport_config.clone());
+
+        let endpoint = quinn::Endpoint::server(server_config, address)?;
+
+        let transport = Arc::new(Self {
+            identity,
+            endpoint,
+            resolver,
+            config,
+            transport_config,
+            handler,
+            connections: Arc::new(Mutex::new(HashMap::new())),
+            dialing: Mutex::new(HashMap::new()),
+            in_flight: Mutex::new(HashMap::new()),
+            probes: Arc::new(Mutex::new(HashMap::new())),
+            shutdown: CancellationToken::new(),
+            tasks: TaskTracker::new(),
+        });
+
+        let accepting = transport.clone();
+        transport.tasks.spawn(async move { accepting.accept_loop().await });
+        tracing::info!("Node {} listening on {}", transport.identity.node_id, transport.endpoint.local_addr()?);
+        Ok(transport)
+    }
+
+    pub fn node_id(&self) -> Uuid {
+        self.identity.node_id
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr, NetworkError> {
+        Ok(self.endpoint.local_addr()?)
+    }
+
+    /// Nodes with an open connection
+    pub fn connected_nodes(&self) -> Vec<Uuid> {
+        let mut connections = self.connections.lock().unwrap();
+        connections.retain(|_, connection| connection.close_reason().is_none());
+        connections.keys().copied().collect()
+    }
+
+    /// Open connection to a node, dialing it if there is none
+    pub async fn connect(&self, node_id: Uuid) -> Result<quinn::Connection, NetworkError> {
+        if self.shutdown.is_cancelled() {
+            return Err(NetworkError::Shutdown);
+        }
+        if let Some(connection) = self.live_connection(&node_id) {
+            return Ok(connection);
+        }
+
+        // One dial per node at a time; later callers reuse its connection
+        let lock = self.dialing.lock().unwrap().entry(node_id).or_default().clone();
+        let _dialing = DialGuard {
+            dialing: &self.dialing,
+            node_id,
+            guard: Some(lock.lock_owned().await),
+        };
+        if let Some(connection) = self.live_connection(&node_id) {
+            return Ok(connection);
+        }
+
+        let address = self.resolver.resolve(&node_id).ok_or(NetworkError::UnknownNode(node_id))?;
+        let client_config = self.client_config(address.certificate_fingerprint)?;
+
+        let mut last_error = String::from("No addresses published");
+        for socket_address in &address.addresses {
+            let connecting = match self.endpoint.connect_with(client_config.clone(), *socket_address, &server_name(&node_id)) {
+                Ok(connecting) => connecting,
+                Err(e) => {
+                    last_error = e.to_string();
+                    continue;
+                },
+            };
+            match connecting.await {
+                Ok(connection) => {
+                    tracing::debug!("Connected to node {} at {}", node_id, socket_address);
+                    self.register(node_id, connection.clone());
+                    return Ok(connection);
+                },
+                Err(e) => {
+                    tracing::debug!("Dialing node {} at {} failed: {}", node_id, socket_address, e);
+                    last_error = e.to_string();
+                },
+            }
+        }
+
+        Err(NetworkError::Unreachable {
+            node_id,
+            reason: last_error,
+        })
+    }
+
+    /// Send a request to a node on a new stream and wait for its response
+    ///
+    /// Fails with `Timeout` if the whole exchange takes longer than `request_timeout`.
+    pub async fn request(&self, node_id: Uuid, request: &[u8]) -> Result<Vec<u8>, NetworkError> {
+        if request.len() > self.config.max_message_size {
+            return Err(NetworkError::MessageTooLarge {
+                size: request.len(),
+                limit: self.config.max_message_size,
+            });
+        }
+        let _in_flight = InFlight::start(&self.in_flight, node_id);
+        let exchange = async {
+            let connection = self.connect(node_id).await?;
+            let (mut send, mut recv) = connection.open_bi().await?;
+            send.write_all(request).await?;
+            send.finish().await?;
+            Ok(recv.read_to_end(self.config.max_message_size).await?)
+        };
+        tokio::time::timeout(self.config.request_timeout, exchange)
+            .await
+            .map_err(|_| NetworkError::Timeout {
+                node_id,
+                timeout: self.config.request_timeout,
+            })?
+    }
+
+    /// Requests to a node that are still waiting for a response
+    pub fn in_flight(&self, node_id: &Uuid) -> usize {
+        self.in_flight.lock().unwrap().get(node_id).copied().unwrap_or(0)
+    }
+
+    /// Send a probe datagram to a node and wait for it to come back
+    ///
+    /// Datagrams are never retransmitted, so a probe that is lost on the way
+    /// in either direction times out.
+    pub async fn probe(&self, node_id: Uuid, timeout: Duration) -> Result<ProbeSample, NetworkError> {
+        let unanswered = || NetworkError::Unreachable {
+            node_id,
+            reason: format!("No probe reply within {:?}", timeout),
+        };
+        let started = Instant::now();
+        let connection = tokio::time::timeout(timeout, self.connect(node_id)).await.map_err(|_| unanswered())??;
+
+        // Unguessable, so no node can answer a probe it never received
+        let token: u64 = rand::random();
+        let pending = (node_id, token);
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.probes.lock().unwrap().insert(pending, reply_tx);
+        let mut datagram = vec![PROBE_REQUEST];
+        datagram.extend_from_slice(&token.to_be_bytes());
+        let sent = Instant::now();
+        let result = match connection.send_datagram(datagram.into()) {
+            Ok(()) => tokio::time::timeout(timeout.saturating_sub(started.elapsed()), reply_rx).await,
+            Err(e) => {
+                self.probes.lock().unwrap().remove(&pending);
+                return Err(e.into());
+            },
+        };
+        let received = match result {
+            Ok(Ok(received)) => received,
+            _ => {
+                self.probes.lock().unwrap().remove(&pending);
+                return Err(unanswered());
+            },
+        };
+
+        let rtt = received.duration_since(sent);
+        let path = connection.stats().path;
+        let throughput_mbps = match path.rtt.as_micros() {
+            0 => 0,
+            // Bytes per microsecond times eight is megabits per second
+            micros => (path.cwnd as u128 * 8 / micros).min(u32::MAX as u128) as u32,
+        };
+        Ok(ProbeSample { rtt, throughput_mbps })
+    }
+
+    /// Close a node's connection; the next request dials again
+    pub fn disconnect(&self, node_id: &Uuid) {
+        if let Some(connection) = self.connections.lock().unwrap().remove(node_id) {
+            connection.close(0u32.into(), b"disconnect");
+        }
+    }
+
+    /// Stop accepting work, let in-flight requests finish, then close every connection
+    ///
+    /// Requests still running after `shutdown_timeout` are abandoned.
+    pub async fn shutdown(&self) {
+        self.shutdown.cancel();
+        self.endpoint.reject_new_connections();
+        self.tasks.close();
+        if tokio::time::timeout(self.config.shutdown_timeout, self.tasks.wait()).await.is_err() {
+            tracing::warn!("Node {} shut down with requests still running", self.identity.node_id);
+        }
+
+        for (_, connection) in self.connections.lock().unwrap().drain() {
+            connection.close(0u32.into(), b"shutdown");
+        }
+        self.endpoint.close(0u32.into(), b"shutdown");
+        let _ = tokio::time::timeout(self.config.shutdown_timeout, self.endpoint.wait_idle()).await;
+        tracing::info!("Node {} transport shut down", self.identity.node_id);
+    }
+
+    fn live_connection(&self, node_id: &Uuid) -> Option<quinn::Connection> {
+        let mut connections = self.connections.lock().unwrap();
+        match connections.get(node_id) {
+            Some(connection) if connection.close_reason().is_none() => Some(connection.clone()),
+            Some(_) => {
+                connections.remove(node_id);
+                None
+            },
+            None => None,
+        }
+    }
+
+    fn client_config(&self, fingerprint: CertificateFingerprint) -> Result<quinn::ClientConfig, NetworkError> {
+        let mut tls = rustls::ClientConfig::builder()
+            .with_safe_defaults()
+            .with_custom_certificate_verifier(Arc::new(PinnedCertificate { fingerprint }))
+            .with_client_auth_cert(vec![self.identity.certificate.clone()], self.identity.private_key.clone())?;
+        tls.alpn_protocols = vec![ALPN_PROTOCOL.to_vec()];
+        let mut config = quinn::ClientConfig::new(Arc::new(tls));
+        config.transport_config(self.transport_config.clone());
+        Ok(config)
+    }
+
+    /// Keep a connection for reuse and serve the streams the peer opens on it
+    fn register(&self, node_id: Uuid, connection: quinn::Connection) {
+        {
+            let mut connections = self.connections.lock().unwrap();
+            match connections.get(&node_id) {
+                // Both sides dialed at once; keep the connection already in use
+                Some(existing) if existing.close_reason().is_none() => {},
+                _ => {
+                    connections.insert(node_id, connection.clone());
+                },
+            }
+        }
+
+        let datagrams = connection.clone();
+        let probes = self.probes.clone();
+        let shutdown = self.shutdown.clone();
+        self.tasks.spawn(async move {
+            loop {
+                let datagram = tokio::select! {
+                    _ = shutdown.cancelled() => break,
+                    datagram = datagrams.read_datagram() => datagram,
+                };
+                let Ok(datagram) = datagram else { break };
+                answer_probe(node_id, &datagrams, &probes, &datagram);
+            }
+        });
+
+        let handler = self.handler.clone();
+        let shutdown = self.shutdown.clone();
+        let tasks = self.tasks.clone();
+        let connections = self.connections.clone();
+        self.tasks.spawn(async move {
+            loop {
+                let stream = tokio::select! {
+                    _ = shutdown.cancelled() => break,
+                    stream = connection.accept_bi() => stream,
+                };
+                let (send, recv) = match stream {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        tracing::debug!("Connection to node {} closed: {}", node_id, e);
+                        break;
+                    },
+                };
+                tasks.spawn(handler(node_id, connection.clone(), send, recv));
+            }
+
+            let mut connections = connections.lock().unwrap();
+            if connections.get(&node_id).is_some_and(|current| current.stable_id() == connection.stable_id()) {
+                connections.remove(&node_id);
+            }
+        });
+    }
+
+    async fn accept_loop(self: Arc<Self>) {
+        loop {
+            let connecting = tokio::select! {
+                _ = self.shutdown.cancelled() => break,
+                connecting = self.endpoint.accept() => connecting,
+            };
+            let Some(connecting) = connecting else { break };
+
+            let transport = self.clone();
+            self.tasks.spawn(async move {
+                let connection = match connecting.await {
+                    Ok(connection) => connection,
+                    Err(e) => {
+                        tracing::debug!("Incoming connection failed: {}", e);
+                        return;
+                    },
+                };
+                match transport.peer_node(&connection) {
+                    Some(node_id) => {
+                        tracing::debug!("Accepted connection from node {}", node_id);
+                        transport.register(node_id, connection);
+                    },
+                    None => connection.close(1u32.into(), b"unknown node"),
+                }
+            });
+        }
+    }
+
+    /// Node ID behind the certificate the peer presented
+    fn peer_node(&self, connection: &quinn::Connection) -> Option<Uuid> {
+        let certificates = connection.peer_identity()?.downcast::<Vec<Certificate>>().ok()?;
+        let certificate = certificates.first()?;
+        self.resolver.identify(&certificate_fingerprint(certificate))
+    }
+}
+
+/// Holds a node's dial lock, and forgets the lock once no other caller waits on it
+struct DialGuard<'a> {
+    dialing: &'a DialLocks,
+    node_id: Uuid,
+    guard: Option<tokio::sync::OwnedMutexGuard<()>>,
+}
+
+impl Drop for DialGuard<'_> {
+    fn drop(&mut self) {
+        let Some(guard) = self.guard.take() else { return };
+        let lock = tokio::sync::OwnedMutexGuard::mutex(&guard).clone();
+        drop(guard);
+        // New callers clone the lock under the map's mutex, so the count cannot grow while it is held
+        let mut dialing = self.dialing.lock().unwrap();
+        let waiting = Arc::strong_count(&lock) > 2;
+        if !waiting && dialing.get(&self.node_id).is_some_and(|entry| Arc::ptr_eq(entry, &lock)) {
+            dialing.remove(&self.node_id);
+        }
+    }
+}
+
+/// Counts one request to a node for as long as it lives
+struct InFlight<'a> {
+    requests: &'a Mutex<HashMap<Uuid, usize>>,
+    node_id: Uuid,
+}
+
+impl<'a> InFlight<'a> {
+    fn start(requests: &'a Mutex<HashMap<Uuid, usize>>, node_id: Uuid) -> Self {
+        *requests.lock().unwrap().entry(node_id).or_default() += 1;
+        Self { requests, node_id }
+    }
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        let mut requests = self.requests.lock().unwrap();
+        if let Some(count) = requests.get_mut(&self.node_id) {
+            *count -= 1;
+            if *count == 0 {
+                requests.remove(&self.node_id);
+            }
+        }
+    }
+}
+
+/// Echo a probe request from `node_id`, or wake the `probe` call a reply belongs to
+fn answer_probe(node_id: Uuid, connection: &quinn::Connection, probes: &PendingProbes, datagram: &[u8]) {
+    let received = Instant::now();
+    let Some((&kind, token)) = datagram.split_first() else { return };
+    let Ok(token) = <[u8; 8]>::try_from(token) else { return };
+    match kind {
+        PROBE_REQUEST => {
+            let mut reply = vec![PROBE_REPLY];
+            reply.extend_from_slice(&token);
+            let _ = connection.send_datagram(reply.into());
+        },
+        PROBE_REPLY => complete_probe(probes, node_id, u64::from_be_bytes(token), received),
+        _ => {},
+    }
+}
+
+/// Wake the probe of `node_id` waiting on `token`; the same token from another node is ignored
+fn complete_probe(probes: &PendingProbes, node_id: Uuid, token: u64, received: Instant) {
+    if let Some(waiter) = probes.lock().unwrap().remove(&(node_id, token)) {
+        let _ = waiter.send(received);
+    }
+}
+
+/// Accepts only the server certificate a node published
+struct PinnedCertificate {
+    fingerprint: CertificateFingerprint,
+}
+
+impl ServerCertVerifier for PinnedCertificate {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        if certificate_fingerprint(end_entity) != self.fingerprint {
+            return Err(rustls::Error::InvalidCertificate(CertificateError::ApplicationVerificationFailure));
+        }
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+/// Accepts client certificates of nodes the resolver knows
+struct KnownNodeVerifier {
+    resolver: Arc<dyn AddressResolver>,
+}
+
+impl ClientCertVerifier for KnownNodeVerifier {
+    fn client_auth_root_subjects(&self) -> &[DistinguishedName] {
+        &[]
+    }
+
+    fn verify_client_cert(
+        &self,
+        end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _now: SystemTime,
+    ) -> Result<ClientCertVerified, rustls::Error> {
+        if self.resolver.identify(&certificate_fingerprint(end_entity)).is_none() {
+            return Err(rustls::Error::InvalidCertificate(CertificateError::ApplicationVerificationFailure));
+        }
+        Ok(ClientCertVerified::assertion())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Silent;
+
+    impl RequestHandler for Silent {
+        async fn handle(&self, _from: Uuid, _request: Vec<u8>) -> Vec<u8> {
+            Vec::new()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dial_locks_are_forgotten() {
+        let resolver = Arc::new(StaticResolver::new());
+        let identity = NodeIdentity::generate(Uuid::new_v4()).unwrap();
+        let address = SocketAddr::from(([127, 0, 0, 1], 0));
+        let transport = QuicTransport::bind(identity, address, resolver.clone(), Arc::new(Silent), QuicConfig::default()).unwrap();
+
+        let unknown = Uuid::new_v4();
+        let (first, second) = tokio::join!(transport.connect(unknown), transport.connect(unknown));
+        assert!(first.is_err() && second.is_err());
+        assert!(transport.dialing.lock().unwrap().is_empty());
+
+        // Nothing answers at this address, so the dial is abandoned mid-handshake
+        let silent = NodeIdentity::generate(Uuid::new_v4()).unwrap();
+        let socket = std::net::UdpSocket::bind(address).unwrap();
+        let unanswered = socket.local_addr().unwrap().to_string();
+        resolver.insert(NodeAddress::from_spore_entry(silent.node_id, &[unanswered], silent.fingerprint()));
+        assert!(tokio::time::timeout(Duration::from_millis(50), transport.connect(silent.node_id)).await.is_err());
+        assert!(transport.dialing.lock().unwrap().is_empty());
+
+        transport.shutdown().await;
+    }
+
+    #[test]
+    fn test_probe_replies_only_count_from_the_probed_node() {
+        let probes: PendingProbes = Arc::default();
+        let (probed, other) = (Uuid::new_v4(), Uuid::new_v4());
+        let (reply_tx, mut reply_rx) = oneshot::channel();
+        probes.lock().unwrap().insert((probed, 7), reply_tx);
+
+        complete_probe(&probes, other, 7, Instant::now());
+        complete_probe(&probes, probed, 8, Instant::now());
+        assert!(reply_rx.try_recv().is_err());
+        assert_eq!(probes.lock().unwrap().len(), 1);
+
+        complete_probe(&probes, probed, 7, Instant::now());
+        assert!(reply_rx.try_recv().is_ok());
+        assert!(probes.lock().unwrap().is_empty());
+    }
+}
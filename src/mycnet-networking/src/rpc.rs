@@ -0,0 +1,628 @@
+//! Request/response and streaming RPC over framed QUIC streams
+//!
+//! Every call runs on its own stream: the caller sends one `Request` or
+//! `StreamRequest` frame and the callee answers with a `Response`, a run of
+//! `StreamItem`s closed by `StreamEnd`, or an `Error`. Before the first call
+//! on a connection the caller sends `Hello` with the versions it speaks and
+//! uses the version the callee picks for every call on that connection, so
+//! nodes on different releases keep talking during a rolling upgrade. From
+//! version 2 the caller then offers its capabilities, and the protocol the
+//! callee picks decides how payloads are compressed and framed. Both sides
+//! keep what they agreed per connection.
+
+use crate::health::ConnectionHealthMonitor;
+use crate::profile::{decode_payload, encode_payload, select_protocol, LinkConditions, NodeCapabilities, ProtocolSettings};
+use crate::quic::{QuicTransport, StreamHandler};
+use crate::wire::{read_frame, write_frame, Envelope, MessageType, PayloadEncoding, VersionRange, MIN_PROTOCOL_VERSION};
+use crate::NetworkError;
+use ed25519_dalek::{SigningKey, VerifyingKey};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Default)]
+pub struct RpcConfig {
+    /// Versions this node offers or accepts; narrow it to hold a cluster on
+    /// an older version during an upgrade
+    pub versions: VersionRange,
+    /// Advertised in the profile handshake; `max_frame_size` is also the
+    /// largest frame or decoded payload this node accepts
+    pub capabilities: NodeCapabilities,
+}
+
+/// Methods a node serves over RPC
+pub trait RpcService: Send + Sync + 'static {
+    fn call(&self, from: Uuid, method: &str, request: Vec<u8>) -> impl Future<Output = Result<Vec<u8>, String>> + Send;
+
+    /// Answer a streaming call by sending items to `sink`
+    fn stream(
+        &self,
+        from: Uuid,
+        method: &str,
+        request: Vec<u8>,
+        sink: &mut ResponseSink<'_>,
+    ) -> impl Future<Output = Result<(), String>> + Send {
+        let _ = (from, request, sink);
+        let message = format!("Method {} does not stream", method);
+        async move { Err(message) }
+    }
+}
+
+/// Looks up the key a node signs requests with
+pub type SigningKeyLookup = Arc<dyn Fn(&Uuid) -> Option<VerifyingKey> + Send + Sync>;
+
+/// Serves RPC calls arriving on a transport's streams
+pub struct RpcServer<S> {
+    node_id: Uuid,
+    service: Arc<S>,
+    config: RpcConfig,
+    signing_keys: Option<SigningKeyLookup>,
+    signing_key: Option<SigningKey>,
+    /// Settings accepted for each node's current connection
+    sessions: Arc<Mutex<HashMap<Uuid, AcceptedProfile>>>,
+}
+
+/// Settings the server accepted on one connection
+struct AcceptedProfile {
+    connection_id: usize,
+    settings: ProtocolSettings,
+}
+
+/// Sends stream items back to the caller of a streaming call
+pub struct ResponseSink<'a> {
+    send: &'a mut quinn::SendStream,
+    request: &'a Envelope,
+    node_id: Uuid,
+    encoding: PayloadEncoding,
+    max_frame_size: usize,
+}
+
+/// Calls methods on other nodes
+pub struct RpcClient {
+    transport: Arc<QuicTransport>,
+    config: RpcConfig,
+    signing_key: Option<SigningKey>,
+    /// Keys nodes sign their profile accepts with
+    profile_keys: Option<SigningKeyLookup>,
+    health_monitor: Option<Arc<ConnectionHealthMonitor>>,
+    next_correlation_id: AtomicU64,
+    link_conditions: Mutex<HashMap<Uuid, LinkConditions>>,
+    sessions: Mutex<HashMap<Uuid, Session>>,
+}
+
+/// What was agreed with a node on one connection
+#[derive(Clone)]
+struct Session {
+    connection: quinn::Connection,
+    version: u16,
+    settings: ProtocolSettings,
+    /// `None` for peers too old for the profile handshake
+    remote: Option<NodeCapabilities>,
+    /// Connection traffic at the last key update we asked for
+    keys_updated_at: Arc<AtomicU64>,
+}
+
+/// Items of a streaming call, read as they arrive
+pub struct ResponseStream {
+    recv: quinn::RecvStream,
+    correlation_id: u64,
+    max_frame_size: usize,
+    finished: bool,
+}
+
+impl<S: RpcService> RpcServer<S> {
+    pub fn new(node_id: Uuid, service: Arc<S>, config: RpcConfig) -> Self {
+        Self {
+            node_id,
+            service,
+            config,
+            signing_keys: None,
+            signing_key: None,
+            sessions: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Refuse requests that are not signed by their sender
+    pub fn require_signatures(mut self, keys: SigningKeyLookup) -> Self {
+        self.signing_keys = Some(keys);
+        self
+    }
+
+    /// Sign profile accepts with `key`, so callers can tell the settings came from this node
+    pub fn with_signing_key(mut self, key: SigningKey) -> Self {
+        self.signing_key = Some(key);
+        self
+    }
+
+    async fn serve(
+        &self,
+        from: Uuid,
+        connection: &quinn::Connection,
+        send: &mut quinn::SendStream,
+        recv: &mut quinn::RecvStream,
+    ) -> Result<(), NetworkError> {
+        let max_frame_size = self.max_frame_size(from, connection);
+        let Some(mut request) = read_frame(recv, max_frame_size).await? else {
+            return Ok(());
+        };
+
+        // Replies are encoded the way the request was
+        let encoding = request.encoding;
+        if let Err(e) = self
+            .check_request(from, &request)
+            .and_then(|()| self.check_encoding(encoding))
+            .and_then(|()| decode_payload(&mut request, max_frame_size))
+        {
+            self.reply_error(send, &request, &e.to_string(), max_frame_size).await?;
+            return Err(e);
+        }
+
+        match request.message_type.clone() {
+            MessageType::Hello(remote) => {
+                let ack = MessageType::HelloAck {
+                    version: self.config.versions.negotiate(&remote),
+                    supported: self.config.versions,
+                };
+                let reply = request.reply(ack, self.node_id, Vec::new());
+                write_frame(send, &reply, max_frame_size).await
+            },
+            MessageType::ProfileOffer(remote) => {
+                let protocol = select_protocol(&self.config.capabilities, &remote);
+                let settings = ProtocolSettings::resolve(&protocol, &self.config.capabilities, &remote);
+                let accept = MessageType::ProfileAccept {
+                    protocol,
+                    capabilities: self.config.capabilities.clone(),
+                };
+                let mut reply = request.reply(accept, self.node_id, Vec::new());
+                if let Some(key) = &self.signing_key {
+                    reply.sign(key)?;
+                }
+                write_frame(send, &reply, max_frame_size).await?;
+                self.accept_profile(from, connection, settings);
+                Ok(())
+            },
+            MessageType::Request { method } => {
+                let reply = match self.service.call(from, &method, request.payload.clone()).await {
+                    Ok(response) => {
+                        let mut reply = request.reply(MessageType::Response, self.node_id, response);
+                        encode_payload(&mut reply, encoding)?;
+                        reply
+                    },
+                    Err(message) => request.reply(MessageType::Error, self.node_id, message.into_bytes()),
+                };
+                match write_frame(send, &reply, max_frame_size).await {
+                    Err(e @ NetworkError::MessageTooLarge { .. }) => {
+                        self.reply_error(send, &request, &e.to_string(), max_frame_size).await?;
+                        Err(e)
+                    },
+                    result => result,
+                }
+            },
+            MessageType::StreamRequest { method } => {
+                let mut sink = ResponseSink {
+                    send,
+                    request: &request,
+                    node_id: self.node_id,
+                    encoding,
+                    max_frame_size,
+                };
+                let result = self.service.stream(from, &method, request.payload.clone(), &mut sink).await;
+                let end = match result {
+                    Ok(()) => request.reply(MessageType::StreamEnd, self.node_id, Vec::new()),
+                    Err(message) => request.reply(MessageType::Error, self.node_id, message.into_bytes()),
+                };
+                write_frame(send, &end, max_frame_size).await
+            },
+            other => {
+                let message = format!("Unexpected {:?} frame", other);
+                self.reply_error(send, &request, &message, max_frame_size).await?;
+                Err(NetworkError::MalformedFrame(message))
+            },
+        }
+    }
+
+    fn check_request(&self, from: Uuid, request: &Envelope) -> Result<(), NetworkError> {
+        if request.sender != from {
+            return Err(NetworkError::SenderMismatch {
+                claimed: request.sender,
+                actual: from,
+            });
+        }
+        if matches!(request.message_type, MessageType::Hello(_)) {
+            return Ok(());
+        }
+        if !self.config.versions.contains(request.version) {
+            return Err(NetworkError::UnsupportedVersion(request.version));
+        }
+        if let Some(keys) = &self.signing_keys {
+            let key = keys(&from).ok_or(NetworkError::InvalidSignature { sender: from })?;
+            request.verify_signature(&key)?;
+        }
+        Ok(())
+    }
+
+    /// Refuse encodings this node does not offer
+    fn check_encoding(&self, encoding: PayloadEncoding) -> Result<(), NetworkError> {
+        if encoding.compression.is_some() && !self.config.capabilities.compression {
+            return Err(NetworkError::Payload("Compressed payloads are not accepted".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Frame limit agreed with a node on this connection, or our own before any agreement
+    fn max_frame_size(&self, from: Uuid, connection: &quinn::Connection) -> usize {
+        match self.sessions.lock().unwrap().get(&from) {
+            Some(accepted) if accepted.connection_id == connection.stable_id() => accepted.settings.max_frame_size,
+            _ => self.config.capabilities.max_frame_size,
+        }
+    }
+
+    /// Keep the settings accepted on a connection until it closes
+    fn accept_profile(&self, from: Uuid, connection: &quinn::Connection, settings: ProtocolSettings) {
+        let connection_id = connection.stable_id();
+        let replaced = self.sessions.lock().unwrap().insert(from, AcceptedProfile { connection_id, settings });
+        // A renegotiation on the same connection already has a task waiting for it to close
+        if replaced.is_some_and(|previous| previous.connection_id == connection_id) {
+            return;
+        }
+        let sessions = Arc::downgrade(&self.sessions);
+        let connection = connection.clone();
+        tokio::spawn(async move {
+            connection.closed().await;
+            let Some(sessions) = sessions.upgrade() else { return };
+            let mut sessions = sessions.lock().unwrap();
+            if sessions.get(&from).is_some_and(|accepted| accepted.connection_id == connection_id) {
+                sessions.remove(&from);
+            }
+        });
+    }
+
+    /// Errors go out unencoded so any caller can read them
+    async fn reply_error(
+        &self,
+        send: &mut quinn::SendStream,
+        request: &Envelope,
+        message: &str,
+        max_frame_size: usize,
+    ) -> Result<(), NetworkError> {
+        let reply = request.reply(MessageType::Error, self.node_id, message.as_bytes().to_vec());
+        write_frame(send, &reply, max_frame_size).await
+    }
+}
+
+impl<S: RpcService> StreamHandler for RpcServer<S> {
+    async fn handle_stream(
+        &self,
+        from: Uuid,
+        connection: quinn::Connection,
+        mut send: quinn::SendStream,
+        mut recv: quinn::RecvStream,
+    ) {
+        if let Err(e) = self.serve(from, &connection, &mut send, &mut recv).await {
+            tracing::debug!("RPC from node {} failed: {}", from, e);
+        }
+        let _ = send.finish().await;
+    }
+}
+
+impl ResponseSink<'_> {
+    pub async fn send(&mut self, item: Vec<u8>) -> Result<(), NetworkError> {
+        let mut frame = self.request.reply(MessageType::StreamItem, self.node_id, item);
+        encode_payload(&mut frame, self.encoding)?;
+        write_frame(self.send, &frame, self.max_frame_size).await
+    }
+}
+
+impl RpcClient {
+    pub fn new(transport: Arc<QuicTransport>, config: RpcConfig) -> Self {
+        Self {
+            transport,
+            config,
+            signing_key: None,
+            profile_keys: None,
+            health_monitor: None,
+            next_correlation_id: AtomicU64::new(1),
+            link_conditions: Mutex::new(HashMap::new()),
+            sessions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Sign every request with `key`
+    pub fn with_signing_key(mut self, key: SigningKey) -> Self {
+        self.signing_key = Some(key);
+        self
+    }
+
+    /// Refuse protocol settings a node did not sign
+    pub fn require_signatures(mut self, keys: SigningKeyLookup) -> Self {
+        self.profile_keys = Some(keys);
+        self
+    }
+
+    /// Take link conditions from `monitor`'s probes before each call
+    ///
+    /// A change that alters the agreed protocol renegotiates it, as with
+    /// `set_link_conditions`.
+    pub fn with_health_monitor(mut self, monitor: Arc<ConnectionHealthMonitor>) -> Self {
+        self.health_monitor = Some(monitor);
+        self
+    }
+
+    /// Call `method` on a node and wait for its response
+    pub async fn call(&self, node_id: Uuid, method: &str, request: Vec<u8>) -> Result<Vec<u8>, NetworkError> {
+        let message_type = MessageType::Request {
+            method: method.to_string(),
+        };
+        let (correlation_id, mut recv, session) = self.send_request(node_id, message_type, request).await?;
+        let max_frame_size = session.settings.max_frame_size;
+        let mut response = read_frame(&mut recv, max_frame_size)
+            .await?
+            .ok_or_else(|| NetworkError::MalformedFrame("Stream ended before a response".to_string()))?;
+        check_correlation(&response, correlation_id)?;
+
+        match response.message_type {
+            MessageType::Response => {
+                decode_payload(&mut response, max_frame_size)?;
+                Ok(response.payload)
+            },
+            _ => Err(unexpected_reply(&response)),
+        }
+    }
+
+    /// Call a streaming `method` on a node
+    pub async fn stream(&self, node_id: Uuid, method: &str, request: Vec<u8>) -> Result<ResponseStream, NetworkError> {
+        let message_type = MessageType::StreamRequest {
+            method: method.to_string(),
+        };
+        let (correlation_id, recv, session) = self.send_request(node_id, message_type, request).await?;
+        Ok(ResponseStream {
+            recv,
+            correlation_id,
+            max_frame_size: session.settings.max_frame_size,
+            finished: false,
+        })
+    }
+
+    /// Version used with a node, negotiating it if the connection is new
+    pub async fn negotiated_version(&self, node_id: Uuid) -> Result<u16, NetworkError> {
+        Ok(self.session(node_id).await?.version)
+    }
+
+    /// Protocol settings used with a node, negotiating them if the connection is new
+    pub async fn protocol(&self, node_id: Uuid) -> Result<ProtocolSettings, NetworkError> {
+        Ok(self.session(node_id).await?.settings)
+    }
+
+    /// Record new measurements of the link to a node
+    ///
+    /// Returns true if they change the agreed protocol, in which case it is
+    /// renegotiated before the next call.
+    pub fn set_link_conditions(&self, node_id: Uuid, conditions: LinkConditions) -> bool {
+        self.link_conditions.lock().unwrap().insert(node_id, conditions);
+
+        let mut sessions = self.sessions.lock().unwrap();
+        let Some(remote) = sessions.get(&node_id).and_then(|session| session.remote.clone()) else {
+            return false;
+        };
+        let local = self.local_capabilities(&node_id);
+        let settings = ProtocolSettings::resolve(&select_protocol(&local, &remote), &local, &remote);
+        if sessions.get(&node_id).is_some_and(|session| session.settings == settings) {
+            return false;
+        }
+        tracing::debug!("Link to node {} changed, renegotiating protocol", node_id);
+        sessions.remove(&node_id);
+        true
+    }
+
+    /// Drop what was agreed with a node so the next call negotiates again
+    pub fn renegotiate(&self, node_id: &Uuid) {
+        self.sessions.lock().unwrap().remove(node_id);
+    }
+
+    async fn session(&self, node_id: Uuid) -> Result<Session, NetworkError> {
+        if let Some(conditions) = self.health_monitor.as_ref().and_then(|monitor| monitor.link_conditions(&node_id)) {
+            if self.link_conditions.lock().unwrap().get(&node_id) != Some(&conditions) {
+                self.set_link_conditions(node_id, conditions);
+            }
+        }
+        let connection = self.transport.connect(node_id).await?;
+        if let Some(session) = self.sessions.lock().unwrap().get(&node_id) {
+            if session.connection.stable_id() == connection.stable_id() {
+                return Ok(session.clone());
+            }
+        }
+
+        let version = self.hello(&connection).await?;
+        let (settings, remote) = if version >= 2 {
+            let local = self.local_capabilities(&node_id);
+            let mut offer = Envelope::new(
+                version,
+                MessageType::ProfileOffer(local.clone()),
+                self.next_correlation_id(),
+                self.transport.node_id(),
+                Vec::new(),
+            );
+            self.sign(&mut offer)?;
+            let accept = self.exchange(&connection, &offer).await?;
+            self.check_accept(node_id, &accept)?;
+            match accept.message_type {
+                MessageType::ProfileAccept { protocol, capabilities } => {
+                    (ProtocolSettings::resolve(&protocol, &local, &capabilities), Some(capabilities))
+                },
+                _ => return Err(unexpected_reply(&accept)),
+            }
+        } else {
+            (ProtocolSettings::baseline(), None)
+        };
+
+        tracing::debug!("Agreed version {} and {:?} with node {}", version, settings.protocol, node_id);
+        let session = Session {
+            connection,
+            version,
+            settings,
+            remote,
+            keys_updated_at: Arc::new(AtomicU64::new(0)),
+        };
+        self.sessions.lock().unwrap().insert(node_id, session.clone());
+        Ok(session)
+    }
+
+    async fn hello(&self, connection: &quinn::Connection) -> Result<u16, NetworkError> {
+        let hello = Envelope::new(
+            MIN_PROTOCOL_VERSION,
+            MessageType::Hello(self.config.versions),
+            self.next_correlation_id(),
+            self.transport.node_id(),
+            Vec::new(),
+        );
+        let ack = self.exchange(connection, &hello).await?;
+        match ack.message_type {
+            MessageType::HelloAck {
+                version: Some(version),
+                ..
+            } if self.config.versions.contains(version) => Ok(version),
+            MessageType::HelloAck { supported, .. } => Err(NetworkError::VersionMismatch {
+                local: self.config.versions,
+                remote: supported,
+            }),
+            _ => Err(unexpected_reply(&ack)),
+        }
+    }
+
+    /// Send one handshake frame on a new stream and read the reply
+    async fn exchange(&self, connection: &quinn::Connection, envelope: &Envelope) -> Result<Envelope, NetworkError> {
+        let max_frame_size = self.config.capabilities.max_frame_size;
+        let (mut send, mut recv) = connection.open_bi().await?;
+        write_frame(&mut send, envelope, max_frame_size).await?;
+        send.finish().await?;
+        let reply = read_frame(&mut recv, max_frame_size)
+            .await?
+            .ok_or_else(|| NetworkError::MalformedFrame("Stream ended before a reply".to_string()))?;
+        check_correlation(&reply, envelope.correlation_id)?;
+        Ok(reply)
+    }
+
+    async fn send_request(
+        &self,
+        node_id: Uuid,
+        message_type: MessageType,
+        payload: Vec<u8>,
+    ) -> Result<(u64, quinn::RecvStream, Session), NetworkError> {
+        let session = self.session(node_id).await?;
+        let mut request = Envelope::new(session.version, message_type, self.next_correlation_id(), self.transport.node_id(), payload);
+        encode_payload(&mut request, session.settings.encoding())?;
+        self.sign(&mut request)?;
+        session.update_keys_if_due();
+
+        let (mut send, recv) = session.connection.open_bi().await?;
+        write_frame(&mut send, &request, session.settings.max_frame_size).await?;
+        send.finish().await?;
+        Ok((request.correlation_id, recv, session))
+    }
+
+    /// The accept must come from the node asked, signed by it if we know its key
+    fn check_accept(&self, node_id: Uuid, accept: &Envelope) -> Result<(), NetworkError> {
+        if accept.sender != node_id {
+            return Err(NetworkError::SenderMismatch {
+                claimed: accept.sender,
+                actual: node_id,
+            });
+        }
+        if matches!(accept.message_type, MessageType::Error) {
+            return Ok(());
+        }
+        if let Some(keys) = &self.profile_keys {
+            let key = keys(&node_id).ok_or(NetworkError::InvalidSignature { sender: node_id })?;
+            accept.verify_signature(&key)?;
+        }
+        Ok(())
+    }
+
+    fn sign(&self, envelope: &mut Envelope) -> Result<(), NetworkError> {
+        match &self.signing_key {
+            Some(key) => envelope.sign(key),
+            None => Ok(()),
+        }
+    }
+
+    /// Our capabilities as advertised to one node, with what we know of its link
+    fn local_capabilities(&self, node_id: &Uuid) -> NodeCapabilities {
+        let mut capabilities = self.config.capabilities.clone();
+        if let Some(conditions) = self.link_conditions.lock().unwrap().get(node_id) {
+            capabilities.conditions = *conditions;
+        }
+        capabilities
+    }
+
+    fn next_correlation_id(&self) -> u64 {
+        self.next_correlation_id.fetch_add(1, Ordering::Relaxed)
+    }
+}
+
+impl Session {
+    /// Update the connection's traffic keys once it has carried what the
+    /// agreed encryption level allows since the last update
+    fn update_keys_if_due(&self) {
+        let Some(limit) = self.settings.key_update_bytes else { return };
+        let stats = self.connection.stats();
+        let traffic = stats.udp_tx.bytes + stats.udp_rx.bytes;
+        let updated_at = self.keys_updated_at.load(Ordering::Relaxed);
+        if traffic.saturating_sub(updated_at) >= limit
+            && self
+                .keys_updated_at
+                .compare_exchange(updated_at, traffic, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok()
+        {
+            self.connection.force_key_update();
+        }
+    }
+}
+
+impl ResponseStream {
+    /// Next item, or `None` once the callee has ended the stream
+    pub async fn next(&mut self) -> Option<Result<Vec<u8>, NetworkError>> {
+        if self.finished {
+            return None;
+        }
+        let item = match read_frame(&mut self.recv, self.max_frame_size).await {
+            Ok(Some(mut frame)) => match check_correlation(&frame, self.correlation_id) {
+                Err(e) => Err(e),
+                Ok(()) => match frame.message_type {
+                    MessageType::StreamItem => match decode_payload(&mut frame, self.max_frame_size) {
+                        Ok(()) => return Some(Ok(frame.payload)),
+                        Err(e) => Err(e),
+                    },
+                    MessageType::StreamEnd => {
+                        self.finished = true;
+                        return None;
+                    },
+                    _ => Err(unexpected_reply(&frame)),
+                },
+            },
+            Ok(None) => Err(NetworkError::MalformedFrame("Stream ended before its end frame".to_string())),
+            Err(e) => Err(e),
+        };
+        self.finished = true;
+        Some(item)
+    }
+}
+
+fn check_correlation(response: &Envelope, correlation_id: u64) -> Result<(), NetworkError> {
+    if response.correlation_id != correlation_id {
+        return Err(NetworkError::MalformedFrame(format!(
+            "Response for request {} on the stream of request {}",
+            response.correlation_id, correlation_id
+        )));
+    }
+    Ok(())
+}
+
+/// Error for a reply of the wrong type, surfacing remote errors as such
+fn unexpected_reply(reply: &Envelope) -> NetworkError {
+    match &reply.message_type {
+        MessageType::Error => NetworkError::Remote(String::from_utf8_lossy(&reply.payload).into_owned()),
+        other => NetworkError::MalformedFrame(format!("Unexpected {:?} frame", other)),
+    }
+}
@@ -0,0 +1,397 @@
+//! Framed, versioned envelope for inter-node messages
+//!
+//! A frame is a 4-byte big-endian length, then a 2-byte big-endian protocol
+//! version, then the bincode-encoded envelope body for that version. The
+//! version sits outside the encoded body so a node can refuse a frame from a
+//! newer peer cleanly instead of misreading it.
+
+use crate::profile::NodeCapabilities;
+use crate::{CommunicationProtocol, CompressionLevel, NetworkError};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use uuid::Uuid;
+
+/// Newest protocol version this build speaks
+///
+/// Version 2 adds payload encoding and the profile handshake.
+pub const PROTOCOL_VERSION: u16 = 2;
+
+/// Oldest protocol version this build still speaks
+///
+/// Hello exchanges are always framed with this version so that any peer can
+/// read them.
+pub const MIN_PROTOCOL_VERSION: u16 = 1;
+
+pub const DEFAULT_MAX_FRAME_SIZE: usize = 16 * 1024 * 1024;
+
+const LENGTH_PREFIX: usize = 4;
+const VERSION_PREFIX: usize = 2;
+
+/// Inclusive range of protocol versions a node accepts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VersionRange {
+    pub min: u16,
+    pub max: u16,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MessageType {
+    /// Versions the connecting node speaks
+    Hello(VersionRange),
+    /// Version chosen for the connection, if any, and the versions the answering node speaks
+    HelloAck {
+        version: Option<u16>,
+        supported: VersionRange,
+    },
+    Request {
+        method: String,
+    },
+    Response,
+    /// Request answered by zero or more `StreamItem`s and a `StreamEnd`
+    StreamRequest {
+        method: String,
+    },
+    StreamItem,
+    StreamEnd,
+    /// Request failed; the payload is a UTF-8 message
+    Error,
+    /// Capabilities of the connecting node
+    ProfileOffer(NodeCapabilities),
+    /// Protocol chosen by the answering node, and its capabilities
+    ProfileAccept {
+        protocol: CommunicationProtocol,
+        capabilities: NodeCapabilities,
+    },
+}
+
+/// How a payload was transformed before sending; decoding reverses it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PayloadEncoding {
+    pub compression: Option<CompressionLevel>,
+}
+
+/// Message exchanged between nodes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Envelope {
+    pub version: u16,
+    pub message_type: MessageType,
+    /// Ties responses and stream items to their request
+    pub correlation_id: u64,
+    pub sender: Uuid,
+    pub encoding: PayloadEncoding,
+    pub payload: Vec<u8>,
+    /// ed25519 signature by the sender over everything else in the envelope
+    pub signature: Option<Vec<u8>>,
+}
+
+/// Body layout of protocol version 1
+#[derive(Serialize, Deserialize)]
+struct EnvelopeV1 {
+    message_type: MessageType,
+    correlation_id: u64,
+    sender: Uuid,
+    payload: Vec<u8>,
+    signature: Option<Vec<u8>>,
+}
+
+/// Body layout of protocol version 2
+#[derive(Serialize, Deserialize)]
+struct EnvelopeV2 {
+    message_type: MessageType,
+    correlation_id: u64,
+    sender: Uuid,
+    encoding: PayloadEncoding,
+    payload: Vec<u8>,
+    signature: Option<Vec<u8>>,
+}
+
+impl VersionRange {
+    /// Versions this build speaks
+    pub fn supported() -> Self {
+        Self {
+            min: MIN_PROTOCOL_VERSION,
+            max: PROTOCOL_VERSION,
+        }
+    }
+
+    pub fn contains(&self, version: u16) -> bool {
+        self.min <= version && version <= self.max
+    }
+
+    /// Highest version both ranges contain
+    pub fn negotiate(&self, other: &VersionRange) -> Option<u16> {
+        let version = self.max.min(other.max);
+        (version >= self.min.max(other.min)).then_some(version)
+    }
+}
+
+impl Default for VersionRange {
+    fn default() -> Self {
+        Self::supported()
+    }
+}
+
+impl fmt::Display for VersionRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "v{}-v{}", self.min, self.max)
+    }
+}
+
+impl Envelope {
+    pub fn new(version: u16, message_type: MessageType, correlation_id: u64, sender: Uuid, payload: Vec<u8>) -> Self {
+        Self {
+            version,
+            message_type,
+            correlation_id,
+            sender,
+            encoding: PayloadEncoding::default(),
+            payload,
+            signature: None,
+        }
+    }
+
+    /// Envelope answering this one, with the same version and correlation ID
+    pub fn reply(&self, message_type: MessageType, sender: Uuid, payload: Vec<u8>) -> Self {
+        Self::new(self.version, message_type, self.correlation_id, sender, payload)
+    }
+
+    pub fn sign(&mut self, key: &SigningKey) -> Result<(), NetworkError> {
+        let signature = key.sign(&self.signing_bytes()?);
+        self.signature = Some(signature.to_bytes().to_vec());
+        Ok(())
+    }
+
+    /// Check the signature against the sender's key; unsigned envelopes fail
+    pub fn verify_signature(&self, key: &VerifyingKey) -> Result<(), NetworkError> {
+        let invalid = || NetworkError::InvalidSignature { sender: self.sender };
+        let signature = self.signature.as_deref().ok_or_else(invalid)?;
+        let signature = Signature::from_bytes(signature.try_into().map_err(|_| invalid())?);
+        key.verify(&self.signing_bytes()?, &signature).map_err(|_| invalid())
+    }
+
+    /// Encode as a complete frame, length prefix included
+    pub fn encode(&self) -> Result<Vec<u8>, NetworkError> {
+        let body = match self.version {
+            1 if self.encoding != PayloadEncoding::default() => {
+                return Err(NetworkError::MalformedFrame("Payload encoding needs protocol version 2".to_string()))
+            },
+            1 => bincode::serialize(&EnvelopeV1 {
+                message_type: self.message_type.clone(),
+                correlation_id: self.correlation_id,
+                sender: self.sender,
+                payload: self.payload.clone(),
+                signature: self.signature.clone(),
+            })?,
+            2 => bincode::serialize(&EnvelopeV2 {
+                message_type: self.message_type.clone(),
+                correlation_id: self.correlation_id,
+                sender: self.sender,
+                encoding: self.encoding,
+                payload: self.payload.clone(),
+                signature: self.signature.clone(),
+            })?,
+            version => return Err(NetworkError::UnsupportedVersion(version)),
+        };
+
+        let length = VERSION_PREFIX + body.len();
+        let mut frame = Vec::with_capacity(LENGTH_PREFIX + length);
+        frame.extend_from_slice(&(length as u32).to_be_bytes());
+        frame.extend_from_slice(&self.version.to_be_bytes());
+        frame.extend_from_slice(&body);
+        Ok(frame)
+    }
+
+    /// Decode a frame with its length prefix already removed
+    pub fn decode(frame: &[u8]) -> Result<Self, NetworkError> {
+        if frame.len() < VERSION_PREFIX {
+            return Err(NetworkError::MalformedFrame(format!("{} byte frame has no version", frame.len())));
+        }
+        let version = u16::from_be_bytes([frame[0], frame[1]]);
+        let body = &frame[VERSION_PREFIX..];
+        match version {
+            1 => {
+                let envelope: EnvelopeV1 = bincode::deserialize(body)?;
+                Ok(Self {
+                    version,
+                    message_type: envelope.message_type,
+                    correlation_id: envelope.correlation_id,
+                    sender: envelope.sender,
+                    encoding: PayloadEncoding::default(),
+                    payload: envelope.payload,
+                    signature: envelope.signature,
+                })
+            },
+            2 => {
+                let envelope: EnvelopeV2 = bincode::deserialize(body)?;
+                Ok(Self {
+                    version,
+                    message_type: envelope.message_type,
+                    correlation_id: envelope.correlation_id,
+                    sender: envelope.sender,
+                    encoding: envelope.encoding,
+                    payload: envelope.payload,
+                    signature: envelope.signature,
+                })
+            },
+            version => Err(NetworkError::UnsupportedVersion(version)),
+        }
+    }
+
+    fn signing_bytes(&self) -> Result<Vec<u8>, NetworkError> {
+        let header = (self.version, &self.message_type, self.correlation_id, self.sender);
+        // Version 1 signatures must stay verifiable by version 1 nodes
+        Ok(match self.version {
+            1 => bincode::serialize(&(header, &self.payload))?,
+            _ => bincode::serialize(&(header, self.encoding, &self.payload))?,
+        })
+    }
+}
+
+/// Write one envelope as a frame
+pub async fn write_frame<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    envelope: &Envelope,
+    max_frame_size: usize,
+) -> Result<(), NetworkError> {
+    let frame = envelope.encode()?;
+    let size = frame.len() - LENGTH_PREFIX;
+    if size > max_frame_size {
+        return Err(NetworkError::MessageTooLarge {
+            size,
+            limit: max_frame_size,
+        });
+    }
+    writer.write_all(&frame).await?;
+    Ok(())
+}
+
+/// Read the next frame, or `None` if the stream ended cleanly between frames
+pub async fn read_frame<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    max_frame_size: usize,
+) -> Result<Option<Envelope>, NetworkError> {
+    let mut length = [0u8; LENGTH_PREFIX];
+    let mut filled = 0;
+    while filled < LENGTH_PREFIX {
+        match reader.read(&mut length[filled..]).await? {
+            0 if filled == 0 => return Ok(None),
+            0 => return Err(NetworkError::MalformedFrame("Stream ended inside a length prefix".to_string())),
+            n => filled += n,
+        }
+    }
+
+    let size = u32::from_be_bytes(length) as usize;
+    if size > max_frame_size {
+        return Err(NetworkError::MessageTooLarge {
+            size,
+            limit: max_frame_size,
+        });
+    }
+    let mut frame = vec![0u8; size];
+    reader.read_exact(&mut frame).await?;
+    Envelope::decode(&frame).map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signing_key(seed: u8) -> SigningKey {
+        SigningKey::from_bytes(&[seed; 32])
+    }
+
+    fn request(version: u16) -> Envelope {
+        Envelope::new(
+            version,
+            MessageType::Request {
+                method: "storage.read".to_string(),
+            },
+            42,
+            Uuid::new_v4(),
+            b"payload".to_vec(),
+        )
+    }
+
+    #[test]
+    fn test_version_negotiation() {
+        let range = |min, max| VersionRange { min, max };
+
+        // Rolling upgrade: the newer node falls back to the older one's version
+        assert_eq!(range(1, 2).negotiate(&range(1, 1)), Some(1));
+        assert_eq!(range(1, 1).negotiate(&range(1, 2)), Some(1));
+        assert_eq!(range(2, 3).negotiate(&range(1, 2)), Some(2));
+        assert_eq!(range(1, 3).negotiate(&range(2, 5)), Some(3));
+        // Once the old version is retired the ranges no longer overlap
+        assert_eq!(range(3, 4).negotiate(&range(1, 2)), None);
+    }
+
+    #[tokio::test]
+    async fn test_frames_round_trip() {
+        let (mut client, mut server) = tokio::io::duplex(4096);
+        let first = request(PROTOCOL_VERSION);
+        let second = first.reply(MessageType::StreamEnd, Uuid::new_v4(), Vec::new());
+
+        write_frame(&mut client, &first, DEFAULT_MAX_FRAME_SIZE).await.unwrap();
+        write_frame(&mut client, &second, DEFAULT_MAX_FRAME_SIZE).await.unwrap();
+        drop(client);
+
+        assert_eq!(read_frame(&mut server, DEFAULT_MAX_FRAME_SIZE).await.unwrap(), Some(first));
+        let decoded = read_frame(&mut server, DEFAULT_MAX_FRAME_SIZE).await.unwrap().unwrap();
+        assert_eq!(decoded.correlation_id, 42);
+        assert_eq!(decoded, second);
+        assert_eq!(read_frame(&mut server, DEFAULT_MAX_FRAME_SIZE).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_rejects_unknown_versions_and_oversized_frames() {
+        assert!(matches!(request(9).encode(), Err(NetworkError::UnsupportedVersion(9))));
+
+        // Version 1 frames have no room for a payload encoding
+        let mut encoded = request(1);
+        encoded.encoding.compression = Some(CompressionLevel::Low);
+        assert!(matches!(encoded.encode(), Err(NetworkError::MalformedFrame(_))));
+        encoded.version = 2;
+        assert_eq!(Envelope::decode(&encoded.encode().unwrap()[LENGTH_PREFIX..]).unwrap(), encoded);
+        let v1 = request(1);
+        assert_eq!(Envelope::decode(&v1.encode().unwrap()[LENGTH_PREFIX..]).unwrap(), v1);
+
+        // A frame from a newer node is refused by version, not misparsed
+        let mut frame = request(PROTOCOL_VERSION).encode().unwrap();
+        frame[LENGTH_PREFIX..LENGTH_PREFIX + VERSION_PREFIX].copy_from_slice(&9u16.to_be_bytes());
+        assert!(matches!(Envelope::decode(&frame[LENGTH_PREFIX..]), Err(NetworkError::UnsupportedVersion(9))));
+
+        let (mut client, mut server) = tokio::io::duplex(4096);
+        let large = Envelope::new(PROTOCOL_VERSION, MessageType::Response, 1, Uuid::new_v4(), vec![0u8; 1024]);
+        assert!(matches!(
+            write_frame(&mut client, &large, 512).await,
+            Err(NetworkError::MessageTooLarge { limit: 512, .. })
+        ));
+        write_frame(&mut client, &large, DEFAULT_MAX_FRAME_SIZE).await.unwrap();
+        assert!(matches!(
+            read_frame(&mut server, 512).await,
+            Err(NetworkError::MessageTooLarge { limit: 512, .. })
+        ));
+    }
+
+    #[test]
+    fn test_signatures() {
+        let key = signing_key(7);
+        let mut envelope = request(PROTOCOL_VERSION);
+        assert!(envelope.verify_signature(&key.verifying_key()).is_err());
+
+        envelope.sign(&key).unwrap();
+        let decoded = Envelope::decode(&envelope.encode().unwrap()[LENGTH_PREFIX..]).unwrap();
+        decoded.verify_signature(&key.verifying_key()).unwrap();
+
+        let other = signing_key(8);
+        assert!(decoded.verify_signature(&other.verifying_key()).is_err());
+        let mut tampered = decoded;
+        tampered.payload = b"other".to_vec();
+        assert!(matches!(
+            tampered.verify_signature(&key.verifying_key()),
+            Err(NetworkError::InvalidSignature { .. })
+        ));
+    }
+}
@@ -0,0 +1,41 @@
+//! Loopback nodes shared by the networking integration tests
+
+use mycnet_networking::{NetworkError, NodeAddress, NodeIdentity, QuicTransport, StaticResolver};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use uuid::Uuid;
+
+pub struct Node {
+    pub identity: NodeIdentity,
+    pub resolver: Arc<StaticResolver>,
+    pub transport: Arc<QuicTransport>,
+}
+
+/// Start a node with a new identity on a free loopback port
+///
+/// `bind` creates the transport from the identity, address and resolver, so
+/// each test picks its own handler and configuration.
+pub fn start_node(
+    bind: impl FnOnce(NodeIdentity, SocketAddr, Arc<StaticResolver>) -> Result<Arc<QuicTransport>, NetworkError>,
+) -> Node {
+    let identity = NodeIdentity::generate(Uuid::new_v4()).unwrap();
+    let resolver = Arc::new(StaticResolver::new());
+    let transport = bind(identity.clone(), SocketAddr::from(([127, 0, 0, 1], 0)), resolver.clone()).unwrap();
+    Node {
+        identity,
+        resolver,
+        transport,
+    }
+}
+
+/// Address record other nodes use to dial `node`
+pub fn address_of(node: &Node) -> NodeAddress {
+    let address = node.transport.local_addr().unwrap().to_string();
+    NodeAddress::from_spore_entry(node.identity.node_id, &[address], node.identity.fingerprint())
+}
+
+/// Let two nodes resolve each other
+pub fn introduce(a: &Node, b: &Node) {
+    a.resolver.insert(address_of(b));
+    b.resolver.insert(address_of(a));
+}
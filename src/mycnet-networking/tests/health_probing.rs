@@ -0,0 +1,150 @@
+//! Health probing tests between nodes on the loopback interface
+
+mod common;
+
+use common::{introduce, start_node, Node};
+use mycnet_networking::{
+    ConnectionHealthMonitor, ConnectionSelectionStrategy, HealthConfig, HealthEvent, MultiHomingManager, QuicConfig, QuicTransport,
+    RequestHandler,
+};
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Answers with the node's name
+struct Name(&'static str);
+
+impl RequestHandler for Name {
+    async fn handle(&self, _from: Uuid, _request: Vec<u8>) -> Vec<u8> {
+        self.0.as_bytes().to_vec()
+    }
+}
+
+fn start_named(name: &'static str) -> Node {
+    start_node(|identity, address, resolver| {
+        let config = QuicConfig {
+            idle_timeout: Duration::from_secs(1),
+            keep_alive_interval: Duration::from_millis(250),
+            ..QuicConfig::default()
+        };
+        QuicTransport::bind(identity, address, resolver, Arc::new(Name(name)), config)
+    })
+}
+
+fn fast_probes() -> HealthConfig {
+    HealthConfig {
+        probe_interval: Duration::from_millis(100),
+        probe_timeout: Duration::from_millis(300),
+        failure_threshold: 2,
+        recovery_threshold: 2,
+        ..HealthConfig::default()
+    }
+}
+
+#[tokio::test]
+async fn test_probes_measure_live_links() {
+    let a = start_named("a");
+    let b = start_named("b");
+    introduce(&a, &b);
+
+    let monitor = Arc::new(ConnectionHealthMonitor::with_transport(a.transport.clone(), fast_probes()));
+    monitor.watch(b.identity.node_id);
+    monitor.start();
+    // Throughput is measured once a probe has come back
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while monitor.health(&b.identity.node_id).is_none_or(|health| health.throughput_mbps == 0) {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap();
+
+    let health = monitor.health(&b.identity.node_id).unwrap();
+    assert!(health.is_healthy);
+    assert_eq!(health.packet_loss_percent, 0.0);
+    assert!(health.latency_ms < 100);
+    assert!(health.throughput_mbps > 0);
+    let conditions = monitor.link_conditions(&b.identity.node_id).unwrap();
+    assert_eq!(conditions.bandwidth_mbps, health.throughput_mbps);
+
+    monitor.stop();
+    a.transport.shutdown().await;
+    b.transport.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_monitor_restarts_after_stop() {
+    let a = start_named("a");
+    let b = start_named("b");
+    introduce(&a, &b);
+
+    let monitor = Arc::new(ConnectionHealthMonitor::with_transport(a.transport.clone(), fast_probes()));
+    monitor.watch(b.identity.node_id);
+    let last_check = || monitor.health(&b.identity.node_id).map(|health| health.last_check);
+    let probed_after = |since| async move {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while last_check() <= since {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+    };
+
+    monitor.start();
+    probed_after(None).await;
+
+    // A probe already sent may still land, but no new ones go out
+    monitor.stop();
+    tokio::time::sleep(Duration::from_millis(400)).await;
+    let stopped_at = last_check();
+    tokio::time::sleep(Duration::from_millis(400)).await;
+    assert_eq!(last_check(), stopped_at);
+
+    monitor.start();
+    probed_after(stopped_at).await;
+
+    monitor.stop();
+    a.transport.shutdown().await;
+    b.transport.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_stopped_node_is_reported_and_avoided() {
+    let a = start_named("a");
+    let b = start_named("b");
+    let c = start_named("c");
+    introduce(&a, &b);
+    introduce(&a, &c);
+
+    let mut manager = MultiHomingManager::with_transport(ConnectionSelectionStrategy::RoundRobin, a.transport.clone())
+        .with_health_config(fast_probes());
+    let mut events = manager.health_monitor().subscribe();
+    let endpoint = manager
+        .establish_multi_homed_connections(vec![b.identity.node_id, c.identity.node_id])
+        .await
+        .unwrap();
+    assert_eq!(endpoint.healthy_connections().len(), 2);
+
+    b.transport.shutdown().await;
+    let event = tokio::time::timeout(Duration::from_secs(5), events.recv()).await.unwrap().unwrap();
+    assert!(matches!(event, HealthEvent::Unhealthy { node_id, .. } if node_id == b.identity.node_id));
+    assert!(!manager.health_monitor().is_healthy(&b.identity.node_id));
+
+    // The endpoint hears about it from the same broadcast
+    tokio::time::timeout(Duration::from_secs(1), async {
+        while endpoint.healthy_connections().len() != 1 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap();
+    assert_eq!(endpoint.healthy_connections()[0].node_id, c.identity.node_id);
+    for _ in 0..2 {
+        assert_eq!(endpoint.route_request(b"ping").await.unwrap(), b"c");
+    }
+
+    manager.health_monitor().stop();
+    a.transport.shutdown().await;
+    c.transport.shutdown().await;
+}
@@ -0,0 +1,202 @@
+//! QUIC transport tests between nodes on the loopback interface
+
+mod common;
+
+use common::{address_of, introduce, start_node, Node};
+use mycnet_networking::{
+    ConnectionSelectionStrategy, MultiHomingManager, NetworkError, NodeAddress, QuicConfig, QuicTransport, RequestHandler,
+};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Answers with the request prefixed by the node's name
+struct Echo {
+    name: &'static str,
+    delay: Duration,
+    /// Requests being handled right now
+    handling: Arc<AtomicUsize>,
+}
+
+impl RequestHandler for Echo {
+    async fn handle(&self, _from: Uuid, request: Vec<u8>) -> Vec<u8> {
+        self.handling.fetch_add(1, Ordering::SeqCst);
+        tokio::time::sleep(self.delay).await;
+        self.handling.fetch_sub(1, Ordering::SeqCst);
+        let mut response = self.name.as_bytes().to_vec();
+        response.extend_from_slice(&request);
+        response
+    }
+}
+
+/// Dials to stopped nodes time out quickly
+fn quick_timeouts() -> QuicConfig {
+    QuicConfig {
+        idle_timeout: Duration::from_secs(1),
+        keep_alive_interval: Duration::from_millis(250),
+        ..QuicConfig::default()
+    }
+}
+
+fn start_echo(name: &'static str, delay: Duration) -> Node {
+    start_echo_with(name, delay, quick_timeouts()).0
+}
+
+/// Start an echo node, with the count of requests it is handling
+fn start_echo_with(name: &'static str, delay: Duration, config: QuicConfig) -> (Node, Arc<AtomicUsize>) {
+    let handling = Arc::new(AtomicUsize::new(0));
+    let handler = Echo {
+        name,
+        delay,
+        handling: handling.clone(),
+    };
+    let node = start_node(|identity, address, resolver| QuicTransport::bind(identity, address, resolver, Arc::new(handler), config));
+    (node, handling)
+}
+
+/// Wait until `condition` holds, failing the test after a few seconds
+async fn wait_for(condition: impl Fn() -> bool) {
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while !condition() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn test_request_response_reuses_connection() {
+    let a = start_echo("a:", Duration::ZERO);
+    let b = start_echo("b:", Duration::ZERO);
+    introduce(&a, &b);
+
+    let response = a.transport.request(b.identity.node_id, b"ping").await.unwrap();
+    assert_eq!(response, b"b:ping");
+    let connection = a.transport.connect(b.identity.node_id).await.unwrap();
+
+    // Concurrent requests share the connection on separate streams
+    let requests: Vec<_> = (0..8)
+        .map(|i| {
+            let transport = a.transport.clone();
+            let node_id = b.identity.node_id;
+            tokio::spawn(async move { transport.request(node_id, format!("{}", i).as_bytes()).await })
+        })
+        .collect();
+    for (i, request) in requests.into_iter().enumerate() {
+        assert_eq!(request.await.unwrap().unwrap(), format!("b:{}", i).into_bytes());
+    }
+    assert_eq!(a.transport.connect(b.identity.node_id).await.unwrap().stable_id(), connection.stable_id());
+
+    // The dialed node answers back over the same connection instead of dialing
+    let response = b.transport.request(a.identity.node_id, b"pong").await.unwrap();
+    assert_eq!(response, b"a:pong");
+    assert_eq!(b.transport.connected_nodes(), vec![a.identity.node_id]);
+
+    a.transport.shutdown().await;
+    b.transport.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_unknown_and_impersonating_nodes_are_rejected() {
+    let a = start_echo("a:", Duration::ZERO);
+    let b = start_echo("b:", Duration::ZERO);
+
+    // No address published for b
+    let err = a.transport.request(b.identity.node_id, b"ping").await.unwrap_err();
+    assert!(matches!(err, NetworkError::UnknownNode(id) if id == b.identity.node_id));
+
+    // b does not know a, so a's client certificate is refused
+    a.resolver.insert(address_of(&b));
+    assert!(a.transport.request(b.identity.node_id, b"ping").await.is_err());
+
+    // A node answering at b's address with another certificate is not b
+    let impostor = start_echo("x:", Duration::ZERO);
+    impostor.resolver.insert(address_of(&a));
+    a.resolver.insert(NodeAddress {
+        addresses: vec![impostor.transport.local_addr().unwrap()],
+        ..address_of(&b)
+    });
+    assert!(a.transport.request(b.identity.node_id, b"ping").await.is_err());
+
+    for node in [a, b, impostor] {
+        node.transport.shutdown().await;
+    }
+}
+
+#[tokio::test]
+async fn test_shutdown_drains_in_flight_requests() {
+    let a = start_echo("a:", Duration::ZERO);
+    let (b, handling) = start_echo_with("b:", Duration::from_millis(200), quick_timeouts());
+    introduce(&a, &b);
+
+    let transport = a.transport.clone();
+    let node_id = b.identity.node_id;
+    let in_flight = tokio::spawn(async move { transport.request(node_id, b"slow").await });
+    wait_for(|| handling.load(Ordering::SeqCst) == 1).await;
+
+    b.transport.shutdown().await;
+    assert_eq!(in_flight.await.unwrap().unwrap(), b"b:slow");
+    assert!(b.transport.connected_nodes().is_empty());
+    assert!(matches!(b.transport.request(a.identity.node_id, b"ping").await, Err(NetworkError::Shutdown)));
+    assert!(a.transport.request(b.identity.node_id, b"ping").await.is_err());
+
+    a.transport.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_slow_requests_time_out() {
+    let config = QuicConfig {
+        request_timeout: Duration::from_millis(100),
+        ..QuicConfig::default()
+    };
+    let (a, _) = start_echo_with("a:", Duration::ZERO, config);
+    let b = start_echo("b:", Duration::from_millis(500));
+    introduce(&a, &b);
+
+    let transport = a.transport.clone();
+    let node_id = b.identity.node_id;
+    let slow = tokio::spawn(async move { transport.request(node_id, b"slow").await });
+    wait_for(|| a.transport.in_flight(&node_id) == 1).await;
+
+    let err = slow.await.unwrap().unwrap_err();
+    assert!(matches!(err, NetworkError::Timeout { node_id: id, .. } if id == node_id));
+    assert_eq!(a.transport.in_flight(&node_id), 0);
+
+    a.transport.shutdown().await;
+    b.transport.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_virtual_endpoint_fails_over() {
+    let client = start_echo("client:", Duration::ZERO);
+    let primary = start_echo("primary:", Duration::ZERO);
+    let secondary = start_echo("secondary:", Duration::ZERO);
+    introduce(&client, &primary);
+    introduce(&client, &secondary);
+
+    let mut manager = MultiHomingManager::with_transport(ConnectionSelectionStrategy::LatencyBased, client.transport.clone());
+    let endpoint = manager
+        .establish_multi_homed_connections(vec![primary.identity.node_id, secondary.identity.node_id, Uuid::new_v4()])
+        .await
+        .unwrap();
+    assert_eq!(endpoint.physical_connections.len(), 2);
+    assert!(endpoint.physical_connections.iter().all(|conn| conn.addresses[0].ip().is_loopback()));
+
+    primary.transport.shutdown().await;
+    for _ in 0..2 {
+        assert_eq!(endpoint.route_request(b"ping").await.unwrap(), b"secondary:ping");
+    }
+
+    secondary.transport.shutdown().await;
+    assert!(endpoint.route_request(b"ping").await.is_err());
+    client.transport.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_manager_without_transport_cannot_connect() {
+    let mut manager = MultiHomingManager::new(ConnectionSelectionStrategy::RoundRobin);
+    let result = manager.establish_multi_homed_connections(vec![Uuid::new_v4()]).await;
+    assert!(matches!(result, Err(NetworkError::NoConnections)));
+}
@@ -0,0 +1,350 @@
+//! RPC tests between nodes on the loopback interface
+
+mod common;
+
+use common::{introduce, start_node, Node};
+use ed25519_dalek::SigningKey;
+use mycnet_networking::rpc::SigningKeyLookup;
+use mycnet_networking::wire::{read_frame, write_frame, DEFAULT_MAX_FRAME_SIZE};
+use mycnet_networking::{
+    AdaptationStrategy, CommunicationProtocol, CompressionLevel, ConnectionHealthMonitor, ConnectionSelectionStrategy, Envelope,
+    HealthConfig, LinkConditions, MessageType, MultiHomingManager, NetworkError, NodeCapabilities, NodeTier, ProtocolSettings, QuicConfig, QuicTransport,
+    ResponseSink, RpcClient, RpcConfig, RpcServer, RpcService, VersionRange, PROTOCOL_VERSION,
+};
+use std::sync::Arc;
+use uuid::Uuid;
+
+struct TestService;
+
+impl RpcService for TestService {
+    async fn call(&self, from: Uuid, method: &str, request: Vec<u8>) -> Result<Vec<u8>, String> {
+        match method {
+            "echo" => Ok(request),
+            "whoami" => Ok(from.as_bytes().to_vec()),
+            _ => Err(format!("Unknown method {}", method)),
+        }
+    }
+
+    async fn stream(&self, _from: Uuid, method: &str, request: Vec<u8>, sink: &mut ResponseSink<'_>) -> Result<(), String> {
+        if method != "count" {
+            return Err(format!("Unknown method {}", method));
+        }
+        for i in 0..request[0] {
+            sink.send(vec![i]).await.map_err(|e| e.to_string())?;
+        }
+        if request.get(1) == Some(&1) {
+            return Err("Stopped early".to_string());
+        }
+        Ok(())
+    }
+}
+
+fn start_rpc_node(server: impl FnOnce(Uuid) -> RpcServer<TestService>) -> Node {
+    start_node(|identity, address, resolver| {
+        let server = Arc::new(server(identity.node_id));
+        QuicTransport::bind_streams(identity, address, resolver, server, QuicConfig::default())
+    })
+}
+
+fn default_server(node_id: Uuid) -> RpcServer<TestService> {
+    RpcServer::new(node_id, Arc::new(TestService), RpcConfig::default())
+}
+
+fn tier_config(tier: NodeTier) -> RpcConfig {
+    RpcConfig {
+        capabilities: NodeCapabilities::new(tier),
+        ..RpcConfig::default()
+    }
+}
+
+fn tier_server(tier: NodeTier) -> impl FnOnce(Uuid) -> RpcServer<TestService> {
+    move |node_id| RpcServer::new(node_id, Arc::new(TestService), tier_config(tier))
+}
+
+fn signing_key(seed: u8) -> SigningKey {
+    SigningKey::from_bytes(&[seed; 32])
+}
+
+#[tokio::test]
+async fn test_calls_and_streams() {
+    let a = start_rpc_node(default_server);
+    let b = start_rpc_node(default_server);
+    introduce(&a, &b);
+    let client = RpcClient::new(a.transport.clone(), RpcConfig::default());
+
+    assert_eq!(client.call(b.identity.node_id, "echo", b"hello".to_vec()).await.unwrap(), b"hello");
+    assert_eq!(
+        client.call(b.identity.node_id, "whoami", Vec::new()).await.unwrap(),
+        a.identity.node_id.as_bytes().to_vec()
+    );
+    assert!(matches!(
+        client.call(b.identity.node_id, "missing", Vec::new()).await,
+        Err(NetworkError::Remote(message)) if message.contains("missing")
+    ));
+    assert_eq!(client.negotiated_version(b.identity.node_id).await.unwrap(), PROTOCOL_VERSION);
+
+    let mut items = client.stream(b.identity.node_id, "count", vec![3]).await.unwrap();
+    let mut received = Vec::new();
+    while let Some(item) = items.next().await {
+        received.push(item.unwrap());
+    }
+    assert_eq!(received, vec![vec![0], vec![1], vec![2]]);
+
+    // Items sent before a failure still arrive, followed by the error
+    let mut items = client.stream(b.identity.node_id, "count", vec![2, 1]).await.unwrap();
+    assert_eq!(items.next().await.unwrap().unwrap(), vec![0]);
+    assert_eq!(items.next().await.unwrap().unwrap(), vec![1]);
+    assert!(matches!(items.next().await, Some(Err(NetworkError::Remote(_)))));
+    assert!(items.next().await.is_none());
+
+    a.transport.shutdown().await;
+    b.transport.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_version_negotiation_across_releases() {
+    // b still runs an older release pinned to version 1
+    let a = start_rpc_node(default_server);
+    let b = start_rpc_node(|node_id| {
+        RpcServer::new(
+            node_id,
+            Arc::new(TestService),
+            RpcConfig {
+                versions: VersionRange { min: 1, max: 1 },
+                ..RpcConfig::default()
+            },
+        )
+    });
+    introduce(&a, &b);
+
+    let client = RpcClient::new(a.transport.clone(), RpcConfig::default());
+    assert_eq!(client.negotiated_version(b.identity.node_id).await.unwrap(), 1);
+    assert_eq!(client.call(b.identity.node_id, "echo", vec![1]).await.unwrap(), vec![1]);
+    // Version 1 has no profile handshake, so payloads travel as they are
+    assert_eq!(client.protocol(b.identity.node_id).await.unwrap(), ProtocolSettings::baseline());
+
+    // A node that has retired version 1 cannot talk to b
+    let newer = RpcClient::new(
+        a.transport.clone(),
+        RpcConfig {
+            versions: VersionRange { min: 2, max: 3 },
+            ..RpcConfig::default()
+        },
+    );
+    let err = newer.call(b.identity.node_id, "echo", vec![1]).await.unwrap_err();
+    assert!(matches!(
+        err,
+        NetworkError::VersionMismatch { remote, .. } if remote == VersionRange { min: 1, max: 1 }
+    ));
+
+    a.transport.shutdown().await;
+    b.transport.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_signatures_and_sender_checks() {
+    let signer = signing_key(3);
+    let public = signer.verifying_key();
+    let a = start_rpc_node(default_server);
+    let b = start_rpc_node(move |node_id| {
+        let keys: SigningKeyLookup = Arc::new(move |_| Some(public));
+        default_server(node_id).require_signatures(keys)
+    });
+    introduce(&a, &b);
+
+    let unsigned = RpcClient::new(a.transport.clone(), RpcConfig::default());
+    assert!(matches!(
+        unsigned.call(b.identity.node_id, "echo", vec![1]).await,
+        Err(NetworkError::Remote(_))
+    ));
+    let signed = RpcClient::new(a.transport.clone(), RpcConfig::default()).with_signing_key(signing_key(3));
+    assert_eq!(signed.call(b.identity.node_id, "echo", vec![1]).await.unwrap(), vec![1]);
+
+    // A request claiming another sender than the authenticated peer is refused
+    let connection = a.transport.connect(b.identity.node_id).await.unwrap();
+    let (mut send, mut recv) = connection.open_bi().await.unwrap();
+    let mut forged = Envelope::new(
+        PROTOCOL_VERSION,
+        MessageType::Request {
+            method: "echo".to_string(),
+        },
+        7,
+        Uuid::new_v4(),
+        vec![1],
+    );
+    forged.sign(&signer).unwrap();
+    write_frame(&mut send, &forged, DEFAULT_MAX_FRAME_SIZE).await.unwrap();
+    send.finish().await.unwrap();
+    let reply = read_frame(&mut recv, DEFAULT_MAX_FRAME_SIZE).await.unwrap().unwrap();
+    assert_eq!(reply.message_type, MessageType::Error);
+    assert_eq!(reply.correlation_id, 7);
+    assert_eq!(reply.sender, b.identity.node_id);
+
+    a.transport.shutdown().await;
+    b.transport.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_profile_accepts_are_signed() {
+    let signer = signing_key(5);
+    let public = signer.verifying_key();
+    let a = start_rpc_node(default_server);
+    let b = start_rpc_node(move |node_id| default_server(node_id).with_signing_key(signer));
+    let unsigned = start_rpc_node(default_server);
+    introduce(&a, &b);
+    introduce(&a, &unsigned);
+
+    let keys: SigningKeyLookup = Arc::new(move |_| Some(public));
+    let client = RpcClient::new(a.transport.clone(), RpcConfig::default()).require_signatures(keys.clone());
+    assert_eq!(client.call(b.identity.node_id, "echo", vec![1]).await.unwrap(), vec![1]);
+    assert!(matches!(
+        client.protocol(unsigned.identity.node_id).await,
+        Err(NetworkError::InvalidSignature { sender }) if sender == unsigned.identity.node_id
+    ));
+
+    // An accept signed with another node's key is refused too
+    let wrong: SigningKeyLookup = Arc::new(|_| Some(signing_key(6).verifying_key()));
+    let client = RpcClient::new(a.transport.clone(), RpcConfig::default()).require_signatures(wrong);
+    assert!(matches!(client.protocol(b.identity.node_id).await, Err(NetworkError::InvalidSignature { .. })));
+
+    for node in [a, b, unsigned] {
+        node.transport.shutdown().await;
+    }
+}
+
+#[tokio::test]
+async fn test_sclerotia_links_use_high_performance_settings() {
+    let a = start_rpc_node(tier_server(NodeTier::Sclerotia));
+    let b = start_rpc_node(tier_server(NodeTier::Sclerotia));
+    introduce(&a, &b);
+    let client = RpcClient::new(a.transport.clone(), tier_config(NodeTier::Sclerotia));
+
+    let settings = client.protocol(b.identity.node_id).await.unwrap();
+    assert!(matches!(settings.protocol, CommunicationProtocol::HighPerformance { .. }));
+    assert_eq!(settings.compression, Some(CompressionLevel::Low));
+
+    let payload = vec![7u8; 64 * 1024];
+    assert_eq!(client.call(b.identity.node_id, "echo", payload.clone()).await.unwrap(), payload);
+    let mut items = client.stream(b.identity.node_id, "count", vec![2]).await.unwrap();
+    assert_eq!(items.next().await.unwrap().unwrap(), vec![0]);
+    assert_eq!(items.next().await.unwrap().unwrap(), vec![1]);
+    assert!(items.next().await.is_none());
+
+    a.transport.shutdown().await;
+    b.transport.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_multi_homed_connections_record_the_negotiated_protocol() {
+    let a = start_rpc_node(tier_server(NodeTier::Sclerotia));
+    let b = start_rpc_node(tier_server(NodeTier::Sclerotia));
+    introduce(&a, &b);
+    let client = Arc::new(RpcClient::new(a.transport.clone(), tier_config(NodeTier::Sclerotia)));
+    let mut manager = MultiHomingManager::with_transport(ConnectionSelectionStrategy::RoundRobin, a.transport.clone())
+        .with_rpc_client(client.clone());
+
+    let endpoint = manager.establish_multi_homed_connections(vec![b.identity.node_id]).await.unwrap();
+    let settings = client.protocol(b.identity.node_id).await.unwrap();
+    assert!(matches!(settings.protocol, CommunicationProtocol::HighPerformance { .. }));
+    assert_eq!(endpoint.physical_connections[0].protocol, settings.protocol);
+
+    a.transport.shutdown().await;
+    b.transport.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_hyphae_links_use_small_frames() {
+    let a = start_rpc_node(tier_server(NodeTier::Hyphae));
+    let b = start_rpc_node(tier_server(NodeTier::Sclerotia));
+    introduce(&a, &b);
+    let client = RpcClient::new(a.transport.clone(), tier_config(NodeTier::Hyphae));
+
+    let settings = client.protocol(b.identity.node_id).await.unwrap();
+    assert!(matches!(settings.protocol, CommunicationProtocol::Lightweight { .. }));
+    assert_eq!(client.call(b.identity.node_id, "echo", vec![1; 1024]).await.unwrap(), vec![1; 1024]);
+
+    // Incompressible payloads over the lightweight frame limit are refused before sending
+    let mut large = vec![0u8; 2 * settings.max_frame_size];
+    for (i, byte) in large.iter_mut().enumerate() {
+        *byte = (i * 7919 % 251) as u8 ^ (i >> 8) as u8;
+    }
+    assert!(matches!(
+        client.call(b.identity.node_id, "echo", large.clone()).await,
+        Err(NetworkError::MessageTooLarge { .. })
+    ));
+
+    // The server holds the connection to the agreed limit as well
+    let connection = a.transport.connect(b.identity.node_id).await.unwrap();
+    let (mut send, mut recv) = connection.open_bi().await.unwrap();
+    let method = MessageType::Request {
+        method: "echo".to_string(),
+    };
+    let oversized = Envelope::new(PROTOCOL_VERSION, method, 9, a.identity.node_id, large);
+    // It stops reading past the limit, so the write may already fail
+    if write_frame(&mut send, &oversized, DEFAULT_MAX_FRAME_SIZE).await.is_ok() {
+        let _ = send.finish().await;
+    }
+    assert!(!matches!(read_frame(&mut recv, DEFAULT_MAX_FRAME_SIZE).await, Ok(Some(_))));
+
+    a.transport.shutdown().await;
+    b.transport.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_link_conditions_renegotiate_adaptive_links() {
+    let a = start_rpc_node(tier_server(NodeTier::Sclerotia));
+    let b = start_rpc_node(tier_server(NodeTier::Rhizomorph));
+    introduce(&a, &b);
+    let client = RpcClient::new(a.transport.clone(), tier_config(NodeTier::Sclerotia));
+
+    let before = client.protocol(b.identity.node_id).await.unwrap();
+    assert!(matches!(before.protocol, CommunicationProtocol::Adaptive { .. }));
+
+    let changed = client.set_link_conditions(
+        b.identity.node_id,
+        LinkConditions {
+            latency_ms: 0,
+            bandwidth_mbps: 5,
+        },
+    );
+    assert!(changed);
+    let after = client.protocol(b.identity.node_id).await.unwrap();
+    assert_ne!(after.compression, before.compression);
+    assert_eq!(client.call(b.identity.node_id, "echo", vec![3; 4096]).await.unwrap(), vec![3; 4096]);
+
+    a.transport.shutdown().await;
+    b.transport.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_health_probes_feed_link_conditions() {
+    let a = start_rpc_node(tier_server(NodeTier::Sclerotia));
+    let b = start_rpc_node(tier_server(NodeTier::Rhizomorph));
+    introduce(&a, &b);
+    let monitor = Arc::new(ConnectionHealthMonitor::with_transport(a.transport.clone(), HealthConfig::default()));
+    let client = RpcClient::new(a.transport.clone(), tier_config(NodeTier::Sclerotia)).with_health_monitor(monitor.clone());
+
+    let before = client.protocol(b.identity.node_id).await.unwrap();
+    assert!(matches!(
+        before.protocol,
+        CommunicationProtocol::Adaptive {
+            adaptation_strategy: AdaptationStrategy::CapabilityBased,
+            ..
+        }
+    ));
+
+    // Once the link has been measured the next call adapts to it
+    assert!(monitor.check_connection_health(b.identity.node_id).await.is_healthy);
+    let after = client.protocol(b.identity.node_id).await.unwrap();
+    assert!(!matches!(
+        after.protocol,
+        CommunicationProtocol::Adaptive {
+            adaptation_strategy: AdaptationStrategy::CapabilityBased,
+            ..
+        }
+    ));
+    assert_eq!(client.call(b.identity.node_id, "echo", vec![4; 64]).await.unwrap(), vec![4; 64]);
+
+    a.transport.shutdown().await;
+    b.transport.shutdown().await;
+}
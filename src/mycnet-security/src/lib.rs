@@ -15,10 +15,10 @@ pub struct NetworkIdentity {
 }
 
 /// Node authentication credentials
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct NodeCredentials {
     pub node_id: Uuid,
-    pub signing_keypair: ed25519_dalek::Keypair,
+    pub signing_keypair: ed25519_dalek::SigningKey,
     pub encryption_keypair: x25519_dalek::StaticSecret,
     pub network_membership_proof: Vec<u8>,
 }
@@ -28,6 +28,7 @@ pub struct TrustManager {
     trust_scores: HashMap<Uuid, TrustScore>,
     trust_policies: Vec<TrustPolicy>,
     consensus_participation: HashMap<Uuid, ParticipationMetrics>,
+    contribution_penalties: HashMap<Uuid, f32>,
 }
 
 /// Trust score with components
@@ -51,7 +52,7 @@ pub struct TrustPolicy {
 }
 
 /// Access levels based on trust
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum AccessLevel {
     Full,           // Full network access
     Standard,       // Standard operations
@@ -72,9 +73,6 @@ pub struct ParticipationMetrics {
 
 /// Secure channel for encrypted communication
 pub struct SecureChannel {
-    local_secret: x25519_dalek::StaticSecret,
-    remote_public: x25519_dalek::PublicKey,
-    shared_secret: [u8; 32],
     cipher: chacha20poly1305::ChaCha20Poly1305,
 }
 
@@ -82,7 +80,7 @@ pub struct SecureChannel {
 pub struct AuthenticationManager {
     network_identity: NetworkIdentity,
     node_credentials: NodeCredentials,
-    trusted_nodes: HashMap<Uuid, ed25519_dalek::PublicKey>,
+    trusted_nodes: HashMap<Uuid, ed25519_dalek::VerifyingKey>,
 }
 
 impl NetworkIdentity {
@@ -101,7 +99,7 @@ impl NetworkIdentity {
     }
     
     /// Validate network membership cryptographically
-    pub fn validate_membership(&self, node_proof: &[u8], signature: &ed25519_dalek::Signature, public_key: &ed25519_dalek::PublicKey) -> bool {
+    pub fn validate_membership(&self, node_proof: &[u8], signature: &ed25519_dalek::Signature, public_key: &ed25519_dalek::VerifyingKey) -> bool {
         use ed25519_dalek::Verifier;
         
         // Verify signature
@@ -122,13 +120,13 @@ impl NodeCredentials {
     pub fn generate_for_network(network_identity: &NetworkIdentity) -> Self {
         let mut csprng = rand::rngs::OsRng;
         
-        let signing_keypair = ed25519_dalek::Keypair::generate(&mut csprng);
-        let encryption_keypair = x25519_dalek::StaticSecret::new(&mut csprng);
+        let signing_keypair = ed25519_dalek::SigningKey::generate(&mut csprng);
+        let encryption_keypair = x25519_dalek::StaticSecret::random_from_rng(csprng);
         
         // Create network membership proof
         let mut proof_data = Vec::new();
         proof_data.extend_from_slice(&network_identity.isolation_key);
-        proof_data.extend_from_slice(signing_keypair.public.as_bytes());
+        proof_data.extend_from_slice(signing_keypair.verifying_key().as_bytes());
         
         let network_membership_proof = blake3::hash(&proof_data).as_bytes().to_vec();
         
@@ -147,8 +145,8 @@ impl NodeCredentials {
     }
     
     /// Get public signing key
-    pub fn public_signing_key(&self) -> ed25519_dalek::PublicKey {
-        self.signing_keypair.public
+    pub fn public_signing_key(&self) -> ed25519_dalek::VerifyingKey {
+        self.signing_keypair.verifying_key()
     }
     
     /// Get public encryption key
@@ -157,6 +155,12 @@ impl NodeCredentials {
     }
 }
 
+impl Default for TrustManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl TrustManager {
     /// Create new trust manager
     pub fn new() -> Self {
@@ -164,6 +168,7 @@ impl TrustManager {
             trust_scores: HashMap::new(),
             trust_policies: Vec::new(),
             consensus_participation: HashMap::new(),
+            contribution_penalties: HashMap::new(),
         }
     }
     
@@ -182,15 +187,18 @@ impl TrustManager {
         };
         
         let uptime_score = if let Some(metrics) = participation {
-            (metrics.network_uptime_hours / (24.0 * 30.0)).min(1.0) // Max 30 days
+            (metrics.network_uptime_hours / (24.0 * 30.0)).min(1.0) as f32 // Max 30 days
         } else {
             0.5
         };
         
+        // Neutral until a node is caught misbehaving, e.g. serving corrupt data
+        let contribution_score = (0.5 - self.contribution_penalties.get(&node_id).copied().unwrap_or(0.0)).clamp(0.0, 1.0);
+        
         let trust_score = TrustScore {
-            overall_score: (consensus_score + uptime_score) / 2.0,
+            overall_score: (consensus_score + uptime_score + contribution_score) / 3.0,
             consensus_participation: consensus_score,
-            network_contribution: 0.5, // Placeholder
+            network_contribution: contribution_score,
             uptime_reliability: uptime_score,
             security_compliance: 1.0, // Placeholder
             last_updated: chrono::Utc::now(),
@@ -220,15 +228,29 @@ impl TrustManager {
         metrics.last_participation = chrono::Utc::now();
     }
     
+    /// Lower a node's network contribution, applied from its next evaluation on
+    pub fn penalize_network_contribution(&mut self, node_id: Uuid, penalty: f32) {
+        *self.contribution_penalties.entry(node_id).or_insert(0.0) += penalty.max(0.0);
+    }
+    
+    /// Add a policy overriding the default minimum score for its access level
+    pub fn add_trust_policy(&mut self, policy: TrustPolicy) {
+        self.trust_policies.retain(|existing| existing.access_level != policy.access_level);
+        self.trust_policies.push(policy);
+    }
+    
     /// Check if node meets trust policy requirements
     pub fn check_access_permission(&self, node_id: &Uuid, required_access: AccessLevel) -> bool {
         if let Some(trust_score) = self.trust_scores.get(node_id) {
-            let required_score = match required_access {
-                AccessLevel::Full => 0.9,
-                AccessLevel::Standard => 0.7,
-                AccessLevel::Limited => 0.5,
-                AccessLevel::ReadOnly => 0.3,
-                AccessLevel::Restricted => 0.1,
+            let policy = self.trust_policies.iter()
+                .find(|policy| policy.access_level == required_access);
+            let required_score = match (policy, required_access) {
+                (Some(policy), _) => policy.minimum_trust_score,
+                (None, AccessLevel::Full) => 0.9,
+                (None, AccessLevel::Standard) => 0.7,
+                (None, AccessLevel::Limited) => 0.5,
+                (None, AccessLevel::ReadOnly) => 0.3,
+                (None, AccessLevel::Restricted) => 0.1,
             };
             
             trust_score.overall_score >= required_score
@@ -246,14 +268,9 @@ impl SecureChannel {
         // Derive encryption key from shared secret
         let key_material = blake3::hash(shared_secret.as_bytes());
         let key = chacha20poly1305::Key::from_slice(key_material.as_bytes());
-        let cipher = chacha20poly1305::ChaCha20Poly1305::new(key);
+        let cipher = <chacha20poly1305::ChaCha20Poly1305 as chacha20poly1305::KeyInit>::new(key);
         
-        Ok(Self {
-            local_secret,
-            remote_public,
-            shared_secret: *shared_secret.as_bytes(),
-            cipher,
-        })
+        Ok(Self { cipher })
     }
     
     /// Encrypt message for secure transmission
@@ -294,7 +311,7 @@ impl AuthenticationManager {
     }
     
     /// Authenticate remote node
-    pub fn authenticate_node(&mut self, node_id: Uuid, public_key: ed25519_dalek::PublicKey, membership_proof: &[u8], signature: &ed25519_dalek::Signature) -> bool {
+    pub fn authenticate_node(&mut self, node_id: Uuid, public_key: ed25519_dalek::VerifyingKey, membership_proof: &[u8], signature: &ed25519_dalek::Signature) -> bool {
         // Validate network membership
         if !self.network_identity.validate_membership(membership_proof, signature, &public_key) {
             return false;
@@ -308,10 +325,15 @@ impl AuthenticationManager {
     /// Create authentication challenge for remote node
     pub fn create_auth_challenge(&self) -> Vec<u8> {
         let mut challenge = Vec::new();
-        challenge.extend_from_slice(&self.network_identity.network_id.as_bytes());
+        challenge.extend_from_slice(self.network_identity.network_id.as_bytes());
         challenge.extend_from_slice(&chrono::Utc::now().timestamp().to_be_bytes());
         challenge
     }
+    
+    /// Sign a challenge received from a remote node
+    pub fn respond_to_challenge(&self, challenge: &[u8]) -> ed25519_dalek::Signature {
+        self.node_credentials.sign_message(challenge)
+    }
 }
 
 #[cfg(test)]
@@ -342,4 +364,34 @@ mod tests {
         let trust_score = trust_manager.evaluate_trust(node_id);
         assert_eq!(trust_score.overall_score, 0.5); // Default score for new nodes
     }
+    
+    #[test]
+    fn test_trust_policy_overrides_default_minimum() {
+        let mut trust_manager = TrustManager::new();
+        let node_id = Uuid::new_v4();
+        trust_manager.evaluate_trust(node_id);
+        assert!(!trust_manager.check_access_permission(&node_id, AccessLevel::Standard));
+        
+        trust_manager.add_trust_policy(TrustPolicy {
+            policy_id: Uuid::new_v4(),
+            minimum_trust_score: 0.4,
+            required_capabilities: Vec::new(),
+            access_level: AccessLevel::Standard,
+        });
+        assert!(trust_manager.check_access_permission(&node_id, AccessLevel::Standard));
+    }
+    
+    #[test]
+    fn test_challenge_response_verifies_with_node_key() {
+        use ed25519_dalek::Verifier;
+        
+        let network = NetworkIdentity::new_genesis("test".to_string(), vec![]);
+        let credentials = NodeCredentials::generate_for_network(&network);
+        let public_key = credentials.public_signing_key();
+        let auth_manager = AuthenticationManager::new(network, credentials);
+        
+        let challenge = auth_manager.create_auth_challenge();
+        let signature = auth_manager.respond_to_challenge(&challenge);
+        assert!(public_key.verify(&challenge, &signature).is_ok());
+    }
 }
\ No newline at end of file
@@ -63,20 +63,25 @@ pub struct SporeSystem {
 
 /// Primary spore implementation (Raft-based)
 pub struct PrimarySpore {
-    data: SporeData,
-    raft_node: Option<()>, // Placeholder for Raft implementation
+    pub data: SporeData,
 }
 
 /// Seed spore implementation (file-based)
 pub struct SeedSpore {
-    data: SporeData,
-    storage_path: std::path::PathBuf,
+    pub data: SporeData,
+    pub storage_path: std::path::PathBuf,
 }
 
 /// Latent spore implementation (gossip-based)
 pub struct LatentSpore {
-    data: SporeData,
-    gossip_peers: Vec<String>,
+    pub data: SporeData,
+    pub gossip_peers: Vec<String>,
+}
+
+impl Default for SporeSystem {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl SporeSystem {
@@ -109,11 +114,25 @@ impl SporeSystem {
         
         self.primary_spore = Some(PrimarySpore {
             data: spore_data,
-            raft_node: None,
         });
         
         Ok(())
     }
+    
+    /// Primary spore, once the system is initialized
+    pub fn primary_spore(&self) -> Option<&PrimarySpore> {
+        self.primary_spore.as_ref()
+    }
+    
+    /// Seed spores kept on disk
+    pub fn seed_spores(&self) -> &[SeedSpore] {
+        &self.seed_spores
+    }
+    
+    /// Latent spore shared over gossip
+    pub fn latent_spore(&self) -> &LatentSpore {
+        &self.latent_spore
+    }
 }
 
 impl SporeData {
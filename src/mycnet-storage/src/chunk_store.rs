@@ -0,0 +1,557 @@
+//! Content-addressed chunk store persisted in sled
+
+use crate::StorageError;
+use serde::{Deserialize, Serialize};
+use sled::transaction::{ConflictableTransactionError, TransactionError, Transactional};
+use std::ops::Range;
+use uuid::Uuid;
+
+/// blake3 hash identifying a chunk's contents
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct ChunkId(pub [u8; 32]);
+
+/// Chunk reference within a volume manifest
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChunkRef {
+    pub id: ChunkId,
+    pub length: u32,
+}
+
+/// Ordered list of chunks making up a volume's contents
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VolumeManifest {
+    pub volume_id: Uuid,
+    pub chunks: Vec<ChunkRef>,
+    pub total_size: u64,
+}
+
+/// Bounds for content-defined chunking
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChunkingConfig {
+    pub min_size: usize,
+    /// Target average size; rounded up to a power of two for the boundary mask
+    pub avg_size: usize,
+    pub max_size: usize,
+}
+
+/// Result of a garbage collection pass
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GcStats {
+    pub chunks_removed: usize,
+    pub bytes_reclaimed: u64,
+}
+
+/// Result of re-hashing a range of stored chunks
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VerifyBatch {
+    pub checked: usize,
+    pub bytes: u64,
+    /// Chunks whose contents no longer match their ID
+    pub corrupted: Vec<ChunkId>,
+    /// Cursor to resume from; `None` once the end of the store is reached
+    pub next: Option<ChunkId>,
+}
+
+/// Local chunk store shared by every volume on a node
+///
+/// Chunks are deduplicated by content and reference counted by the manifests
+/// that use them. Chunks whose count drops to zero stay on disk until
+/// `collect_garbage` runs.
+pub struct ChunkStore {
+    chunks: sled::Tree,
+    refs: sled::Tree,
+    manifests: sled::Tree,
+    metadata: sled::Tree,
+    config: ChunkingConfig,
+}
+
+/// Key and value of a metadata record
+pub type MetadataRecord = (Vec<u8>, Vec<u8>);
+
+/// Gear table for the rolling hash, fixed so chunk boundaries are stable across nodes
+const GEAR: [u64; 256] = build_gear_table();
+
+const fn build_gear_table() -> [u64; 256] {
+    // splitmix64 sequence
+    let mut table = [0u64; 256];
+    let mut state: u64 = 0x6d79_636e_6574_2d63;
+    let mut i = 0;
+    while i < 256 {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+}
+
+impl ChunkId {
+    /// Hash chunk contents
+    pub fn of(data: &[u8]) -> Self {
+        Self(*blake3::hash(data).as_bytes())
+    }
+}
+
+impl std::fmt::Display for ChunkId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", blake3::Hash::from(self.0).to_hex())
+    }
+}
+
+impl Default for ChunkingConfig {
+    fn default() -> Self {
+        Self {
+            min_size: 2 * 1024,
+            avg_size: 8 * 1024,
+            max_size: 64 * 1024,
+        }
+    }
+}
+
+/// Split data into content-defined chunk ranges using a gear rolling hash
+///
+/// Boundaries depend only on nearby bytes, so an insert or delete only changes
+/// the chunks around the edit and the rest still deduplicate.
+pub fn chunk_boundaries(data: &[u8], config: &ChunkingConfig) -> Vec<Range<usize>> {
+    let mask = (config.avg_size.max(2).next_power_of_two() - 1) as u64;
+    let min_size = config.min_size.max(1);
+    let max_size = config.max_size.max(min_size);
+
+    let mut ranges = Vec::new();
+    let mut start = 0;
+
+    while start < data.len() {
+        let remaining = data.len() - start;
+        let mut end = start + remaining.min(max_size);
+
+        if remaining > min_size {
+            let mut hash: u64 = 0;
+            for (offset, byte) in data[start..end].iter().enumerate().skip(min_size) {
+                hash = (hash << 1).wrapping_add(GEAR[*byte as usize]);
+                if hash & mask == 0 {
+                    end = start + offset + 1;
+                    break;
+                }
+            }
+        }
+
+        ranges.push(start..end);
+        start = end;
+    }
+
+    ranges
+}
+
+impl ChunkStore {
+    /// Open or create a store at a path
+    pub fn open(path: impl AsRef<std::path::Path>) -> Result<Self, StorageError> {
+        Self::from_db(sled::open(path)?)
+    }
+
+    /// Create a store that is deleted when dropped
+    pub fn temporary() -> Result<Self, StorageError> {
+        Self::from_db(sled::Config::new().temporary(true).open()?)
+    }
+
+    fn from_db(db: sled::Db) -> Result<Self, StorageError> {
+        Ok(Self {
+            chunks: db.open_tree("chunks")?,
+            refs: db.open_tree("chunk-refs")?,
+            manifests: db.open_tree("volume-manifests")?,
+            metadata: db.open_tree("volume-metadata")?,
+            config: ChunkingConfig::default(),
+        })
+    }
+
+    /// Replace the chunking bounds used for new writes
+    pub fn with_chunking(mut self, config: ChunkingConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Store a single chunk and take a reference to it
+    pub fn put_chunk(&self, data: &[u8]) -> Result<ChunkId, StorageError> {
+        let id = ChunkId::of(data);
+        run_transaction((&self.chunks, &self.refs).transaction(|(chunks, refs)| {
+            if !stored_copy_intact(chunks.get(id.0)?.as_deref(), &id) {
+                chunks.insert(&id.0, data)?;
+            }
+            let count = decode_count(refs.get(id.0)?.as_deref());
+            refs.insert(&id.0, &(count + 1).to_be_bytes())?;
+            Ok(())
+        }))?;
+        Ok(id)
+    }
+
+    /// Read a chunk, verifying its contents against its ID
+    pub fn get_chunk(&self, id: &ChunkId) -> Result<Vec<u8>, StorageError> {
+        let data = self.chunks
+            .get(id.0)?
+            .ok_or_else(|| StorageError::ChunkNotFound(id.to_string()))?;
+
+        if ChunkId::of(&data) != *id {
+            tracing::warn!("Chunk {} failed integrity verification", id);
+            return Err(StorageError::ChunkCorrupted(id.to_string()));
+        }
+        Ok(data.to_vec())
+    }
+
+    /// Drop one reference to a chunk
+    pub fn release_chunk(&self, id: &ChunkId) -> Result<u64, StorageError> {
+        run_transaction(self.refs.transaction(|refs| {
+            let count = decode_count(refs.get(id.0)?.as_deref()).saturating_sub(1);
+            if count == 0 {
+                refs.remove(&id.0)?;
+            } else {
+                refs.insert(&id.0, &count.to_be_bytes())?;
+            }
+            Ok(count)
+        }))
+    }
+
+    /// Re-hash up to `limit` chunks stored after `after`, in key order
+    pub fn verify_batch(&self, after: Option<&ChunkId>, limit: usize) -> Result<VerifyBatch, StorageError> {
+        let range = match after {
+            Some(id) => self.chunks.range((std::ops::Bound::Excluded(id.0.to_vec()), std::ops::Bound::Unbounded)),
+            None => self.chunks.range::<Vec<u8>, _>(..),
+        };
+
+        let mut batch = VerifyBatch::default();
+        let mut last = None;
+        for item in range.take(limit) {
+            let (key, value) = item?;
+            let id = ChunkId(key.as_ref().try_into().map_err(|_| StorageError::ChunkCorrupted(format!("{:?}", key)))?);
+            if ChunkId::of(&value) != id {
+                tracing::warn!("Chunk {} failed integrity verification", id);
+                batch.corrupted.push(id);
+            }
+            batch.checked += 1;
+            batch.bytes += value.len() as u64;
+            last = Some(id);
+        }
+
+        if batch.checked == limit {
+            batch.next = last;
+        }
+        Ok(batch)
+    }
+
+    /// Replace a chunk's bytes with a verified copy, keeping its references
+    pub fn restore_chunk(&self, id: &ChunkId, data: &[u8]) -> Result<(), StorageError> {
+        if ChunkId::of(data) != *id {
+            return Err(StorageError::ChunkCorrupted(id.to_string()));
+        }
+        self.chunks.insert(id.0, data)?;
+        Ok(())
+    }
+
+    /// Read a chunk's raw bytes without verification
+    pub(crate) fn raw_chunk(&self, id: &ChunkId) -> Result<Option<Vec<u8>>, StorageError> {
+        Ok(self.chunks.get(id.0)?.map(|data| data.to_vec()))
+    }
+
+    /// Simulate on-disk corruption in tests
+    #[cfg(test)]
+    pub(crate) fn overwrite_raw_chunk(&self, id: &ChunkId, data: &[u8]) {
+        self.chunks.insert(id.0, data).unwrap();
+    }
+
+    /// Whether a chunk's bytes are present
+    pub fn contains(&self, id: &ChunkId) -> Result<bool, StorageError> {
+        Ok(self.chunks.contains_key(id.0)?)
+    }
+
+    /// Number of manifest references to a chunk
+    pub fn reference_count(&self, id: &ChunkId) -> Result<u64, StorageError> {
+        Ok(decode_count(self.refs.get(id.0)?.as_deref()))
+    }
+
+    /// Chunk and store a volume's contents, replacing any previous manifest
+    pub fn write_volume(&self, volume_id: Uuid, data: &[u8]) -> Result<VolumeManifest, StorageError> {
+        let pieces: Vec<&[u8]> = chunk_boundaries(data, &self.config).into_iter().map(|range| &data[range]).collect();
+        let manifest = VolumeManifest {
+            volume_id,
+            chunks: pieces
+                .iter()
+                .map(|piece| {
+                    Ok(ChunkRef {
+                        id: ChunkId::of(piece),
+                        length: u32::try_from(piece.len()).map_err(|_| StorageError::ChunkTooLarge(piece.len()))?,
+                    })
+                })
+                .collect::<Result<_, StorageError>>()?,
+            total_size: data.len() as u64,
+        };
+        let encoded = bincode::serialize(&manifest)?;
+
+        run_transaction((&self.chunks, &self.refs, &self.manifests).transaction(|(chunks, refs, manifests)| {
+            for (chunk, piece) in manifest.chunks.iter().zip(&pieces) {
+                if !stored_copy_intact(chunks.get(chunk.id.0)?.as_deref(), &chunk.id) {
+                    chunks.insert(&chunk.id.0, *piece)?;
+                }
+                let count = decode_count(refs.get(chunk.id.0)?.as_deref());
+                refs.insert(&chunk.id.0, &(count + 1).to_be_bytes())?;
+            }
+
+            // References from the replaced manifest are dropped after the new ones are taken
+            if let Some(previous) = manifests.insert(volume_id.as_bytes(), encoded.as_slice())? {
+                let previous: VolumeManifest = bincode::deserialize(&previous)
+                    .map_err(|e| ConflictableTransactionError::Abort(StorageError::from(e)))?;
+                for chunk in previous.chunks {
+                    let count = decode_count(refs.get(chunk.id.0)?.as_deref()).saturating_sub(1);
+                    if count == 0 {
+                        refs.remove(&chunk.id.0)?;
+                    } else {
+                        refs.insert(&chunk.id.0, &count.to_be_bytes())?;
+                    }
+                }
+            }
+            Ok(())
+        }))?;
+
+        Ok(manifest)
+    }
+
+    /// Get a volume's manifest
+    pub fn manifest(&self, volume_id: &Uuid) -> Result<Option<VolumeManifest>, StorageError> {
+        match self.manifests.get(volume_id.as_bytes())? {
+            Some(value) => Ok(Some(bincode::deserialize(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Reassemble a volume's contents, verifying every chunk
+    pub fn read_volume(&self, volume_id: &Uuid) -> Result<Vec<u8>, StorageError> {
+        let manifest = self.manifest(volume_id)?.ok_or(StorageError::VolumeNotFound(*volume_id))?;
+
+        let mut data = Vec::with_capacity(manifest.total_size as usize);
+        for chunk in &manifest.chunks {
+            data.extend_from_slice(&self.get_chunk(&chunk.id)?);
+        }
+        Ok(data)
+    }
+
+    /// Remove a volume's manifest and release its chunks
+    pub fn delete_volume(&self, volume_id: &Uuid) -> Result<VolumeManifest, StorageError> {
+        let manifest = self.manifest(volume_id)?.ok_or(StorageError::VolumeNotFound(*volume_id))?;
+
+        run_transaction((&self.refs, &self.manifests).transaction(|(refs, manifests)| {
+            manifests.remove(volume_id.as_bytes())?;
+            for chunk in &manifest.chunks {
+                let count = decode_count(refs.get(chunk.id.0)?.as_deref()).saturating_sub(1);
+                if count == 0 {
+                    refs.remove(&chunk.id.0)?;
+                } else {
+                    refs.insert(&chunk.id.0, &count.to_be_bytes())?;
+                }
+            }
+            Ok(())
+        }))?;
+
+        Ok(manifest)
+    }
+
+    /// Record a second volume over the same chunks as `source`
+    ///
+    /// Only the manifest is copied; every chunk gains a reference. Writing
+    /// either volume afterwards replaces just that volume's manifest, so the
+    /// other keeps the chunks it was copied with.
+    pub fn copy_volume(&self, source: &Uuid, target: Uuid) -> Result<VolumeManifest, StorageError> {
+        run_transaction((&self.refs, &self.manifests).transaction(|(refs, manifests)| {
+            let encoded = manifests
+                .get(source.as_bytes())?
+                .ok_or(ConflictableTransactionError::Abort(StorageError::VolumeNotFound(*source)))?;
+            if manifests.get(target.as_bytes())?.is_some() {
+                return Err(ConflictableTransactionError::Abort(StorageError::VolumeAlreadyAllocated(target)));
+            }
+
+            let mut manifest: VolumeManifest = bincode::deserialize(&encoded)
+                .map_err(|e| ConflictableTransactionError::Abort(StorageError::from(e)))?;
+            manifest.volume_id = target;
+            let encoded = bincode::serialize(&manifest).map_err(|e| ConflictableTransactionError::Abort(StorageError::from(e)))?;
+
+            for chunk in &manifest.chunks {
+                let count = decode_count(refs.get(chunk.id.0)?.as_deref());
+                refs.insert(&chunk.id.0, &(count + 1).to_be_bytes())?;
+            }
+            manifests.insert(target.as_bytes(), encoded)?;
+            Ok(manifest)
+        }))
+    }
+
+    /// Delete chunks that no manifest or caller references
+    pub fn collect_garbage(&self) -> Result<GcStats, StorageError> {
+        let mut stats = GcStats::default();
+
+        for item in self.chunks.iter() {
+            let (key, _) = item?;
+            // Re-check under a transaction so a concurrent put cannot lose its chunk
+            let removed = run_transaction((&self.chunks, &self.refs).transaction(|(chunks, refs)| {
+                if refs.get(&key)?.is_some() {
+                    return Ok(None);
+                }
+                Ok(chunks.remove(&key)?.map(|data| data.len() as u64))
+            }))?;
+
+            if let Some(bytes) = removed {
+                stats.chunks_removed += 1;
+                stats.bytes_reclaimed += bytes;
+            }
+        }
+
+        if stats.chunks_removed > 0 {
+            tracing::info!("Garbage collected {} chunks ({} bytes)", stats.chunks_removed, stats.bytes_reclaimed);
+        }
+        Ok(stats)
+    }
+
+    /// Store a small metadata record, such as a volume's version, next to the chunks
+    pub fn put_metadata(&self, key: &[u8], value: &[u8]) -> Result<(), StorageError> {
+        self.metadata.insert(key, value)?;
+        Ok(())
+    }
+
+    /// Get a metadata record
+    pub fn metadata(&self, key: &[u8]) -> Result<Option<Vec<u8>>, StorageError> {
+        Ok(self.metadata.get(key)?.map(|value| value.to_vec()))
+    }
+
+    /// Remove a metadata record; removing an absent one succeeds
+    pub fn remove_metadata(&self, key: &[u8]) -> Result<(), StorageError> {
+        self.metadata.remove(key)?;
+        Ok(())
+    }
+
+    /// All metadata records whose key starts with `prefix`
+    pub fn metadata_with_prefix(&self, prefix: &[u8]) -> Result<Vec<MetadataRecord>, StorageError> {
+        self.metadata
+            .scan_prefix(prefix)
+            .map(|item| {
+                let (key, value) = item?;
+                Ok((key.to_vec(), value.to_vec()))
+            })
+            .collect()
+    }
+
+    /// Flush pending writes to disk
+    pub fn flush(&self) -> Result<(), StorageError> {
+        self.chunks.flush()?;
+        self.refs.flush()?;
+        self.manifests.flush()?;
+        self.metadata.flush()?;
+        Ok(())
+    }
+}
+
+/// Whether a stored chunk exists and still matches its ID; rotted copies are rewritten on the next put
+fn stored_copy_intact(stored: Option<&[u8]>, id: &ChunkId) -> bool {
+    stored.is_some_and(|data| ChunkId::of(data) == *id)
+}
+
+fn decode_count(value: Option<&[u8]>) -> u64 {
+    value
+        .and_then(|bytes| bytes.try_into().ok())
+        .map(u64::from_be_bytes)
+        .unwrap_or(0)
+}
+
+fn run_transaction<T>(result: Result<T, TransactionError<StorageError>>) -> Result<T, StorageError> {
+    result.map_err(|e| match e {
+        TransactionError::Abort(e) => e,
+        TransactionError::Storage(e) => StorageError::Backend(e),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pseudo_random(len: usize, seed: u64) -> Vec<u8> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_chunk_boundaries_are_content_defined() {
+        let config = ChunkingConfig::default();
+        let data = pseudo_random(256 * 1024, 7);
+        let ranges = chunk_boundaries(&data, &config);
+
+        assert_eq!(ranges.first().unwrap().start, 0);
+        assert_eq!(ranges.last().unwrap().end, data.len());
+        assert!(ranges.iter().all(|range| range.len() <= config.max_size));
+        assert!(ranges[..ranges.len() - 1].iter().all(|range| range.len() > config.min_size));
+
+        // Prepending bytes only disturbs the chunks near the edit
+        let mut shifted = vec![1u8; 100];
+        shifted.extend_from_slice(&data);
+        let original: std::collections::HashSet<ChunkId> = ranges.iter().map(|r| ChunkId::of(&data[r.clone()])).collect();
+        let shared = chunk_boundaries(&shifted, &config)
+            .into_iter()
+            .filter(|r| original.contains(&ChunkId::of(&shifted[r.clone()])))
+            .count();
+        assert!(shared >= ranges.len() - 2);
+    }
+
+    #[test]
+    fn test_volume_round_trip_dedup_and_gc() {
+        let store = ChunkStore::temporary().unwrap();
+        let data = pseudo_random(100 * 1024, 3);
+        let (first, second) = (Uuid::new_v4(), Uuid::new_v4());
+
+        let manifest = store.write_volume(first, &data).unwrap();
+        store.write_volume(second, &data).unwrap();
+        assert_eq!(store.read_volume(&first).unwrap(), data);
+        assert_eq!(store.reference_count(&manifest.chunks[0].id).unwrap(), 2);
+
+        store.delete_volume(&first).unwrap();
+        assert_eq!(store.collect_garbage().unwrap(), GcStats::default());
+
+        // Overwriting releases the old chunks, which GC then reclaims
+        store.write_volume(second, b"replacement").unwrap();
+        let stats = store.collect_garbage().unwrap();
+        assert_eq!(stats.chunks_removed, manifest.chunks.len());
+        assert_eq!(stats.bytes_reclaimed, data.len() as u64);
+        assert_eq!(store.read_volume(&second).unwrap(), b"replacement");
+        assert!(matches!(store.read_volume(&first), Err(StorageError::VolumeNotFound(_))));
+    }
+
+    #[test]
+    fn test_corrupted_chunk_detected() {
+        let store = ChunkStore::temporary().unwrap();
+        let id = store.put_chunk(b"original").unwrap();
+
+        store.chunks.insert(id.0, b"tampered".as_slice()).unwrap();
+        assert!(matches!(store.get_chunk(&id), Err(StorageError::ChunkCorrupted(_))));
+
+        assert_eq!(store.release_chunk(&id).unwrap(), 0);
+        assert_eq!(store.collect_garbage().unwrap().chunks_removed, 1);
+        assert!(!store.contains(&id).unwrap());
+    }
+
+    #[test]
+    fn test_rotted_chunk_rewritten_on_put() {
+        let store = ChunkStore::temporary().unwrap();
+        let id = store.put_chunk(b"original").unwrap();
+        store.overwrite_raw_chunk(&id, b"tampered");
+
+        assert_eq!(store.put_chunk(b"original").unwrap(), id);
+        assert_eq!(store.get_chunk(&id).unwrap(), b"original");
+        assert_eq!(store.reference_count(&id).unwrap(), 2);
+
+        // Writing a volume that shares the chunk repairs it the same way
+        store.overwrite_raw_chunk(&id, b"tampered");
+        store.write_volume(Uuid::new_v4(), b"original").unwrap();
+        assert_eq!(store.get_chunk(&id).unwrap(), b"original");
+        assert_eq!(store.reference_count(&id).unwrap(), 3);
+    }
+}
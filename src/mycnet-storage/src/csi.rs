@@ -0,0 +1,996 @@
+//! Container Storage Interface semantics for mycnet volumes
+//!
+//! Request handling for the CSI spec's Identity, Controller and Node services
+//! over `TrustAwareStorageManager`. Requests and responses mirror the CSI
+//! messages, and `CsiStatus::from_error` gives the gRPC status each failure
+//! maps to.
+//!
+//! This is not a CSI driver: nothing serves these calls over gRPC and no
+//! `VolumeMounter` is implemented, so a cluster cannot use mycnet volumes
+//! yet (task 5 in `.kiro/specs/mycnet/tasks.md`).
+
+use crate::{
+    ConsistencyLevel, DataClassification, ErasureCoding, ReplicaTransport, ReplicationRequirements, ReplicationStrategy,
+    StorageAccessor, StorageAllocation, StorageError, StorageRequest, TrustAwareStorageManager,
+};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Name the driver registers with the container orchestrator
+pub const DRIVER_NAME: &str = "storage.mycnet.io";
+
+/// Capacity provisioned when a request gives no capacity range
+pub const DEFAULT_CAPACITY_BYTES: u64 = 1 << 30;
+
+/// gRPC status codes CSI calls fail with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CsiStatus {
+    InvalidArgument = 3,
+    NotFound = 5,
+    AlreadyExists = 6,
+    PermissionDenied = 7,
+    ResourceExhausted = 8,
+    FailedPrecondition = 9,
+    Internal = 13,
+    Unavailable = 14,
+}
+
+/// Volume characteristics requested through StorageClass parameters
+///
+/// Recognized keys are `classification`, `replicaCount`, `consistency`,
+/// `replicationStrategy`, `geographicDistribution`, `erasureCoding`
+/// (as `data+parity`, e.g. `4+2`), and `serviceId` and `networkId` for quotas.
+/// `serviceId` also makes the volume service storage, which only that service
+/// can clone. Keys prefixed with `csi.storage.k8s.io/` are set by the external
+/// provisioner and ignored.
+#[derive(Debug, Clone)]
+pub struct StorageClassParameters {
+    pub data_classification: DataClassification,
+    pub replication_requirements: ReplicationRequirements,
+    pub service_id: Option<String>,
+    pub network_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CapacityRange {
+    pub required_bytes: u64,
+    /// 0 means no limit
+    pub limit_bytes: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AccessType {
+    Block,
+    Mount { fs_type: String, mount_flags: Vec<String> },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessMode {
+    SingleNodeWriter,
+    SingleNodeReaderOnly,
+    MultiNodeReaderOnly,
+    MultiNodeSingleWriter,
+    MultiNodeMultiWriter,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VolumeCapability {
+    pub access_type: AccessType,
+    pub access_mode: AccessMode,
+}
+
+/// Existing data a new volume is provisioned from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum VolumeContentSource {
+    Snapshot(String),
+    Volume(String),
+}
+
+/// Topology segments a volume must or should be accessible from
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TopologyRequirement {
+    pub requisite: Vec<HashMap<String, String>>,
+    pub preferred: Vec<HashMap<String, String>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct CreateVolumeRequest {
+    pub name: String,
+    pub capacity_range: Option<CapacityRange>,
+    pub volume_capabilities: Vec<VolumeCapability>,
+    pub parameters: HashMap<String, String>,
+    pub content_source: Option<VolumeContentSource>,
+    pub accessibility_requirements: Option<TopologyRequirement>,
+}
+
+/// Parts of a CreateVolume request a retry must repeat exactly, in a canonical order
+///
+/// Its digest is kept in the volume's `StorageAllocation`, so retries are
+/// checked against it even after the controller restarts.
+#[derive(Serialize)]
+struct VolumeSpec {
+    parameters: BTreeMap<String, String>,
+    content_source: Option<VolumeContentSource>,
+    /// Requisite and preferred topology segments
+    accessibility_requirements: Option<(Vec<Segment>, Vec<Segment>)>,
+}
+
+/// Topology segment with its keys sorted
+type Segment = BTreeMap<String, String>;
+
+/// Volume as reported to the orchestrator
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CsiVolume {
+    pub volume_id: String,
+    pub capacity_bytes: u64,
+    /// Passed back to the node service when the volume is staged and published
+    pub volume_context: HashMap<String, String>,
+    pub content_source: Option<VolumeContentSource>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CsiSnapshot {
+    pub snapshot_id: String,
+    pub source_volume_id: String,
+    pub size_bytes: u64,
+    pub creation_time: i64,
+    pub ready_to_use: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PluginInfo {
+    pub name: String,
+    pub vendor_version: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PluginCapability {
+    ControllerService,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControllerCapability {
+    CreateDeleteVolume,
+    CreateDeleteSnapshot,
+    CloneVolume,
+    ListVolumes,
+    ExpandVolume,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeCapability {
+    StageUnstageVolume,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeInfo {
+    pub node_id: String,
+    pub max_volumes_per_node: u64,
+    /// Topology segments, e.g. `topology.mycnet.io/zone`
+    pub accessible_topology: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct NodeStageVolumeRequest {
+    pub volume_id: String,
+    pub staging_target_path: PathBuf,
+    pub volume_capability: VolumeCapability,
+    pub volume_context: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct NodePublishVolumeRequest {
+    pub volume_id: String,
+    pub staging_target_path: PathBuf,
+    pub target_path: PathBuf,
+    pub volume_capability: VolumeCapability,
+    pub readonly: bool,
+}
+
+/// Makes volumes usable on the local node
+///
+/// Staging attaches the volume once per node, as a block device or a
+/// FUSE-backed filesystem at the staging path. Publishing bind-mounts the
+/// staged volume into each pod's target path.
+pub trait VolumeMounter: Send + Sync {
+    fn stage(&self, volume_id: &Uuid, staging_path: &std::path::Path, capability: &VolumeCapability) -> Result<(), StorageError>;
+
+    fn unstage(&self, volume_id: &Uuid, staging_path: &std::path::Path) -> Result<(), StorageError>;
+
+    fn publish(&self, staging_path: &std::path::Path, target_path: &std::path::Path, readonly: bool) -> Result<(), StorageError>;
+
+    fn unpublish(&self, target_path: &std::path::Path) -> Result<(), StorageError>;
+}
+
+/// Identity and Controller services backed by the storage manager
+pub struct CsiController<T: ReplicaTransport> {
+    manager: TrustAwareStorageManager,
+    transport: Arc<T>,
+    vendor_version: String,
+}
+
+/// Node service for one storage node
+pub struct CsiNode<M: VolumeMounter> {
+    info: NodeInfo,
+    mounter: M,
+    staged: HashMap<Uuid, PathBuf>,
+    published: HashMap<PathBuf, Uuid>,
+}
+
+impl CsiStatus {
+    /// Status code a storage error is reported with
+    pub fn from_error(error: &StorageError) -> Self {
+        match error {
+            StorageError::InvalidRequest(_) | StorageError::ErasureCoding(_) => CsiStatus::InvalidArgument,
+            StorageError::VolumeNotFound(_) | StorageError::PoolNotFound(_) => CsiStatus::NotFound,
+            StorageError::VolumeAlreadyAllocated(_) | StorageError::VolumeNameTaken { .. } => CsiStatus::AlreadyExists,
+            StorageError::AccessDenied { .. } => CsiStatus::PermissionDenied,
+            StorageError::NoSuitablePool { .. }
+            | StorageError::NoEligibleNodes { .. }
+            | StorageError::InsufficientNodes { .. }
+            | StorageError::InsufficientFailureDomains { .. }
+            | StorageError::InsufficientZones { .. }
+            | StorageError::QuotaExceeded { .. } => CsiStatus::ResourceExhausted,
+            StorageError::UntrustedPlacement { .. } | StorageError::ReadOnlyVolume(_) => CsiStatus::FailedPrecondition,
+            StorageError::ReplicaUnavailable(_) | StorageError::ReplicationFailed { .. } | StorageError::NoHealthyReplica(_) => {
+                CsiStatus::Unavailable
+            },
+            _ => CsiStatus::Internal,
+        }
+    }
+}
+
+impl StorageClassParameters {
+    /// Parse StorageClass parameters, rejecting unknown keys and values
+    pub fn parse(parameters: &HashMap<String, String>) -> Result<Self, StorageError> {
+        let mut data_classification = DataClassification::Standard;
+        let mut service_id = None;
+        let mut network_id = None;
+        let mut requirements = ReplicationRequirements {
+            replica_count: 3,
+            consistency_level: ConsistencyLevel::Strong,
+            geographic_distribution: false,
+            replication_strategy: ReplicationStrategy::HierarchyAware,
+            erasure_coding: None,
+        };
+
+        for (key, value) in parameters {
+            match key.as_str() {
+                "classification" => {
+                    data_classification = match value.to_ascii_lowercase().as_str() {
+                        "critical" => DataClassification::Critical,
+                        "sensitive" => DataClassification::Sensitive,
+                        "standard" => DataClassification::Standard,
+                        "public" => DataClassification::Public,
+                        _ => return Err(invalid_parameter(key, value)),
+                    }
+                },
+                "replicaCount" => {
+                    requirements.replica_count = value.parse().ok().filter(|count| *count > 0).ok_or_else(|| invalid_parameter(key, value))?
+                },
+                "consistency" => {
+                    requirements.consistency_level = match value.to_ascii_lowercase().as_str() {
+                        "strong" => ConsistencyLevel::Strong,
+                        "eventual" => ConsistencyLevel::Eventual,
+                        "causal" => ConsistencyLevel::Causal,
+                        _ => return Err(invalid_parameter(key, value)),
+                    }
+                },
+                "replicationStrategy" => {
+                    requirements.replication_strategy = match value.to_ascii_lowercase().as_str() {
+                        "hierarchy-aware" => ReplicationStrategy::HierarchyAware,
+                        "geographic" => ReplicationStrategy::GeographicDistribution,
+                        "trust-diversification" => ReplicationStrategy::TrustDiversification,
+                        "performance" => ReplicationStrategy::PerformanceOptimized,
+                        _ => return Err(invalid_parameter(key, value)),
+                    }
+                },
+                "geographicDistribution" => {
+                    requirements.geographic_distribution = value.parse().map_err(|_| invalid_parameter(key, value))?
+                },
+                "erasureCoding" => {
+                    let (data, parity) = value.split_once('+').ok_or_else(|| invalid_parameter(key, value))?;
+                    let data = data.trim().parse().map_err(|_| invalid_parameter(key, value))?;
+                    let parity = parity.trim().parse().map_err(|_| invalid_parameter(key, value))?;
+                    requirements.erasure_coding = Some(ErasureCoding::new(data, parity)?);
+                },
+                "serviceId" => service_id = Some(value.clone()),
+                "networkId" => network_id = Some(value.clone()),
+                _ if key.starts_with("csi.storage.k8s.io/") => {},
+                _ => return Err(StorageError::InvalidRequest(format!("Unknown StorageClass parameter {}", key))),
+            }
+        }
+
+        Ok(Self {
+            data_classification,
+            replication_requirements: requirements,
+            service_id,
+            network_id,
+        })
+    }
+}
+
+fn invalid_parameter(key: &str, value: &str) -> StorageError {
+    StorageError::InvalidRequest(format!("Invalid value {:?} for StorageClass parameter {}", value, key))
+}
+
+/// Stable volume ID for a CSI volume name, so retried creates are idempotent
+pub fn volume_id_for_name(name: &str) -> Uuid {
+    id_for_name("mycnet-storage csi volume name v1", name)
+}
+
+/// Stable snapshot ID for a CSI snapshot name
+///
+/// Snapshots use their own context, so a snapshot never collides with a
+/// volume of the same name.
+pub fn snapshot_id_for_name(name: &str) -> Uuid {
+    id_for_name("mycnet-storage csi snapshot name v1", name)
+}
+
+fn id_for_name(context: &str, name: &str) -> Uuid {
+    let hash = blake3::derive_key(context, name.as_bytes());
+    let mut bytes = [0u8; 16];
+    bytes.copy_from_slice(&hash[..16]);
+    Uuid::from_bytes(bytes)
+}
+
+fn parse_volume_id(volume_id: &str) -> Result<Uuid, StorageError> {
+    if volume_id.is_empty() {
+        return Err(StorageError::InvalidRequest("Volume ID is required".to_string()));
+    }
+    // IDs this driver never issued cannot exist
+    Uuid::parse_str(volume_id).map_err(|_| StorageError::VolumeNotFound(Uuid::nil()))
+}
+
+fn requested_capacity(range: Option<&CapacityRange>) -> Result<u64, StorageError> {
+    let Some(range) = range else {
+        return Ok(DEFAULT_CAPACITY_BYTES);
+    };
+    if range.limit_bytes != 0 && range.required_bytes > range.limit_bytes {
+        return Err(StorageError::InvalidRequest("Required capacity exceeds the capacity limit".to_string()));
+    }
+    match range.required_bytes {
+        0 if range.limit_bytes != 0 => Ok(range.limit_bytes.min(DEFAULT_CAPACITY_BYTES)),
+        0 => Ok(DEFAULT_CAPACITY_BYTES),
+        required => Ok(required),
+    }
+}
+
+impl VolumeSpec {
+    fn of(request: &CreateVolumeRequest) -> Self {
+        let sorted = |segments: &[HashMap<String, String>]| -> Vec<Segment> {
+            segments.iter().map(|segment| segment.clone().into_iter().collect()).collect()
+        };
+        Self {
+            parameters: request.parameters.clone().into_iter().collect(),
+            content_source: request.content_source.clone(),
+            accessibility_requirements: request
+                .accessibility_requirements
+                .as_ref()
+                .map(|requirement| (sorted(&requirement.requisite), sorted(&requirement.preferred))),
+        }
+    }
+
+    fn digest(&self) -> Result<[u8; 32], StorageError> {
+        Ok(*blake3::hash(&bincode::serialize(self)?).as_bytes())
+    }
+}
+
+fn capability_supported(capability: &VolumeCapability) -> bool {
+    // Replicated volumes are written through a single coordinator, so only
+    // read-only access may span nodes
+    let mode_supported = matches!(
+        capability.access_mode,
+        AccessMode::SingleNodeWriter | AccessMode::SingleNodeReaderOnly | AccessMode::MultiNodeReaderOnly
+    );
+    let type_supported = match &capability.access_type {
+        AccessType::Block => true,
+        AccessType::Mount { fs_type, .. } => fs_type.is_empty() || matches!(fs_type.as_str(), "ext4" | "xfs"),
+    };
+    mode_supported && type_supported
+}
+
+impl<T: ReplicaTransport> CsiController<T> {
+    pub fn new(manager: TrustAwareStorageManager, transport: Arc<T>) -> Self {
+        Self {
+            manager,
+            transport,
+            vendor_version: env!("CARGO_PKG_VERSION").to_string(),
+        }
+    }
+
+    /// Get the storage manager
+    pub fn manager(&self) -> &TrustAwareStorageManager {
+        &self.manager
+    }
+
+    /// Get the storage manager
+    pub fn manager_mut(&mut self) -> &mut TrustAwareStorageManager {
+        &mut self.manager
+    }
+
+    pub fn get_plugin_info(&self) -> PluginInfo {
+        PluginInfo {
+            name: DRIVER_NAME.to_string(),
+            vendor_version: self.vendor_version.clone(),
+        }
+    }
+
+    pub fn get_plugin_capabilities(&self) -> Vec<PluginCapability> {
+        vec![PluginCapability::ControllerService]
+    }
+
+    /// Ready once a storage pool with nodes is registered
+    pub fn probe(&self) -> bool {
+        self.manager.storage_pools.values().any(|pool| !pool.available_nodes.is_empty())
+    }
+
+    pub fn controller_get_capabilities(&self) -> Vec<ControllerCapability> {
+        vec![
+            ControllerCapability::CreateDeleteVolume,
+            ControllerCapability::CreateDeleteSnapshot,
+            ControllerCapability::CloneVolume,
+            ControllerCapability::ListVolumes,
+            ControllerCapability::ExpandVolume,
+        ]
+    }
+
+    /// Provision a volume through `allocate_storage`, or clone one from a content source
+    ///
+    /// Creating a volume that already exists with the same name succeeds if
+    /// its capacity is compatible and the parameters, content source and
+    /// topology requirements match the first request; otherwise it fails with
+    /// `VolumeAlreadyAllocated`. Access modes or filesystems the driver cannot
+    /// serve fail with `InvalidRequest`. Topology requirements are recorded
+    /// but do not yet steer placement.
+    pub async fn create_volume(&mut self, request: CreateVolumeRequest) -> Result<CsiVolume, StorageError> {
+        if request.name.is_empty() {
+            return Err(StorageError::InvalidRequest("Volume name is required".to_string()));
+        }
+        if request.volume_capabilities.is_empty() {
+            return Err(StorageError::InvalidRequest("Volume capabilities are required".to_string()));
+        }
+        if let Some(capability) = request.volume_capabilities.iter().find(|capability| !capability_supported(capability)) {
+            return Err(StorageError::InvalidRequest(format!("Unsupported volume capability {:?}", capability)));
+        }
+        let parameters = StorageClassParameters::parse(&request.parameters)?;
+        let capacity = requested_capacity(request.capacity_range.as_ref())?;
+        let volume_id = volume_id_for_name(&request.name);
+        let digest = VolumeSpec::of(&request).digest()?;
+
+        if let Some(existing) = self.manager.allocation(&volume_id) {
+            let compatible = existing.allocated_size >= capacity
+                && request.capacity_range.is_none_or(|range| range.limit_bytes == 0 || existing.allocated_size <= range.limit_bytes)
+                && existing.data_classification == parameters.data_classification
+                && existing.request_digest.is_none_or(|existing_digest| existing_digest == digest);
+            if !compatible {
+                return Err(StorageError::VolumeAlreadyAllocated(volume_id));
+            }
+            return Ok(self.csi_volume(existing, request.content_source));
+        }
+
+        let allocation = match &request.content_source {
+            Some(source) => {
+                let source_id = match source {
+                    VolumeContentSource::Snapshot(id) | VolumeContentSource::Volume(id) => parse_volume_id(id)?,
+                };
+                // Clones stay with the source's service, so only that service may clone it
+                let accessor = StorageAccessor::for_service(parameters.service_id.as_ref());
+                let source = self.manager.authorize(&source_id, &accessor)?;
+                if source.data_classification != parameters.data_classification {
+                    return Err(StorageError::InvalidRequest(format!(
+                        "Source is classified {:?}, not the requested {:?}",
+                        source.data_classification, parameters.data_classification
+                    )));
+                }
+                if source.allocated_size > capacity {
+                    return Err(StorageError::InvalidRequest(format!(
+                        "Source holds {} bytes, more than the requested {}",
+                        source.allocated_size, capacity
+                    )));
+                }
+                self.manager.clone_volume(&source_id, volume_id, self.transport.as_ref()).await?
+            },
+            None => self
+                .manager
+                .allocate_storage(StorageRequest {
+                    volume_id,
+                    size_bytes: capacity,
+                    data_classification: parameters.data_classification,
+                    replication_requirements: parameters.replication_requirements,
+                    service_id: parameters.service_id,
+                    network_id: parameters.network_id,
+                })
+                .await
+                .map_err(|e| match e.downcast::<StorageError>() {
+                    Ok(e) => *e,
+                    Err(e) => StorageError::InvalidRequest(e.to_string()),
+                })?,
+        };
+
+        tracing::info!("CSI volume {} provisioned as {}", request.name, volume_id);
+        let allocation = match self.manager.allocations.get_mut(&volume_id) {
+            Some(stored) => {
+                stored.request_digest = Some(digest);
+                stored.clone()
+            },
+            None => allocation,
+        };
+        Ok(self.csi_volume(&allocation, request.content_source))
+    }
+
+    /// Grow a volume online through `expand_volume`
+    ///
+    /// Returns the new capacity and whether the node must also expand the
+    /// filesystem, which is the case for mounted volumes.
+    pub fn controller_expand_volume(
+        &mut self,
+        volume_id: &str,
+        capacity_range: CapacityRange,
+        capability: Option<&VolumeCapability>,
+    ) -> Result<(u64, bool), StorageError> {
+        let volume_id = parse_volume_id(volume_id)?;
+        let capacity = requested_capacity(Some(&capacity_range))?;
+        let current = self.manager.allocation(&volume_id).ok_or(StorageError::VolumeNotFound(volume_id))?.allocated_size;
+
+        let allocation = self.manager.expand_volume(&volume_id, capacity.max(current))?;
+        let node_expansion_required = !matches!(capability.map(|capability| &capability.access_type), Some(AccessType::Block));
+        Ok((allocation.allocated_size, node_expansion_required))
+    }
+
+    /// Release a volume and remove it from its nodes; deleting an unknown volume succeeds
+    pub async fn delete_volume(&mut self, volume_id: &str) -> Result<(), StorageError> {
+        let volume_id = match parse_volume_id(volume_id) {
+            Ok(volume_id) => volume_id,
+            Err(StorageError::VolumeNotFound(_)) => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        if self.manager.snapshot(&volume_id).is_some() {
+            return Err(StorageError::InvalidRequest(format!("{} is a snapshot", volume_id)));
+        }
+        match self.manager.delete_volume(&volume_id, self.transport.as_ref()).await {
+            Ok(_) | Err(StorageError::VolumeNotFound(_)) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Capabilities from the request the volume supports, or `None` if any is unsupported
+    pub fn validate_volume_capabilities(&self, volume_id: &str, capabilities: &[VolumeCapability]) -> Result<Option<Vec<VolumeCapability>>, StorageError> {
+        let volume_id = parse_volume_id(volume_id)?;
+        if self.manager.allocation(&volume_id).is_none() {
+            return Err(StorageError::VolumeNotFound(volume_id));
+        }
+        if capabilities.is_empty() {
+            return Err(StorageError::InvalidRequest("Volume capabilities are required".to_string()));
+        }
+
+        if capabilities.iter().all(capability_supported) {
+            Ok(Some(capabilities.to_vec()))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Volumes in ID order, excluding snapshots
+    pub fn list_volumes(&self) -> Vec<CsiVolume> {
+        let mut volumes: Vec<CsiVolume> = self
+            .manager
+            .allocations()
+            .filter(|allocation| self.manager.snapshot(&allocation.volume_id).is_none())
+            .map(|allocation| self.csi_volume(allocation, None))
+            .collect();
+        volumes.sort_by(|a, b| a.volume_id.cmp(&b.volume_id));
+        volumes
+    }
+
+    /// Snapshot a volume; repeating a request with the same name returns the same snapshot
+    pub async fn create_snapshot(&mut self, source_volume_id: &str, name: &str) -> Result<CsiSnapshot, StorageError> {
+        if name.is_empty() {
+            return Err(StorageError::InvalidRequest("Snapshot name is required".to_string()));
+        }
+        let source_id = parse_volume_id(source_volume_id)?;
+        let snapshot_id = snapshot_id_for_name(name);
+
+        let snapshot = match self.manager.snapshot(&snapshot_id) {
+            Some(existing) if existing.volume_id == source_id => existing.clone(),
+            Some(_) => return Err(StorageError::VolumeAlreadyAllocated(snapshot_id)),
+            None => self.manager.create_snapshot(&source_id, snapshot_id, self.transport.as_ref()).await?,
+        };
+
+        Ok(CsiSnapshot {
+            snapshot_id: snapshot.snapshot_id.to_string(),
+            source_volume_id: snapshot.volume_id.to_string(),
+            size_bytes: snapshot.size_bytes,
+            creation_time: snapshot.created_at.timestamp(),
+            ready_to_use: true,
+        })
+    }
+
+    /// Delete a snapshot; deleting an unknown snapshot succeeds
+    pub async fn delete_snapshot(&mut self, snapshot_id: &str) -> Result<(), StorageError> {
+        let snapshot_id = match parse_volume_id(snapshot_id) {
+            Ok(snapshot_id) => snapshot_id,
+            Err(StorageError::VolumeNotFound(_)) => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        match self.manager.delete_snapshot(&snapshot_id, self.transport.as_ref()).await {
+            Ok(_) | Err(StorageError::VolumeNotFound(_)) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn csi_volume(&self, allocation: &StorageAllocation, content_source: Option<VolumeContentSource>) -> CsiVolume {
+        let mut volume_context = HashMap::from([
+            ("classification".to_string(), format!("{:?}", allocation.data_classification).to_lowercase()),
+            ("pool".to_string(), allocation.pool_id.clone()),
+        ]);
+        if let Some(origin) = allocation.origin_volume_id {
+            volume_context.insert("originVolumeId".to_string(), origin.to_string());
+        }
+
+        CsiVolume {
+            volume_id: allocation.volume_id.to_string(),
+            capacity_bytes: allocation.allocated_size,
+            volume_context,
+            content_source,
+        }
+    }
+}
+
+impl<M: VolumeMounter> CsiNode<M> {
+    pub fn new(info: NodeInfo, mounter: M) -> Self {
+        Self {
+            info,
+            mounter,
+            staged: HashMap::new(),
+            published: HashMap::new(),
+        }
+    }
+
+    pub fn node_get_info(&self) -> NodeInfo {
+        self.info.clone()
+    }
+
+    pub fn node_get_capabilities(&self) -> Vec<NodeCapability> {
+        vec![NodeCapability::StageUnstageVolume]
+    }
+
+    /// Attach a volume at its staging path; staging it again at the same path succeeds
+    pub fn node_stage_volume(&mut self, request: NodeStageVolumeRequest) -> Result<(), StorageError> {
+        let volume_id = parse_volume_id(&request.volume_id)?;
+        if request.staging_target_path.as_os_str().is_empty() {
+            return Err(StorageError::InvalidRequest("Staging target path is required".to_string()));
+        }
+
+        match self.staged.get(&volume_id) {
+            Some(path) if *path == request.staging_target_path => return Ok(()),
+            Some(path) => {
+                tracing::warn!("Volume {} is already staged at {}", volume_id, path.display());
+                return Err(StorageError::VolumeAlreadyAllocated(volume_id));
+            },
+            None => {},
+        }
+        if self.info.max_volumes_per_node > 0 && self.staged.len() as u64 >= self.info.max_volumes_per_node {
+            return Err(StorageError::InvalidRequest(format!("Node {} has no volume slots left", self.info.node_id)));
+        }
+
+        self.mounter.stage(&volume_id, &request.staging_target_path, &request.volume_capability)?;
+        self.staged.insert(volume_id, request.staging_target_path);
+        Ok(())
+    }
+
+    /// Detach a staged volume once nothing publishes it
+    pub fn node_unstage_volume(&mut self, volume_id: &str, staging_target_path: &std::path::Path) -> Result<(), StorageError> {
+        let volume_id = parse_volume_id(volume_id)?;
+        let Some(path) = self.staged.get(&volume_id) else {
+            return Ok(());
+        };
+        if path != staging_target_path {
+            return Err(StorageError::InvalidRequest(format!("Volume {} is staged at {}", volume_id, path.display())));
+        }
+        if self.published.values().any(|published| *published == volume_id) {
+            return Err(StorageError::InvalidRequest(format!("Volume {} is still published", volume_id)));
+        }
+
+        self.mounter.unstage(&volume_id, staging_target_path)?;
+        self.staged.remove(&volume_id);
+        Ok(())
+    }
+
+    /// Bind a staged volume into a target path
+    pub fn node_publish_volume(&mut self, request: NodePublishVolumeRequest) -> Result<(), StorageError> {
+        let volume_id = parse_volume_id(&request.volume_id)?;
+        if request.target_path.as_os_str().is_empty() {
+            return Err(StorageError::InvalidRequest("Target path is required".to_string()));
+        }
+        if self.staged.get(&volume_id) != Some(&request.staging_target_path) {
+            return Err(StorageError::InvalidRequest(format!("Volume {} is not staged", volume_id)));
+        }
+
+        match self.published.get(&request.target_path) {
+            Some(published) if *published == volume_id => return Ok(()),
+            Some(_) => {
+                return Err(StorageError::InvalidRequest(format!("{} is in use by another volume", request.target_path.display())))
+            },
+            None => {},
+        }
+        let readonly = request.readonly
+            || matches!(request.volume_capability.access_mode, AccessMode::SingleNodeReaderOnly | AccessMode::MultiNodeReaderOnly);
+
+        self.mounter.publish(&request.staging_target_path, &request.target_path, readonly)?;
+        self.published.insert(request.target_path, volume_id);
+        Ok(())
+    }
+
+    /// Remove a volume from a target path; unpublishing an unknown path succeeds
+    pub fn node_unpublish_volume(&mut self, volume_id: &str, target_path: &std::path::Path) -> Result<(), StorageError> {
+        let volume_id = parse_volume_id(volume_id)?;
+        if self.published.get(target_path) != Some(&volume_id) {
+            return Ok(());
+        }
+        self.mounter.unpublish(target_path)?;
+        self.published.remove(target_path);
+        Ok(())
+    }
+
+    /// Volumes currently staged on this node
+    pub fn staged_volumes(&self) -> HashSet<Uuid> {
+        self.staged.keys().copied().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{add_pool, copies, network_transport, pool, write};
+    use crate::InMemoryTransport;
+    use std::sync::Mutex;
+
+    fn controller() -> CsiController<InMemoryTransport> {
+        let mut manager = TrustAwareStorageManager::new();
+        let mut transport = network_transport();
+        add_pool(&mut manager, &mut transport, pool("pool", 0.95, 1 << 40, Vec::new()), 3);
+        CsiController::new(manager, Arc::new(transport))
+    }
+
+    fn mount_capability() -> VolumeCapability {
+        VolumeCapability {
+            access_type: AccessType::Mount {
+                fs_type: "ext4".to_string(),
+                mount_flags: Vec::new(),
+            },
+            access_mode: AccessMode::SingleNodeWriter,
+        }
+    }
+
+    fn create_request(name: &str, required_bytes: u64) -> CreateVolumeRequest {
+        CreateVolumeRequest {
+            name: name.to_string(),
+            capacity_range: Some(CapacityRange {
+                required_bytes,
+                limit_bytes: 0,
+            }),
+            volume_capabilities: vec![mount_capability()],
+            parameters: HashMap::from([
+                ("classification".to_string(), "critical".to_string()),
+                ("replicaCount".to_string(), "2".to_string()),
+                ("csi.storage.k8s.io/pvc/name".to_string(), "data".to_string()),
+            ]),
+            content_source: None,
+            accessibility_requirements: None,
+        }
+    }
+
+    #[test]
+    fn test_storage_class_parameters() {
+        let parameters = HashMap::from([
+            ("classification".to_string(), "sensitive".to_string()),
+            ("consistency".to_string(), "causal".to_string()),
+            ("erasureCoding".to_string(), "4+2".to_string()),
+        ]);
+        let parsed = StorageClassParameters::parse(&parameters).unwrap();
+        assert_eq!(parsed.data_classification, DataClassification::Sensitive);
+        assert_eq!(parsed.replication_requirements.node_count(), 6);
+
+        let unknown = HashMap::from([("replicas".to_string(), "3".to_string())]);
+        let err = StorageClassParameters::parse(&unknown).unwrap_err();
+        assert_eq!(CsiStatus::from_error(&err), CsiStatus::InvalidArgument);
+    }
+
+    #[tokio::test]
+    async fn test_create_volume_is_idempotent_by_name() {
+        let mut controller = controller();
+        assert!(controller.probe());
+
+        let volume = controller.create_volume(create_request("pvc-1", 4096)).await.unwrap();
+        assert_eq!(volume.capacity_bytes, 4096);
+        assert_eq!(volume.volume_context["classification"], "critical");
+        assert_eq!(controller.create_volume(create_request("pvc-1", 4096)).await.unwrap(), volume);
+
+        let err = controller.create_volume(create_request("pvc-1", 1 << 20)).await.unwrap_err();
+        assert_eq!(CsiStatus::from_error(&err), CsiStatus::AlreadyExists);
+
+        // A retry must repeat the parameters and topology of the first request
+        let mut changed = create_request("pvc-1", 4096);
+        changed.parameters.insert("consistency".to_string(), "eventual".to_string());
+        assert!(matches!(controller.create_volume(changed).await, Err(StorageError::VolumeAlreadyAllocated(_))));
+        let mut changed = create_request("pvc-1", 4096);
+        changed.accessibility_requirements = Some(TopologyRequirement {
+            requisite: vec![HashMap::from([("topology.mycnet.io/zone".to_string(), "eu".to_string())])],
+            preferred: Vec::new(),
+        });
+        assert!(matches!(controller.create_volume(changed).await, Err(StorageError::VolumeAlreadyAllocated(_))));
+
+        assert_eq!(controller.list_volumes().len(), 1);
+        let range = CapacityRange {
+            required_bytes: 8192,
+            limit_bytes: 0,
+        };
+        assert_eq!(controller.controller_expand_volume(&volume.volume_id, range, None).unwrap(), (8192, true));
+        let volume_id = Uuid::parse_str(&volume.volume_id).unwrap();
+        let allocation = controller.manager().allocation(&volume_id).unwrap().clone();
+        write(&controller.transport, &copies(&allocation), allocation.primary_node, volume_id, b"contents");
+
+        controller.delete_volume(&volume.volume_id).await.unwrap();
+        controller.delete_volume(&volume.volume_id).await.unwrap();
+        controller.delete_volume("not-a-volume").await.unwrap();
+        assert!(controller.list_volumes().is_empty());
+
+        // Deleting removes the volume from its nodes, not just the reservation
+        for node_id in std::iter::once(allocation.primary_node).chain(allocation.replica_nodes) {
+            assert!(controller.transport.node(&node_id).unwrap().read(&volume_id).unwrap().is_none());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_create_volume_rejects_unsupported_capabilities() {
+        let mut controller = controller();
+        let mut request = create_request("pvc-1", 4096);
+        request.volume_capabilities.push(VolumeCapability {
+            access_type: AccessType::Block,
+            access_mode: AccessMode::MultiNodeMultiWriter,
+        });
+        let err = controller.create_volume(request).await.unwrap_err();
+        assert_eq!(CsiStatus::from_error(&err), CsiStatus::InvalidArgument);
+
+        let mut request = create_request("pvc-1", 4096);
+        request.volume_capabilities[0].access_type = AccessType::Mount {
+            fs_type: "ntfs".to_string(),
+            mount_flags: Vec::new(),
+        };
+        let err = controller.create_volume(request).await.unwrap_err();
+        assert_eq!(CsiStatus::from_error(&err), CsiStatus::InvalidArgument);
+        assert!(controller.list_volumes().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_create_volume_retries_checked_after_restart() {
+        let mut controller = controller();
+        let mut request = create_request("pvc-1", 4096);
+        request.parameters.insert("serviceId".to_string(), "db".to_string());
+        let volume = controller.create_volume(request.clone()).await.unwrap();
+
+        // A restarted controller recovers the volume from the service's storage spore
+        let key = ed25519_dalek::SigningKey::from_bytes(&[7; 32]);
+        let mut spore = controller.manager().service_spore("db");
+        spore.sign(&key).unwrap();
+        let pool = controller.manager().storage_pool("pool").unwrap().clone();
+        let mut manager = TrustAwareStorageManager::new();
+        for node_id in &pool.available_nodes {
+            manager.trust_evaluator_mut().set_node_trust_score(*node_id, pool.trust_level);
+        }
+        manager.register_storage_pool(crate::StoragePool { used_capacity: 0, ..pool }).unwrap();
+        manager.restore_service_spore(&spore, &key.verifying_key()).unwrap();
+        let mut restarted = CsiController::new(manager, controller.transport.clone());
+
+        assert_eq!(restarted.create_volume(request.clone()).await.unwrap(), volume);
+        let mut changed = request;
+        changed.parameters.insert("consistency".to_string(), "eventual".to_string());
+        assert!(matches!(restarted.create_volume(changed).await, Err(StorageError::VolumeAlreadyAllocated(_))));
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_and_restore_from_snapshot() {
+        let mut controller = controller();
+        let volume = controller.create_volume(create_request("pvc-1", 4096)).await.unwrap();
+        let volume_id = Uuid::parse_str(&volume.volume_id).unwrap();
+        let allocation = controller.manager().allocation(&volume_id).unwrap().clone();
+        write(&controller.transport, &copies(&allocation), allocation.primary_node, volume_id, b"contents");
+
+        let snapshot = controller.create_snapshot(&volume.volume_id, "snap-1").await.unwrap();
+        assert_ne!(snapshot_id_for_name("pvc-1"), volume_id_for_name("pvc-1"));
+        assert_eq!(controller.create_snapshot(&volume.volume_id, "snap-1").await.unwrap(), snapshot);
+        assert_eq!(controller.list_volumes().len(), 1);
+
+        let mut restore = create_request("pvc-2", 4096);
+        restore.content_source = Some(VolumeContentSource::Snapshot(snapshot.snapshot_id.clone()));
+        let mut foreign = restore.clone();
+        foreign.parameters.insert("serviceId".to_string(), "web".to_string());
+        let err = controller.create_volume(foreign).await.unwrap_err();
+        assert_eq!(CsiStatus::from_error(&err), CsiStatus::PermissionDenied);
+        let mut downgraded = restore.clone();
+        downgraded.parameters.insert("classification".to_string(), "public".to_string());
+        let err = controller.create_volume(downgraded).await.unwrap_err();
+        assert_eq!(CsiStatus::from_error(&err), CsiStatus::InvalidArgument);
+
+        let restored = controller.create_volume(restore).await.unwrap();
+        assert_eq!(restored.volume_context["originVolumeId"], volume.volume_id);
+
+        controller.delete_snapshot(&snapshot.snapshot_id).await.unwrap();
+        controller.delete_snapshot(&snapshot.snapshot_id).await.unwrap();
+    }
+
+    #[derive(Default)]
+    struct RecordingMounter {
+        calls: Mutex<Vec<String>>,
+    }
+
+    impl VolumeMounter for RecordingMounter {
+        fn stage(&self, volume_id: &Uuid, _: &std::path::Path, _: &VolumeCapability) -> Result<(), StorageError> {
+            self.calls.lock().unwrap().push(format!("stage {}", volume_id));
+            Ok(())
+        }
+
+        fn unstage(&self, volume_id: &Uuid, _: &std::path::Path) -> Result<(), StorageError> {
+            self.calls.lock().unwrap().push(format!("unstage {}", volume_id));
+            Ok(())
+        }
+
+        fn publish(&self, _: &std::path::Path, target_path: &std::path::Path, readonly: bool) -> Result<(), StorageError> {
+            self.calls.lock().unwrap().push(format!("publish {} {}", target_path.display(), readonly));
+            Ok(())
+        }
+
+        fn unpublish(&self, target_path: &std::path::Path) -> Result<(), StorageError> {
+            self.calls.lock().unwrap().push(format!("unpublish {}", target_path.display()));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_node_stage_and_publish_lifecycle() {
+        let mut node = CsiNode::new(
+            NodeInfo {
+                node_id: "node-a".to_string(),
+                max_volumes_per_node: 8,
+                accessible_topology: HashMap::new(),
+            },
+            RecordingMounter::default(),
+        );
+        let volume_id = Uuid::new_v4().to_string();
+        let staging = PathBuf::from("/var/lib/kubelet/staging/vol");
+        let target = PathBuf::from("/var/lib/kubelet/pods/pod/vol");
+
+        let publish = NodePublishVolumeRequest {
+            volume_id: volume_id.clone(),
+            staging_target_path: staging.clone(),
+            target_path: target.clone(),
+            volume_capability: mount_capability(),
+            readonly: false,
+        };
+        assert!(node.node_publish_volume(publish.clone()).is_err());
+
+        let stage = NodeStageVolumeRequest {
+            volume_id: volume_id.clone(),
+            staging_target_path: staging.clone(),
+            volume_capability: mount_capability(),
+            volume_context: HashMap::new(),
+        };
+        node.node_stage_volume(stage.clone()).unwrap();
+        node.node_stage_volume(stage).unwrap();
+        node.node_publish_volume(publish.clone()).unwrap();
+        node.node_publish_volume(publish).unwrap();
+
+        assert!(node.node_unstage_volume(&volume_id, &staging).is_err());
+        node.node_unpublish_volume(&volume_id, &target).unwrap();
+        node.node_unpublish_volume(&volume_id, &target).unwrap();
+        node.node_unstage_volume(&volume_id, &staging).unwrap();
+        assert!(node.staged_volumes().is_empty());
+
+        // Repeated calls reach the mounter only once each
+        assert_eq!(node.mounter.calls.lock().unwrap().len(), 4);
+    }
+}
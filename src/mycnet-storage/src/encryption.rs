@@ -0,0 +1,378 @@
+//! Per-volume encryption at rest with wrapped data keys
+
+use crate::{chunk_boundaries, ChunkingConfig, StorageError, TrustAwareStorageManager};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Data key wrapped for one authorized node or service
+///
+/// The wrapping key is derived from an X25519 exchange between a one-time
+/// ephemeral key and the recipient's public key, so only the recipient can
+/// unwrap it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WrappedKey {
+    pub recipient: Uuid,
+    pub ephemeral_public: [u8; 32],
+    pub nonce: [u8; 12],
+    pub ciphertext: Vec<u8>,
+}
+
+/// A volume's data key wrapped for every authorized recipient
+///
+/// Rotation re-wraps the same data key for a new recipient set under a new
+/// generation, so stored chunks never need rewriting.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VolumeKeyEnvelope {
+    pub volume_id: Uuid,
+    pub generation: u32,
+    pub wrapped_keys: Vec<WrappedKey>,
+}
+
+/// Unwrapped data key for encrypting and decrypting one volume
+pub struct VolumeCipher {
+    volume_id: Uuid,
+    key: [u8; 32],
+}
+
+/// Bytes of framing before each encrypted chunk: length and nonce
+const FRAME_HEADER: usize = 4 + 12;
+
+const CHUNK_KEY_CONTEXT: &str = "mycnet-storage chunk encryption v1";
+const NONCE_KEY_CONTEXT: &str = "mycnet-storage chunk nonce v1";
+
+impl VolumeKeyEnvelope {
+    /// Generate a data key for a volume and wrap it for the given recipients
+    pub fn generate(volume_id: Uuid, recipients: &[(Uuid, x25519_dalek::PublicKey)]) -> Result<(Self, VolumeCipher), StorageError> {
+        let mut key = [0u8; 32];
+        rand::rngs::OsRng.fill_bytes(&mut key);
+        let cipher = VolumeCipher { volume_id, key };
+
+        let envelope = Self {
+            volume_id,
+            generation: 0,
+            wrapped_keys: wrap_for(&cipher, 0, recipients)?,
+        };
+        Ok((envelope, cipher))
+    }
+
+    /// Unwrap the data key with a recipient's secret
+    pub fn unwrap_key(&self, recipient: &Uuid, secret: &x25519_dalek::StaticSecret) -> Result<VolumeCipher, StorageError> {
+        let wrapped = self.wrapped_keys
+            .iter()
+            .find(|wrapped| wrapped.recipient == *recipient)
+            .ok_or(StorageError::NotAuthorized(*recipient))?;
+
+        let shared = secret.diffie_hellman(&x25519_dalek::PublicKey::from(wrapped.ephemeral_public));
+        let recipient_public = x25519_dalek::PublicKey::from(secret);
+        let wrapping_key = derive_wrapping_key(shared.as_bytes(), &wrapped.ephemeral_public, recipient_public.as_bytes());
+
+        let key = ChaCha20Poly1305::new(Key::from_slice(&wrapping_key))
+            .decrypt(Nonce::from_slice(&wrapped.nonce), Payload {
+                msg: &wrapped.ciphertext,
+                aad: &wrap_aad(&self.volume_id, self.generation, recipient),
+            })
+            .map_err(|_| StorageError::Decryption(format!("Cannot unwrap key for {}", recipient)))?;
+
+        Ok(VolumeCipher {
+            volume_id: self.volume_id,
+            key: key.try_into().map_err(|_| StorageError::Decryption("Unwrapped key has wrong length".to_string()))?,
+        })
+    }
+
+    /// Re-wrap the data key for a new recipient set and bump the generation
+    ///
+    /// Recipients left out of the new set can no longer unwrap the key from
+    /// this envelope; the encrypted data is untouched.
+    pub fn rotate(&self, cipher: &VolumeCipher, recipients: &[(Uuid, x25519_dalek::PublicKey)]) -> Result<Self, StorageError> {
+        if cipher.volume_id != self.volume_id {
+            return Err(StorageError::InvalidRequest("Cipher belongs to a different volume".to_string()));
+        }
+        let generation = self.generation + 1;
+
+        Ok(Self {
+            volume_id: self.volume_id,
+            generation,
+            wrapped_keys: wrap_for(cipher, generation, recipients)?,
+        })
+    }
+
+    /// Recipients able to unwrap the key
+    pub fn recipients(&self) -> Vec<Uuid> {
+        self.wrapped_keys.iter().map(|wrapped| wrapped.recipient).collect()
+    }
+}
+
+impl VolumeCipher {
+    pub fn volume_id(&self) -> Uuid {
+        self.volume_id
+    }
+
+    /// Encrypt volume contents chunk by chunk
+    ///
+    /// Each content-defined chunk is sealed separately, STREAM style: the
+    /// associated data binds the volume, the chunk's index and whether it is
+    /// the last one, so chunks cannot be reordered, dropped or truncated
+    /// without failing authentication. Nonces are derived from a nonce subkey,
+    /// the chunk position and its plaintext. An unchanged chunk at the same
+    /// position therefore encrypts to the same bytes and still deduplicates
+    /// between versions of the volume, while nothing is shared across volumes
+    /// with different keys.
+    pub fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>, StorageError> {
+        let cipher = ChaCha20Poly1305::new(Key::from_slice(&self.chunk_key()));
+        let nonce_key = self.nonce_key();
+        let mut sealed = Vec::with_capacity(plaintext.len() + FRAME_HEADER * 2);
+
+        // Empty contents still get one final chunk so truncation to nothing is detected
+        let mut ranges = chunk_boundaries(plaintext, &ChunkingConfig::default());
+        if ranges.is_empty() {
+            ranges.push(0..0);
+        }
+        let last = ranges.len() - 1;
+
+        for (index, range) in ranges.into_iter().enumerate() {
+            let chunk = &plaintext[range];
+            let aad = chunk_aad(&self.volume_id, index as u64, index == last);
+            let mut hasher = blake3::Hasher::new_keyed(&nonce_key);
+            hasher.update(&aad);
+            hasher.update(chunk);
+            let mut nonce = [0u8; 12];
+            nonce.copy_from_slice(&hasher.finalize().as_bytes()[..12]);
+
+            let ciphertext = cipher
+                .encrypt(Nonce::from_slice(&nonce), Payload { msg: chunk, aad: &aad })
+                .map_err(|_| StorageError::Encryption(format!("Cannot seal chunk {} of volume {}", index, self.volume_id)))?;
+
+            sealed.extend_from_slice(&(ciphertext.len() as u32).to_be_bytes());
+            sealed.extend_from_slice(&nonce);
+            sealed.extend_from_slice(&ciphertext);
+        }
+        Ok(sealed)
+    }
+
+    /// Decrypt and authenticate contents produced by `encrypt`
+    pub fn decrypt(&self, sealed: &[u8]) -> Result<Vec<u8>, StorageError> {
+        let cipher = ChaCha20Poly1305::new(Key::from_slice(&self.chunk_key()));
+        let mut plaintext = Vec::with_capacity(sealed.len());
+        let mut rest = sealed;
+        let mut index = 0u64;
+
+        if rest.is_empty() {
+            return Err(StorageError::Decryption(format!("Contents of volume {} are missing their final chunk", self.volume_id)));
+        }
+        while !rest.is_empty() {
+            if rest.len() < FRAME_HEADER {
+                return Err(StorageError::Decryption("Truncated chunk header".to_string()));
+            }
+            let length = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+            let nonce = &rest[4..FRAME_HEADER];
+            let ciphertext = rest
+                .get(FRAME_HEADER..FRAME_HEADER + length)
+                .ok_or_else(|| StorageError::Decryption("Truncated chunk".to_string()))?;
+
+            let is_final = rest.len() == FRAME_HEADER + length;
+
+            let chunk = cipher
+                .decrypt(Nonce::from_slice(nonce), Payload {
+                    msg: ciphertext,
+                    aad: &chunk_aad(&self.volume_id, index, is_final),
+                })
+                .map_err(|_| StorageError::Decryption(format!("Chunk {} of volume {} failed authentication", index, self.volume_id)))?;
+
+            plaintext.extend_from_slice(&chunk);
+            rest = &rest[FRAME_HEADER + length..];
+            index += 1;
+        }
+        Ok(plaintext)
+    }
+
+    /// Subkey that seals chunks, kept apart from the wrapped data key
+    fn chunk_key(&self) -> [u8; 32] {
+        blake3::derive_key(CHUNK_KEY_CONTEXT, &self.key)
+    }
+
+    /// Subkey that derives chunk nonces
+    fn nonce_key(&self) -> [u8; 32] {
+        blake3::derive_key(NONCE_KEY_CONTEXT, &self.key)
+    }
+}
+
+impl TrustAwareStorageManager {
+    /// Record a volume's key envelope with its allocation
+    ///
+    /// Snapshots and clones are encrypted with their origin's data key, so
+    /// their envelope must belong to the origin volume. A replacement must
+    /// carry a newer generation, so a stale envelope cannot undo a rotation.
+    pub fn set_volume_key(&mut self, volume_id: &Uuid, envelope: VolumeKeyEnvelope) -> Result<(), StorageError> {
+        let allocation = self.allocations.get_mut(volume_id).ok_or(StorageError::VolumeNotFound(*volume_id))?;
+        check_envelope(allocation.volume_id, allocation.origin_volume_id, &envelope)?;
+        if let Some(current) = &allocation.key_envelope {
+            if envelope.generation <= current.generation {
+                return Err(StorageError::InvalidRequest(format!(
+                    "Key generation {} of volume {} does not replace generation {}",
+                    envelope.generation, volume_id, current.generation
+                )));
+            }
+        }
+        allocation.key_envelope = Some(envelope);
+        Ok(())
+    }
+
+    /// Key envelope recorded for a volume
+    pub fn volume_key(&self, volume_id: &Uuid) -> Option<&VolumeKeyEnvelope> {
+        self.allocations.get(volume_id)?.key_envelope.as_ref()
+    }
+}
+
+/// Check that an envelope wraps the data key a volume is encrypted with
+pub(crate) fn check_envelope(volume_id: Uuid, origin_volume_id: Option<Uuid>, envelope: &VolumeKeyEnvelope) -> Result<(), StorageError> {
+    if envelope.volume_id != origin_volume_id.unwrap_or(volume_id) {
+        return Err(StorageError::InvalidRequest(format!("Key envelope of volume {} does not belong to volume {}", envelope.volume_id, volume_id)));
+    }
+    Ok(())
+}
+
+/// Binds a chunk to its volume, its position and whether it ends the contents
+fn chunk_aad(volume_id: &Uuid, index: u64, is_final: bool) -> [u8; 25] {
+    let mut aad = [0u8; 25];
+    aad[..16].copy_from_slice(volume_id.as_bytes());
+    aad[16..24].copy_from_slice(&index.to_be_bytes());
+    aad[24] = is_final as u8;
+    aad
+}
+
+fn wrap_for(cipher: &VolumeCipher, generation: u32, recipients: &[(Uuid, x25519_dalek::PublicKey)]) -> Result<Vec<WrappedKey>, StorageError> {
+    recipients
+        .iter()
+        .map(|(recipient, public_key)| {
+            let ephemeral = x25519_dalek::EphemeralSecret::random_from_rng(rand::rngs::OsRng);
+            let ephemeral_public = x25519_dalek::PublicKey::from(&ephemeral);
+            let shared = ephemeral.diffie_hellman(public_key);
+            let wrapping_key = derive_wrapping_key(shared.as_bytes(), ephemeral_public.as_bytes(), public_key.as_bytes());
+
+            let mut nonce = [0u8; 12];
+            rand::rngs::OsRng.fill_bytes(&mut nonce);
+
+            let ciphertext = ChaCha20Poly1305::new(Key::from_slice(&wrapping_key))
+                .encrypt(Nonce::from_slice(&nonce), Payload {
+                    msg: &cipher.key,
+                    aad: &wrap_aad(&cipher.volume_id, generation, recipient),
+                })
+                .map_err(|_| StorageError::Encryption(format!("Cannot wrap key for {}", recipient)))?;
+
+            Ok(WrappedKey {
+                recipient: *recipient,
+                ephemeral_public: *ephemeral_public.as_bytes(),
+                nonce,
+                ciphertext,
+            })
+        })
+        .collect()
+}
+
+fn derive_wrapping_key(shared: &[u8; 32], ephemeral_public: &[u8; 32], recipient_public: &[u8; 32]) -> [u8; 32] {
+    let mut material = Vec::with_capacity(96);
+    material.extend_from_slice(shared);
+    material.extend_from_slice(ephemeral_public);
+    material.extend_from_slice(recipient_public);
+    blake3::derive_key("mycnet-storage volume key wrap v1", &material)
+}
+
+/// Binds a wrapped key to its volume, generation and recipient
+fn wrap_aad(volume_id: &Uuid, generation: u32, recipient: &Uuid) -> Vec<u8> {
+    let mut aad = Vec::with_capacity(36);
+    aad.extend_from_slice(volume_id.as_bytes());
+    aad.extend_from_slice(&generation.to_be_bytes());
+    aad.extend_from_slice(recipient.as_bytes());
+    aad
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recipient() -> (Uuid, x25519_dalek::StaticSecret, x25519_dalek::PublicKey) {
+        let secret = x25519_dalek::StaticSecret::random_from_rng(rand::rngs::OsRng);
+        let public = x25519_dalek::PublicKey::from(&secret);
+        (Uuid::new_v4(), secret, public)
+    }
+
+    #[test]
+    fn test_wrapped_key_round_trip_and_tamper_detection() {
+        let volume_id = Uuid::new_v4();
+        let (node, secret, public) = recipient();
+        let (outsider, outsider_secret, _) = recipient();
+
+        let (envelope, cipher) = VolumeKeyEnvelope::generate(volume_id, &[(node, public)]).unwrap();
+        let data: Vec<u8> = (0..50_000u32).map(|i| (i % 97) as u8).collect();
+        let sealed = cipher.encrypt(&data).unwrap();
+        assert!(!sealed.windows(64).any(|window| window == &data[..64]));
+
+        let unwrapped = envelope.unwrap_key(&node, &secret).unwrap();
+        assert_eq!(unwrapped.decrypt(&sealed).unwrap(), data);
+        assert!(matches!(envelope.unwrap_key(&outsider, &outsider_secret), Err(StorageError::NotAuthorized(_))));
+
+        let mut tampered = sealed.clone();
+        let last = tampered.len() - 1;
+        tampered[last] ^= 1;
+        assert!(matches!(unwrapped.decrypt(&tampered), Err(StorageError::Decryption(_))));
+    }
+
+    fn frames(sealed: &[u8]) -> Vec<&[u8]> {
+        let mut frames = Vec::new();
+        let mut rest = sealed;
+        while !rest.is_empty() {
+            let length = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+            frames.push(&rest[..FRAME_HEADER + length]);
+            rest = &rest[FRAME_HEADER + length..];
+        }
+        frames
+    }
+
+    #[test]
+    fn test_reordered_or_truncated_chunks_are_rejected() {
+        let (envelope, cipher) = VolumeKeyEnvelope::generate(Uuid::new_v4(), &[]).unwrap();
+        assert!(envelope.recipients().is_empty());
+        let data: Vec<u8> = (0..200_000u32).map(|i| (i.wrapping_mul(2654435761) >> 13) as u8).collect();
+        let sealed = cipher.encrypt(&data).unwrap();
+        let frames = frames(&sealed);
+        assert!(frames.len() > 2);
+
+        // Dropping the tail leaves a chunk that was not sealed as final
+        let truncated: Vec<u8> = frames[..frames.len() - 1].concat();
+        assert!(cipher.decrypt(&truncated).is_err());
+
+        let mut swapped = frames.clone();
+        swapped.swap(0, 1);
+        assert!(cipher.decrypt(&swapped.concat()).is_err());
+
+        // Empty contents are sealed too, so stripping everything is detected
+        let empty = cipher.encrypt(b"").unwrap();
+        assert_eq!(cipher.decrypt(&empty).unwrap(), b"");
+        assert!(cipher.decrypt(b"").is_err());
+    }
+
+    #[test]
+    fn test_rotation_rewraps_without_reencrypting() {
+        let volume_id = Uuid::new_v4();
+        let (old_node, old_secret, old_public) = recipient();
+        let (new_node, new_secret, new_public) = recipient();
+
+        let (envelope, cipher) = VolumeKeyEnvelope::generate(volume_id, &[(old_node, old_public)]).unwrap();
+        let sealed = cipher.encrypt(b"long lived data").unwrap();
+
+        let rotated = envelope.rotate(&cipher, &[(new_node, new_public)]).unwrap();
+        assert_eq!(rotated.generation, 1);
+        assert_eq!(rotated.recipients(), vec![new_node]);
+        assert!(rotated.unwrap_key(&old_node, &old_secret).is_err());
+
+        // Existing ciphertext stays readable under the rotated envelope
+        let unwrapped = rotated.unwrap_key(&new_node, &new_secret).unwrap();
+        assert_eq!(unwrapped.decrypt(&sealed).unwrap(), b"long lived data");
+
+        // Deterministic chunk nonces keep ciphertext stable for unchanged data
+        assert_eq!(unwrapped.encrypt(b"long lived data").unwrap(), sealed);
+    }
+}
@@ -0,0 +1,503 @@
+//! Reed-Solomon erasure coding as an alternative to full replication
+
+use crate::volume_io::send_with_timeout;
+use crate::{FragmentGeneration, ReplicaRequest, ReplicaResponse, ReplicaTransport, ReplicationPlan, StorageError, VolumeCipher};
+use futures::future::join_all;
+use reed_solomon_erasure::galois_8::ReedSolomon;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Split data into `data_shards` fragments plus `parity_shards` recovery fragments
+///
+/// Any `data_shards` of the `data_shards + parity_shards` fragments rebuild the
+/// volume, so up to `parity_shards` fragment nodes can be lost. Instances are
+/// only built through `new` or deserialization, both of which validate the
+/// shard counts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "ShardCounts")]
+pub struct ErasureCoding {
+    data_shards: usize,
+    parity_shards: usize,
+}
+
+/// Unvalidated wire form of `ErasureCoding`
+#[derive(Deserialize)]
+#[cfg_attr(test, derive(Serialize))]
+struct ShardCounts {
+    data_shards: usize,
+    parity_shards: usize,
+}
+
+impl TryFrom<ShardCounts> for ErasureCoding {
+    type Error = StorageError;
+
+    fn try_from(counts: ShardCounts) -> Result<Self, StorageError> {
+        Self::new(counts.data_shards, counts.parity_shards)
+    }
+}
+
+/// Bytes prepended to the data to record its length before padding
+const LENGTH_PREFIX: usize = 8;
+
+impl ErasureCoding {
+    /// Validate a (k, m) configuration
+    pub fn new(data_shards: usize, parity_shards: usize) -> Result<Self, StorageError> {
+        let coding = Self {
+            data_shards,
+            parity_shards,
+        };
+        coding.codec()?;
+        Ok(coding)
+    }
+
+    /// Fragments needed to rebuild the volume
+    pub fn data_shards(&self) -> usize {
+        self.data_shards
+    }
+
+    /// Fragments that can be lost without losing the volume
+    pub fn parity_shards(&self) -> usize {
+        self.parity_shards
+    }
+
+    /// Number of fragments, and therefore nodes, per volume
+    pub fn total_shards(&self) -> usize {
+        self.data_shards + self.parity_shards
+    }
+
+    /// Stored bytes per byte of data
+    pub fn storage_overhead(&self) -> f64 {
+        self.total_shards() as f64 / self.data_shards as f64
+    }
+
+    /// Size of each fragment for data of a given length; `None` on overflow
+    pub fn shard_size(&self, data_len: u64) -> Option<u64> {
+        Some(data_len.checked_add(LENGTH_PREFIX as u64)?.div_ceil(self.data_shards as u64))
+    }
+
+    /// Capacity all fragments of a volume occupy; `None` on overflow
+    pub fn reserved_capacity(&self, data_len: u64) -> Option<u64> {
+        self.shard_size(data_len)?.checked_mul(self.total_shards() as u64)
+    }
+
+    /// Encode data into `total_shards` equally sized fragments
+    pub fn encode(&self, data: &[u8]) -> Result<Vec<Vec<u8>>, StorageError> {
+        let shard_size = self
+            .shard_size(data.len() as u64)
+            .ok_or_else(|| StorageError::ErasureCoding("Data is too large to encode".to_string()))? as usize;
+
+        let mut padded = Vec::with_capacity(shard_size * self.total_shards());
+        padded.extend_from_slice(&(data.len() as u64).to_be_bytes());
+        padded.extend_from_slice(data);
+        padded.resize(shard_size * self.data_shards, 0);
+
+        let mut shards: Vec<Vec<u8>> = padded.chunks(shard_size).map(<[u8]>::to_vec).collect();
+        shards.resize(self.total_shards(), vec![0u8; shard_size]);
+
+        self.codec()?.encode(&mut shards).map_err(erasure_error)?;
+        Ok(shards)
+    }
+
+    /// Recompute every missing fragment in place; needs at least `data_shards` present
+    pub fn reconstruct(&self, shards: &mut [Option<Vec<u8>>]) -> Result<(), StorageError> {
+        let available = shards.iter().filter(|shard| shard.is_some()).count();
+        if available < self.data_shards {
+            return Err(StorageError::InsufficientFragments {
+                available,
+                required: self.data_shards,
+            });
+        }
+        self.codec()?.reconstruct(shards).map_err(erasure_error)
+    }
+
+    /// Recover the original data from any `data_shards` fragments
+    pub fn decode(&self, mut shards: Vec<Option<Vec<u8>>>) -> Result<Vec<u8>, StorageError> {
+        self.reconstruct(&mut shards)?;
+
+        let mut data: Vec<u8> = shards.into_iter().take(self.data_shards).flatten().flatten().collect();
+        let length_bytes: [u8; LENGTH_PREFIX] = data
+            .get(..LENGTH_PREFIX)
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or_else(|| StorageError::ErasureCoding("Fragments are too short".to_string()))?;
+        let end = usize::try_from(u64::from_be_bytes(length_bytes))
+            .ok()
+            .and_then(|length| length.checked_add(LENGTH_PREFIX))
+            .filter(|end| *end <= data.len())
+            .ok_or_else(|| StorageError::ErasureCoding("Recorded length exceeds fragment data".to_string()))?;
+        data.truncate(end);
+        Ok(data.split_off(LENGTH_PREFIX))
+    }
+
+    fn codec(&self) -> Result<ReedSolomon, StorageError> {
+        ReedSolomon::new(self.data_shards, self.parity_shards).map_err(erasure_error)
+    }
+}
+
+fn erasure_error(e: reed_solomon_erasure::Error) -> StorageError {
+    StorageError::ErasureCoding(format!("{:?}", e))
+}
+
+/// Encrypt and encode a volume and store one fragment on each node of its plan
+///
+/// Volumes whose plan requires encryption are sealed with `cipher` first and
+/// refused without one, the same rule `VolumeIo` applies to replicas.
+/// Snapshots are read-only and refuse every write, and the transport's
+/// credential must be allowed to use the volume; replicas check it again
+/// against the access published with `VolumeIo::publish_access`.
+pub async fn write_fragments<T: ReplicaTransport>(
+    transport: &T,
+    plan: &ReplicationPlan,
+    coding: &ErasureCoding,
+    cipher: Option<&VolumeCipher>,
+    data: &[u8],
+    timeout: Duration,
+) -> Result<(), StorageError> {
+    plan.authorize(&transport.credential().accessor)?;
+    if plan.read_only {
+        return Err(StorageError::ReadOnlyVolume(plan.volume_id));
+    }
+    match cipher {
+        Some(cipher) => store_fragments(transport, plan, coding, &cipher.encrypt(data)?, timeout).await,
+        None if plan.encryption_required => Err(StorageError::EncryptionRequired(plan.volume_id)),
+        None => store_fragments(transport, plan, coding, data, timeout).await,
+    }
+}
+
+/// Encode stored volume contents, already encrypted if the plan requires it,
+/// and write every fragment concurrently under a new generation
+///
+/// Succeeds only when every fragment is stored, so the volume starts with
+/// its full tolerance of `parity_shards` lost nodes. Earlier generations stay
+/// readable until the new one is complete and are only then committed away;
+/// fragments of a failed write are discarded again.
+pub(crate) async fn store_fragments<T: ReplicaTransport>(
+    transport: &T,
+    plan: &ReplicationPlan,
+    coding: &ErasureCoding,
+    stored: &[u8],
+    timeout: Duration,
+) -> Result<(), StorageError> {
+    let nodes = plan.nodes();
+    if nodes.len() != coding.total_shards() {
+        return Err(StorageError::InvalidRequest(format!(
+            "Plan has {} nodes but erasure coding needs {}",
+            nodes.len(),
+            coding.total_shards()
+        )));
+    }
+
+    let held = fetch_generations(transport, plan, timeout).await;
+    let generation = FragmentGeneration {
+        sequence: held.iter().flatten().map(|generation| generation.sequence).max().map_or(0, |sequence| sequence + 1),
+        writer: Uuid::new_v4(),
+    };
+
+    let writes = nodes.iter().zip(coding.encode(stored)?).enumerate().map(|(index, (node_id, fragment))| {
+        let request = ReplicaRequest::WriteFragment {
+            volume_id: plan.volume_id,
+            index,
+            generation,
+            data: fragment,
+        };
+        async move { (index, *node_id, send_with_timeout(transport, *node_id, request, timeout).await.is_ok()) }
+    });
+    let results = join_all(writes).await;
+
+    let stored: Vec<(usize, Uuid)> = results.iter().filter(|(_, _, ok)| *ok).map(|(index, node_id, _)| (*index, *node_id)).collect();
+    let complete = stored.len() == nodes.len();
+    let finish = stored.iter().map(|(index, node_id)| {
+        let (volume_id, index) = (plan.volume_id, *index);
+        let request = if complete {
+            ReplicaRequest::CommitFragment { volume_id, index, generation }
+        } else {
+            ReplicaRequest::DiscardFragment { volume_id, index, generation }
+        };
+        send_with_timeout(transport, *node_id, request, timeout)
+    });
+    for result in join_all(finish).await {
+        match result {
+            Err(e) if complete => tracing::warn!("Earlier fragments of volume {} were not removed: {}", plan.volume_id, e),
+            Err(e) => tracing::warn!("Partial fragment of volume {} was not removed: {}", plan.volume_id, e),
+            Ok(_) => {},
+        }
+    }
+
+    if !complete {
+        return Err(StorageError::ReplicationFailed {
+            acknowledged: stored.len(),
+            required: nodes.len(),
+        });
+    }
+    Ok(())
+}
+
+/// Generations each of the plan's nodes holds of its fragment; unreachable nodes hold none
+async fn fetch_generations<T: ReplicaTransport>(transport: &T, plan: &ReplicationPlan, timeout: Duration) -> Vec<Vec<FragmentGeneration>> {
+    let queries = plan.nodes().into_iter().enumerate().map(|(index, node_id)| {
+        let request = ReplicaRequest::FragmentGenerations {
+            volume_id: plan.volume_id,
+            index,
+        };
+        async move {
+            match send_with_timeout(transport, node_id, request, timeout).await {
+                Ok(ReplicaResponse::Generations(generations)) => generations,
+                _ => Vec::new(),
+            }
+        }
+    });
+    join_all(queries).await
+}
+
+/// Newest generation with at least `data_shards` fragments outside `excluded` positions
+async fn readable_generation<T: ReplicaTransport>(
+    transport: &T,
+    plan: &ReplicationPlan,
+    coding: &ErasureCoding,
+    excluded: impl Fn(usize) -> bool,
+    timeout: Duration,
+) -> Result<FragmentGeneration, StorageError> {
+    let mut holders: HashMap<FragmentGeneration, usize> = HashMap::new();
+    for (position, generations) in fetch_generations(transport, plan, timeout).await.into_iter().enumerate() {
+        if !excluded(position) {
+            for generation in generations {
+                *holders.entry(generation).or_default() += 1;
+            }
+        }
+    }
+
+    holders
+        .iter()
+        .filter(|(_, count)| **count >= coding.data_shards())
+        .map(|(generation, _)| *generation)
+        .max()
+        .ok_or_else(|| StorageError::InsufficientFragments {
+            available: holders.values().copied().max().unwrap_or(0),
+            required: coding.data_shards(),
+        })
+}
+
+/// Fetch one generation of the fragments from the plan's nodes concurrently; unreachable or missing fragments are `None`
+pub async fn fetch_fragments<T: ReplicaTransport>(
+    transport: &T,
+    plan: &ReplicationPlan,
+    generation: FragmentGeneration,
+    timeout: Duration,
+) -> Vec<Option<Vec<u8>>> {
+    let reads = plan.nodes().into_iter().enumerate().map(|(index, node_id)| {
+        let request = ReplicaRequest::ReadFragment {
+            volume_id: plan.volume_id,
+            index,
+            generation,
+        };
+        async move {
+            match send_with_timeout(transport, node_id, request, timeout).await {
+                Ok(ReplicaResponse::Fragment(fragment)) => fragment,
+                _ => None,
+            }
+        }
+    });
+    join_all(reads).await
+}
+
+/// Read and decrypt a volume, reconstructing it when some fragments are unavailable
+///
+/// The transport's credential must be allowed to use the volume.
+pub async fn read_fragments<T: ReplicaTransport>(
+    transport: &T,
+    plan: &ReplicationPlan,
+    coding: &ErasureCoding,
+    cipher: Option<&VolumeCipher>,
+    timeout: Duration,
+) -> Result<Vec<u8>, StorageError> {
+    plan.authorize(&transport.credential().accessor)?;
+    let stored = load_fragments(transport, plan, coding, timeout).await?;
+    match cipher {
+        Some(cipher) => cipher.decrypt(&stored),
+        None if plan.encryption_required => Err(StorageError::EncryptionRequired(plan.volume_id)),
+        None => Ok(stored),
+    }
+}
+
+/// Stored volume contents decoded from its fragments, still encrypted if the plan requires it
+///
+/// Only fragments of the newest generation that enough nodes hold are decoded.
+pub(crate) async fn load_fragments<T: ReplicaTransport>(
+    transport: &T,
+    plan: &ReplicationPlan,
+    coding: &ErasureCoding,
+    timeout: Duration,
+) -> Result<Vec<u8>, StorageError> {
+    let generation = readable_generation(transport, plan, coding, |_| false, timeout).await?;
+    coding.decode(fetch_fragments(transport, plan, generation, timeout).await)
+}
+
+/// Rebuild fragment `index` from the surviving fragments and store it on `target`
+///
+/// Fragments held by `excluded_nodes` and the fragment being replaced are
+/// never used as sources, and the fragment is rebuilt in the newest
+/// generation the remaining sources can decode. Returns the size of the
+/// rebuilt fragment.
+pub async fn rebuild_fragment<T: ReplicaTransport>(
+    transport: &T,
+    plan: &ReplicationPlan,
+    coding: &ErasureCoding,
+    index: usize,
+    target: Uuid,
+    excluded_nodes: &HashSet<Uuid>,
+    timeout: Duration,
+) -> Result<u64, StorageError> {
+    let nodes = plan.nodes();
+    let excluded = |position: usize| position == index || nodes.get(position).is_some_and(|node_id| excluded_nodes.contains(node_id));
+    let generation = readable_generation(transport, plan, coding, excluded, timeout).await?;
+    let mut fragments = fetch_fragments(transport, plan, generation, timeout).await;
+    for (position, fragment) in fragments.iter_mut().enumerate() {
+        if excluded(position) {
+            *fragment = None;
+        }
+    }
+    coding.reconstruct(&mut fragments)?;
+
+    let fragment = fragments
+        .into_iter()
+        .nth(index)
+        .flatten()
+        .ok_or_else(|| StorageError::InvalidRequest(format!("Fragment index {} is out of range", index)))?;
+    let size = fragment.len() as u64;
+
+    let request = ReplicaRequest::WriteFragment {
+        volume_id: plan.volume_id,
+        index,
+        generation,
+        data: fragment,
+    };
+    send_with_timeout(transport, target, request, timeout).await?;
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{network_transport, plan, replica_nodes};
+    use crate::volume_io::fragment_key;
+    use crate::{ReplicationStrategy, VolumeKeyEnvelope};
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    #[test]
+    fn test_encode_decode_with_missing_fragments() {
+        let coding = ErasureCoding::new(4, 2).unwrap();
+        let data: Vec<u8> = (0..10_000u32).map(|i| (i % 251) as u8).collect();
+
+        let shards = coding.encode(&data).unwrap();
+        assert_eq!(shards.len(), 6);
+        assert_eq!(coding.storage_overhead(), 1.5);
+
+        let mut partial: Vec<Option<Vec<u8>>> = shards.into_iter().map(Some).collect();
+        partial[0] = None;
+        partial[5] = None;
+        assert_eq!(coding.decode(partial.clone()).unwrap(), data);
+
+        partial[2] = None;
+        assert!(matches!(
+            coding.decode(partial),
+            Err(StorageError::InsufficientFragments { available: 3, required: 4 })
+        ));
+        assert!(ErasureCoding::new(0, 2).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_fragments_read_and_rebuilt_over_transport() {
+        let coding = ErasureCoding::new(2, 1).unwrap();
+        let mut transport = network_transport();
+        let nodes = replica_nodes(&mut transport, 4);
+        let mut plan = plan(&nodes[..3], ReplicationStrategy::HierarchyAware);
+
+        write_fragments(&transport, &plan, &coding, None, b"erasure coded volume", TIMEOUT).await.unwrap();
+
+        transport.set_available(nodes[1], false);
+        assert_eq!(read_fragments(&transport, &plan, &coding, None, TIMEOUT).await.unwrap(), b"erasure coded volume");
+
+        // Rebuild the lost fragment onto the spare node and drop the failed one
+        let excluded = HashSet::from([nodes[1]]);
+        rebuild_fragment(&transport, &plan, &coding, 1, nodes[3], &excluded, TIMEOUT).await.unwrap();
+        plan.replica_nodes[0] = nodes[3];
+        transport.set_available(nodes[2], false);
+        assert_eq!(read_fragments(&transport, &plan, &coding, None, TIMEOUT).await.unwrap(), b"erasure coded volume");
+
+        // A failed write leaves no partial fragments behind
+        let failed_plan = ReplicationPlan {
+            volume_id: Uuid::new_v4(),
+            ..plan.clone()
+        };
+        let err = write_fragments(&transport, &failed_plan, &coding, None, b"partial", TIMEOUT).await.unwrap_err();
+        assert!(matches!(err, StorageError::ReplicationFailed { acknowledged: 2, required: 3 }));
+        for node_id in [nodes[0], nodes[3]] {
+            assert!(transport.node(&node_id).unwrap().fragment_generations(&failed_plan.volume_id, 0).unwrap().is_empty());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failed_overwrite_keeps_the_previous_generation() {
+        let coding = ErasureCoding::new(2, 1).unwrap();
+        let mut transport = network_transport();
+        let nodes = replica_nodes(&mut transport, 3);
+        let plan = plan(&nodes, ReplicationStrategy::HierarchyAware);
+
+        write_fragments(&transport, &plan, &coding, None, b"first contents", TIMEOUT).await.unwrap();
+        let first = transport.node(&nodes[0]).unwrap().fragment_generations(&plan.volume_id, 0).unwrap();
+
+        // The overwrite reaches two nodes, which is enough to decode, but is discarded again
+        transport.set_available(nodes[2], false);
+        assert!(write_fragments(&transport, &plan, &coding, None, b"second contents", TIMEOUT).await.is_err());
+        transport.set_available(nodes[2], true);
+        assert_eq!(read_fragments(&transport, &plan, &coding, None, TIMEOUT).await.unwrap(), b"first contents");
+
+        // A complete overwrite replaces the previous generation everywhere
+        write_fragments(&transport, &plan, &coding, None, b"third contents", TIMEOUT).await.unwrap();
+        for (index, node_id) in nodes.iter().enumerate() {
+            let node = transport.node(node_id).unwrap();
+            let generations = node.fragment_generations(&plan.volume_id, index).unwrap();
+            assert_eq!(generations.len(), 1);
+            assert!(generations[0] > first[0]);
+            let stale = node.store().manifest(&fragment_key(&plan.volume_id, index, &first[0])).unwrap();
+            assert!(stale.is_none());
+        }
+
+        // Fragments of different generations are never decoded together
+        let node = transport.node(&nodes[0]).unwrap();
+        node.write_fragment(&plan.volume_id, 0, FragmentGeneration { sequence: 99, writer: Uuid::new_v4() }, b"stray").unwrap();
+        assert_eq!(read_fragments(&transport, &plan, &coding, None, TIMEOUT).await.unwrap(), b"third contents");
+    }
+
+    #[tokio::test]
+    async fn test_encrypted_plans_require_a_cipher() {
+        let coding = ErasureCoding::new(2, 1).unwrap();
+        let mut transport = network_transport();
+        let nodes = replica_nodes(&mut transport, 3);
+        let plan = ReplicationPlan {
+            encryption_required: true,
+            ..plan(&nodes, ReplicationStrategy::HierarchyAware)
+        };
+
+        let err = write_fragments(&transport, &plan, &coding, None, b"secret", TIMEOUT).await.unwrap_err();
+        assert!(matches!(err, StorageError::EncryptionRequired(_)));
+
+        let (_, cipher) = VolumeKeyEnvelope::generate(plan.volume_id, &[]).unwrap();
+        write_fragments(&transport, &plan, &coding, Some(&cipher), b"secret", TIMEOUT).await.unwrap();
+        let stored = load_fragments(&transport, &plan, &coding, TIMEOUT).await.unwrap();
+        assert!(!stored.windows(6).any(|window| window == b"secret"));
+        assert!(read_fragments(&transport, &plan, &coding, None, TIMEOUT).await.is_err());
+        assert_eq!(read_fragments(&transport, &plan, &coding, Some(&cipher), TIMEOUT).await.unwrap(), b"secret");
+    }
+
+    #[test]
+    fn test_invalid_shard_counts_never_deserialize() {
+        let encoded = bincode::serialize(&ShardCounts { data_shards: 0, parity_shards: 2 }).unwrap();
+        assert!(bincode::deserialize::<ErasureCoding>(&encoded).is_err());
+        let encoded = bincode::serialize(&ShardCounts { data_shards: 4, parity_shards: 2 }).unwrap();
+        assert_eq!(bincode::deserialize::<ErasureCoding>(&encoded).unwrap(), ErasureCoding::new(4, 2).unwrap());
+        assert_eq!(ErasureCoding::new(4, 2).unwrap().reserved_capacity(u64::MAX), None);
+    }
+}
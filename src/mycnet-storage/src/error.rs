@@ -0,0 +1,116 @@
+//! Typed errors for storage operations
+
+use crate::quota::{QuotaResource, QuotaScope};
+use uuid::Uuid;
+
+/// Errors returned by storage placement and management
+#[derive(Debug, thiserror::Error)]
+pub enum StorageError {
+    #[error("Invalid storage request: {0}")]
+    InvalidRequest(String),
+
+    #[error("Storage pool {pool_id} has no eligible nodes")]
+    NoEligibleNodes { pool_id: String },
+
+    #[error("Placement needs {required} nodes but only {available} are eligible")]
+    InsufficientNodes { required: usize, available: usize },
+
+    #[error("Placement needs {required} distinct failure domains but only {available} are available")]
+    InsufficientFailureDomains { required: usize, available: usize },
+
+    #[error("Placement needs {required} distinct geographic zones but only {available} are available")]
+    InsufficientZones { required: usize, available: usize },
+
+    #[error("Unknown storage node {0}")]
+    UnknownNode(Uuid),
+
+    #[error("No storage pool with trust {minimum_trust_score} or higher can reserve {required_capacity} bytes")]
+    NoSuitablePool { minimum_trust_score: f32, required_capacity: u64 },
+
+    #[error("Storage pool {0} not found")]
+    PoolNotFound(String),
+
+    #[error("Storage pool {0} is already registered")]
+    DuplicatePool(String),
+
+    #[error("Storage pool {pool_id} still holds {volumes} volumes")]
+    PoolInUse { pool_id: String, volumes: usize },
+
+    #[error("Volume {0} is already allocated")]
+    VolumeAlreadyAllocated(Uuid),
+
+    #[error("Volume {0} not found")]
+    VolumeNotFound(Uuid),
+
+    #[error("Chunk {0} not found")]
+    ChunkNotFound(String),
+
+    #[error("Chunk {0} failed integrity verification")]
+    ChunkCorrupted(String),
+
+    #[error("Chunk of {0} bytes exceeds the 4 GiB chunk size limit")]
+    ChunkTooLarge(usize),
+
+    #[error("Node {node_id} has trust {trust_score}, below the {required} the volume's classification requires")]
+    UntrustedPlacement { node_id: Uuid, trust_score: f32, required: f32 },
+
+    #[error("Service {service_id} already has a volume named {name}")]
+    VolumeNameTaken { service_id: String, name: String },
+
+    #[error("Access to volume {volume_id} denied for {accessor}")]
+    AccessDenied { volume_id: Uuid, accessor: String },
+
+    #[error("Storage spore of service {0} has no valid signature")]
+    InvalidSporeSignature(String),
+
+    #[error("Credential for {0} is not signed by the storage authority")]
+    InvalidCredential(String),
+
+    #[error("Only the network may send maintenance requests, not {0}")]
+    MaintenanceDenied(String),
+
+    #[error("Quota for {scope} exceeded: {resource} would reach {requested}, limit is {limit}")]
+    QuotaExceeded { scope: QuotaScope, resource: QuotaResource, requested: u64, limit: u64 },
+
+    #[error("Replica node {0} is unavailable")]
+    ReplicaUnavailable(Uuid),
+
+    #[error("Only {acknowledged} of {required} required replicas acknowledged")]
+    ReplicationFailed { acknowledged: usize, required: usize },
+
+    #[error("No reachable replica of volume {0} has caught up with this session")]
+    CausalReadUnavailable(Uuid),
+
+    #[error("No healthy replica of volume {0} is reachable")]
+    NoHealthyReplica(Uuid),
+
+    #[error("Erasure coding error: {0}")]
+    ErasureCoding(String),
+
+    #[error("Only {available} fragments are available, {required} are needed")]
+    InsufficientFragments { available: usize, required: usize },
+
+    #[error("Volume {0} requires encryption but no data key is registered")]
+    EncryptionRequired(Uuid),
+
+    #[error("Volume {0} is a read-only snapshot")]
+    ReadOnlyVolume(Uuid),
+
+    #[error("Volume {0} changed while it was being copied")]
+    VersionConflict(Uuid),
+
+    #[error("Node {0} is not authorized for this volume key")]
+    NotAuthorized(Uuid),
+
+    #[error("Encryption failed: {0}")]
+    Encryption(String),
+
+    #[error("Decryption failed: {0}")]
+    Decryption(String),
+
+    #[error("Storage backend error: {0}")]
+    Backend(#[from] sled::Error),
+
+    #[error("Serialization error: {0}")]
+    Serialization(#[from] bincode::Error),
+}
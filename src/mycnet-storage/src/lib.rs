@@ -1,9 +1,51 @@
 //! Mycnet Storage - Trust-aware distributed storage system
 
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
 use uuid::Uuid;
 
+use metrics::StorageMetrics;
+use volume_io::send_with_timeout;
+
+pub mod chunk_store;
+pub mod csi;
+pub mod encryption;
+pub mod erasure;
+pub mod error;
+pub mod metrics;
+pub mod placement;
+pub mod quota;
+pub mod repair;
+pub mod scrub;
+pub mod service;
+pub mod snapshot;
+pub mod tiering;
+pub mod volume_io;
+
+#[cfg(test)]
+mod test_support;
+
+pub use chunk_store::{chunk_boundaries, ChunkId, ChunkRef, ChunkStore, ChunkingConfig, GcStats, MetadataRecord, VerifyBatch, VolumeManifest};
+pub use csi::{CsiController, CsiNode, CsiStatus, StorageClassParameters, VolumeMounter};
+pub use encryption::{VolumeCipher, VolumeKeyEnvelope, WrappedKey};
+pub use erasure::ErasureCoding;
+pub use error::StorageError;
+pub use metrics::{
+    IoOperation, LatencyHistogram, PoolStatus, RepairTotals, ScrubTotals, StorageStatus, VolumeHealth, VolumeStatus, LATENCY_BUCKETS,
+};
+pub use placement::{place_replicas, NodeTier, Placement, PlacementCandidate, PlacementConstraints, StorageNode};
+pub use quota::{QuotaReport, QuotaResource, QuotaScope, QuotaUsage, StorageQuota};
+pub use repair::{RepairAction, RepairConfig, RepairController, RepairReport, UnderReplicatedVolume};
+pub use scrub::{ScrubConfig, ScrubReport, Scrubber};
+pub use service::{AccessCredential, ServiceStorageSpore, ServiceVolumeRecord, StorageAccessor, VolumeAccess};
+pub use snapshot::VolumeSnapshot;
+pub use tiering::{TierMigration, TierMove, TierPolicy, TieringConfig, TieringController, TieringReport, VolumeHeat};
+pub use volume_io::{
+    ClientSession, FragmentGeneration, InMemoryTransport, ReplicaNode, ReplicaRequest, ReplicaResponse, ReplicaTransport, StrongAck, VersionVector,
+    VersionedWrite, VolumeDigest, VolumeIo, VolumeIoConfig, WriteReceipt,
+};
+
 /// Storage allocation request with trust requirements
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StorageRequest {
@@ -11,10 +53,14 @@ pub struct StorageRequest {
     pub size_bytes: u64,
     pub data_classification: DataClassification,
     pub replication_requirements: ReplicationRequirements,
+    /// Service the volume is charged to for quotas
+    pub service_id: Option<String>,
+    /// Network the volume is charged to for quotas
+    pub network_id: Option<String>,
 }
 
 /// Data classification levels affecting trust requirements
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum DataClassification {
     Critical,   // Requires high-trust nodes only
     Sensitive,  // Requires medium to high trust
@@ -28,6 +74,27 @@ pub struct ReplicationRequirements {
     pub replica_count: usize,
     pub consistency_level: ConsistencyLevel,
     pub geographic_distribution: bool,
+    pub replication_strategy: ReplicationStrategy,
+    /// Store Reed-Solomon fragments instead of `replica_count` full copies
+    pub erasure_coding: Option<ErasureCoding>,
+}
+
+impl ReplicationRequirements {
+    /// Number of nodes a volume is placed on: one per copy or per fragment
+    pub fn node_count(&self) -> usize {
+        match &self.erasure_coding {
+            Some(coding) => coding.total_shards(),
+            None => self.replica_count,
+        }
+    }
+
+    /// Pool capacity a volume of `size_bytes` occupies across all nodes
+    pub fn reserved_capacity(&self, size_bytes: u64) -> Option<u64> {
+        match &self.erasure_coding {
+            Some(coding) => coding.reserved_capacity(size_bytes),
+            None => size_bytes.checked_mul(self.replica_count as u64),
+        }
+    }
 }
 
 /// Storage consistency levels
@@ -43,6 +110,25 @@ pub struct TrustAwareStorageManager {
     storage_pools: HashMap<String, StoragePool>,
     trust_evaluator: TrustEvaluator,
     replication_manager: ReplicationManager,
+    allocations: HashMap<Uuid, StorageAllocation>,
+    snapshots: HashMap<Uuid, VolumeSnapshot>,
+    quotas: HashMap<QuotaScope, StorageQuota>,
+    overcommit_policy: OvercommitPolicy,
+    access_heat: HashMap<Uuid, VolumeHeat>,
+    tier_pins: HashMap<Uuid, NodeTier>,
+    tier_policy: TierPolicy,
+    /// Volume names per service, by service ID
+    service_namespaces: HashMap<String, HashMap<String, Uuid>>,
+    metrics: StorageMetrics,
+    /// How long a node may take to answer the manager's copy and delete requests
+    request_timeout: Duration,
+}
+
+/// How far reservations may exceed a pool's physical capacity
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct OvercommitPolicy {
+    /// Reservable capacity as a multiple of total capacity; 1.0 disables overcommit
+    pub max_ratio: f64,
 }
 
 /// Storage pool organized by trust level
@@ -53,6 +139,8 @@ pub struct StoragePool {
     pub available_nodes: Vec<Uuid>,
     pub total_capacity: u64,
     pub used_capacity: u64,
+    /// Hierarchy level of the pool's nodes, used for tiering
+    pub tier: NodeTier,
 }
 
 /// Trust evaluator for storage nodes
@@ -63,6 +151,8 @@ pub struct TrustEvaluator {
 /// Replication manager for distributed storage
 pub struct ReplicationManager {
     active_replications: HashMap<Uuid, ReplicationPlan>,
+    nodes: HashMap<Uuid, StorageNode>,
+    constraints: PlacementConstraints,
 }
 
 /// Replication plan for a volume
@@ -72,10 +162,36 @@ pub struct ReplicationPlan {
     pub primary_node: Uuid,
     pub replica_nodes: Vec<Uuid>,
     pub replication_strategy: ReplicationStrategy,
+    /// Data must be encrypted by the writer before it reaches any replica
+    pub encryption_required: bool,
+    /// Copies must stay in distinct geographic zones, including replacements
+    pub distinct_zones: bool,
+    /// Snapshots: the contents are fixed and writes are refused
+    pub read_only: bool,
+    /// Service owning the volume; `None` for network storage
+    pub service_id: Option<String>,
+    /// Other services allowed to read and write the volume
+    pub shared_with: HashSet<String>,
+}
+
+impl ReplicationPlan {
+    /// Nodes holding the volume, primary first; fragment `i` of an erasure-coded volume lives on node `i`
+    pub fn nodes(&self) -> Vec<Uuid> {
+        std::iter::once(self.primary_node).chain(self.replica_nodes.iter().copied()).collect()
+    }
+
+    /// Nodes with the fragment each holds, or `None` for full replicas
+    pub fn copies(&self, erasure_coded: bool) -> Vec<(Uuid, Option<usize>)> {
+        self.nodes()
+            .into_iter()
+            .enumerate()
+            .map(|(index, node_id)| (node_id, erasure_coded.then_some(index)))
+            .collect()
+    }
 }
 
 /// Replication strategies
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum ReplicationStrategy {
     HierarchyAware,        // Distribute across node hierarchy levels
     GeographicDistribution, // Distribute geographically
@@ -83,6 +199,26 @@ pub enum ReplicationStrategy {
     PerformanceOptimized,  // Optimize for read/write performance
 }
 
+impl Default for OvercommitPolicy {
+    fn default() -> Self {
+        Self { max_ratio: 1.0 }
+    }
+}
+
+impl StoragePool {
+    /// Capacity that can still be reserved under an overcommit policy
+    pub fn reservable_capacity(&self, policy: &OvercommitPolicy) -> u64 {
+        let limit = (self.total_capacity as f64 * policy.max_ratio.max(1.0)) as u64;
+        limit.saturating_sub(self.used_capacity)
+    }
+}
+
+impl Default for TrustAwareStorageManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl TrustAwareStorageManager {
     /// Create new storage manager
     pub fn new() -> Self {
@@ -90,35 +226,290 @@ impl TrustAwareStorageManager {
             storage_pools: HashMap::new(),
             trust_evaluator: TrustEvaluator::new(),
             replication_manager: ReplicationManager::new(),
+            allocations: HashMap::new(),
+            snapshots: HashMap::new(),
+            quotas: HashMap::new(),
+            overcommit_policy: OvercommitPolicy::default(),
+            access_heat: HashMap::new(),
+            tier_pins: HashMap::new(),
+            tier_policy: TierPolicy::default(),
+            service_namespaces: HashMap::new(),
+            metrics: StorageMetrics::default(),
+            request_timeout: Duration::from_secs(30),
         }
     }
     
     /// Allocate storage with trust constraints
+    ///
+    /// Every copy consumes pool capacity, so `size_bytes * replica_count` (or
+    /// the size of all fragments for erasure-coded volumes) is reserved in the
+    /// selected pool until the volume is released.
     pub async fn allocate_storage(&mut self, request: StorageRequest) -> Result<StorageAllocation, Box<dyn std::error::Error>> {
         tracing::info!("Allocating storage for volume: {:?}", request.volume_id);
+
+        if self.allocations.contains_key(&request.volume_id) {
+            return Err(StorageError::VolumeAlreadyAllocated(request.volume_id).into());
+        }
+        if request.size_bytes == 0 {
+            return Err(StorageError::InvalidRequest("Volume size must be greater than zero".to_string()).into());
+        }
+        let reserved_capacity = request.replication_requirements
+            .reserved_capacity(request.size_bytes)
+            .ok_or_else(|| StorageError::InvalidRequest("Requested capacity overflows".to_string()))?;
+        self.check_quotas(&quota::scopes(request.service_id.as_ref(), request.network_id.as_ref()), &QuotaUsage {
+            bytes: request.size_bytes,
+            volumes: 1,
+            replica_bytes: reserved_capacity,
+        })?;
         
         // 1. Evaluate trust requirements
         let trust_requirements = self.evaluate_trust_requirements(&request).await?;
         
-        // 2. Select appropriate storage pool
-        let storage_pool = self.select_storage_pool(&trust_requirements)?;
-        
-        // 3. Create replication plan
-        let replication_plan = self.replication_manager.create_replication_plan(&request, &storage_pool).await?;
+        // 2. Place the volume in the best-fitting pool whose nodes can hold it
+        let pools: Vec<StoragePool> = self.candidate_pools(&trust_requirements, reserved_capacity)?
+            .into_iter()
+            .cloned()
+            .collect();
+        let mut placement_error = None;
+        let mut placed = None;
+        for pool in pools {
+            match self.replication_manager
+                .create_replication_plan(&request, &pool, &self.trust_evaluator, trust_requirements.minimum_trust_score)
+                .await
+            {
+                Ok(plan) => {
+                    placed = Some((pool, plan));
+                    break;
+                },
+                Err(e) => {
+                    tracing::debug!("Cannot place volume {} in pool {}: {}", request.volume_id, pool.pool_id, e);
+                    placement_error = Some(e);
+                },
+            }
+        }
+        // 3. Fail with the last placement error when no pool could hold the volume
+        let (storage_pool, replication_plan) = match placed {
+            Some(placed) => placed,
+            None => return Err(placement_error.expect("at least one candidate pool was tried").into()),
+        };
         
-        // 4. Allocate storage on selected nodes
+        // 4. Reserve capacity and record the allocation
+        if let Some(pool) = self.storage_pools.get_mut(&storage_pool.pool_id) {
+            pool.used_capacity += reserved_capacity;
+        }
+
         let allocation = StorageAllocation {
             volume_id: request.volume_id,
+            pool_id: storage_pool.pool_id,
+            data_classification: request.data_classification,
+            erasure_coding: request.replication_requirements.erasure_coding,
             primary_node: replication_plan.primary_node,
             replica_nodes: replication_plan.replica_nodes,
             allocated_size: request.size_bytes,
+            reserved_capacity,
+            origin_volume_id: None,
+            key_envelope: None,
+            service_id: request.service_id,
+            network_id: request.network_id,
+            request_digest: None,
         };
+        self.allocations.insert(allocation.volume_id, allocation.clone());
         
         Ok(allocation)
     }
+
+    /// Release a volume's reservation and replication plan
+    pub fn release_storage(&mut self, volume_id: &Uuid) -> Result<StorageAllocation, StorageError> {
+        let allocation = self.allocations
+            .remove(volume_id)
+            .ok_or(StorageError::VolumeNotFound(*volume_id))?;
+
+        if let Some(pool) = self.storage_pools.get_mut(&allocation.pool_id) {
+            pool.used_capacity = pool.used_capacity.saturating_sub(allocation.reserved_capacity);
+        }
+        self.replication_manager.remove_plan(volume_id);
+        self.snapshots.remove(volume_id);
+        self.access_heat.remove(volume_id);
+        self.tier_pins.remove(volume_id);
+        self.remove_service_entries(&allocation);
+
+        tracing::info!("Released {} bytes for volume {}", allocation.reserved_capacity, volume_id);
+        Ok(allocation)
+    }
+
+    /// Release a volume and delete its copies from its nodes
+    ///
+    /// Nodes that cannot be reached keep their copy until scrubbing or a
+    /// later delete removes it; the reservation is released regardless.
+    pub async fn delete_volume<T: ReplicaTransport>(&mut self, volume_id: &Uuid, transport: &T) -> Result<StorageAllocation, StorageError> {
+        let plan = self.replication_manager.active_plan(volume_id).cloned();
+        let allocation = self.release_storage(volume_id)?;
+
+        let copies = plan.map(|plan| plan.copies(allocation.erasure_coding.is_some())).unwrap_or_default();
+        for (node_id, fragment) in copies {
+            let request = ReplicaRequest::DeleteVolume { volume_id: *volume_id, fragment };
+            if let Err(e) = send_with_timeout(transport, node_id, request, self.request_timeout).await {
+                tracing::warn!("Volume {} could not be removed from node {}: {}", volume_id, node_id, e);
+            }
+        }
+        Ok(allocation)
+    }
+
+    /// Allocation currently held by a volume
+    pub fn allocation(&self, volume_id: &Uuid) -> Option<&StorageAllocation> {
+        self.allocations.get(volume_id)
+    }
+
+    /// Add a storage pool
+    pub fn register_storage_pool(&mut self, pool: StoragePool) -> Result<(), StorageError> {
+        if self.storage_pools.contains_key(&pool.pool_id) {
+            return Err(StorageError::DuplicatePool(pool.pool_id));
+        }
+        self.storage_pools.insert(pool.pool_id.clone(), pool);
+        Ok(())
+    }
+
+    /// Remove a storage pool that holds no allocations
+    pub fn remove_storage_pool(&mut self, pool_id: &str) -> Result<StoragePool, StorageError> {
+        let volumes = self.allocations.values().filter(|allocation| allocation.pool_id == pool_id).count();
+        if volumes > 0 {
+            return Err(StorageError::PoolInUse {
+                pool_id: pool_id.to_string(),
+                volumes,
+            });
+        }
+        self.storage_pools
+            .remove(pool_id)
+            .ok_or_else(|| StorageError::PoolNotFound(pool_id.to_string()))
+    }
+
+    /// Get a storage pool
+    pub fn storage_pool(&self, pool_id: &str) -> Option<&StoragePool> {
+        self.storage_pools.get(pool_id)
+    }
+
+    /// Set how far reservations may exceed physical pool capacity
+    pub fn set_overcommit_policy(&mut self, policy: OvercommitPolicy) {
+        self.overcommit_policy = policy;
+    }
+
+    /// Set how long a node may take to answer a snapshot, clone or delete request
+    pub fn set_request_timeout(&mut self, timeout: Duration) {
+        self.request_timeout = timeout;
+    }
     
     async fn evaluate_trust_requirements(&self, request: &StorageRequest) -> Result<TrustRequirements, Box<dyn std::error::Error>> {
-        let requirements = match request.data_classification {
+        Ok(TrustRequirements::for_classification(&request.data_classification))
+    }
+
+    /// All current allocations
+    pub fn allocations(&self) -> impl Iterator<Item = &StorageAllocation> {
+        self.allocations.values()
+    }
+
+    /// Get the trust evaluator
+    pub fn trust_evaluator(&self) -> &TrustEvaluator {
+        &self.trust_evaluator
+    }
+
+    /// Choose a node to take over from failed copies of a volume
+    ///
+    /// The replacement comes from the volume's pool, meets its classification's
+    /// trust threshold and avoids the failure domains of the surviving copies.
+    pub fn select_replacement(&self, volume_id: &Uuid, failed_nodes: &HashSet<Uuid>) -> Result<Uuid, StorageError> {
+        let allocation = self.allocations.get(volume_id).ok_or(StorageError::VolumeNotFound(*volume_id))?;
+        let pool = self.storage_pools
+            .get(&allocation.pool_id)
+            .ok_or_else(|| StorageError::PoolNotFound(allocation.pool_id.clone()))?;
+        let requirements = TrustRequirements::for_classification(&allocation.data_classification);
+
+        self.replication_manager
+            .select_replacement(volume_id, failed_nodes, pool, &self.trust_evaluator, requirements.minimum_trust_score)
+    }
+
+    /// Swap a failed node for its replacement in a volume's allocation and plan
+    pub fn replace_replica(&mut self, volume_id: &Uuid, failed_node: Uuid, replacement: Uuid) -> Result<StorageAllocation, StorageError> {
+        let allocation = self.allocations.get_mut(volume_id).ok_or(StorageError::VolumeNotFound(*volume_id))?;
+        let plan = self.replication_manager.replace_node(volume_id, failed_node, replacement)?;
+
+        allocation.primary_node = plan.primary_node;
+        allocation.replica_nodes = plan.replica_nodes;
+        Ok(allocation.clone())
+    }
+    
+    /// Register topology details for a node that can hold replicas
+    pub fn register_storage_node(&mut self, node: StorageNode) {
+        self.replication_manager.register_node(node);
+    }
+
+    /// Get the trust evaluator
+    pub fn trust_evaluator_mut(&mut self) -> &mut TrustEvaluator {
+        &mut self.trust_evaluator
+    }
+
+    /// Get the replication manager
+    pub fn replication_manager(&self) -> &ReplicationManager {
+        &self.replication_manager
+    }
+
+    /// Pools able to take a reservation, best fit first
+    ///
+    /// Prefers the lowest trust level that satisfies the requirements, so
+    /// high-trust pools stay free for data that needs them, then the pool left
+    /// with the least spare capacity. Pool ID breaks remaining ties.
+    fn candidate_pools(&self, requirements: &TrustRequirements, reserved_capacity: u64) -> Result<Vec<&StoragePool>, StorageError> {
+        let mut pools: Vec<&StoragePool> = self.storage_pools
+            .values()
+            .filter(|pool| pool.trust_level >= requirements.minimum_trust_score)
+            .filter(|pool| pool.reservable_capacity(&self.overcommit_policy) >= reserved_capacity)
+            .collect();
+        if pools.is_empty() {
+            return Err(StorageError::NoSuitablePool {
+                minimum_trust_score: requirements.minimum_trust_score,
+                required_capacity: reserved_capacity,
+            });
+        }
+        pools.sort_by(|a, b| {
+            a.trust_level
+                .total_cmp(&b.trust_level)
+                .then(a.reservable_capacity(&self.overcommit_policy).cmp(&b.reservable_capacity(&self.overcommit_policy)))
+                .then(a.pool_id.cmp(&b.pool_id))
+        });
+        Ok(pools)
+    }
+
+    /// Fail with `UntrustedPlacement` if a node is below the classification's trust threshold
+    pub(crate) fn check_node_trust(
+        &self,
+        classification: &DataClassification,
+        nodes: impl IntoIterator<Item = Uuid>,
+    ) -> Result<(), StorageError> {
+        let requirements = TrustRequirements::for_classification(classification);
+        for node_id in nodes {
+            let trust_score = self.trust_evaluator.get_node_trust_score(&node_id);
+            if trust_score < requirements.minimum_trust_score {
+                return Err(StorageError::UntrustedPlacement {
+                    node_id,
+                    trust_score,
+                    required: requirements.minimum_trust_score,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Trust requirements for storage allocation
+#[derive(Debug, Clone)]
+pub struct TrustRequirements {
+    pub minimum_trust_score: f32,
+    pub encryption_required: bool,
+}
+
+impl TrustRequirements {
+    /// Requirements every node holding data of a classification must meet
+    pub fn for_classification(classification: &DataClassification) -> Self {
+        match classification {
             DataClassification::Critical => TrustRequirements {
                 minimum_trust_score: 0.9,
                 encryption_required: true,
@@ -135,33 +526,46 @@ impl TrustAwareStorageManager {
                 minimum_trust_score: 0.1,
                 encryption_required: false,
             },
-        };
-        
-        Ok(requirements)
-    }
-    
-    fn select_storage_pool(&self, requirements: &TrustRequirements) -> Result<&StoragePool, Box<dyn std::error::Error>> {
-        self.storage_pools
-            .values()
-            .find(|pool| pool.trust_level >= requirements.minimum_trust_score)
-            .ok_or_else(|| "No suitable storage pool found".into())
+        }
     }
 }
 
-/// Trust requirements for storage allocation
-#[derive(Debug, Clone)]
-pub struct TrustRequirements {
-    pub minimum_trust_score: f32,
-    pub encryption_required: bool,
-}
-
 /// Storage allocation result
 #[derive(Debug, Clone)]
 pub struct StorageAllocation {
     pub volume_id: Uuid,
+    pub pool_id: String,
+    pub data_classification: DataClassification,
+    pub erasure_coding: Option<ErasureCoding>,
     pub primary_node: Uuid,
     pub replica_nodes: Vec<Uuid>,
     pub allocated_size: u64,
+    /// Pool capacity held for all copies of the volume
+    pub reserved_capacity: u64,
+    /// Volume a snapshot or clone descends from; its data key encrypts this volume's contents
+    pub origin_volume_id: Option<Uuid>,
+    /// Data key wrapped for the volume's authorized recipients, once the writer registered one
+    pub key_envelope: Option<VolumeKeyEnvelope>,
+    pub service_id: Option<String>,
+    pub network_id: Option<String>,
+    /// Digest of the provisioning request, so retries can be told apart from conflicting requests
+    pub request_digest: Option<[u8; 32]>,
+}
+
+impl StorageAllocation {
+    /// Pool capacity the volume would occupy at `size_bytes`
+    pub fn reserved_capacity_for(&self, size_bytes: u64) -> Option<u64> {
+        match &self.erasure_coding {
+            Some(coding) => coding.reserved_capacity(size_bytes),
+            None => size_bytes.checked_mul(1 + self.replica_nodes.len() as u64),
+        }
+    }
+}
+
+impl Default for TrustEvaluator {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl TrustEvaluator {
@@ -174,30 +578,187 @@ impl TrustEvaluator {
     pub fn get_node_trust_score(&self, node_id: &Uuid) -> f32 {
         self.node_trust_scores.get(node_id).copied().unwrap_or(0.5)
     }
+
+    pub fn set_node_trust_score(&mut self, node_id: Uuid, trust_score: f32) {
+        self.node_trust_scores.insert(node_id, trust_score.clamp(0.0, 1.0));
+    }
+
+    /// Lower a node's trust score, e.g. after it served corrupt data
+    pub fn penalize_node(&mut self, node_id: Uuid, penalty: f32) {
+        let trust_score = self.get_node_trust_score(&node_id) - penalty;
+        self.set_node_trust_score(node_id, trust_score);
+    }
+}
+
+impl Default for ReplicationManager {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl ReplicationManager {
     pub fn new() -> Self {
         Self {
             active_replications: HashMap::new(),
+            nodes: HashMap::new(),
+            constraints: PlacementConstraints::default(),
         }
     }
-    
-    pub async fn create_replication_plan(&self, request: &StorageRequest, pool: &StoragePool) -> Result<ReplicationPlan, Box<dyn std::error::Error>> {
+
+    /// Register or update topology details for a storage node
+    pub fn register_node(&mut self, node: StorageNode) {
+        self.nodes.insert(node.node_id, node);
+    }
+
+    /// Forget a node that left the network
+    pub fn remove_node(&mut self, node_id: &Uuid) -> Option<StorageNode> {
+        self.nodes.remove(node_id)
+    }
+
+    /// Replace the constraints applied to new placements
+    pub fn set_constraints(&mut self, constraints: PlacementConstraints) {
+        self.constraints = constraints;
+    }
+
+    /// Plan currently in force for a volume
+    pub fn active_plan(&self, volume_id: &Uuid) -> Option<&ReplicationPlan> {
+        self.active_replications.get(volume_id)
+    }
+
+    /// Record a plan derived from an existing one, such as a snapshot's
+    pub(crate) fn insert_plan(&mut self, plan: ReplicationPlan) {
+        self.active_replications.insert(plan.volume_id, plan);
+    }
+
+    pub(crate) fn active_plan_mut(&mut self, volume_id: &Uuid) -> Option<&mut ReplicationPlan> {
+        self.active_replications.get_mut(volume_id)
+    }
+
+    /// Drop the plan for a deleted volume
+    pub fn remove_plan(&mut self, volume_id: &Uuid) -> Option<ReplicationPlan> {
+        self.active_replications.remove(volume_id)
+    }
+
+    /// Place a volume's copies on pool nodes using the requested strategy
+    ///
+    /// Nodes below `minimum_trust_score` are skipped. Erasure-coded volumes get
+    /// one node per fragment, in fragment order.
+    pub async fn create_replication_plan(
+        &mut self,
+        request: &StorageRequest,
+        pool: &StoragePool,
+        trust_evaluator: &TrustEvaluator,
+        minimum_trust_score: f32,
+    ) -> Result<ReplicationPlan, StorageError> {
+        let candidates = self.candidates(pool, trust_evaluator, minimum_trust_score);
+        if candidates.is_empty() {
+            return Err(StorageError::NoEligibleNodes {
+                pool_id: pool.pool_id.clone(),
+            });
+        }
+
+        let strategy = request.replication_requirements.replication_strategy;
+        let constraints = PlacementConstraints {
+            distinct_zones: self.constraints.distinct_zones || request.replication_requirements.geographic_distribution,
+            ..self.constraints.clone()
+        };
+        let placement = place_replicas(&strategy, &candidates, request.replication_requirements.node_count(), &constraints)?;
+
         let plan = ReplicationPlan {
             volume_id: request.volume_id,
-            primary_node: pool.available_nodes[0], // Simplified selection
-            replica_nodes: pool.available_nodes[1..request.replication_requirements.replica_count.min(pool.available_nodes.len())].to_vec(),
-            replication_strategy: ReplicationStrategy::HierarchyAware,
+            primary_node: placement.primary_node,
+            replica_nodes: placement.replica_nodes,
+            replication_strategy: strategy,
+            encryption_required: TrustRequirements::for_classification(&request.data_classification).encryption_required,
+            distinct_zones: constraints.distinct_zones,
+            read_only: false,
+            service_id: request.service_id.clone(),
+            shared_with: HashSet::new(),
         };
-        
+        self.active_replications.insert(plan.volume_id, plan.clone());
+
         Ok(plan)
     }
+
+    /// Choose one pool node to replace failed copies in a volume's plan
+    pub fn select_replacement(
+        &self,
+        volume_id: &Uuid,
+        failed_nodes: &HashSet<Uuid>,
+        pool: &StoragePool,
+        trust_evaluator: &TrustEvaluator,
+        minimum_trust_score: f32,
+    ) -> Result<Uuid, StorageError> {
+        let plan = self.active_replications.get(volume_id).ok_or(StorageError::VolumeNotFound(*volume_id))?;
+        let current: Vec<Uuid> = std::iter::once(plan.primary_node).chain(plan.replica_nodes.iter().copied()).collect();
+
+        let surviving: Vec<&StorageNode> = current
+            .iter()
+            .filter(|node_id| !failed_nodes.contains(node_id))
+            .filter_map(|node_id| self.nodes.get(node_id))
+            .collect();
+        let surviving_domains: HashSet<&str> = surviving.iter().map(|node| node.failure_domain.as_str()).collect();
+        let surviving_zones: HashSet<&str> = surviving.iter().filter_map(|node| node.zone.as_deref()).collect();
+        let constraints = PlacementConstraints {
+            distinct_zones: self.constraints.distinct_zones || plan.distinct_zones,
+            ..self.constraints.clone()
+        };
+
+        let candidates: Vec<PlacementCandidate> = self.candidates(pool, trust_evaluator, minimum_trust_score)
+            .into_iter()
+            .filter(|candidate| !current.contains(&candidate.node.node_id) && !failed_nodes.contains(&candidate.node.node_id))
+            .filter(|candidate| {
+                !constraints.distinct_failure_domains || !surviving_domains.contains(candidate.node.failure_domain.as_str())
+            })
+            .filter(|candidate| {
+                !constraints.distinct_zones || candidate.node.zone.as_deref().is_some_and(|zone| !surviving_zones.contains(zone))
+            })
+            .collect();
+
+        let placement = place_replicas(&plan.replication_strategy, &candidates, 1, &constraints)?;
+        Ok(placement.primary_node)
+    }
+
+    /// Put `replacement` in the position `failed_node` held in a volume's plan
+    pub fn replace_node(&mut self, volume_id: &Uuid, failed_node: Uuid, replacement: Uuid) -> Result<ReplicationPlan, StorageError> {
+        let plan = self.active_replications.get_mut(volume_id).ok_or(StorageError::VolumeNotFound(*volume_id))?;
+
+        if plan.primary_node == failed_node {
+            plan.primary_node = replacement;
+        } else if let Some(slot) = plan.replica_nodes.iter_mut().find(|node_id| **node_id == failed_node) {
+            *slot = replacement;
+        } else {
+            return Err(StorageError::UnknownNode(failed_node));
+        }
+        Ok(plan.clone())
+    }
+
+    /// Pool nodes meeting a trust threshold, with their topology
+    ///
+    /// Nodes without registered topology are treated as Hyphae in their own failure domain.
+    fn candidates(&self, pool: &StoragePool, trust_evaluator: &TrustEvaluator, minimum_trust_score: f32) -> Vec<PlacementCandidate> {
+        pool.available_nodes
+            .iter()
+            .map(|node_id| PlacementCandidate {
+                node: self.nodes.get(node_id).cloned().unwrap_or_else(|| StorageNode {
+                    node_id: *node_id,
+                    tier: NodeTier::Hyphae,
+                    zone: None,
+                    failure_domain: node_id.to_string(),
+                    latency_ms: u32::MAX,
+                    bandwidth_mbps: 0,
+                }),
+                trust_score: trust_evaluator.get_node_trust_score(node_id),
+            })
+            .filter(|candidate| candidate.trust_score >= minimum_trust_score)
+            .collect()
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_support::pool;
     
     #[test]
     fn test_storage_manager_creation() {
@@ -205,9 +766,126 @@ mod tests {
         assert!(manager.storage_pools.is_empty());
     }
     
-    #[test]
-    fn test_trust_requirements_evaluation() {
-        // Test would verify trust requirement calculation
-        assert!(true);
+    #[tokio::test]
+    async fn test_best_fit_selection_and_release() {
+        let mut manager = TrustAwareStorageManager::new();
+        let nodes = vec![Uuid::new_v4(), Uuid::new_v4()];
+        manager.register_storage_pool(pool("large", 0.6, 10_000, nodes.clone())).unwrap();
+        manager.register_storage_pool(pool("small", 0.6, 3_000, nodes.clone())).unwrap();
+        manager.register_storage_pool(pool("critical", 0.95, 100_000, nodes.clone())).unwrap();
+        assert!(matches!(
+            manager.register_storage_pool(pool("small", 0.6, 1, Vec::new())),
+            Err(StorageError::DuplicatePool(_))
+        ));
+
+        // 2 copies of 1024 bytes fit the tighter standard pool, not the high-trust one
+        let first = manager.allocate_storage(request(2, ReplicationStrategy::HierarchyAware)).await.unwrap();
+        assert_eq!(first.pool_id, "small");
+        assert_eq!(manager.storage_pool("small").unwrap().used_capacity, 2048);
+
+        let second = manager.allocate_storage(request(2, ReplicationStrategy::HierarchyAware)).await.unwrap();
+        assert_eq!(second.pool_id, "large");
+
+        assert!(matches!(manager.remove_storage_pool("small"), Err(StorageError::PoolInUse { volumes: 1, .. })));
+        manager.release_storage(&first.volume_id).unwrap();
+        assert_eq!(manager.storage_pool("small").unwrap().used_capacity, 0);
+        assert!(manager.release_storage(&first.volume_id).is_err());
+        assert!(manager.remove_storage_pool("small").is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_allocation_falls_back_when_placement_fails() {
+        let mut manager = TrustAwareStorageManager::new();
+        // The best-fit pool has capacity but too few nodes for three copies
+        manager.register_storage_pool(pool("narrow", 0.6, 4_000, vec![Uuid::new_v4()])).unwrap();
+        manager.register_storage_pool(pool("wide", 0.6, 10_000, (0..3).map(|_| Uuid::new_v4()).collect())).unwrap();
+
+        let allocation = manager.allocate_storage(request(3, ReplicationStrategy::HierarchyAware)).await.unwrap();
+        assert_eq!(allocation.pool_id, "wide");
+        assert_eq!(manager.storage_pool("narrow").unwrap().used_capacity, 0);
+    }
+
+    #[tokio::test]
+    async fn test_overcommit_policy() {
+        let mut manager = TrustAwareStorageManager::new();
+        manager.register_storage_pool(pool("tiny", 0.6, 1024, vec![Uuid::new_v4()])).unwrap();
+
+        manager.allocate_storage(request(1, ReplicationStrategy::HierarchyAware)).await.unwrap();
+        let err = manager.allocate_storage(request(1, ReplicationStrategy::HierarchyAware)).await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<StorageError>(), Some(StorageError::NoSuitablePool { .. })));
+
+        manager.set_overcommit_policy(OvercommitPolicy { max_ratio: 2.0 });
+        assert!(manager.allocate_storage(request(1, ReplicationStrategy::HierarchyAware)).await.is_ok());
+    }
+
+    fn request(replica_count: usize, replication_strategy: ReplicationStrategy) -> StorageRequest {
+        StorageRequest {
+            volume_id: Uuid::new_v4(),
+            size_bytes: 1024,
+            data_classification: DataClassification::Standard,
+            replication_requirements: ReplicationRequirements {
+                replica_count,
+                consistency_level: ConsistencyLevel::Strong,
+                geographic_distribution: false,
+                replication_strategy,
+                erasure_coding: None,
+            },
+            service_id: None,
+            network_id: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_replication_plan_uses_strategy_and_trust() {
+        let mut replication = ReplicationManager::new();
+        let mut trust = TrustEvaluator::new();
+        let tiers = [NodeTier::Sclerotia, NodeTier::Sclerotia, NodeTier::Rhizomorph, NodeTier::Hyphae];
+
+        let nodes: Vec<Uuid> = tiers
+            .iter()
+            .enumerate()
+            .map(|(index, tier)| {
+                let node_id = Uuid::new_v4();
+                replication.register_node(StorageNode {
+                    node_id,
+                    tier: *tier,
+                    zone: None,
+                    failure_domain: format!("rack-{}", index),
+                    latency_ms: 10,
+                    bandwidth_mbps: 1000,
+                });
+                trust.set_node_trust_score(node_id, 0.8);
+                node_id
+            })
+            .collect();
+        trust.set_node_trust_score(nodes[3], 0.2);
+
+        let pool = StoragePool {
+            pool_id: "standard".to_string(),
+            trust_level: 0.5,
+            available_nodes: nodes.clone(),
+            total_capacity: 1 << 30,
+            used_capacity: 0,
+            tier: NodeTier::Sclerotia,
+        };
+
+        let volume = request(2, ReplicationStrategy::HierarchyAware);
+        let plan = replication.create_replication_plan(&volume, &pool, &trust, 0.5).await.unwrap();
+        assert!(plan.replica_nodes.contains(&nodes[2]));
+        assert_eq!(replication.active_plan(&volume.volume_id).unwrap().primary_node, plan.primary_node);
+
+        // The low-trust Hyphae node is filtered out, leaving too few nodes
+        let err = replication
+            .create_replication_plan(&request(4, ReplicationStrategy::HierarchyAware), &pool, &trust, 0.5)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, StorageError::InsufficientNodes { required: 4, available: 3 }));
+
+        let empty = StoragePool { available_nodes: Vec::new(), ..pool };
+        let err = replication
+            .create_replication_plan(&request(1, ReplicationStrategy::PerformanceOptimized), &empty, &trust, 0.5)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, StorageError::NoEligibleNodes { .. }));
     }
 }
\ No newline at end of file
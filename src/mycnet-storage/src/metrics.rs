@@ -0,0 +1,514 @@
+//! Storage health reporting and Prometheus export
+//!
+//! `TrustAwareStorageManager::status` reports pool usage, the replication
+//! health of every volume, the repair backlog, scrub results and I/O latency.
+//! Repair and scrub passes feed their results in as they run; latency is
+//! collected from each `VolumeIo` with `take_io_latency`.
+
+use crate::repair::repair_priority;
+use crate::{
+    DataClassification, NodeTier, RepairReport, ScrubReport, StorageAllocation, TrustAwareStorageManager, TrustRequirements,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::Write;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Upper bounds of the latency histogram buckets, in seconds
+pub const LATENCY_BUCKETS: [f64; 14] = [0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// Volume I/O operation whose latency is measured
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum IoOperation {
+    Read,
+    Write,
+}
+
+/// Latency distribution over `LATENCY_BUCKETS`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LatencyHistogram {
+    /// Observations per bucket, not cumulative; the last entry counts those above every bound
+    pub counts: Vec<u64>,
+    pub sum_seconds: f64,
+    pub count: u64,
+}
+
+/// Replication health of a volume
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VolumeHealth {
+    /// Every copy or fragment is on an available, trusted node
+    Healthy,
+    /// Some copies are gone but the volume can still be read and repaired
+    Degraded,
+    /// Too few copies remain to read the volume
+    Lost,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PoolStatus {
+    pub pool_id: String,
+    pub tier: NodeTier,
+    pub trust_level: f32,
+    pub total_capacity: u64,
+    pub used_capacity: u64,
+    /// Capacity still reservable under the overcommit policy
+    pub reservable_capacity: u64,
+    pub volumes: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VolumeStatus {
+    pub volume_id: Uuid,
+    pub pool_id: String,
+    pub data_classification: DataClassification,
+    pub health: VolumeHealth,
+    pub healthy_copies: usize,
+    /// Copies or fragments the plan places
+    pub total_copies: usize,
+    /// Copies or fragments needed to read the volume
+    pub readable_copies: usize,
+}
+
+/// Totals of every repair pass recorded
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RepairTotals {
+    pub passes: u64,
+    pub copies_repaired: u64,
+    pub bytes_copied: u64,
+    pub failures: u64,
+    pub last_run: Option<DateTime<Utc>>,
+}
+
+/// Totals of every scrub recorded
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ScrubTotals {
+    pub passes: u64,
+    pub chunks_checked: u64,
+    pub bytes_checked: u64,
+    pub corrupted_chunks: u64,
+    pub repaired_chunks: u64,
+    pub divergent_replicas: u64,
+    pub repaired_replicas: u64,
+    /// Corruption the most recent scrub found but could not repair
+    pub last_unrepaired: u64,
+    pub last_run: Option<DateTime<Utc>>,
+}
+
+/// Point-in-time health of the storage subsystem
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StorageStatus {
+    pub pools: Vec<PoolStatus>,
+    pub volumes: Vec<VolumeStatus>,
+    /// Volumes that are not healthy, as the repair controller would order them
+    pub repair_backlog: Vec<Uuid>,
+    pub repair: RepairTotals,
+    pub scrub: ScrubTotals,
+    pub io_latency: BTreeMap<IoOperation, LatencyHistogram>,
+    pub generated_at: DateTime<Utc>,
+}
+
+/// Observations the manager keeps for status reports
+#[derive(Debug, Default)]
+pub(crate) struct StorageMetrics {
+    /// Nodes the last repair pass considered down
+    unavailable_nodes: HashSet<Uuid>,
+    repair: RepairTotals,
+    scrub: ScrubTotals,
+    io_latency: BTreeMap<IoOperation, LatencyHistogram>,
+}
+
+impl IoOperation {
+    fn label(&self) -> &'static str {
+        match self {
+            IoOperation::Read => "read",
+            IoOperation::Write => "write",
+        }
+    }
+}
+
+impl VolumeHealth {
+    fn label(&self) -> &'static str {
+        match self {
+            VolumeHealth::Healthy => "healthy",
+            VolumeHealth::Degraded => "degraded",
+            VolumeHealth::Lost => "lost",
+        }
+    }
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self {
+            counts: vec![0; LATENCY_BUCKETS.len() + 1],
+            sum_seconds: 0.0,
+            count: 0,
+        }
+    }
+}
+
+impl LatencyHistogram {
+    pub fn observe(&mut self, latency: Duration) {
+        let seconds = latency.as_secs_f64();
+        let bucket = LATENCY_BUCKETS.iter().position(|bound| seconds <= *bound).unwrap_or(LATENCY_BUCKETS.len());
+        self.counts[bucket] += 1;
+        self.sum_seconds += seconds;
+        self.count += 1;
+    }
+
+    pub fn merge(&mut self, other: &LatencyHistogram) {
+        for (count, other) in self.counts.iter_mut().zip(&other.counts) {
+            *count += other;
+        }
+        self.sum_seconds += other.sum_seconds;
+        self.count += other.count;
+    }
+
+    /// Upper bound of the bucket holding quantile `q`, or `None` when empty or above every bound
+    pub fn quantile(&self, q: f64) -> Option<f64> {
+        if self.count == 0 {
+            return None;
+        }
+        let rank = (q.clamp(0.0, 1.0) * self.count as f64).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for (bucket, count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return LATENCY_BUCKETS.get(bucket).copied();
+            }
+        }
+        None
+    }
+}
+
+impl StorageMetrics {
+    pub(crate) fn record_repair(&mut self, report: &RepairReport, unavailable_nodes: &HashSet<Uuid>) {
+        self.unavailable_nodes = unavailable_nodes.clone();
+        self.repair.passes += 1;
+        self.repair.copies_repaired += report.repaired.len() as u64;
+        self.repair.bytes_copied += report.repaired.iter().map(|action| action.bytes_copied).sum::<u64>();
+        self.repair.failures += report.failed.len() as u64;
+        self.repair.last_run = Some(Utc::now());
+    }
+
+    pub(crate) fn record_scrub(&mut self, report: &ScrubReport) {
+        self.scrub.passes += 1;
+        self.scrub.chunks_checked += report.chunks_checked as u64;
+        self.scrub.bytes_checked += report.bytes_checked;
+        self.scrub.corrupted_chunks += report.corrupted_chunks.len() as u64;
+        self.scrub.repaired_chunks += report.repaired_chunks as u64;
+        self.scrub.divergent_replicas += report.divergent_replicas.len() as u64;
+        self.scrub.repaired_replicas += report.repaired_replicas as u64;
+        self.scrub.last_unrepaired = report.unrepaired.len() as u64;
+        self.scrub.last_run = Some(Utc::now());
+    }
+}
+
+impl TrustAwareStorageManager {
+    /// Fold a repair pass into the status report
+    ///
+    /// `RepairController::run_once` calls this itself; `unavailable_nodes`
+    /// are the nodes the controller considers down.
+    pub fn record_repair_pass(&mut self, report: &RepairReport, unavailable_nodes: &HashSet<Uuid>) {
+        self.metrics.record_repair(report, unavailable_nodes);
+    }
+
+    /// Fold a scrub into the status report
+    ///
+    /// `Scrubber::run_once` calls this itself; record reports from direct
+    /// `scrub_local` or `scrub_volume` calls here as well.
+    pub fn record_scrub_pass(&mut self, report: &ScrubReport) {
+        self.metrics.record_scrub(report);
+    }
+
+    /// Add latency observations, typically drained from `VolumeIo::take_io_latency`
+    pub fn record_io_latency(&mut self, latency: &BTreeMap<IoOperation, LatencyHistogram>) {
+        for (operation, histogram) in latency {
+            self.metrics.io_latency.entry(*operation).or_default().merge(histogram);
+        }
+    }
+
+    /// Replication health of one volume
+    pub fn volume_status(&self, volume_id: &Uuid) -> Option<VolumeStatus> {
+        let allocation = self.allocations.get(volume_id)?;
+        Some(self.status_of(allocation))
+    }
+
+    /// Current health of pools, volumes, repair, scrubbing and I/O
+    pub fn status(&self) -> StorageStatus {
+        let mut pools: Vec<PoolStatus> = self
+            .storage_pools
+            .values()
+            .map(|pool| PoolStatus {
+                pool_id: pool.pool_id.clone(),
+                tier: pool.tier,
+                trust_level: pool.trust_level,
+                total_capacity: pool.total_capacity,
+                used_capacity: pool.used_capacity,
+                reservable_capacity: pool.reservable_capacity(&self.overcommit_policy),
+                volumes: self.allocations.values().filter(|allocation| allocation.pool_id == pool.pool_id).count(),
+            })
+            .collect();
+        pools.sort_by(|a, b| a.pool_id.cmp(&b.pool_id));
+
+        let mut volumes: Vec<VolumeStatus> = self.allocations.values().map(|allocation| self.status_of(allocation)).collect();
+        volumes.sort_by_key(|volume| volume.volume_id);
+
+        let mut backlog: Vec<&VolumeStatus> = volumes.iter().filter(|volume| volume.health != VolumeHealth::Healthy).collect();
+        backlog.sort_by(|a, b| {
+            repair_priority(&a.data_classification)
+                .cmp(&repair_priority(&b.data_classification))
+                .then(a.healthy_copies.cmp(&b.healthy_copies))
+                .then(a.volume_id.cmp(&b.volume_id))
+        });
+        let repair_backlog = backlog.into_iter().map(|volume| volume.volume_id).collect();
+
+        StorageStatus {
+            pools,
+            volumes,
+            repair_backlog,
+            repair: self.metrics.repair.clone(),
+            scrub: self.metrics.scrub.clone(),
+            io_latency: self.metrics.io_latency.clone(),
+            generated_at: Utc::now(),
+        }
+    }
+
+    fn status_of(&self, allocation: &StorageAllocation) -> VolumeStatus {
+        let minimum = TrustRequirements::for_classification(&allocation.data_classification).minimum_trust_score;
+        let nodes: Vec<Uuid> = match self.replication_manager.active_plan(&allocation.volume_id) {
+            Some(plan) => plan.nodes(),
+            None => std::iter::once(allocation.primary_node).chain(allocation.replica_nodes.iter().copied()).collect(),
+        };
+        let healthy_copies = nodes
+            .iter()
+            .filter(|node_id| {
+                !self.metrics.unavailable_nodes.contains(node_id) && self.trust_evaluator.get_node_trust_score(node_id) >= minimum
+            })
+            .count();
+        let readable_copies = allocation.erasure_coding.map(|coding| coding.data_shards()).unwrap_or(1);
+
+        let health = if healthy_copies == nodes.len() {
+            VolumeHealth::Healthy
+        } else if healthy_copies >= readable_copies {
+            VolumeHealth::Degraded
+        } else {
+            VolumeHealth::Lost
+        };
+
+        VolumeStatus {
+            volume_id: allocation.volume_id,
+            pool_id: allocation.pool_id.clone(),
+            data_classification: allocation.data_classification,
+            health,
+            healthy_copies,
+            total_copies: nodes.len(),
+            readable_copies,
+        }
+    }
+}
+
+impl StorageStatus {
+    /// Render the status in the Prometheus text exposition format
+    pub fn to_prometheus(&self) -> String {
+        let mut out = String::new();
+
+        header(&mut out, "mycnet_storage_pool_capacity_bytes", "gauge", "Total capacity of a storage pool");
+        for pool in &self.pools {
+            let _ = writeln!(out, "mycnet_storage_pool_capacity_bytes{} {}", pool_labels(pool), pool.total_capacity);
+        }
+        header(&mut out, "mycnet_storage_pool_used_bytes", "gauge", "Capacity reserved in a storage pool");
+        for pool in &self.pools {
+            let _ = writeln!(out, "mycnet_storage_pool_used_bytes{} {}", pool_labels(pool), pool.used_capacity);
+        }
+        header(&mut out, "mycnet_storage_pool_reservable_bytes", "gauge", "Capacity still reservable in a storage pool");
+        for pool in &self.pools {
+            let _ = writeln!(out, "mycnet_storage_pool_reservable_bytes{} {}", pool_labels(pool), pool.reservable_capacity);
+        }
+        header(&mut out, "mycnet_storage_pool_volumes", "gauge", "Volumes allocated in a storage pool");
+        for pool in &self.pools {
+            let _ = writeln!(out, "mycnet_storage_pool_volumes{} {}", pool_labels(pool), pool.volumes);
+        }
+
+        header(&mut out, "mycnet_storage_volumes", "gauge", "Volumes by replication health");
+        for health in [VolumeHealth::Healthy, VolumeHealth::Degraded, VolumeHealth::Lost] {
+            let count = self.volumes.iter().filter(|volume| volume.health == health).count();
+            let _ = writeln!(out, "mycnet_storage_volumes{{health=\"{}\"}} {}", health.label(), count);
+        }
+        header(&mut out, "mycnet_storage_volume_health", "gauge", "Replication health of a volume, one series per state");
+        for volume in &self.volumes {
+            for health in [VolumeHealth::Healthy, VolumeHealth::Degraded, VolumeHealth::Lost] {
+                let _ = writeln!(
+                    out,
+                    "mycnet_storage_volume_health{{volume=\"{}\",pool=\"{}\",health=\"{}\"}} {}",
+                    volume.volume_id,
+                    escape(&volume.pool_id),
+                    health.label(),
+                    u8::from(volume.health == health)
+                );
+            }
+        }
+        header(&mut out, "mycnet_storage_volume_healthy_copies", "gauge", "Copies or fragments of a volume on available, trusted nodes");
+        for volume in &self.volumes {
+            let _ = writeln!(
+                out,
+                "mycnet_storage_volume_healthy_copies{{volume=\"{}\",pool=\"{}\"}} {}",
+                volume.volume_id,
+                escape(&volume.pool_id),
+                volume.healthy_copies
+            );
+        }
+
+        header(&mut out, "mycnet_storage_repair_backlog_volumes", "gauge", "Volumes waiting for repair");
+        let _ = writeln!(out, "mycnet_storage_repair_backlog_volumes {}", self.repair_backlog.len());
+        counter(&mut out, "mycnet_storage_repair_passes_total", "Repair passes run", self.repair.passes);
+        counter(&mut out, "mycnet_storage_repaired_copies_total", "Copies restored by repair", self.repair.copies_repaired);
+        counter(&mut out, "mycnet_storage_repair_bytes_total", "Bytes copied by repair", self.repair.bytes_copied);
+        counter(&mut out, "mycnet_storage_repair_failures_total", "Volume repairs that failed", self.repair.failures);
+        timestamp(&mut out, "mycnet_storage_repair_last_run_timestamp_seconds", "Time of the last repair pass", self.repair.last_run);
+
+        counter(&mut out, "mycnet_storage_scrub_passes_total", "Scrubs run", self.scrub.passes);
+        counter(&mut out, "mycnet_storage_scrub_chunks_checked_total", "Chunks re-hashed by scrubbing", self.scrub.chunks_checked);
+        counter(&mut out, "mycnet_storage_scrub_bytes_checked_total", "Bytes re-hashed by scrubbing", self.scrub.bytes_checked);
+        counter(&mut out, "mycnet_storage_scrub_corrupted_chunks_total", "Chunks that failed verification", self.scrub.corrupted_chunks);
+        counter(&mut out, "mycnet_storage_scrub_repaired_chunks_total", "Corrupted chunks replaced", self.scrub.repaired_chunks);
+        counter(&mut out, "mycnet_storage_scrub_divergent_replicas_total", "Replicas that disagreed with the majority", self.scrub.divergent_replicas);
+        counter(&mut out, "mycnet_storage_scrub_repaired_replicas_total", "Divergent replicas rewritten", self.scrub.repaired_replicas);
+        header(&mut out, "mycnet_storage_scrub_unrepaired", "gauge", "Corruption the last scrub could not repair");
+        let _ = writeln!(out, "mycnet_storage_scrub_unrepaired {}", self.scrub.last_unrepaired);
+        timestamp(&mut out, "mycnet_storage_scrub_last_run_timestamp_seconds", "Time of the last scrub", self.scrub.last_run);
+
+        header(&mut out, "mycnet_storage_io_duration_seconds", "histogram", "Latency of volume reads and writes");
+        for (operation, histogram) in &self.io_latency {
+            let mut cumulative = 0;
+            for (bound, count) in LATENCY_BUCKETS.iter().zip(&histogram.counts) {
+                cumulative += count;
+                let _ = writeln!(
+                    out,
+                    "mycnet_storage_io_duration_seconds_bucket{{operation=\"{}\",le=\"{}\"}} {}",
+                    operation.label(),
+                    bound,
+                    cumulative
+                );
+            }
+            let _ = writeln!(
+                out,
+                "mycnet_storage_io_duration_seconds_bucket{{operation=\"{}\",le=\"+Inf\"}} {}",
+                operation.label(),
+                histogram.count
+            );
+            let _ = writeln!(out, "mycnet_storage_io_duration_seconds_sum{{operation=\"{}\"}} {}", operation.label(), histogram.sum_seconds);
+            let _ = writeln!(out, "mycnet_storage_io_duration_seconds_count{{operation=\"{}\"}} {}", operation.label(), histogram.count);
+        }
+
+        out
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn counter(out: &mut String, name: &str, help: &str, value: u64) {
+    header(out, name, "counter", help);
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+/// Gauge of a Unix timestamp, omitted until the event first happens
+fn timestamp(out: &mut String, name: &str, help: &str, at: Option<DateTime<Utc>>) {
+    if let Some(at) = at {
+        header(out, name, "gauge", help);
+        let _ = writeln!(out, "{} {}", name, at.timestamp_millis() as f64 / 1000.0);
+    }
+}
+
+fn pool_labels(pool: &PoolStatus) -> String {
+    format!("{{pool=\"{}\",tier=\"{}\"}}", escape(&pool.pool_id), format!("{:?}", pool.tier).to_lowercase())
+}
+
+/// Escape a label value per the exposition format
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{add_pool, network_transport, pool, request};
+    use crate::{ClientSession, ConsistencyLevel, InMemoryTransport, VolumeIo, VolumeIoConfig};
+    use std::sync::Arc;
+
+    async fn setup() -> (TrustAwareStorageManager, InMemoryTransport, StorageAllocation) {
+        let mut manager = TrustAwareStorageManager::new();
+        let mut transport = network_transport();
+        add_pool(&mut manager, &mut transport, pool("pool", 0.8, 10_000, Vec::new()), 2);
+        let allocation = manager.allocate_storage(request(1000, DataClassification::Sensitive)).await.unwrap();
+        (manager, transport, allocation)
+    }
+
+    #[tokio::test]
+    async fn test_volume_health_follows_unavailable_nodes() {
+        let (mut manager, _transport, volume) = setup().await;
+        let status = manager.status();
+        assert_eq!(status.pools[0].used_capacity, 2000);
+        assert_eq!(status.pools[0].volumes, 1);
+        assert_eq!(status.volumes[0].health, VolumeHealth::Healthy);
+        assert!(status.repair_backlog.is_empty());
+
+        let mut down = HashSet::from([volume.replica_nodes[0]]);
+        manager.record_repair_pass(&RepairReport::default(), &down);
+        let status = manager.volume_status(&volume.volume_id).unwrap();
+        assert_eq!((status.health, status.healthy_copies), (VolumeHealth::Degraded, 1));
+        assert_eq!(manager.status().repair_backlog, vec![volume.volume_id]);
+
+        // A node below the classification's trust threshold counts as lost too
+        down.clear();
+        manager.record_repair_pass(&RepairReport::default(), &down);
+        manager.trust_evaluator_mut().set_node_trust_score(volume.primary_node, 0.1);
+        manager.trust_evaluator_mut().set_node_trust_score(volume.replica_nodes[0], 0.1);
+        assert_eq!(manager.volume_status(&volume.volume_id).unwrap().health, VolumeHealth::Lost);
+        assert_eq!(manager.status().repair.passes, 2);
+    }
+
+    #[test]
+    fn test_latency_histogram_buckets() {
+        let mut histogram = LatencyHistogram::default();
+        histogram.observe(Duration::from_micros(300));
+        histogram.observe(Duration::from_millis(3));
+        histogram.observe(Duration::from_secs(30));
+        assert_eq!(histogram.counts[0], 1);
+        assert_eq!(histogram.counts[3], 1);
+        assert_eq!(histogram.counts[LATENCY_BUCKETS.len()], 1);
+        assert_eq!(histogram.quantile(0.5), Some(0.005));
+        assert_eq!(histogram.quantile(1.0), None);
+    }
+
+    #[tokio::test]
+    async fn test_prometheus_export() {
+        let (mut manager, transport, volume) = setup().await;
+        let plan = manager.replication_manager().active_plan(&volume.volume_id).unwrap().clone();
+        let io = VolumeIo::new(Uuid::new_v4(), Arc::new(transport), VolumeIoConfig::default());
+        // Failed operations are timed too; this one is refused for lacking a data key
+        assert!(io.write(&plan, &ConsistencyLevel::Strong, b"data".to_vec(), &mut ClientSession::new()).await.is_err());
+        manager.record_io_latency(&io.take_io_latency());
+        assert!(io.take_io_latency().is_empty());
+        manager.record_repair_pass(&RepairReport::default(), &HashSet::from([volume.primary_node]));
+
+        let text = manager.status().to_prometheus();
+        assert!(text.contains("# TYPE mycnet_storage_pool_used_bytes gauge\n"));
+        assert!(text.contains("mycnet_storage_pool_used_bytes{pool=\"pool\",tier=\"sclerotia\"} 2000\n"));
+        assert!(text.contains("mycnet_storage_volumes{health=\"degraded\"} 1\n"));
+        assert!(text.contains(&format!(
+            "mycnet_storage_volume_health{{volume=\"{}\",pool=\"pool\",health=\"degraded\"}} 1\n",
+            volume.volume_id
+        )));
+        assert!(text.contains("mycnet_storage_repair_backlog_volumes 1\n"));
+        assert!(text.contains("mycnet_storage_io_duration_seconds_bucket{operation=\"write\",le=\"+Inf\"} 1\n"));
+        assert!(text.contains("mycnet_storage_io_duration_seconds_count{operation=\"write\"} 1\n"));
+        assert!(!text.contains("operation=\"read\""));
+        assert!(!text.contains("mycnet_storage_scrub_last_run_timestamp_seconds"));
+        assert_eq!(escape("a\"b\\c\nd"), "a\\\"b\\\\c\\nd");
+    }
+}
@@ -0,0 +1,322 @@
+//! Replica placement for each replication strategy
+
+use crate::{ReplicationStrategy, StorageError};
+pub use mycnet_core::NodeTier;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use uuid::Uuid;
+
+/// Static description of a node that can hold replicas
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StorageNode {
+    pub node_id: Uuid,
+    pub tier: NodeTier,
+    /// Geographic zone label, if known
+    pub zone: Option<String>,
+    /// Nodes sharing a failure domain (host, rack, power feed) can fail together
+    pub failure_domain: String,
+    pub latency_ms: u32,
+    pub bandwidth_mbps: u32,
+}
+
+/// Node considered for placement together with its current trust score
+#[derive(Debug, Clone)]
+pub struct PlacementCandidate {
+    pub node: StorageNode,
+    pub trust_score: f32,
+}
+
+/// Constraints every placement must satisfy
+#[derive(Debug, Clone)]
+pub struct PlacementConstraints {
+    /// Require every copy to be in a different failure domain
+    pub distinct_failure_domains: bool,
+    /// Require every copy to be in a different geographic zone; nodes without a zone are skipped
+    pub distinct_zones: bool,
+    /// Nodes that must not receive a copy
+    pub excluded_nodes: HashSet<Uuid>,
+}
+
+/// Nodes chosen for a volume, primary first
+#[derive(Debug, Clone, PartialEq)]
+pub struct Placement {
+    pub primary_node: Uuid,
+    pub replica_nodes: Vec<Uuid>,
+}
+
+/// Trust band used by `TrustDiversification`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum TrustBand {
+    High,
+    Medium,
+    Low,
+}
+
+impl Default for PlacementConstraints {
+    fn default() -> Self {
+        Self {
+            distinct_failure_domains: true,
+            distinct_zones: false,
+            excluded_nodes: HashSet::new(),
+        }
+    }
+}
+
+impl TrustBand {
+    fn of(trust_score: f32) -> Self {
+        if trust_score >= 0.9 {
+            TrustBand::High
+        } else if trust_score >= 0.7 {
+            TrustBand::Medium
+        } else {
+            TrustBand::Low
+        }
+    }
+}
+
+impl Placement {
+    /// All nodes holding a copy, primary first
+    pub fn all_nodes(&self) -> Vec<Uuid> {
+        std::iter::once(self.primary_node).chain(self.replica_nodes.iter().copied()).collect()
+    }
+}
+
+/// Choose `copies` nodes for a volume according to a strategy
+///
+/// Candidates are grouped by the dimension the strategy spreads over (tier,
+/// zone or trust band) and picked round-robin across groups, best first within
+/// each group, so copies land in as many groups as possible. The first pick is
+/// the primary.
+pub fn place_replicas(
+    strategy: &ReplicationStrategy,
+    candidates: &[PlacementCandidate],
+    copies: usize,
+    constraints: &PlacementConstraints,
+) -> Result<Placement, StorageError> {
+    if copies == 0 {
+        return Err(StorageError::InvalidRequest("Replica count must be at least 1".to_string()));
+    }
+
+    let eligible: Vec<&PlacementCandidate> = candidates
+        .iter()
+        .filter(|candidate| !constraints.excluded_nodes.contains(&candidate.node.node_id))
+        .filter(|candidate| !constraints.distinct_zones || candidate.node.zone.is_some())
+        .collect();
+
+    if eligible.len() < copies {
+        return Err(StorageError::InsufficientNodes {
+            required: copies,
+            available: eligible.len(),
+        });
+    }
+    if constraints.distinct_failure_domains {
+        let domains: HashSet<&str> = eligible.iter().map(|c| c.node.failure_domain.as_str()).collect();
+        if domains.len() < copies {
+            return Err(StorageError::InsufficientFailureDomains {
+                required: copies,
+                available: domains.len(),
+            });
+        }
+    }
+    if constraints.distinct_zones {
+        let zones: HashSet<&str> = eligible.iter().filter_map(|c| c.node.zone.as_deref()).collect();
+        if zones.len() < copies {
+            return Err(StorageError::InsufficientZones {
+                required: copies,
+                available: zones.len(),
+            });
+        }
+    }
+
+    let groups = group_candidates(strategy, eligible);
+    let chosen = pick_round_robin(groups, copies, constraints);
+
+    if chosen.len() < copies {
+        return Err(StorageError::InsufficientNodes {
+            required: copies,
+            available: chosen.len(),
+        });
+    }
+
+    Ok(Placement {
+        primary_node: chosen[0],
+        replica_nodes: chosen[1..].to_vec(),
+    })
+}
+
+/// Split candidates into ordered groups, each sorted best first
+fn group_candidates<'a>(strategy: &ReplicationStrategy, eligible: Vec<&'a PlacementCandidate>) -> Vec<Vec<&'a PlacementCandidate>> {
+    let by_trust = |a: &&PlacementCandidate, b: &&PlacementCandidate| {
+        b.trust_score
+            .total_cmp(&a.trust_score)
+            .then(a.node.node_id.cmp(&b.node.node_id))
+    };
+
+    let mut groups: Vec<Vec<&PlacementCandidate>> = match strategy {
+        ReplicationStrategy::HierarchyAware => {
+            let mut tiers: BTreeMap<NodeTier, Vec<&PlacementCandidate>> = BTreeMap::new();
+            for candidate in eligible {
+                tiers.entry(candidate.node.tier).or_default().push(candidate);
+            }
+            tiers.into_values().collect()
+        },
+        ReplicationStrategy::GeographicDistribution => {
+            let mut zones: BTreeMap<&str, Vec<&PlacementCandidate>> = BTreeMap::new();
+            for candidate in eligible {
+                zones.entry(candidate.node.zone.as_deref().unwrap_or("")).or_default().push(candidate);
+            }
+            zones.into_values().collect()
+        },
+        ReplicationStrategy::TrustDiversification => {
+            let mut bands: BTreeMap<TrustBand, Vec<&PlacementCandidate>> = BTreeMap::new();
+            for candidate in eligible {
+                bands.entry(TrustBand::of(candidate.trust_score)).or_default().push(candidate);
+            }
+            bands.into_values().collect()
+        },
+        ReplicationStrategy::PerformanceOptimized => {
+            let mut fastest = eligible;
+            fastest.sort_by(|a, b| {
+                a.node.latency_ms
+                    .cmp(&b.node.latency_ms)
+                    .then(b.node.bandwidth_mbps.cmp(&a.node.bandwidth_mbps))
+                    .then(a.node.node_id.cmp(&b.node.node_id))
+            });
+            return vec![fastest];
+        },
+    };
+
+    for group in &mut groups {
+        group.sort_by(by_trust);
+    }
+    if matches!(strategy, ReplicationStrategy::GeographicDistribution) {
+        // Start with the zone holding the most trusted node so the primary is the best available
+        groups.sort_by(|a, b| by_trust(&a[0], &b[0]));
+    }
+    groups
+}
+
+fn pick_round_robin(groups: Vec<Vec<&PlacementCandidate>>, copies: usize, constraints: &PlacementConstraints) -> Vec<Uuid> {
+    let mut iterators: Vec<_> = groups.into_iter().map(|group| group.into_iter()).collect();
+    let mut used_domains = HashSet::new();
+    let mut used_zones = HashSet::new();
+    let mut chosen = Vec::new();
+
+    while chosen.len() < copies {
+        let mut progressed = false;
+
+        for iterator in iterators.iter_mut() {
+            if chosen.len() == copies {
+                break;
+            }
+            // Take the next candidate in this group that does not repeat a failure domain or zone
+            let next = iterator.by_ref().find(|candidate| {
+                (!constraints.distinct_failure_domains || !used_domains.contains(candidate.node.failure_domain.as_str()))
+                    && (!constraints.distinct_zones || !used_zones.contains(&candidate.node.zone))
+            });
+            if let Some(candidate) = next {
+                used_domains.insert(candidate.node.failure_domain.as_str());
+                used_zones.insert(&candidate.node.zone);
+                chosen.push(candidate.node.node_id);
+                progressed = true;
+            }
+        }
+
+        if !progressed {
+            break;
+        }
+    }
+
+    chosen
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(tier: NodeTier, zone: &str, domain: &str, trust_score: f32, latency_ms: u32) -> PlacementCandidate {
+        PlacementCandidate {
+            node: StorageNode {
+                node_id: Uuid::new_v4(),
+                tier,
+                zone: Some(zone.to_string()),
+                failure_domain: domain.to_string(),
+                latency_ms,
+                bandwidth_mbps: 1000,
+            },
+            trust_score,
+        }
+    }
+
+    #[test]
+    fn test_hierarchy_aware_spreads_tiers() {
+        let candidates = vec![
+            candidate(NodeTier::Sclerotia, "a", "rack-1", 0.95, 5),
+            candidate(NodeTier::Sclerotia, "a", "rack-2", 0.94, 5),
+            candidate(NodeTier::Rhizomorph, "a", "rack-3", 0.8, 10),
+            candidate(NodeTier::Hyphae, "a", "rack-4", 0.6, 30),
+        ];
+
+        let placement = place_replicas(&ReplicationStrategy::HierarchyAware, &candidates, 3, &PlacementConstraints::default()).unwrap();
+        assert_eq!(placement.primary_node, candidates[0].node.node_id);
+        assert!(placement.replica_nodes.contains(&candidates[2].node.node_id));
+        assert!(placement.replica_nodes.contains(&candidates[3].node.node_id));
+    }
+
+    #[test]
+    fn test_geographic_and_trust_spread() {
+        let candidates = vec![
+            candidate(NodeTier::Sclerotia, "eu", "d1", 0.95, 5),
+            candidate(NodeTier::Sclerotia, "eu", "d2", 0.93, 5),
+            candidate(NodeTier::Sclerotia, "us", "d3", 0.75, 5),
+        ];
+
+        let geo = place_replicas(&ReplicationStrategy::GeographicDistribution, &candidates, 2, &PlacementConstraints::default()).unwrap();
+        assert_eq!(geo.all_nodes(), vec![candidates[0].node.node_id, candidates[2].node.node_id]);
+
+        let trust = place_replicas(&ReplicationStrategy::TrustDiversification, &candidates, 2, &PlacementConstraints::default()).unwrap();
+        assert!(trust.all_nodes().contains(&candidates[2].node.node_id));
+    }
+
+    #[test]
+    fn test_distinct_zones_constraint() {
+        let candidates = vec![
+            candidate(NodeTier::Sclerotia, "eu", "d1", 0.95, 5),
+            candidate(NodeTier::Rhizomorph, "eu", "d2", 0.9, 5),
+            candidate(NodeTier::Hyphae, "us", "d3", 0.7, 5),
+        ];
+        let constraints = PlacementConstraints {
+            distinct_zones: true,
+            ..PlacementConstraints::default()
+        };
+
+        // Hierarchy-aware would otherwise put both copies in "eu"
+        let placement = place_replicas(&ReplicationStrategy::HierarchyAware, &candidates, 2, &constraints).unwrap();
+        assert_eq!(placement.all_nodes(), vec![candidates[0].node.node_id, candidates[2].node.node_id]);
+        assert!(matches!(
+            place_replicas(&ReplicationStrategy::HierarchyAware, &candidates, 3, &constraints),
+            Err(StorageError::InsufficientZones { required: 3, available: 2 })
+        ));
+    }
+
+    #[test]
+    fn test_performance_optimized_and_typed_errors() {
+        let candidates = vec![
+            candidate(NodeTier::Hyphae, "a", "d1", 0.6, 40),
+            candidate(NodeTier::Sclerotia, "a", "d2", 0.9, 2),
+            candidate(NodeTier::Rhizomorph, "a", "d2", 0.8, 3),
+        ];
+
+        let fast = place_replicas(&ReplicationStrategy::PerformanceOptimized, &candidates, 2, &PlacementConstraints::default()).unwrap();
+        assert_eq!(fast.all_nodes(), vec![candidates[1].node.node_id, candidates[0].node.node_id]);
+
+        assert!(matches!(
+            place_replicas(&ReplicationStrategy::PerformanceOptimized, &candidates, 3, &PlacementConstraints::default()),
+            Err(StorageError::InsufficientFailureDomains { required: 3, available: 2 })
+        ));
+        assert!(matches!(
+            place_replicas(&ReplicationStrategy::HierarchyAware, &[], 1, &PlacementConstraints::default()),
+            Err(StorageError::InsufficientNodes { required: 1, available: 0 })
+        ));
+    }
+}
@@ -0,0 +1,313 @@
+//! Storage quotas per service and network, and online volume expansion
+
+use crate::{StorageAllocation, StorageError, TrustAwareStorageManager, TrustRequirements};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use uuid::Uuid;
+
+/// Owner a quota applies to
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum QuotaScope {
+    Service(String),
+    Network(String),
+}
+
+/// Resource a quota limits
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum QuotaResource {
+    /// Logical bytes of all volumes
+    Bytes,
+    Volumes,
+    /// Pool capacity reserved for every copy or fragment
+    ReplicaBytes,
+}
+
+/// Limits for one scope; `None` leaves a resource unlimited
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StorageQuota {
+    pub max_bytes: Option<u64>,
+    pub max_volumes: Option<u64>,
+    pub max_replica_bytes: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuotaUsage {
+    pub bytes: u64,
+    pub volumes: u64,
+    pub replica_bytes: u64,
+}
+
+/// Quota and current usage of one scope
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuotaReport {
+    pub scope: QuotaScope,
+    pub quota: StorageQuota,
+    pub usage: QuotaUsage,
+}
+
+impl fmt::Display for QuotaScope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QuotaScope::Service(service_id) => write!(f, "service {}", service_id),
+            QuotaScope::Network(network_id) => write!(f, "network {}", network_id),
+        }
+    }
+}
+
+impl fmt::Display for QuotaResource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QuotaResource::Bytes => write!(f, "bytes"),
+            QuotaResource::Volumes => write!(f, "volumes"),
+            QuotaResource::ReplicaBytes => write!(f, "replica bytes"),
+        }
+    }
+}
+
+impl StorageQuota {
+    /// Check usage after a change against every limit
+    pub fn check(&self, scope: &QuotaScope, usage: &QuotaUsage) -> Result<(), StorageError> {
+        let limits = [
+            (QuotaResource::Bytes, usage.bytes, self.max_bytes),
+            (QuotaResource::Volumes, usage.volumes, self.max_volumes),
+            (QuotaResource::ReplicaBytes, usage.replica_bytes, self.max_replica_bytes),
+        ];
+        for (resource, requested, limit) in limits {
+            if let Some(limit) = limit.filter(|limit| requested > *limit) {
+                return Err(StorageError::QuotaExceeded {
+                    scope: scope.clone(),
+                    resource,
+                    requested,
+                    limit,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+impl QuotaUsage {
+    fn of(allocation: &StorageAllocation) -> Self {
+        Self {
+            bytes: allocation.allocated_size,
+            volumes: 1,
+            replica_bytes: allocation.reserved_capacity,
+        }
+    }
+
+    pub(crate) fn plus(&self, other: &QuotaUsage) -> Self {
+        Self {
+            bytes: self.bytes.saturating_add(other.bytes),
+            volumes: self.volumes.saturating_add(other.volumes),
+            replica_bytes: self.replica_bytes.saturating_add(other.replica_bytes),
+        }
+    }
+}
+
+/// Scopes an allocation is charged to
+pub(crate) fn scopes(service_id: Option<&String>, network_id: Option<&String>) -> Vec<QuotaScope> {
+    service_id
+        .map(|service_id| QuotaScope::Service(service_id.clone()))
+        .into_iter()
+        .chain(network_id.map(|network_id| QuotaScope::Network(network_id.clone())))
+        .collect()
+}
+
+impl TrustAwareStorageManager {
+    /// Set or replace the quota for a scope
+    ///
+    /// Existing allocations are never revoked; a quota below current usage
+    /// only blocks further growth.
+    pub fn set_quota(&mut self, scope: QuotaScope, quota: StorageQuota) {
+        self.quotas.insert(scope, quota);
+    }
+
+    /// Remove a scope's quota, leaving it unlimited
+    pub fn remove_quota(&mut self, scope: &QuotaScope) -> Option<StorageQuota> {
+        self.quotas.remove(scope)
+    }
+
+    /// Current usage charged to a scope, including snapshots and clones
+    pub fn quota_usage(&self, scope: &QuotaScope) -> QuotaUsage {
+        self.allocations
+            .values()
+            .filter(|allocation| scopes(allocation.service_id.as_ref(), allocation.network_id.as_ref()).contains(scope))
+            .fold(QuotaUsage::default(), |usage, allocation| usage.plus(&QuotaUsage::of(allocation)))
+    }
+
+    /// Usage of every scope with a quota, ordered by scope
+    pub fn quota_reports(&self) -> Vec<QuotaReport> {
+        let mut reports: Vec<QuotaReport> = self
+            .quotas
+            .iter()
+            .map(|(scope, quota)| QuotaReport {
+                scope: scope.clone(),
+                quota: quota.clone(),
+                usage: self.quota_usage(scope),
+            })
+            .collect();
+        reports.sort_by_key(|report| report.scope.to_string());
+        reports
+    }
+
+    /// Check that adding `additional` to each scope stays within its quota
+    pub(crate) fn check_quotas(&self, scopes: &[QuotaScope], additional: &QuotaUsage) -> Result<(), StorageError> {
+        for scope in scopes {
+            if let Some(quota) = self.quotas.get(scope) {
+                quota.check(scope, &self.quota_usage(scope).plus(additional))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Grow a volume in place
+    ///
+    /// Re-checks that every node holding the volume still meets its
+    /// classification, that the pool can reserve the extra capacity and that
+    /// the volume's quotas allow it. Expanding to the current size succeeds
+    /// without changes; shrinking is rejected.
+    pub fn expand_volume(&mut self, volume_id: &Uuid, new_size: u64) -> Result<StorageAllocation, StorageError> {
+        let allocation = self.allocations.get(volume_id).ok_or(StorageError::VolumeNotFound(*volume_id))?;
+        if new_size < allocation.allocated_size {
+            return Err(StorageError::InvalidRequest(format!(
+                "Volume {} holds {} bytes and cannot shrink to {}",
+                volume_id, allocation.allocated_size, new_size
+            )));
+        }
+        if new_size == allocation.allocated_size {
+            return Ok(allocation.clone());
+        }
+        if self.snapshots.contains_key(volume_id) {
+            return Err(StorageError::InvalidRequest(format!("Snapshot {} cannot be expanded", volume_id)));
+        }
+
+        let reserved_capacity = allocation
+            .reserved_capacity_for(new_size)
+            .ok_or_else(|| StorageError::InvalidRequest("Requested capacity overflows".to_string()))?;
+        let additional = reserved_capacity - allocation.reserved_capacity;
+
+        let requirements = TrustRequirements::for_classification(&allocation.data_classification);
+        self.check_node_trust(
+            &allocation.data_classification,
+            std::iter::once(allocation.primary_node).chain(allocation.replica_nodes.iter().copied()),
+        )?;
+
+        let pool = self.storage_pools
+            .get(&allocation.pool_id)
+            .ok_or_else(|| StorageError::PoolNotFound(allocation.pool_id.clone()))?;
+        if pool.reservable_capacity(&self.overcommit_policy) < additional {
+            return Err(StorageError::NoSuitablePool {
+                minimum_trust_score: requirements.minimum_trust_score,
+                required_capacity: additional,
+            });
+        }
+
+        self.check_quotas(
+            &scopes(allocation.service_id.as_ref(), allocation.network_id.as_ref()),
+            &QuotaUsage {
+                bytes: new_size - allocation.allocated_size,
+                volumes: 0,
+                replica_bytes: additional,
+            },
+        )?;
+
+        if let Some(pool) = self.storage_pools.get_mut(&allocation.pool_id) {
+            pool.used_capacity += additional;
+        }
+        let allocation = self.allocations.get_mut(volume_id).ok_or(StorageError::VolumeNotFound(*volume_id))?;
+        allocation.allocated_size = new_size;
+        allocation.reserved_capacity = reserved_capacity;
+
+        tracing::info!("Expanded volume {} to {} bytes", volume_id, new_size);
+        Ok(allocation.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{add_pool, network_transport, pool};
+    use crate::{DataClassification, ReplicationStrategy, StorageRequest};
+
+    fn manager() -> TrustAwareStorageManager {
+        let mut manager = TrustAwareStorageManager::new();
+        add_pool(&mut manager, &mut network_transport(), pool("pool", 0.8, 10_000, Vec::new()), 2);
+        manager
+    }
+
+    fn request(size_bytes: u64, service_id: &str) -> StorageRequest {
+        let mut request = crate::test_support::request(size_bytes, DataClassification::Standard);
+        request.replication_requirements.replication_strategy = ReplicationStrategy::HierarchyAware;
+        request.service_id = Some(service_id.to_string());
+        request.network_id = Some("edge".to_string());
+        request
+    }
+
+    fn quota_error(err: Box<dyn std::error::Error>) -> (QuotaScope, QuotaResource) {
+        match err.downcast_ref::<StorageError>() {
+            Some(StorageError::QuotaExceeded { scope, resource, .. }) => (scope.clone(), *resource),
+            other => panic!("expected a quota error, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_allocation_checks_service_and_network_quotas() {
+        let mut manager = manager();
+        let service = QuotaScope::Service("web".to_string());
+        manager.set_quota(service.clone(), StorageQuota {
+            max_volumes: Some(2),
+            max_replica_bytes: Some(3000),
+            ..StorageQuota::default()
+        });
+
+        manager.allocate_storage(request(1000, "web")).await.unwrap();
+        let err = manager.allocate_storage(request(1000, "web")).await.unwrap_err();
+        assert_eq!(quota_error(err), (service.clone(), QuotaResource::ReplicaBytes));
+
+        manager.allocate_storage(request(500, "web")).await.unwrap();
+        let err = manager.allocate_storage(request(1, "web")).await.unwrap_err();
+        assert_eq!(quota_error(err), (service.clone(), QuotaResource::Volumes));
+
+        // Network quotas apply across services
+        let network = QuotaScope::Network("edge".to_string());
+        manager.set_quota(network.clone(), StorageQuota {
+            max_bytes: Some(1600),
+            ..StorageQuota::default()
+        });
+        let err = manager.allocate_storage(request(200, "db")).await.unwrap_err();
+        assert_eq!(quota_error(err), (network.clone(), QuotaResource::Bytes));
+        manager.allocate_storage(request(100, "db")).await.unwrap();
+
+        let reports = manager.quota_reports();
+        assert_eq!(reports[0].scope, network);
+        assert_eq!(reports[0].usage, QuotaUsage { bytes: 1600, volumes: 3, replica_bytes: 3200 });
+        assert_eq!(manager.quota_usage(&service).replica_bytes, 3000);
+    }
+
+    #[tokio::test]
+    async fn test_expand_volume_rechecks_capacity_and_quota() {
+        let mut manager = manager();
+        let allocation = manager.allocate_storage(request(1000, "web")).await.unwrap();
+        let volume_id = allocation.volume_id;
+
+        let expanded = manager.expand_volume(&volume_id, 2000).unwrap();
+        assert_eq!(expanded.reserved_capacity, 4000);
+        assert_eq!(manager.storage_pool("pool").unwrap().used_capacity, 4000);
+        assert!(manager.expand_volume(&volume_id, 1000).is_err());
+        assert_eq!(manager.expand_volume(&volume_id, 2000).unwrap().allocated_size, 2000);
+
+        let err = manager.expand_volume(&volume_id, 6000).unwrap_err();
+        assert!(matches!(err, StorageError::NoSuitablePool { required_capacity: 8000, .. }));
+
+        manager.set_quota(QuotaScope::Service("web".to_string()), StorageQuota {
+            max_bytes: Some(2500),
+            ..StorageQuota::default()
+        });
+        let err = manager.expand_volume(&volume_id, 3000).unwrap_err();
+        assert!(matches!(err, StorageError::QuotaExceeded { resource: QuotaResource::Bytes, requested: 3000, limit: 2500, .. }));
+
+        manager.trust_evaluator_mut().set_node_trust_score(allocation.primary_node, 0.3);
+        assert!(matches!(manager.expand_volume(&volume_id, 2200), Err(StorageError::UntrustedPlacement { .. })));
+        assert_eq!(manager.allocation(&volume_id).unwrap().allocated_size, 2000);
+    }
+}
@@ -0,0 +1,522 @@
+//! Re-replication of volumes whose copies are lost or untrusted
+
+use crate::erasure::rebuild_fragment;
+use crate::volume_io::{read_newest, send_with_timeout};
+use crate::{
+    DataClassification, ErasureCoding, ReplicaRequest, ReplicaTransport, StorageError, TrustAwareStorageManager, TrustRequirements,
+};
+use mycnet_networking::{ConnectionHealthMonitor, HealthEvent};
+use std::collections::HashSet;
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast::{self, error::TryRecvError};
+use uuid::Uuid;
+
+/// Limits on how much repair work runs at once
+#[derive(Debug, Clone)]
+pub struct RepairConfig {
+    /// Bytes copied per second across all repairs; 0 disables the limit
+    pub max_bytes_per_second: u64,
+    /// Volumes repaired in a single pass
+    pub max_repairs_per_pass: usize,
+    /// How long a replica may take to answer one repair request
+    pub request_timeout: Duration,
+}
+
+/// Volume with fewer healthy copies than its plan requires
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnderReplicatedVolume {
+    pub volume_id: Uuid,
+    pub data_classification: DataClassification,
+    pub healthy_copies: usize,
+    pub required_copies: usize,
+    /// Nodes that are down or below the classification's trust threshold
+    pub failed_nodes: Vec<Uuid>,
+}
+
+/// Replacement of one failed copy
+#[derive(Debug, Clone, PartialEq)]
+pub struct RepairAction {
+    pub volume_id: Uuid,
+    pub failed_node: Uuid,
+    pub replacement: Uuid,
+    pub bytes_copied: u64,
+}
+
+/// Outcome of a repair pass
+#[derive(Debug, Default)]
+pub struct RepairReport {
+    pub repaired: Vec<RepairAction>,
+    pub failed: Vec<(Uuid, StorageError)>,
+    /// Volumes still under-replicated after the pass
+    pub under_replicated: Vec<UnderReplicatedVolume>,
+}
+
+/// Watches node health and trust and restores lost copies
+///
+/// Each pass finds volumes with failed copies, repairs the most important
+/// ones first (by classification, then by copies lost) and streams the newest
+/// healthy copy to a replacement chosen through placement.
+pub struct RepairController {
+    config: RepairConfig,
+    down_nodes: HashSet<Uuid>,
+    limiter: ByteRateLimiter,
+    health: Option<HealthFeed>,
+}
+
+/// Subscription to a networking health monitor
+struct HealthFeed {
+    events: broadcast::Receiver<HealthEvent>,
+    monitor: Weak<ConnectionHealthMonitor>,
+}
+
+/// Token bucket pacing repair traffic
+pub(crate) struct ByteRateLimiter {
+    bytes_per_second: u64,
+    available: f64,
+    last_refill: Instant,
+}
+
+impl Default for RepairConfig {
+    fn default() -> Self {
+        Self {
+            max_bytes_per_second: 50 * 1024 * 1024,
+            max_repairs_per_pass: 16,
+            request_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+impl ByteRateLimiter {
+    pub(crate) fn new(bytes_per_second: u64) -> Self {
+        Self {
+            bytes_per_second,
+            available: bytes_per_second as f64,
+            last_refill: Instant::now(),
+        }
+    }
+
+    /// Wait until `bytes` may be sent
+    pub(crate) async fn acquire(&mut self, bytes: u64) {
+        if self.bytes_per_second == 0 {
+            return;
+        }
+        let rate = self.bytes_per_second as f64;
+
+        let now = Instant::now();
+        self.available = (self.available + now.duration_since(self.last_refill).as_secs_f64() * rate).min(rate);
+        self.last_refill = now;
+
+        self.available -= bytes as f64;
+        if self.available < 0.0 {
+            // Transfers larger than the bucket borrow against future refills
+            tokio::time::sleep(Duration::from_secs_f64(-self.available / rate)).await;
+            self.available = 0.0;
+            self.last_refill = Instant::now();
+        }
+    }
+}
+
+/// Repair order of a classification, most urgent first
+pub(crate) fn repair_priority(classification: &DataClassification) -> u8 {
+    match classification {
+        DataClassification::Critical => 0,
+        DataClassification::Sensitive => 1,
+        DataClassification::Standard => 2,
+        DataClassification::Public => 3,
+    }
+}
+
+impl RepairController {
+    pub fn new(config: RepairConfig) -> Self {
+        let limiter = ByteRateLimiter::new(config.max_bytes_per_second);
+        Self {
+            config,
+            down_nodes: HashSet::new(),
+            limiter,
+            health: None,
+        }
+    }
+
+    /// Mark nodes down and up as `monitor` reports them unhealthy or recovered
+    ///
+    /// Events are applied at the start of every pass. If the controller falls
+    /// behind the event stream it re-reads the health of every node holding a
+    /// copy from the monitor.
+    pub fn follow_health(&mut self, monitor: &Arc<ConnectionHealthMonitor>) {
+        self.health = Some(HealthFeed {
+            events: monitor.subscribe(),
+            monitor: Arc::downgrade(monitor),
+        });
+    }
+
+    /// Apply health events received since the last pass
+    pub fn sync_health(&mut self, manager: &TrustAwareStorageManager) {
+        let Some(feed) = &mut self.health else { return };
+        let mut lagged = false;
+        loop {
+            match feed.events.try_recv() {
+                Ok(HealthEvent::Unhealthy { node_id, .. }) => {
+                    self.down_nodes.insert(node_id);
+                },
+                Ok(HealthEvent::Recovered { node_id, .. }) => {
+                    self.down_nodes.remove(&node_id);
+                },
+                Err(TryRecvError::Lagged(_)) => lagged = true,
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Closed) => {
+                    self.health = None;
+                    break;
+                },
+            }
+        }
+
+        let Some(monitor) = self.health.as_ref().and_then(|feed| feed.monitor.upgrade()) else { return };
+        if lagged {
+            let nodes: HashSet<Uuid> = manager
+                .allocations()
+                .flat_map(|allocation| std::iter::once(allocation.primary_node).chain(allocation.replica_nodes.iter().copied()))
+                .collect();
+            for node_id in nodes {
+                match monitor.health(&node_id) {
+                    Some(health) if !health.is_healthy => {
+                        self.down_nodes.insert(node_id);
+                    },
+                    Some(_) => {
+                        self.down_nodes.remove(&node_id);
+                    },
+                    None => {},
+                }
+            }
+        }
+    }
+
+    /// Record that a node stopped responding or left the network
+    pub fn report_node_down(&mut self, node_id: Uuid) {
+        self.down_nodes.insert(node_id);
+    }
+
+    /// Record that a node is reachable again
+    pub fn report_node_up(&mut self, node_id: &Uuid) {
+        self.down_nodes.remove(node_id);
+    }
+
+    /// Whether a node is currently considered down
+    pub fn is_down(&self, node_id: &Uuid) -> bool {
+        self.down_nodes.contains(node_id)
+    }
+
+    /// Volumes with copies on down or untrusted nodes, most urgent first
+    pub fn under_replicated(&self, manager: &TrustAwareStorageManager) -> Vec<UnderReplicatedVolume> {
+        let mut volumes: Vec<UnderReplicatedVolume> = manager
+            .allocations()
+            .filter_map(|allocation| {
+                let minimum = TrustRequirements::for_classification(&allocation.data_classification).minimum_trust_score;
+                let nodes: Vec<Uuid> = std::iter::once(allocation.primary_node)
+                    .chain(allocation.replica_nodes.iter().copied())
+                    .collect();
+
+                let failed_nodes: Vec<Uuid> = nodes
+                    .iter()
+                    .copied()
+                    .filter(|node_id| {
+                        self.down_nodes.contains(node_id) || manager.trust_evaluator().get_node_trust_score(node_id) < minimum
+                    })
+                    .collect();
+
+                (!failed_nodes.is_empty()).then(|| UnderReplicatedVolume {
+                    volume_id: allocation.volume_id,
+                    data_classification: allocation.data_classification,
+                    healthy_copies: nodes.len() - failed_nodes.len(),
+                    required_copies: nodes.len(),
+                    failed_nodes,
+                })
+            })
+            .collect();
+
+        volumes.sort_by(|a, b| {
+            repair_priority(&a.data_classification)
+                .cmp(&repair_priority(&b.data_classification))
+                .then(a.healthy_copies.cmp(&b.healthy_copies))
+                .then(a.volume_id.cmp(&b.volume_id))
+        });
+        volumes
+    }
+
+    /// Run one repair pass
+    pub async fn run_once<T: ReplicaTransport>(&mut self, manager: &mut TrustAwareStorageManager, transport: &T) -> RepairReport {
+        let mut report = RepairReport::default();
+        self.sync_health(manager);
+
+        for volume in self.under_replicated(manager).into_iter().take(self.config.max_repairs_per_pass) {
+            match self.repair_volume(manager, transport, &volume).await {
+                Ok(actions) => report.repaired.extend(actions),
+                Err(e) => {
+                    tracing::warn!("Repair of volume {} failed: {}", volume.volume_id, e);
+                    report.failed.push((volume.volume_id, e));
+                },
+            }
+        }
+
+        report.under_replicated = self.under_replicated(manager);
+        manager.record_repair_pass(&report, &self.down_nodes);
+        report
+    }
+
+    async fn repair_volume<T: ReplicaTransport>(
+        &mut self,
+        manager: &mut TrustAwareStorageManager,
+        transport: &T,
+        volume: &UnderReplicatedVolume,
+    ) -> Result<Vec<RepairAction>, StorageError> {
+        let allocation = manager.allocation(&volume.volume_id).ok_or(StorageError::VolumeNotFound(volume.volume_id))?;
+        let failed: HashSet<Uuid> = volume.failed_nodes.iter().copied().collect();
+        if let Some(coding) = allocation.erasure_coding {
+            return self.repair_fragments(manager, transport, volume, &coding, failed).await;
+        }
+
+        let sources: Vec<Uuid> = std::iter::once(allocation.primary_node)
+            .chain(allocation.replica_nodes.iter().copied())
+            .filter(|node_id| !failed.contains(node_id))
+            .collect();
+
+        // Copy the version that supersedes every other healthy copy, so a
+        // lagging survivor never overwrites newer data on the replacement.
+        // A volume that was never written only needs its replacement nodes.
+        let contents = read_newest(transport, &sources, volume.volume_id, self.config.request_timeout).await?;
+        let bytes_copied = contents.as_ref().map(|contents| contents.data.len() as u64).unwrap_or(0);
+
+        let mut actions = Vec::new();
+        let mut excluded = failed.clone();
+        for failed_node in &volume.failed_nodes {
+            let replacement = manager.select_replacement(&volume.volume_id, &excluded)?;
+
+            if let Some(contents) = &contents {
+                self.limiter.acquire(bytes_copied).await;
+                send_with_timeout(transport, replacement, ReplicaRequest::Write(contents.clone()), self.config.request_timeout).await?;
+            }
+
+            manager.replace_replica(&volume.volume_id, *failed_node, replacement)?;
+            excluded.insert(replacement);
+
+            tracing::info!("Replaced {} with {} for volume {}", failed_node, replacement, volume.volume_id);
+            actions.push(RepairAction {
+                volume_id: volume.volume_id,
+                failed_node: *failed_node,
+                replacement,
+                bytes_copied,
+            });
+        }
+
+        Ok(actions)
+    }
+
+    /// Rebuild each lost fragment of an erasure-coded volume onto a replacement node
+    async fn repair_fragments<T: ReplicaTransport>(
+        &mut self,
+        manager: &mut TrustAwareStorageManager,
+        transport: &T,
+        volume: &UnderReplicatedVolume,
+        coding: &ErasureCoding,
+        failed: HashSet<Uuid>,
+    ) -> Result<Vec<RepairAction>, StorageError> {
+        let mut actions = Vec::new();
+        let mut excluded = failed.clone();
+
+        for failed_node in &volume.failed_nodes {
+            let plan = manager
+                .replication_manager()
+                .active_plan(&volume.volume_id)
+                .cloned()
+                .ok_or(StorageError::VolumeNotFound(volume.volume_id))?;
+            let index = plan
+                .nodes()
+                .iter()
+                .position(|node_id| node_id == failed_node)
+                .ok_or(StorageError::UnknownNode(*failed_node))?;
+            let replacement = manager.select_replacement(&volume.volume_id, &excluded)?;
+
+            let shard_size = manager
+                .allocation(&volume.volume_id)
+                .and_then(|allocation| coding.shard_size(allocation.allocated_size))
+                .unwrap_or(0);
+            self.limiter.acquire(shard_size).await;
+            let bytes_copied = rebuild_fragment(transport, &plan, coding, index, replacement, &failed, self.config.request_timeout).await?;
+
+            manager.replace_replica(&volume.volume_id, *failed_node, replacement)?;
+            excluded.insert(replacement);
+
+            tracing::info!("Rebuilt fragment {} of volume {} on {}", index, volume.volume_id, replacement);
+            actions.push(RepairAction {
+                volume_id: volume.volume_id,
+                failed_node: *failed_node,
+                replacement,
+                bytes_copied,
+            });
+        }
+
+        Ok(actions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::erasure::{read_fragments, write_fragments};
+    use crate::test_support::{add_pool, copies, network_transport, pool, write};
+    use crate::{InMemoryTransport, ReplicationStrategy, StorageRequest, VersionVector, VersionedWrite};
+    use std::collections::BTreeMap;
+
+    async fn setup(node_count: usize) -> (TrustAwareStorageManager, InMemoryTransport, Vec<Uuid>) {
+        let mut manager = TrustAwareStorageManager::new();
+        let mut transport = network_transport();
+        let nodes = add_pool(&mut manager, &mut transport, pool("pool", 0.95, 1 << 30, Vec::new()), node_count);
+        (manager, transport, nodes)
+    }
+
+    fn request(classification: DataClassification, erasure_coding: Option<ErasureCoding>) -> StorageRequest {
+        let mut request = crate::test_support::request(4, classification);
+        request.replication_requirements.replication_strategy = ReplicationStrategy::HierarchyAware;
+        request.replication_requirements.erasure_coding = erasure_coding;
+        request
+    }
+
+    async fn allocate(manager: &mut TrustAwareStorageManager, transport: &InMemoryTransport, classification: DataClassification) -> Uuid {
+        let allocation = manager.allocate_storage(request(classification, None)).await.unwrap();
+        write(transport, &copies(&allocation), allocation.primary_node, allocation.volume_id, b"data");
+        allocation.volume_id
+    }
+
+    #[tokio::test]
+    async fn test_failed_node_replaced_and_data_streamed() {
+        let (mut manager, transport, _) = setup(4).await;
+        let volume_id = allocate(&mut manager, &transport, DataClassification::Critical).await;
+        let failed = manager.allocation(&volume_id).unwrap().replica_nodes[0];
+
+        let mut controller = RepairController::new(RepairConfig::default());
+        controller.report_node_down(failed);
+        transport.set_available(failed, false);
+
+        let report = controller.run_once(&mut manager, &transport).await;
+        assert_eq!(report.repaired.len(), 1);
+        assert!(report.under_replicated.is_empty());
+
+        let replacement = report.repaired[0].replacement;
+        assert!(manager.allocation(&volume_id).unwrap().replica_nodes.contains(&replacement));
+        assert_eq!(transport.node(&replacement).unwrap().read(&volume_id).unwrap().unwrap().data, b"data");
+    }
+
+    #[tokio::test]
+    async fn test_empty_volume_gets_replacement() {
+        let (mut manager, transport, _) = setup(4).await;
+        let allocation = manager.allocate_storage(request(DataClassification::Standard, None)).await.unwrap();
+        let failed = allocation.replica_nodes[0];
+
+        let mut controller = RepairController::new(RepairConfig::default());
+        controller.report_node_down(failed);
+        transport.set_available(failed, false);
+
+        // Healthy nodes answer without a copy, so only the plan changes
+        let report = controller.run_once(&mut manager, &transport).await;
+        assert_eq!(report.repaired.len(), 1);
+        assert_eq!(report.repaired[0].bytes_copied, 0);
+        assert!(!manager.allocation(&allocation.volume_id).unwrap().replica_nodes.contains(&failed));
+
+        // With every copy unreachable the volume is not considered empty
+        let allocation = manager.allocation(&allocation.volume_id).unwrap().clone();
+        for node_id in std::iter::once(allocation.primary_node).chain(allocation.replica_nodes.iter().copied()) {
+            controller.report_node_down(node_id);
+            transport.set_available(node_id, false);
+        }
+        controller.report_node_up(&allocation.primary_node);
+        let report = controller.run_once(&mut manager, &transport).await;
+        assert!(matches!(report.failed[0].1, StorageError::NoHealthyReplica(_)));
+    }
+
+    #[tokio::test]
+    async fn test_untrusted_nodes_reported_critical_first() {
+        let (mut manager, transport, nodes) = setup(2).await;
+        let standard = allocate(&mut manager, &transport, DataClassification::Standard).await;
+        let critical = allocate(&mut manager, &transport, DataClassification::Critical).await;
+
+        // 0.8 still satisfies Standard but not Critical
+        manager.trust_evaluator_mut().set_node_trust_score(nodes[1], 0.8);
+        let controller = RepairController::new(RepairConfig::default());
+        let volumes = controller.under_replicated(&manager);
+        assert_eq!(volumes.len(), 1);
+        assert_eq!(volumes[0].volume_id, critical);
+
+        manager.trust_evaluator_mut().set_node_trust_score(nodes[0], 0.2);
+        let volumes = controller.under_replicated(&manager);
+        assert_eq!(volumes[0].volume_id, critical);
+        assert_eq!(volumes[1].volume_id, standard);
+
+        // No spare nodes exist, so repair fails and the volumes stay reported
+        let mut controller = controller;
+        let report = controller.run_once(&mut manager, &transport).await;
+        assert!(report.repaired.is_empty());
+        assert_eq!(report.under_replicated.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_repair_copies_newest_version_on_health_events() {
+        let (mut manager, transport, _) = setup(5).await;
+        let mut wide = request(DataClassification::Critical, None);
+        wide.replication_requirements.replica_count = 3;
+        let allocation = manager.allocate_storage(wide).await.unwrap();
+        let (old, new) = (VersionVector(BTreeMap::from([(allocation.primary_node, 1)])), VersionVector(BTreeMap::from([(allocation.primary_node, 2)])));
+
+        for node_id in std::iter::once(allocation.primary_node).chain(allocation.replica_nodes.iter().copied()) {
+            let node = transport.node(&node_id).unwrap();
+            node.apply(VersionedWrite { volume_id: allocation.volume_id, version: old.clone(), data: b"old".to_vec() }).unwrap();
+        }
+        // Only the last replica received the newest write
+        transport
+            .node(&allocation.replica_nodes[1])
+            .unwrap()
+            .apply(VersionedWrite { volume_id: allocation.volume_id, version: new.clone(), data: b"new".to_vec() })
+            .unwrap();
+
+        let monitor = Arc::new(ConnectionHealthMonitor::new());
+        let mut controller = RepairController::new(RepairConfig::default());
+        controller.follow_health(&monitor);
+        let failed = allocation.primary_node;
+        transport.set_available(failed, false);
+        // Without a transport every probe fails, so the node turns unhealthy
+        for _ in 0..3 {
+            monitor.check_connection_health(failed).await;
+        }
+
+        let report = controller.run_once(&mut manager, &transport).await;
+        assert!(controller.is_down(&failed));
+        assert_eq!(report.repaired.len(), 1);
+        let copied = transport.node(&report.repaired[0].replacement).unwrap().read(&allocation.volume_id).unwrap().unwrap();
+        assert_eq!((copied.version, copied.data), (new, b"new".to_vec()));
+    }
+
+    #[tokio::test]
+    async fn test_lost_fragment_rebuilt() {
+        let (mut manager, transport, _) = setup(4).await;
+        let coding = ErasureCoding::new(2, 1).unwrap();
+        let allocation = manager.allocate_storage(request(DataClassification::Public, Some(coding))).await.unwrap();
+        assert_eq!(allocation.replica_nodes.len(), 2);
+        assert_eq!(Some(allocation.reserved_capacity), coding.reserved_capacity(4));
+
+        let plan = manager.replication_manager().active_plan(&allocation.volume_id).unwrap().clone();
+        write_fragments(&transport, &plan, &coding, None, b"data", Duration::from_secs(5)).await.unwrap();
+
+        let failed = allocation.primary_node;
+        let mut controller = RepairController::new(RepairConfig::default());
+        controller.report_node_down(failed);
+        transport.set_available(failed, false);
+
+        let report = controller.run_once(&mut manager, &transport).await;
+        assert_eq!(report.repaired.len(), 1);
+        assert!(report.under_replicated.is_empty());
+
+        // The rebuilt fragment replaces the lost one, so another loss is still tolerated
+        let plan = manager.replication_manager().active_plan(&allocation.volume_id).unwrap().clone();
+        transport.set_available(plan.replica_nodes[1], false);
+        assert_eq!(read_fragments(&transport, &plan, &coding, None, Duration::from_secs(5)).await.unwrap(), b"data");
+    }
+}
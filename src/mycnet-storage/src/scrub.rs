@@ -0,0 +1,498 @@
+//! Background scrubbing for bit rot and divergent replicas
+
+use crate::repair::ByteRateLimiter;
+use crate::volume_io::send_with_timeout;
+use crate::{
+    ChunkId, ReplicaNode, ReplicaRequest, ReplicaResponse, ReplicaTransport, ReplicationPlan, StorageError, TrustAwareStorageManager,
+    TrustEvaluator, VersionVector, VolumeDigest,
+};
+use mycnet_security::TrustManager;
+use std::collections::{BTreeSet, HashMap};
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Pacing and penalties for scrubbing
+#[derive(Debug, Clone)]
+pub struct ScrubConfig {
+    /// Bytes re-hashed or copied per second; 0 disables the limit
+    pub max_bytes_per_second: u64,
+    /// Chunks verified per local scrub step
+    pub batch_size: usize,
+    /// Storage trust lost per chunk a node served that failed its checksum
+    pub corruption_penalty: f32,
+    /// How long a replica may take to answer one scrub request
+    pub request_timeout: Duration,
+}
+
+/// Outcome of scrubbing
+#[derive(Debug, Default)]
+pub struct ScrubReport {
+    pub chunks_checked: usize,
+    pub bytes_checked: u64,
+    /// Local chunks that failed verification, by node
+    pub corrupted_chunks: Vec<(Uuid, ChunkId)>,
+    pub repaired_chunks: usize,
+    pub volumes_checked: usize,
+    /// Replicas whose copy was unreadable or disagreed with the majority, as (volume, node)
+    pub divergent_replicas: Vec<(Uuid, Uuid)>,
+    pub repaired_replicas: usize,
+    /// Corruption found but not repaired, by volume or by node for local chunks
+    pub unrepaired: Vec<(Uuid, StorageError)>,
+    /// Trust each node should lose for chunks that failed their checksum
+    ///
+    /// Only confirmed corruption counts; copies that merely disagree with the
+    /// majority are rewritten without a penalty. The same penalties apply to
+    /// storage placement and to a node's network contribution.
+    pub storage_trust_penalties: HashMap<Uuid, f32>,
+}
+
+/// Re-hashes stored chunks and compares replicas across nodes
+///
+/// Local scrubbing walks a node's chunk store in batches, resuming where the
+/// previous step stopped, and replaces chunks whose contents no longer match
+/// their ID with verified copies fetched from peers. Volume scrubbing compares
+/// the digests of every replica of a volume and rewrites copies that are
+/// unreadable or disagree with the majority at the newest version.
+pub struct Scrubber {
+    config: ScrubConfig,
+    limiter: ByteRateLimiter,
+    cursors: HashMap<Uuid, ChunkId>,
+}
+
+impl Default for ScrubConfig {
+    fn default() -> Self {
+        Self {
+            max_bytes_per_second: 10 * 1024 * 1024,
+            batch_size: 256,
+            corruption_penalty: 0.05,
+            request_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+impl ScrubReport {
+    /// Fold another report into this one
+    pub fn merge(&mut self, other: ScrubReport) {
+        self.chunks_checked += other.chunks_checked;
+        self.bytes_checked += other.bytes_checked;
+        self.corrupted_chunks.extend(other.corrupted_chunks);
+        self.repaired_chunks += other.repaired_chunks;
+        self.volumes_checked += other.volumes_checked;
+        self.divergent_replicas.extend(other.divergent_replicas);
+        self.repaired_replicas += other.repaired_replicas;
+        self.unrepaired.extend(other.unrepaired);
+        for (node_id, penalty) in other.storage_trust_penalties {
+            self.penalize(node_id, penalty);
+        }
+    }
+
+    /// Whether any corruption was found
+    pub fn is_clean(&self) -> bool {
+        self.corrupted_chunks.is_empty() && self.divergent_replicas.is_empty()
+    }
+
+    /// Lower the storage trust of every node that served corrupt data
+    pub fn apply_penalties(&self, trust_evaluator: &mut TrustEvaluator) {
+        for (node_id, penalty) in &self.storage_trust_penalties {
+            trust_evaluator.penalize_node(*node_id, *penalty);
+        }
+    }
+
+    /// Lower the network contribution trust of every node that served corrupt data
+    pub fn apply_network_penalties(&self, trust_manager: &mut TrustManager) {
+        for (node_id, penalty) in &self.storage_trust_penalties {
+            trust_manager.penalize_network_contribution(*node_id, *penalty);
+        }
+    }
+
+    fn penalize(&mut self, node_id: Uuid, penalty: f32) {
+        *self.storage_trust_penalties.entry(node_id).or_insert(0.0) += penalty;
+    }
+}
+
+impl Scrubber {
+    pub fn new(config: ScrubConfig) -> Self {
+        let limiter = ByteRateLimiter::new(config.max_bytes_per_second);
+        Self {
+            config,
+            limiter,
+            cursors: HashMap::new(),
+        }
+    }
+
+    /// Verify the next batch of a node's chunks and repair corrupt ones from peers
+    ///
+    /// Returns whether the pass over the node's store completed with this
+    /// batch; the next call then starts again from the beginning.
+    pub async fn scrub_local<T: ReplicaTransport>(
+        &mut self,
+        node: &ReplicaNode,
+        peers: &[Uuid],
+        transport: &T,
+        report: &mut ScrubReport,
+    ) -> Result<bool, StorageError> {
+        let node_id = node.node_id();
+        let batch = node.store().verify_batch(self.cursors.get(&node_id), self.config.batch_size.max(1))?;
+        self.limiter.acquire(batch.bytes).await;
+
+        report.chunks_checked += batch.checked;
+        report.bytes_checked += batch.bytes;
+
+        for id in batch.corrupted {
+            report.corrupted_chunks.push((node_id, id));
+            report.penalize(node_id, self.config.corruption_penalty);
+
+            match fetch_chunk(transport, peers, &id, self.config.request_timeout).await {
+                Some(data) => {
+                    self.limiter.acquire(data.len() as u64).await;
+                    node.store().restore_chunk(&id, &data)?;
+                    report.repaired_chunks += 1;
+                },
+                None => {
+                    tracing::warn!("No peer holds a verified copy of chunk {}", id);
+                    report.unrepaired.push((node_id, StorageError::ChunkCorrupted(id.to_string())));
+                },
+            }
+        }
+
+        match batch.next {
+            Some(next) => {
+                self.cursors.insert(node_id, next);
+                Ok(false)
+            },
+            None => {
+                self.cursors.remove(&node_id);
+                Ok(true)
+            },
+        }
+    }
+
+    /// Compare every replica of a volume and rewrite corrupt or divergent copies
+    ///
+    /// Unreachable nodes and copies that are missing or stale are left to
+    /// hint delivery and the repair controller. When the newest copies are
+    /// concurrent, or no digest holds a majority among them, nothing is
+    /// rewritten. Only replicas whose chunks fail their checksum are
+    /// penalized.
+    pub async fn scrub_volume<T: ReplicaTransport>(
+        &mut self,
+        plan: &ReplicationPlan,
+        transport: &T,
+        report: &mut ScrubReport,
+    ) -> Result<(), StorageError> {
+        report.volumes_checked += 1;
+
+        let mut digests: Vec<(Uuid, VolumeDigest)> = Vec::new();
+        let mut corrupt = Vec::new();
+        for node_id in plan.nodes() {
+            let request = ReplicaRequest::Digest { volume_id: plan.volume_id };
+            match send_with_timeout(transport, node_id, request, self.config.request_timeout).await {
+                Ok(ReplicaResponse::Digest(Some(digest))) => digests.push((node_id, digest)),
+                Ok(ReplicaResponse::Digest(None)) => {},
+                Ok(_) => return Err(StorageError::InvalidRequest("Unexpected replica response".to_string())),
+                Err(StorageError::ChunkCorrupted(chunk)) => {
+                    tracing::warn!("Replica of volume {} on {} failed the checksum of chunk {}", plan.volume_id, node_id, chunk);
+                    report.penalize(node_id, self.config.corruption_penalty);
+                    corrupt.push(node_id);
+                },
+                // Unreachable or failing nodes are the repair controller's concern
+                Err(e) => tracing::debug!("Replica of volume {} on {} was not scrubbed: {}", plan.volume_id, node_id, e),
+            }
+        }
+
+        let newest = match newest_version(&digests) {
+            Some(version) => version,
+            None if corrupt.is_empty() => return Ok(()),
+            None => {
+                for node_id in &corrupt {
+                    report.divergent_replicas.push((plan.volume_id, *node_id));
+                }
+                report.unrepaired.push((plan.volume_id, StorageError::NoHealthyReplica(plan.volume_id)));
+                return Ok(());
+            },
+        };
+
+        let current: Vec<&(Uuid, VolumeDigest)> = digests.iter().filter(|(_, digest)| digest.version == newest).collect();
+        let mut votes: HashMap<[u8; 32], usize> = HashMap::new();
+        for (_, digest) in &current {
+            *votes.entry(digest.digest).or_insert(0) += 1;
+        }
+        let (winner, count) = votes.iter().max_by_key(|(_, count)| **count).map(|(digest, count)| (*digest, *count)).unwrap();
+
+        if count * 2 <= current.len() {
+            tracing::warn!("Replicas of volume {} disagree with no majority", plan.volume_id);
+            report.unrepaired.push((plan.volume_id, StorageError::NoHealthyReplica(plan.volume_id)));
+            return Ok(());
+        }
+        corrupt.extend(current.iter().filter(|(_, digest)| digest.digest != winner).map(|(node_id, _)| *node_id));
+        if corrupt.is_empty() {
+            return Ok(());
+        }
+
+        for node_id in &corrupt {
+            report.divergent_replicas.push((plan.volume_id, *node_id));
+        }
+
+        // Any node holding the majority copy can serve the repair
+        let sources: Vec<Uuid> = current.iter().filter(|(_, digest)| digest.digest == winner).map(|(node_id, _)| *node_id).collect();
+        let healthy = match fetch_verified(transport, plan.volume_id, &sources, &winner, self.config.request_timeout).await {
+            Some(write) => write,
+            None => {
+                report.unrepaired.push((plan.volume_id, StorageError::NoHealthyReplica(plan.volume_id)));
+                return Ok(());
+            },
+        };
+
+        for node_id in corrupt {
+            self.limiter.acquire(healthy.data.len() as u64).await;
+            match send_with_timeout(transport, node_id, ReplicaRequest::Restore(healthy.clone()), self.config.request_timeout).await {
+                Ok(_) => report.repaired_replicas += 1,
+                Err(e) => report.unrepaired.push((plan.volume_id, e)),
+            }
+        }
+        Ok(())
+    }
+
+    /// Run one local chunk batch on `local`, scrub every replicated volume the
+    /// manager tracks, and apply the resulting trust penalties
+    ///
+    /// Corrupt local chunks are fetched from the other nodes sharing a volume
+    /// with `local`. Erasure-coded volumes hold a different fragment on each
+    /// node, so they are only covered by local chunk scrubbing. Penalties lower
+    /// both the manager's storage trust and the network contribution trust kept
+    /// by `trust_manager`.
+    pub async fn run_once<T: ReplicaTransport>(
+        &mut self,
+        local: &ReplicaNode,
+        manager: &mut TrustAwareStorageManager,
+        trust_manager: &mut TrustManager,
+        transport: &T,
+    ) -> ScrubReport {
+        let plans: Vec<ReplicationPlan> = manager
+            .allocations()
+            .filter_map(|allocation| manager.replication_manager().active_plan(&allocation.volume_id).cloned())
+            .collect();
+        let peers: Vec<Uuid> = plans
+            .iter()
+            .map(|plan| plan.nodes())
+            .filter(|nodes| nodes.contains(&local.node_id()))
+            .flatten()
+            .filter(|node_id| *node_id != local.node_id())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+
+        let mut report = ScrubReport::default();
+        if let Err(e) = self.scrub_local(local, &peers, transport, &mut report).await {
+            report.unrepaired.push((local.node_id(), e));
+        }
+        for plan in plans {
+            if manager.allocation(&plan.volume_id).is_some_and(|allocation| allocation.erasure_coding.is_some()) {
+                continue;
+            }
+            if let Err(e) = self.scrub_volume(&plan, transport, &mut report).await {
+                report.unrepaired.push((plan.volume_id, e));
+            }
+        }
+
+        report.apply_penalties(manager.trust_evaluator_mut());
+        report.apply_network_penalties(trust_manager);
+        manager.record_scrub_pass(&report);
+        report
+    }
+}
+
+/// Version no other copy dominates, if the newest copies agree on one
+fn newest_version(digests: &[(Uuid, VolumeDigest)]) -> Option<VersionVector> {
+    let mut maximal: Vec<&VersionVector> = Vec::new();
+    for (_, digest) in digests {
+        if digests.iter().any(|(_, other)| other.version != digest.version && other.version.dominates(&digest.version)) {
+            continue;
+        }
+        if !maximal.contains(&&digest.version) {
+            maximal.push(&digest.version);
+        }
+    }
+    match maximal.as_slice() {
+        [version] => Some((*version).clone()),
+        _ => None,
+    }
+}
+
+/// First verified copy of a chunk held by any peer
+async fn fetch_chunk<T: ReplicaTransport>(transport: &T, peers: &[Uuid], id: &ChunkId, timeout: Duration) -> Option<Vec<u8>> {
+    for peer in peers {
+        if let Ok(ReplicaResponse::Chunk(Some(data))) = send_with_timeout(transport, *peer, ReplicaRequest::ReadChunk { id: *id }, timeout).await {
+            if ChunkId::of(&data) == *id {
+                return Some(data);
+            }
+        }
+    }
+    None
+}
+
+/// Read a volume from the first source whose contents match the expected digest
+async fn fetch_verified<T: ReplicaTransport>(
+    transport: &T,
+    volume_id: Uuid,
+    sources: &[Uuid],
+    digest: &[u8; 32],
+    timeout: Duration,
+) -> Option<crate::VersionedWrite> {
+    for source in sources {
+        if let Ok(ReplicaResponse::Data(Some(write))) = send_with_timeout(transport, *source, ReplicaRequest::Read { volume_id }, timeout).await {
+            if blake3::hash(&write.data).as_bytes() == digest {
+                return Some(write);
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{add_pool, copies, network_transport, plan, pool, replica_nodes, request, write};
+    use crate::{DataClassification, InMemoryTransport, ReplicationStrategy, VersionedWrite};
+
+    fn cluster(count: usize) -> (InMemoryTransport, Vec<Uuid>) {
+        let mut transport = network_transport();
+        let nodes = replica_nodes(&mut transport, count);
+        (transport, nodes)
+    }
+
+    /// Flip a bit in every chunk a volume uses on one node
+    fn rot(transport: &InMemoryTransport, node_id: &Uuid, volume_id: &Uuid) {
+        let store = transport.node(node_id).unwrap().store();
+        for chunk in store.manifest(volume_id).unwrap().unwrap().chunks {
+            let mut data = store.raw_chunk(&chunk.id).unwrap().unwrap();
+            data[0] ^= 1;
+            store.overwrite_raw_chunk(&chunk.id, &data);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_local_bit_rot_repaired_from_peer() {
+        let (transport, nodes) = cluster(2);
+        let volume_id = Uuid::new_v4();
+        write(&transport, &nodes, nodes[0], volume_id, b"bits that will rot");
+        rot(&transport, &nodes[0], &volume_id);
+
+        let mut scrubber = Scrubber::new(ScrubConfig::default());
+        let mut report = ScrubReport::default();
+        let local = transport.node(&nodes[0]).unwrap().clone();
+        let finished = scrubber.scrub_local(&local, &nodes[1..], &transport, &mut report).await.unwrap();
+
+        assert!(finished);
+        assert_eq!(report.corrupted_chunks.len(), 1);
+        assert_eq!(report.repaired_chunks, 1);
+        assert!(report.storage_trust_penalties[&nodes[0]] > 0.0);
+        assert_eq!(local.read(&volume_id).unwrap().unwrap().data, b"bits that will rot");
+
+        let mut clean = ScrubReport::default();
+        scrubber.scrub_local(&local, &nodes[1..], &transport, &mut clean).await.unwrap();
+        assert!(clean.is_clean());
+    }
+
+    #[tokio::test]
+    async fn test_local_scrub_resumes_across_batches() {
+        let (transport, nodes) = cluster(1);
+        let store = transport.node(&nodes[0]).unwrap().store();
+        for index in 0..5u8 {
+            store.put_chunk(&[index; 16]).unwrap();
+        }
+
+        let mut scrubber = Scrubber::new(ScrubConfig {
+            batch_size: 2,
+            ..ScrubConfig::default()
+        });
+        let mut report = ScrubReport::default();
+        let local = transport.node(&nodes[0]).unwrap().clone();
+        let mut steps = 1;
+        while !scrubber.scrub_local(&local, &[], &transport, &mut report).await.unwrap() {
+            steps += 1;
+        }
+        assert_eq!(steps, 3);
+        assert_eq!(report.chunks_checked, 5);
+    }
+
+    #[tokio::test]
+    async fn test_corrupt_replica_rewritten_and_penalized() {
+        let (transport, nodes) = cluster(3);
+        let volume_id = Uuid::new_v4();
+        write(&transport, &nodes, nodes[0], volume_id, b"replicated contents");
+        rot(&transport, &nodes[2], &volume_id);
+
+        let plan = ReplicationPlan {
+            volume_id,
+            ..plan(&nodes, ReplicationStrategy::HierarchyAware)
+        };
+        let mut scrubber = Scrubber::new(ScrubConfig::default());
+        let mut report = ScrubReport::default();
+        scrubber.scrub_volume(&plan, &transport, &mut report).await.unwrap();
+
+        assert_eq!(report.divergent_replicas, vec![(volume_id, nodes[2])]);
+        assert_eq!(report.repaired_replicas, 1);
+        assert_eq!(transport.node(&nodes[2]).unwrap().read(&volume_id).unwrap().unwrap().data, b"replicated contents");
+
+        let mut trust = TrustEvaluator::new();
+        trust.set_node_trust_score(nodes[2], 0.9);
+        report.apply_penalties(&mut trust);
+        assert!(trust.get_node_trust_score(&nodes[2]) < 0.9);
+        assert_eq!(trust.get_node_trust_score(&nodes[0]), 0.5);
+
+        let mut trust_manager = TrustManager::new();
+        report.apply_network_penalties(&mut trust_manager);
+        assert!(trust_manager.evaluate_trust(nodes[2]).network_contribution < 0.5);
+        assert_eq!(trust_manager.evaluate_trust(nodes[0]).network_contribution, 0.5);
+
+        let mut clean = ScrubReport::default();
+        scrubber.scrub_volume(&plan, &transport, &mut clean).await.unwrap();
+        assert!(clean.is_clean());
+    }
+
+    #[tokio::test]
+    async fn test_divergent_replica_rewritten_without_penalty() {
+        let (transport, nodes) = cluster(3);
+        let volume_id = Uuid::new_v4();
+        write(&transport, &nodes, nodes[0], volume_id, b"replicated contents");
+        // Same version, different contents, but every chunk still matches its checksum
+        let stored = transport.node(&nodes[2]).unwrap().read(&volume_id).unwrap().unwrap();
+        transport.node(&nodes[2]).unwrap().restore(VersionedWrite { data: b"diverged contents".to_vec(), ..stored }).unwrap();
+
+        let plan = ReplicationPlan {
+            volume_id,
+            ..plan(&nodes, ReplicationStrategy::HierarchyAware)
+        };
+        let mut scrubber = Scrubber::new(ScrubConfig::default());
+        let mut report = ScrubReport::default();
+        scrubber.scrub_volume(&plan, &transport, &mut report).await.unwrap();
+
+        assert_eq!(report.divergent_replicas, vec![(volume_id, nodes[2])]);
+        assert_eq!(report.repaired_replicas, 1);
+        assert!(report.storage_trust_penalties.is_empty());
+        assert_eq!(transport.node(&nodes[2]).unwrap().read(&volume_id).unwrap().unwrap().data, b"replicated contents");
+    }
+
+    #[tokio::test]
+    async fn test_run_once_scrubs_local_chunks_and_lowers_network_trust() {
+        let mut manager = TrustAwareStorageManager::new();
+        let mut transport = network_transport();
+        add_pool(&mut manager, &mut transport, pool("scrub", 0.9, 100_000, Vec::new()), 2);
+        let allocation = manager.allocate_storage(request(1000, DataClassification::Standard)).await.unwrap();
+        let nodes = copies(&allocation);
+        write(&transport, &nodes, nodes[0], allocation.volume_id, b"bits that will rot");
+        rot(&transport, &nodes[0], &allocation.volume_id);
+
+        let mut trust_manager = TrustManager::new();
+        let local = transport.node(&nodes[0]).unwrap().clone();
+        let report = Scrubber::new(ScrubConfig::default()).run_once(&local, &mut manager, &mut trust_manager, &transport).await;
+
+        assert_eq!(report.repaired_chunks, 1);
+        assert!(report.divergent_replicas.is_empty());
+        assert_eq!(local.read(&allocation.volume_id).unwrap().unwrap().data, b"bits that will rot");
+        assert!(manager.trust_evaluator().get_node_trust_score(&nodes[0]) < 0.9);
+        assert!(trust_manager.evaluate_trust(nodes[0]).network_contribution < 0.5);
+        assert_eq!(trust_manager.evaluate_trust(nodes[1]).network_contribution, 0.5);
+    }
+}
@@ -0,0 +1,702 @@
+//! Service-scoped storage: access checks, per-service namespaces and service spores
+//!
+//! Volumes with a `service_id` are service storage and belong to that
+//! service alone; volumes without one are network storage. Every plan
+//! carries its owner and shares, and coordinators publish them to the
+//! replicas. Each replica request carries an `AccessCredential` signed by the
+//! storage authority, so replicas check the accessor themselves and a service
+//! cannot reach another service's volumes by bypassing the coordinator. A
+//! service's spore record lists everything needed to find its volumes again
+//! after the managing node fails over, and is signed by the node that
+//! published it.
+
+use crate::encryption::check_envelope;
+use crate::quota::{self, QuotaScope, QuotaUsage};
+use crate::{
+    DataClassification, ErasureCoding, ReplicationPlan, ReplicationStrategy, StorageAllocation, StorageError,
+    TrustAwareStorageManager, TrustRequirements, VolumeKeyEnvelope, VolumeSnapshot,
+};
+use chrono::{DateTime, Utc};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use uuid::Uuid;
+
+/// Principal asking to use a volume
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum StorageAccessor {
+    /// Network infrastructure, which owns volumes without a service
+    #[default]
+    Network,
+    Service(String),
+}
+
+/// Proof, signed by the storage authority, that the holder acts for an accessor
+///
+/// Nodes hold a network credential and services one for their own service.
+/// Credentials are bearer tokens and must be kept as secret as a signing key.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccessCredential {
+    pub accessor: StorageAccessor,
+    /// ed25519 signature by the storage authority over the accessor
+    pub signature: Vec<u8>,
+}
+
+/// Who may use a volume, as replicas record it
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VolumeAccess {
+    /// Service owning the volume; `None` for network storage
+    pub service_id: Option<String>,
+    pub shared_with: BTreeSet<String>,
+}
+
+/// One volume of a service, with enough placement detail to re-adopt it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ServiceVolumeRecord {
+    pub volume_id: Uuid,
+    /// Name of the volume in the service's namespace
+    pub name: Option<String>,
+    pub pool_id: String,
+    pub data_classification: DataClassification,
+    pub erasure_coding: Option<ErasureCoding>,
+    pub primary_node: Uuid,
+    pub replica_nodes: Vec<Uuid>,
+    pub replication_strategy: ReplicationStrategy,
+    pub encryption_required: bool,
+    #[serde(default)]
+    pub distinct_zones: bool,
+    pub allocated_size: u64,
+    pub reserved_capacity: u64,
+    pub origin_volume_id: Option<Uuid>,
+    pub network_id: Option<String>,
+    /// Other services allowed to use the volume
+    pub shared_with: Vec<String>,
+    /// Wrapped data key, so the volume stays readable after failover
+    #[serde(default)]
+    pub key_envelope: Option<VolumeKeyEnvelope>,
+    /// Digest of the request the volume was provisioned from
+    #[serde(default)]
+    pub request_digest: Option<[u8; 32]>,
+}
+
+/// Storage spore for a service: its volumes and where their replicas live
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ServiceStorageSpore {
+    pub service_id: String,
+    pub volumes: Vec<ServiceVolumeRecord>,
+    /// Which of the volumes are snapshots, and of what
+    pub snapshots: Vec<VolumeSnapshot>,
+    pub updated_at: DateTime<Utc>,
+    /// ed25519 signature by the publishing node over everything else in the spore
+    #[serde(default)]
+    pub signature: Option<Vec<u8>>,
+}
+
+impl fmt::Display for StorageAccessor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageAccessor::Network => write!(f, "network"),
+            StorageAccessor::Service(id) => write!(f, "service {}", id),
+        }
+    }
+}
+
+impl StorageAccessor {
+    /// Accessor for an optional service ID, as carried by requests and allocations
+    pub fn for_service(service_id: Option<&String>) -> Self {
+        match service_id {
+            Some(id) => StorageAccessor::Service(id.clone()),
+            None => StorageAccessor::Network,
+        }
+    }
+}
+
+impl AccessCredential {
+    /// Sign a credential for `accessor` with the storage authority's key
+    pub fn issue(accessor: StorageAccessor, authority: &SigningKey) -> Result<Self, StorageError> {
+        let signature = authority.sign(&credential_bytes(&accessor)?);
+        Ok(Self {
+            accessor,
+            signature: signature.to_bytes().to_vec(),
+        })
+    }
+
+    /// Check the signature against the storage authority's key
+    pub fn verify(&self, authority: &VerifyingKey) -> Result<(), StorageError> {
+        let invalid = || StorageError::InvalidCredential(self.accessor.to_string());
+        let signature = Signature::from_bytes(self.signature.as_slice().try_into().map_err(|_| invalid())?);
+        authority.verify(&credential_bytes(&self.accessor)?, &signature).map_err(|_| invalid())
+    }
+}
+
+fn credential_bytes(accessor: &StorageAccessor) -> Result<Vec<u8>, StorageError> {
+    Ok(bincode::serialize(&("mycnet-storage-credential", accessor))?)
+}
+
+impl VolumeAccess {
+    /// Check that `accessor` may read or write the volume
+    ///
+    /// Service volumes are open to their own service and to services they are
+    /// shared with; network volumes only to the network.
+    pub fn authorize(&self, volume_id: &Uuid, accessor: &StorageAccessor) -> Result<(), StorageError> {
+        let allowed = match (&self.service_id, accessor) {
+            (None, StorageAccessor::Network) => true,
+            (Some(owner), StorageAccessor::Service(service_id)) => owner == service_id || self.shared_with.contains(service_id),
+            _ => false,
+        };
+
+        if !allowed {
+            tracing::warn!("Denied {} access to volume {}", accessor, volume_id);
+            return Err(StorageError::AccessDenied {
+                volume_id: *volume_id,
+                accessor: accessor.to_string(),
+            });
+        }
+        Ok(())
+    }
+}
+
+impl ServiceStorageSpore {
+    pub fn to_bytes(&self) -> Result<Vec<u8>, StorageError> {
+        Ok(bincode::serialize(self)?)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, StorageError> {
+        Ok(bincode::deserialize(bytes)?)
+    }
+
+    pub fn sign(&mut self, key: &SigningKey) -> Result<(), StorageError> {
+        let signature = key.sign(&self.signing_bytes()?);
+        self.signature = Some(signature.to_bytes().to_vec());
+        Ok(())
+    }
+
+    /// Check the signature against the publishing node's key; unsigned spores fail
+    pub fn verify_signature(&self, key: &VerifyingKey) -> Result<(), StorageError> {
+        let invalid = || StorageError::InvalidSporeSignature(self.service_id.clone());
+        let signature = self.signature.as_deref().ok_or_else(invalid)?;
+        let signature = Signature::from_bytes(signature.try_into().map_err(|_| invalid())?);
+        key.verify(&self.signing_bytes()?, &signature).map_err(|_| invalid())
+    }
+
+    fn signing_bytes(&self) -> Result<Vec<u8>, StorageError> {
+        Ok(bincode::serialize(&(&self.service_id, &self.volumes, &self.snapshots, self.updated_at))?)
+    }
+}
+
+impl ReplicationPlan {
+    /// Owner and shares of the volume, as published to its replicas
+    pub fn access(&self) -> VolumeAccess {
+        VolumeAccess {
+            service_id: self.service_id.clone(),
+            shared_with: self.shared_with.iter().cloned().collect(),
+        }
+    }
+
+    /// Check that `accessor` may read or write the volume, see `VolumeAccess::authorize`
+    pub fn authorize(&self, accessor: &StorageAccessor) -> Result<(), StorageError> {
+        self.access().authorize(&self.volume_id, accessor)
+    }
+}
+
+impl TrustAwareStorageManager {
+    /// Check that `accessor` may use a volume
+    pub fn authorize(&self, volume_id: &Uuid, accessor: &StorageAccessor) -> Result<&StorageAllocation, StorageError> {
+        let allocation = self.allocations.get(volume_id).ok_or(StorageError::VolumeNotFound(*volume_id))?;
+        let plan = self.replication_manager.active_plan(volume_id).ok_or(StorageError::VolumeNotFound(*volume_id))?;
+        plan.authorize(accessor)?;
+        Ok(allocation)
+    }
+
+    /// Let another service use a volume; only the owning service may share it
+    ///
+    /// Plans fetched before the change keep their old shares.
+    pub fn share_volume(&mut self, volume_id: &Uuid, owner: &str, service_id: &str) -> Result<(), StorageError> {
+        self.check_owner(volume_id, owner)?;
+        if let Some(plan) = self.replication_manager.active_plan_mut(volume_id) {
+            plan.shared_with.insert(service_id.to_string());
+        }
+        Ok(())
+    }
+
+    /// Withdraw a service's access to a shared volume
+    pub fn unshare_volume(&mut self, volume_id: &Uuid, owner: &str, service_id: &str) -> Result<(), StorageError> {
+        self.check_owner(volume_id, owner)?;
+        if let Some(plan) = self.replication_manager.active_plan_mut(volume_id) {
+            plan.shared_with.remove(service_id);
+        }
+        Ok(())
+    }
+
+    /// Bind a name in a service's namespace to one of its volumes
+    ///
+    /// Names are unique within a service only, so different services can use
+    /// the same name. Rebinding a name to the volume it already names is a no-op.
+    pub fn name_volume(&mut self, service_id: &str, name: &str, volume_id: &Uuid) -> Result<(), StorageError> {
+        self.check_owner(volume_id, service_id)?;
+        if name.is_empty() {
+            return Err(StorageError::InvalidRequest("Volume name is required".to_string()));
+        }
+
+        let namespace = self.service_namespaces.entry(service_id.to_string()).or_default();
+        match namespace.get(name) {
+            Some(existing) if existing != volume_id => Err(StorageError::VolumeNameTaken {
+                service_id: service_id.to_string(),
+                name: name.to_string(),
+            }),
+            _ => {
+                namespace.retain(|_, bound| bound != volume_id);
+                namespace.insert(name.to_string(), *volume_id);
+                Ok(())
+            },
+        }
+    }
+
+    /// Volume a name refers to in a service's namespace
+    pub fn resolve_volume(&self, service_id: &str, name: &str) -> Option<Uuid> {
+        self.service_namespaces.get(service_id)?.get(name).copied()
+    }
+
+    /// Volumes owned by a service, including its snapshots and clones
+    pub fn service_volumes(&self, service_id: &str) -> Vec<&StorageAllocation> {
+        let mut volumes: Vec<&StorageAllocation> = self
+            .allocations
+            .values()
+            .filter(|allocation| allocation.service_id.as_deref() == Some(service_id))
+            .collect();
+        volumes.sort_by_key(|allocation| allocation.volume_id);
+        volumes
+    }
+
+    /// Current storage spore for a service
+    pub fn service_spore(&self, service_id: &str) -> ServiceStorageSpore {
+        let names: HashMap<Uuid, &String> = self
+            .service_namespaces
+            .get(service_id)
+            .map(|namespace| namespace.iter().map(|(name, volume_id)| (*volume_id, name)).collect())
+            .unwrap_or_default();
+
+        let volumes = self
+            .service_volumes(service_id)
+            .into_iter()
+            .filter_map(|allocation| {
+                let plan = self.replication_manager.active_plan(&allocation.volume_id)?;
+                Some(ServiceVolumeRecord {
+                    volume_id: allocation.volume_id,
+                    name: names.get(&allocation.volume_id).map(|name| name.to_string()),
+                    pool_id: allocation.pool_id.clone(),
+                    data_classification: allocation.data_classification,
+                    erasure_coding: allocation.erasure_coding,
+                    primary_node: allocation.primary_node,
+                    replica_nodes: allocation.replica_nodes.clone(),
+                    replication_strategy: plan.replication_strategy,
+                    encryption_required: plan.encryption_required,
+                    distinct_zones: plan.distinct_zones,
+                    allocated_size: allocation.allocated_size,
+                    reserved_capacity: allocation.reserved_capacity,
+                    origin_volume_id: allocation.origin_volume_id,
+                    network_id: allocation.network_id.clone(),
+                    shared_with: {
+                        let mut services: Vec<String> = plan.shared_with.iter().cloned().collect();
+                        services.sort();
+                        services
+                    },
+                    key_envelope: allocation.key_envelope.clone(),
+                    request_digest: allocation.request_digest,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let snapshots = volumes
+            .iter()
+            .filter_map(|record| self.snapshots.get(&record.volume_id).cloned())
+            .collect();
+
+        ServiceStorageSpore {
+            service_id: service_id.to_string(),
+            volumes,
+            snapshots,
+            updated_at: Utc::now(),
+            signature: None,
+        }
+    }
+
+    /// Take over a service's volumes from its spore after failover
+    ///
+    /// The spore must carry a valid signature by `publisher`, the node that
+    /// managed the service. Volumes already tracked are left as they are. The
+    /// others go through the checks of a new allocation: their nodes and pool
+    /// must meet the classification's trust threshold, and the pools and
+    /// quotas must have room for them. Every record is checked before any is
+    /// adopted, so a spore that fails a check changes nothing. Returns the
+    /// adopted volumes.
+    pub fn restore_service_spore(&mut self, spore: &ServiceStorageSpore, publisher: &VerifyingKey) -> Result<Vec<Uuid>, StorageError> {
+        spore.verify_signature(publisher)?;
+
+        let mut reservations: HashMap<&str, u64> = HashMap::new();
+        let mut usage: HashMap<QuotaScope, QuotaUsage> = HashMap::new();
+        for record in &spore.volumes {
+            let pool = self.storage_pools.get(&record.pool_id).ok_or_else(|| StorageError::PoolNotFound(record.pool_id.clone()))?;
+            if let Some(existing) = self.allocations.get(&record.volume_id) {
+                if existing.service_id.as_deref() != Some(spore.service_id.as_str()) {
+                    return Err(StorageError::VolumeAlreadyAllocated(record.volume_id));
+                }
+                continue;
+            }
+            if let Some(envelope) = &record.key_envelope {
+                check_envelope(record.volume_id, record.origin_volume_id, envelope)?;
+            }
+            if let Some(name) = &record.name {
+                if self.resolve_volume(&spore.service_id, name).is_some_and(|bound| bound != record.volume_id) {
+                    return Err(StorageError::VolumeNameTaken {
+                        service_id: spore.service_id.clone(),
+                        name: name.clone(),
+                    });
+                }
+            }
+
+            let requirements = TrustRequirements::for_classification(&record.data_classification);
+            let reserved = reservations.entry(pool.pool_id.as_str()).or_insert(0);
+            *reserved = reserved.saturating_add(record.reserved_capacity);
+            if pool.trust_level < requirements.minimum_trust_score || pool.reservable_capacity(&self.overcommit_policy) < *reserved {
+                return Err(StorageError::NoSuitablePool {
+                    minimum_trust_score: requirements.minimum_trust_score,
+                    required_capacity: *reserved,
+                });
+            }
+            self.check_node_trust(
+                &record.data_classification,
+                std::iter::once(record.primary_node).chain(record.replica_nodes.iter().copied()),
+            )?;
+
+            let added = QuotaUsage {
+                bytes: record.allocated_size,
+                volumes: 1,
+                replica_bytes: record.reserved_capacity,
+            };
+            for scope in quota::scopes(Some(&spore.service_id), record.network_id.as_ref()) {
+                let total = usage.entry(scope).or_default();
+                *total = total.plus(&added);
+            }
+        }
+        for (scope, added) in &usage {
+            self.check_quotas(std::slice::from_ref(scope), added)?;
+        }
+
+        let mut adopted = Vec::new();
+        for record in &spore.volumes {
+            if self.allocations.contains_key(&record.volume_id) {
+                continue;
+            }
+            if let Some(pool) = self.storage_pools.get_mut(&record.pool_id) {
+                pool.used_capacity += record.reserved_capacity;
+            }
+            self.replication_manager.insert_plan(ReplicationPlan {
+                volume_id: record.volume_id,
+                primary_node: record.primary_node,
+                replica_nodes: record.replica_nodes.clone(),
+                replication_strategy: record.replication_strategy,
+                encryption_required: record.encryption_required,
+                distinct_zones: record.distinct_zones,
+                read_only: spore.snapshots.iter().any(|snapshot| snapshot.snapshot_id == record.volume_id),
+                service_id: Some(spore.service_id.clone()),
+                shared_with: record.shared_with.iter().cloned().collect(),
+            });
+            self.allocations.insert(record.volume_id, StorageAllocation {
+                volume_id: record.volume_id,
+                pool_id: record.pool_id.clone(),
+                data_classification: record.data_classification,
+                erasure_coding: record.erasure_coding,
+                primary_node: record.primary_node,
+                replica_nodes: record.replica_nodes.clone(),
+                allocated_size: record.allocated_size,
+                reserved_capacity: record.reserved_capacity,
+                origin_volume_id: record.origin_volume_id,
+                key_envelope: record.key_envelope.clone(),
+                service_id: Some(spore.service_id.clone()),
+                network_id: record.network_id.clone(),
+                request_digest: record.request_digest,
+            });
+            if let Some(name) = &record.name {
+                self.service_namespaces
+                    .entry(spore.service_id.clone())
+                    .or_default()
+                    .insert(name.clone(), record.volume_id);
+            }
+            adopted.push(record.volume_id);
+        }
+
+        for snapshot in &spore.snapshots {
+            if adopted.contains(&snapshot.snapshot_id) {
+                self.snapshots.insert(snapshot.snapshot_id, snapshot.clone());
+            }
+        }
+
+        tracing::info!("Adopted {} volumes of service {}", adopted.len(), spore.service_id);
+        Ok(adopted)
+    }
+
+    /// Forget a released volume's name
+    pub(crate) fn remove_service_entries(&mut self, allocation: &StorageAllocation) {
+        if let Some(service_id) = &allocation.service_id {
+            if let Some(namespace) = self.service_namespaces.get_mut(service_id) {
+                namespace.retain(|_, bound| *bound != allocation.volume_id);
+                if namespace.is_empty() {
+                    self.service_namespaces.remove(service_id);
+                }
+            }
+        }
+    }
+
+    fn check_owner(&self, volume_id: &Uuid, service_id: &str) -> Result<(), StorageError> {
+        let allocation = self.allocations.get(volume_id).ok_or(StorageError::VolumeNotFound(*volume_id))?;
+        if allocation.service_id.as_deref() != Some(service_id) {
+            return Err(StorageError::AccessDenied {
+                volume_id: *volume_id,
+                accessor: StorageAccessor::Service(service_id.to_string()).to_string(),
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{add_pool, credential, network_transport, pool, request, write};
+    use crate::{
+        ClientSession, ConsistencyLevel, InMemoryTransport, ReplicaRequest, ReplicaResponse, ReplicaTransport, VersionVector, VersionedWrite,
+        VolumeIo, VolumeIoConfig,
+    };
+    use std::sync::Arc;
+
+    async fn setup() -> (TrustAwareStorageManager, InMemoryTransport, Vec<Uuid>) {
+        let mut manager = TrustAwareStorageManager::new();
+        let mut transport = network_transport();
+        let nodes = add_pool(&mut manager, &mut transport, pool("pool", 0.8, 10_000, Vec::new()), 2);
+        (manager, transport, nodes)
+    }
+
+    async fn allocate(manager: &mut TrustAwareStorageManager, service_id: Option<&str>) -> Uuid {
+        let mut request = request(1000, DataClassification::Sensitive);
+        request.service_id = service_id.map(str::to_string);
+        manager.allocate_storage(request).await.unwrap().volume_id
+    }
+
+    fn service(id: &str) -> StorageAccessor {
+        StorageAccessor::Service(id.to_string())
+    }
+
+    #[tokio::test]
+    async fn test_other_services_denied_until_shared() {
+        let (mut manager, _transport, _) = setup().await;
+        let volume_id = allocate(&mut manager, Some("ledger")).await;
+        let network_volume = allocate(&mut manager, None).await;
+
+        assert!(manager.authorize(&volume_id, &service("ledger")).is_ok());
+        assert!(matches!(
+            manager.authorize(&volume_id, &service("web")),
+            Err(StorageError::AccessDenied { .. })
+        ));
+        assert!(manager.authorize(&volume_id, &StorageAccessor::Network).is_err());
+        assert!(manager.authorize(&network_volume, &service("ledger")).is_err());
+        assert!(manager.authorize(&network_volume, &StorageAccessor::Network).is_ok());
+
+        // Only the owner can share
+        assert!(manager.share_volume(&volume_id, "web", "web").is_err());
+        manager.share_volume(&volume_id, "ledger", "web").unwrap();
+        assert!(manager.authorize(&volume_id, &service("web")).is_ok());
+        manager.unshare_volume(&volume_id, "ledger", "web").unwrap();
+        assert!(manager.authorize(&volume_id, &service("web")).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_volume_io_checks_the_session_accessor() {
+        let (mut manager, transport, _) = setup().await;
+        let volume_id = allocate(&mut manager, Some("ledger")).await;
+        let plan = manager.replication_manager().active_plan(&volume_id).unwrap().clone();
+        let io = VolumeIo::new(plan.primary_node, Arc::new(transport), VolumeIoConfig::default());
+
+        for accessor in [service("web"), StorageAccessor::Network] {
+            let mut session = ClientSession::for_credential(credential(accessor));
+            let write = io.write(&plan, &ConsistencyLevel::Strong, b"data".to_vec(), &mut session).await;
+            assert!(matches!(write, Err(StorageError::AccessDenied { .. })));
+            let read = io.read(&plan, &ConsistencyLevel::Strong, &mut session).await;
+            assert!(matches!(read, Err(StorageError::AccessDenied { .. })));
+        }
+
+        // The owner passes the access check and reaches the encryption check
+        let mut session = ClientSession::for_credential(credential(service("ledger")));
+        let write = io.write(&plan, &ConsistencyLevel::Strong, b"data".to_vec(), &mut session).await;
+        assert!(matches!(write, Err(StorageError::EncryptionRequired(_))));
+        assert_eq!(io.take_accesses().get(&volume_id), Some(&1));
+    }
+
+    #[tokio::test]
+    async fn test_replicas_deny_services_that_bypass_the_coordinator() {
+        let (mut manager, transport, _) = setup().await;
+        let transport = Arc::new(transport);
+        let volume_id = allocate(&mut manager, Some("ledger")).await;
+        let plan = manager.replication_manager().active_plan(&volume_id).unwrap().clone();
+        let (_, cipher) = VolumeKeyEnvelope::generate(volume_id, &[]).unwrap();
+        let mut io = VolumeIo::new(plan.primary_node, transport.clone(), VolumeIoConfig::default());
+        io.register_volume_key(cipher);
+        let (ledger, web) = (credential(service("ledger")), credential(service("web")));
+        let node_id = plan.primary_node;
+        let read = ReplicaRequest::Read { volume_id };
+
+        // Before the coordinator publishes the volume's access, replicas treat it as network storage
+        assert!(matches!(transport.send_as(node_id, &ledger, read.clone()).await, Err(StorageError::AccessDenied { .. })));
+        let mut session = ClientSession::for_credential(ledger.clone());
+        io.write(&plan, &ConsistencyLevel::Strong, b"ledger".to_vec(), &mut session).await.unwrap();
+        assert!(matches!(transport.send_as(node_id, &ledger, read.clone()).await, Ok(ReplicaResponse::Data(Some(_)))));
+
+        // Another service holding a valid credential of its own cannot read, overwrite or delete the volume
+        let overwrite = ReplicaRequest::Write(VersionedWrite {
+            volume_id,
+            version: VersionVector([(Uuid::new_v4(), 100)].into_iter().collect()),
+            data: b"web".to_vec(),
+        });
+        for request in [read.clone(), overwrite] {
+            assert!(matches!(transport.send_as(node_id, &web, request).await, Err(StorageError::AccessDenied { .. })));
+        }
+        let delete = ReplicaRequest::DeleteVolume { volume_id, fragment: None };
+        assert!(matches!(transport.send_as(node_id, &web, delete).await, Err(StorageError::MaintenanceDenied(_))));
+        assert!(transport.node(&node_id).unwrap().read(&volume_id).unwrap().is_some_and(|write| write.data != b"web"));
+
+        // Claiming to be the owner takes a credential the authority signed
+        let forged = AccessCredential::issue(service("ledger"), &SigningKey::from_bytes(&[3; 32])).unwrap();
+        assert!(matches!(transport.send_as(node_id, &forged, read.clone()).await, Err(StorageError::InvalidCredential(_))));
+        let mut session = ClientSession::for_credential(forged);
+        assert!(io.read(&plan, &ConsistencyLevel::Strong, &mut session).await.is_err());
+
+        // Shares reach the replicas with the next plan a coordinator uses
+        manager.share_volume(&volume_id, "ledger", "web").unwrap();
+        let plan = manager.replication_manager().active_plan(&volume_id).unwrap().clone();
+        io.read(&plan, &ConsistencyLevel::Strong, &mut ClientSession::for_credential(web.clone())).await.unwrap();
+        assert!(matches!(transport.send_as(node_id, &web, read).await, Ok(ReplicaResponse::Data(Some(_)))));
+    }
+
+    #[tokio::test]
+    async fn test_namespaces_are_per_service() {
+        let (mut manager, _transport, _) = setup().await;
+        let ledger = allocate(&mut manager, Some("ledger")).await;
+        let web = allocate(&mut manager, Some("web")).await;
+
+        manager.name_volume("ledger", "data", &ledger).unwrap();
+        manager.name_volume("web", "data", &web).unwrap();
+        assert_eq!(manager.resolve_volume("ledger", "data"), Some(ledger));
+        assert_eq!(manager.resolve_volume("web", "data"), Some(web));
+
+        // A service cannot name another service's volume, nor reuse a taken name
+        assert!(matches!(manager.name_volume("web", "ledger", &ledger), Err(StorageError::AccessDenied { .. })));
+        let second = allocate(&mut manager, Some("ledger")).await;
+        assert!(matches!(manager.name_volume("ledger", "data", &second), Err(StorageError::VolumeNameTaken { .. })));
+
+        manager.release_storage(&ledger).unwrap();
+        assert_eq!(manager.resolve_volume("ledger", "data"), None);
+        manager.name_volume("ledger", "data", &second).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_spore_restores_volumes_after_failover() {
+        let (mut manager, transport, nodes) = setup().await;
+        let volume_id = allocate(&mut manager, Some("ledger")).await;
+        allocate(&mut manager, Some("web")).await;
+        manager.name_volume("ledger", "data", &volume_id).unwrap();
+        manager.share_volume(&volume_id, "ledger", "audit").unwrap();
+        let (envelope, cipher) = VolumeKeyEnvelope::generate(volume_id, &[]).unwrap();
+        manager.set_volume_key(&volume_id, envelope.clone()).unwrap();
+        assert!(manager.set_volume_key(&volume_id, envelope.clone()).is_err());
+        let rotated = envelope.rotate(&cipher, &[]).unwrap();
+        manager.set_volume_key(&volume_id, rotated.clone()).unwrap();
+        assert!(manager.set_volume_key(&volume_id, envelope).is_err());
+        write(&transport, &nodes, nodes[0], volume_id, b"ledger");
+        let snapshot_id = Uuid::new_v4();
+        manager.create_snapshot(&volume_id, snapshot_id, &transport).await.unwrap();
+
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let mut spore = manager.service_spore("ledger");
+        spore.sign(&key).unwrap();
+        let spore = ServiceStorageSpore::from_bytes(&spore.to_bytes().unwrap()).unwrap();
+        assert_eq!(spore.volumes.len(), 2);
+
+        // A standby manager that knows the pool but none of the volumes
+        let mut standby = TrustAwareStorageManager::new();
+        standby.register_storage_pool(pool("pool", 0.8, 10_000, nodes.clone())).unwrap();
+        for node_id in &nodes {
+            standby.trust_evaluator_mut().set_node_trust_score(*node_id, 0.8);
+        }
+        let mut adopted = standby.restore_service_spore(&spore, &key.verifying_key()).unwrap();
+        adopted.sort();
+        let mut expected = vec![volume_id, snapshot_id];
+        expected.sort();
+        assert_eq!(adopted, expected);
+
+        let original = manager.allocation(&volume_id).unwrap();
+        let restored = standby.allocation(&volume_id).unwrap();
+        assert_eq!(restored.primary_node, original.primary_node);
+        assert_eq!(restored.replica_nodes, original.replica_nodes);
+        assert_eq!(standby.resolve_volume("ledger", "data"), Some(volume_id));
+        assert!(standby.authorize(&volume_id, &service("audit")).is_ok());
+        assert_eq!(standby.list_snapshots(&volume_id).len(), 1);
+        assert!(standby.replication_manager().active_plan(&snapshot_id).is_some());
+        assert_eq!(standby.storage_pool("pool").unwrap().used_capacity, 4000);
+
+        // The data key travels with the spore, and the snapshot shares its origin's
+        assert_eq!(standby.volume_key(&volume_id), Some(&rotated));
+        assert_eq!(standby.volume_key(&snapshot_id), Some(&rotated));
+
+        // Restoring again changes nothing
+        assert!(standby.restore_service_spore(&spore, &key.verifying_key()).unwrap().is_empty());
+        assert_eq!(standby.storage_pool("pool").unwrap().used_capacity, 4000);
+    }
+
+    #[tokio::test]
+    async fn test_spore_restore_checks_signature_trust_and_capacity() {
+        let (mut manager, _transport, nodes) = setup().await;
+        allocate(&mut manager, Some("ledger")).await;
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let mut spore = manager.service_spore("ledger");
+
+        let standby = || {
+            let mut standby = TrustAwareStorageManager::new();
+            standby.register_storage_pool(pool("pool", 0.8, 10_000, nodes.clone())).unwrap();
+            for node_id in &nodes {
+                standby.trust_evaluator_mut().set_node_trust_score(*node_id, 0.8);
+            }
+            standby
+        };
+
+        // Unsigned, tampered and foreign spores are refused
+        let mut restored = standby();
+        assert!(matches!(
+            restored.restore_service_spore(&spore, &key.verifying_key()),
+            Err(StorageError::InvalidSporeSignature(_))
+        ));
+        spore.sign(&key).unwrap();
+        let mut tampered = spore.clone();
+        tampered.volumes[0].shared_with.push("web".to_string());
+        assert!(restored.restore_service_spore(&tampered, &key.verifying_key()).is_err());
+        let other = SigningKey::from_bytes(&[8; 32]);
+        assert!(restored.restore_service_spore(&spore, &other.verifying_key()).is_err());
+        assert!(restored.allocations().next().is_none());
+
+        // Nodes that lost the classification's trust are not adopted
+        restored.trust_evaluator_mut().set_node_trust_score(nodes[0], 0.5);
+        assert!(matches!(
+            restored.restore_service_spore(&spore, &key.verifying_key()),
+            Err(StorageError::UntrustedPlacement { .. })
+        ));
+
+        // Nor are volumes the pool has no room for
+        let mut restored = standby();
+        restored.storage_pools.get_mut("pool").unwrap().used_capacity = 9000;
+        assert!(matches!(
+            restored.restore_service_spore(&spore, &key.verifying_key()),
+            Err(StorageError::NoSuitablePool { .. })
+        ));
+        assert!(restored.allocations().next().is_none());
+    }
+}
@@ -0,0 +1,427 @@
+//! Point-in-time snapshots and copy-on-write clones of volumes
+
+use crate::quota::{self, QuotaUsage};
+use crate::volume_io::send_with_timeout;
+use crate::{
+    ReplicaRequest, ReplicaResponse, ReplicaTransport, ReplicationPlan, StorageAllocation, StorageError, TrustAwareStorageManager,
+    TrustRequirements, VersionVector, VersionedWrite,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Read-only copy of a volume at the time it was taken
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VolumeSnapshot {
+    pub snapshot_id: Uuid,
+    pub volume_id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub size_bytes: u64,
+}
+
+impl TrustAwareStorageManager {
+    /// Snapshot a volume on the nodes holding it
+    ///
+    /// Each node records a second manifest over the volume's existing chunks,
+    /// so no data is copied. Every node copies the same version, so the
+    /// snapshot is one point in time, and its plan is read-only. The snapshot
+    /// keeps the volume's classification and placement, and is tracked like
+    /// any other allocation so repair, scrubbing and the volume's quotas cover
+    /// it. It reserves the volume's full capacity, since its chunks stay alive
+    /// after the volume is rewritten.
+    pub async fn create_snapshot<T: ReplicaTransport>(
+        &mut self,
+        volume_id: &Uuid,
+        snapshot_id: Uuid,
+        transport: &T,
+    ) -> Result<VolumeSnapshot, StorageError> {
+        if self.snapshots.contains_key(volume_id) {
+            return Err(StorageError::InvalidRequest(format!("Volume {} is a snapshot", volume_id)));
+        }
+        let source = self.allocations.get(volume_id).cloned().ok_or(StorageError::VolumeNotFound(*volume_id))?;
+        self.derive_volume(&source, snapshot_id, true, transport).await?;
+
+        let snapshot = VolumeSnapshot {
+            snapshot_id,
+            volume_id: *volume_id,
+            created_at: Utc::now(),
+            size_bytes: source.allocated_size,
+        };
+        self.snapshots.insert(snapshot_id, snapshot.clone());
+
+        tracing::info!("Created snapshot {} of volume {}", snapshot_id, volume_id);
+        Ok(snapshot)
+    }
+
+    /// Snapshots of a volume, oldest first
+    pub fn list_snapshots(&self, volume_id: &Uuid) -> Vec<&VolumeSnapshot> {
+        let mut snapshots: Vec<&VolumeSnapshot> = self.snapshots.values().filter(|snapshot| snapshot.volume_id == *volume_id).collect();
+        snapshots.sort_by_key(|snapshot| (snapshot.created_at, snapshot.snapshot_id));
+        snapshots
+    }
+
+    /// Get a snapshot
+    pub fn snapshot(&self, snapshot_id: &Uuid) -> Option<&VolumeSnapshot> {
+        self.snapshots.get(snapshot_id)
+    }
+
+    /// Delete a snapshot from its nodes and release its reservation
+    ///
+    /// Chunks still used by the volume or its clones keep their other
+    /// references; the rest are reclaimed by the nodes' garbage collection.
+    pub async fn delete_snapshot<T: ReplicaTransport>(&mut self, snapshot_id: &Uuid, transport: &T) -> Result<VolumeSnapshot, StorageError> {
+        let snapshot = self.snapshots.get(snapshot_id).cloned().ok_or(StorageError::VolumeNotFound(*snapshot_id))?;
+        self.delete_volume(snapshot_id, transport).await?;
+        Ok(snapshot)
+    }
+
+    /// Create a writable volume sharing the chunks of a volume or snapshot
+    ///
+    /// The clone shares chunks with its source until either is written. It
+    /// inherits the source's classification and placement. An encrypted clone
+    /// is read with the data key of its `origin_volume_id`.
+    pub async fn clone_volume<T: ReplicaTransport>(
+        &mut self,
+        source_id: &Uuid,
+        clone_id: Uuid,
+        transport: &T,
+    ) -> Result<StorageAllocation, StorageError> {
+        let source = self.allocations.get(source_id).cloned().ok_or(StorageError::VolumeNotFound(*source_id))?;
+        let allocation = self.derive_volume(&source, clone_id, false, transport).await?;
+
+        tracing::info!("Cloned volume {} from {}", clone_id, source_id);
+        Ok(allocation)
+    }
+
+    /// Copy a volume's manifests to a new volume on the same nodes and record it
+    ///
+    /// Replicated volumes are pinned to the version that dominates every
+    /// copy: nodes at that version copy locally and lagging nodes receive the
+    /// finished copy from one that did. Erasure-coded fragments are copied
+    /// with every generation their nodes hold, so readers of the copy pick
+    /// the same generation as readers of the source. A source that was never
+    /// written has nothing to copy, so only its plan is recorded.
+    async fn derive_volume<T: ReplicaTransport>(
+        &mut self,
+        source: &StorageAllocation,
+        volume_id: Uuid,
+        read_only: bool,
+        transport: &T,
+    ) -> Result<StorageAllocation, StorageError> {
+        if self.allocations.contains_key(&volume_id) {
+            return Err(StorageError::VolumeAlreadyAllocated(volume_id));
+        }
+        let plan = self.replication_manager
+            .active_plan(&source.volume_id)
+            .cloned()
+            .ok_or(StorageError::VolumeNotFound(source.volume_id))?;
+
+        // Chunks are shared locally, so the copy lives on the source's nodes,
+        // which must still satisfy the source's classification
+        let requirements = TrustRequirements::for_classification(&source.data_classification);
+        self.check_node_trust(&source.data_classification, plan.nodes())?;
+
+        self.check_quotas(&quota::scopes(source.service_id.as_ref(), source.network_id.as_ref()), &QuotaUsage {
+            bytes: source.allocated_size,
+            volumes: 1,
+            replica_bytes: source.reserved_capacity,
+        })?;
+
+        let pool = self.storage_pools
+            .get(&source.pool_id)
+            .ok_or_else(|| StorageError::PoolNotFound(source.pool_id.clone()))?;
+        if pool.reservable_capacity(&self.overcommit_policy) < source.reserved_capacity {
+            return Err(StorageError::NoSuitablePool {
+                minimum_trust_score: requirements.minimum_trust_score,
+                required_capacity: source.reserved_capacity,
+            });
+        }
+
+        let timeout = self.request_timeout;
+        let (written, pinned) = match source.erasure_coding {
+            Some(_) => (fragments_written(transport, &plan, timeout).await?, None),
+            None => match pinned_version(transport, &plan, timeout).await? {
+                Some(version) => (true, Some(version)),
+                None => (false, None),
+            },
+        };
+        let targets = if written { plan.copies(source.erasure_coding.is_some()) } else { Vec::new() };
+
+        let mut copied = Vec::new();
+        let mut lagging = Vec::new();
+        for (node_id, fragment) in &targets {
+            let request = ReplicaRequest::CopyVolume {
+                source: source.volume_id,
+                target: volume_id,
+                fragment: *fragment,
+                version: pinned.clone(),
+            };
+            match send_with_timeout(transport, *node_id, request, timeout).await {
+                Ok(_) => copied.push((*node_id, *fragment)),
+                Err(e) => {
+                    tracing::warn!("Node {} could not copy volume {}: {}", node_id, source.volume_id, e);
+                    lagging.push(*node_id);
+                },
+            }
+        }
+
+        // Replicas not at the pinned version take the finished copy instead
+        if pinned.is_some() && !lagging.is_empty() {
+            if let Some(finished) = read_copy(transport, &copied, volume_id, timeout).await {
+                for node_id in lagging {
+                    match send_with_timeout(transport, node_id, ReplicaRequest::Restore(finished.clone()), timeout).await {
+                        Ok(_) => copied.push((node_id, None)),
+                        Err(e) => tracing::warn!("Node {} could not take the copy of volume {}: {}", node_id, source.volume_id, e),
+                    }
+                }
+            }
+        }
+
+        if copied.len() < targets.len() {
+            for (node_id, fragment) in copied.iter().copied() {
+                let _ = send_with_timeout(transport, node_id, ReplicaRequest::DeleteVolume { volume_id, fragment }, timeout).await;
+            }
+            return Err(StorageError::ReplicationFailed {
+                acknowledged: copied.len(),
+                required: targets.len(),
+            });
+        }
+
+        if let Some(pool) = self.storage_pools.get_mut(&source.pool_id) {
+            pool.used_capacity += source.reserved_capacity;
+        }
+        // Shares are granted per volume, so copies start unshared
+        self.replication_manager.insert_plan(ReplicationPlan {
+            volume_id,
+            read_only,
+            shared_with: Default::default(),
+            ..plan
+        });
+
+        let allocation = StorageAllocation {
+            volume_id,
+            origin_volume_id: Some(source.origin_volume_id.unwrap_or(source.volume_id)),
+            request_digest: None,
+            ..source.clone()
+        };
+        self.allocations.insert(volume_id, allocation.clone());
+        Ok(allocation)
+    }
+}
+
+/// Version every reachable copy of a volume is at or behind
+///
+/// `None` when every node that answered holds nothing, since the volume
+/// was never written. Concurrent copies have no single point in time to
+/// pin, so the copy is refused until they converge.
+async fn pinned_version<T: ReplicaTransport>(transport: &T, plan: &ReplicationPlan, timeout: Duration) -> Result<Option<VersionVector>, StorageError> {
+    let mut answered = false;
+    let mut versions = Vec::new();
+    for node_id in plan.nodes() {
+        let request = ReplicaRequest::Digest { volume_id: plan.volume_id };
+        if let Ok(ReplicaResponse::Digest(digest)) = send_with_timeout(transport, node_id, request, timeout).await {
+            answered = true;
+            versions.extend(digest.map(|digest| digest.version));
+        }
+    }
+    if versions.is_empty() {
+        return if answered { Ok(None) } else { Err(StorageError::NoHealthyReplica(plan.volume_id)) };
+    }
+    versions
+        .iter()
+        .find(|candidate| versions.iter().all(|version| candidate.dominates(version)))
+        .cloned()
+        .map(Some)
+        .ok_or(StorageError::VersionConflict(plan.volume_id))
+}
+
+/// Whether any reachable node holds a fragment of an erasure-coded volume
+async fn fragments_written<T: ReplicaTransport>(transport: &T, plan: &ReplicationPlan, timeout: Duration) -> Result<bool, StorageError> {
+    let mut answered = false;
+    for (index, node_id) in plan.nodes().into_iter().enumerate() {
+        let request = ReplicaRequest::FragmentGenerations { volume_id: plan.volume_id, index };
+        if let Ok(ReplicaResponse::Generations(generations)) = send_with_timeout(transport, node_id, request, timeout).await {
+            if !generations.is_empty() {
+                return Ok(true);
+            }
+            answered = true;
+        }
+    }
+    if answered {
+        Ok(false)
+    } else {
+        Err(StorageError::NoHealthyReplica(plan.volume_id))
+    }
+}
+
+/// Contents of a finished copy from the first node that serves it
+async fn read_copy<T: ReplicaTransport>(
+    transport: &T,
+    holders: &[(Uuid, Option<usize>)],
+    volume_id: Uuid,
+    timeout: Duration,
+) -> Option<VersionedWrite> {
+    for (node_id, _) in holders {
+        if let Ok(ReplicaResponse::Data(Some(write))) = send_with_timeout(transport, *node_id, ReplicaRequest::Read { volume_id }, timeout).await {
+            return Some(write);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{add_pool, copies, network_transport, pool, read, request};
+    use crate::{ClientSession, ConsistencyLevel, DataClassification, InMemoryTransport, VolumeIo, VolumeIoConfig};
+    use std::sync::Arc;
+
+    async fn setup() -> (TrustAwareStorageManager, InMemoryTransport, StorageAllocation) {
+        let mut manager = TrustAwareStorageManager::new();
+        let mut transport = network_transport();
+        add_pool(&mut manager, &mut transport, pool("pool", 0.8, 10_000, Vec::new()), 2);
+        let allocation = manager.allocate_storage(request(1000, DataClassification::Sensitive)).await.unwrap();
+        write(&transport, &allocation, allocation.volume_id, b"version one");
+        (manager, transport, allocation)
+    }
+
+    fn write(transport: &InMemoryTransport, allocation: &StorageAllocation, volume_id: Uuid, data: &[u8]) {
+        crate::test_support::write(transport, &copies(allocation), allocation.primary_node, volume_id, data);
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_keeps_point_in_time_contents() {
+        let (mut manager, transport, volume) = setup().await;
+        let snapshot_id = Uuid::new_v4();
+        let snapshot = manager.create_snapshot(&volume.volume_id, snapshot_id, &transport).await.unwrap();
+        assert_eq!(manager.list_snapshots(&volume.volume_id), vec![&snapshot]);
+
+        let copy = manager.allocation(&snapshot_id).unwrap();
+        assert_eq!(copy.data_classification, DataClassification::Sensitive);
+        assert_eq!(copy.origin_volume_id, Some(volume.volume_id));
+        assert_eq!(manager.storage_pool("pool").unwrap().used_capacity, 4000);
+
+        write(&transport, &volume, volume.volume_id, b"version two");
+        assert_eq!(read(&transport, &volume.primary_node, &snapshot_id).unwrap(), b"version one");
+        assert_eq!(read(&transport, &volume.primary_node, &volume.volume_id).unwrap(), b"version two");
+
+        manager.delete_snapshot(&snapshot_id, &transport).await.unwrap();
+        assert!(manager.list_snapshots(&volume.volume_id).is_empty());
+        assert!(read(&transport, &volume.primary_node, &snapshot_id).is_none());
+        assert_eq!(manager.storage_pool("pool").unwrap().used_capacity, 2000);
+
+        // The snapshot's chunks are reclaimable once nothing references them
+        let store = transport.node(&volume.primary_node).unwrap().store();
+        assert_eq!(store.collect_garbage().unwrap().chunks_removed, 1);
+    }
+
+    #[tokio::test]
+    async fn test_clone_shares_chunks_until_written() {
+        let (mut manager, transport, volume) = setup().await;
+        let clone_id = Uuid::new_v4();
+        let clone = manager.clone_volume(&volume.volume_id, clone_id, &transport).await.unwrap();
+        assert_eq!(clone.primary_node, volume.primary_node);
+
+        let store = transport.node(&volume.primary_node).unwrap().store();
+        let chunk = store.manifest(&volume.volume_id).unwrap().unwrap().chunks[0].id;
+        assert_eq!(store.reference_count(&chunk).unwrap(), 2);
+
+        write(&transport, &clone, clone_id, b"diverged clone");
+        assert_eq!(store.reference_count(&chunk).unwrap(), 1);
+        assert_eq!(read(&transport, &volume.primary_node, &volume.volume_id).unwrap(), b"version one");
+
+        assert!(matches!(
+            manager.clone_volume(&volume.volume_id, clone_id, &transport).await,
+            Err(StorageError::VolumeAlreadyAllocated(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_refused_below_classification_trust() {
+        let (mut manager, transport, volume) = setup().await;
+        manager.trust_evaluator_mut().set_node_trust_score(volume.replica_nodes[0], 0.5);
+
+        let result = manager.create_snapshot(&volume.volume_id, Uuid::new_v4(), &transport).await;
+        assert!(matches!(result, Err(StorageError::UntrustedPlacement { required, .. }) if required == 0.7));
+
+        // A failed copy leaves no partial snapshot behind
+        manager.trust_evaluator_mut().set_node_trust_score(volume.replica_nodes[0], 0.8);
+        transport.set_available(volume.replica_nodes[0], false);
+        let snapshot_id = Uuid::new_v4();
+        assert!(manager.create_snapshot(&volume.volume_id, snapshot_id, &transport).await.is_err());
+        assert!(read(&transport, &volume.primary_node, &snapshot_id).is_none());
+        assert!(manager.list_snapshots(&volume.volume_id).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_never_written_volume_snapshots_as_empty() {
+        let mut manager = TrustAwareStorageManager::new();
+        let mut transport = network_transport();
+        add_pool(&mut manager, &mut transport, pool("pool", 0.8, 10_000, Vec::new()), 2);
+        let volume = manager.allocate_storage(request(1000, DataClassification::Sensitive)).await.unwrap();
+
+        // One node answering that it holds nothing is enough to know there is nothing to copy
+        transport.set_available(volume.replica_nodes[0], false);
+        let snapshot_id = Uuid::new_v4();
+        manager.create_snapshot(&volume.volume_id, snapshot_id, &transport).await.unwrap();
+        assert!(manager.replication_manager().active_plan(&snapshot_id).unwrap().read_only);
+        assert!(read(&transport, &volume.primary_node, &snapshot_id).is_none());
+
+        // With no node answering, an empty volume cannot be told from an unreachable one
+        transport.set_available(volume.primary_node, false);
+        let result = manager.clone_volume(&volume.volume_id, Uuid::new_v4(), &transport).await;
+        assert!(matches!(result, Err(StorageError::NoHealthyReplica(id)) if id == volume.volume_id));
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_refuses_writes() {
+        let (mut manager, transport, volume) = setup().await;
+        let snapshot_id = Uuid::new_v4();
+        manager.create_snapshot(&volume.volume_id, snapshot_id, &transport).await.unwrap();
+        let plan = manager.replication_manager().active_plan(&snapshot_id).unwrap().clone();
+        assert!(plan.read_only);
+
+        let transport = Arc::new(transport);
+        let io = VolumeIo::new(volume.primary_node, transport.clone(), VolumeIoConfig::default());
+        let result = io.write(&plan, &ConsistencyLevel::Strong, b"rewritten".to_vec(), &mut ClientSession::default()).await;
+        assert!(matches!(result, Err(StorageError::ReadOnlyVolume(id)) if id == snapshot_id));
+        assert_eq!(read(&transport, &volume.primary_node, &snapshot_id).unwrap(), b"version one");
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_pins_one_version_across_replicas() {
+        let (mut manager, transport, volume) = setup().await;
+
+        // The primary has taken a write the replica has not seen yet
+        let primary = transport.node(&volume.primary_node).unwrap();
+        let mut version = primary.read(&volume.volume_id).unwrap().unwrap().version;
+        version.increment(volume.primary_node);
+        primary
+            .apply(VersionedWrite {
+                volume_id: volume.volume_id,
+                version,
+                data: b"version two".to_vec(),
+            })
+            .unwrap();
+
+        let snapshot_id = Uuid::new_v4();
+        manager.create_snapshot(&volume.volume_id, snapshot_id, &transport).await.unwrap();
+        assert_eq!(read(&transport, &volume.primary_node, &snapshot_id).unwrap(), b"version two");
+        assert_eq!(read(&transport, &volume.replica_nodes[0], &snapshot_id).unwrap(), b"version two");
+
+        // Concurrent versions have no single point in time
+        let replica = transport.node(&volume.replica_nodes[0]).unwrap();
+        let mut version = replica.read(&volume.volume_id).unwrap().unwrap().version;
+        version.increment(volume.replica_nodes[0]);
+        replica
+            .apply(VersionedWrite {
+                volume_id: volume.volume_id,
+                version,
+                data: b"diverged".to_vec(),
+            })
+            .unwrap();
+        let result = manager.create_snapshot(&volume.volume_id, Uuid::new_v4(), &transport).await;
+        assert!(matches!(result, Err(StorageError::VersionConflict(id)) if id == volume.volume_id));
+    }
+}
@@ -0,0 +1,135 @@
+//! Fixtures shared by the unit tests of every storage module
+
+use crate::{
+    AccessCredential, ChunkStore, ConsistencyLevel, DataClassification, InMemoryTransport, NodeTier, ReplicaNode, ReplicationPlan,
+    ReplicationRequirements, ReplicationStrategy, StorageAllocation, StorageNode, StoragePool, StorageRequest,
+    StorageAccessor, TrustAwareStorageManager, VersionedWrite,
+};
+use ed25519_dalek::SigningKey;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Key of the storage authority every test replica trusts
+pub(crate) fn authority() -> SigningKey {
+    SigningKey::from_bytes(&[9; 32])
+}
+
+/// A credential for `accessor` signed by the test authority
+pub(crate) fn credential(accessor: StorageAccessor) -> AccessCredential {
+    AccessCredential::issue(accessor, &authority()).unwrap()
+}
+
+/// A transport sending with a node's network credential
+pub(crate) fn network_transport() -> InMemoryTransport {
+    InMemoryTransport::new(credential(StorageAccessor::Network))
+}
+
+/// Start `count` replica nodes on temporary stores and attach them to the transport
+pub(crate) fn replica_nodes(transport: &mut InMemoryTransport, count: usize) -> Vec<Uuid> {
+    (0..count)
+        .map(|_| {
+            let node = Arc::new(ReplicaNode::new(Uuid::new_v4(), ChunkStore::temporary().unwrap(), authority().verifying_key()));
+            let node_id = node.node_id();
+            transport.add_node(node);
+            node_id
+        })
+        .collect()
+}
+
+/// A writable, unencrypted plan for a new volume with `nodes[0]` as primary and the rest as replicas
+pub(crate) fn plan(nodes: &[Uuid], replication_strategy: ReplicationStrategy) -> ReplicationPlan {
+    ReplicationPlan {
+        volume_id: Uuid::new_v4(),
+        primary_node: nodes[0],
+        replica_nodes: nodes[1..].to_vec(),
+        replication_strategy,
+        encryption_required: false,
+        distinct_zones: false,
+        read_only: false,
+        service_id: None,
+        shared_with: Default::default(),
+    }
+}
+
+/// An empty Sclerotia pool
+pub(crate) fn pool(pool_id: &str, trust_level: f32, total_capacity: u64, available_nodes: Vec<Uuid>) -> StoragePool {
+    StoragePool {
+        pool_id: pool_id.to_string(),
+        trust_level,
+        available_nodes,
+        total_capacity,
+        used_capacity: 0,
+        tier: NodeTier::Sclerotia,
+    }
+}
+
+/// Start `node_count` replica nodes for a pool and register them and the pool with the manager.
+/// Each node sits in its own failure domain of the pool's tier and is trusted at the pool's level.
+pub(crate) fn add_pool(
+    manager: &mut TrustAwareStorageManager,
+    transport: &mut InMemoryTransport,
+    mut pool: StoragePool,
+    node_count: usize,
+) -> Vec<Uuid> {
+    let nodes = replica_nodes(transport, node_count);
+    for (index, node_id) in nodes.iter().enumerate() {
+        manager.register_storage_node(StorageNode {
+            node_id: *node_id,
+            tier: pool.tier,
+            zone: None,
+            failure_domain: format!("{}-{}", pool.pool_id, index),
+            latency_ms: 5,
+            bandwidth_mbps: 1000,
+        });
+        manager.trust_evaluator_mut().set_node_trust_score(*node_id, pool.trust_level);
+    }
+    pool.available_nodes = nodes.clone();
+    manager.register_storage_pool(pool).unwrap();
+    nodes
+}
+
+/// A strongly consistent two-replica request without erasure coding, service or network
+pub(crate) fn request(size_bytes: u64, data_classification: DataClassification) -> StorageRequest {
+    StorageRequest {
+        volume_id: Uuid::new_v4(),
+        size_bytes,
+        data_classification,
+        replication_requirements: ReplicationRequirements {
+            replica_count: 2,
+            consistency_level: ConsistencyLevel::Strong,
+            geographic_distribution: false,
+            replication_strategy: ReplicationStrategy::TrustDiversification,
+            erasure_coding: None,
+        },
+        service_id: None,
+        network_id: None,
+    }
+}
+
+/// The primary node of an allocation followed by its replicas
+pub(crate) fn copies(allocation: &StorageAllocation) -> Vec<Uuid> {
+    std::iter::once(allocation.primary_node).chain(allocation.replica_nodes.iter().copied()).collect()
+}
+
+/// Apply the same write on every node, advancing the version the first node holds by `writer`
+pub(crate) fn write(transport: &InMemoryTransport, nodes: &[Uuid], writer: Uuid, volume_id: Uuid, data: &[u8]) {
+    let current = transport.node(&nodes[0]).unwrap().read(&volume_id).unwrap();
+    let mut version = current.map(|write| write.version).unwrap_or_default();
+    version.increment(writer);
+    for node_id in nodes {
+        transport
+            .node(node_id)
+            .unwrap()
+            .apply(VersionedWrite {
+                volume_id,
+                version: version.clone(),
+                data: data.to_vec(),
+            })
+            .unwrap();
+    }
+}
+
+/// The data a node holds for a volume, if any
+pub(crate) fn read(transport: &InMemoryTransport, node_id: &Uuid, volume_id: &Uuid) -> Option<Vec<u8>> {
+    transport.node(node_id).unwrap().read(volume_id).unwrap().map(|write| write.data)
+}
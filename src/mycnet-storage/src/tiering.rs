@@ -0,0 +1,746 @@
+//! Heat-based tiering of volumes across node hierarchy levels
+
+use crate::erasure::{load_fragments, store_fragments};
+use crate::repair::ByteRateLimiter;
+use crate::volume_io::{read_newest, send_with_timeout};
+use crate::{
+    ConsistencyLevel, ErasureCoding, NodeTier, ReplicaRequest, ReplicaTransport, ReplicationPlan, ReplicationRequirements,
+    StorageError, StoragePool, StorageRequest, TrustAwareStorageManager, TrustRequirements,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+use uuid::Uuid;
+
+/// When volumes count as hot or cold
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TierPolicy {
+    /// Time for an access to lose half its weight
+    pub half_life: Duration,
+    /// Heat at or above which a volume belongs on Sclerotia pools
+    pub hot_threshold: f64,
+    /// Heat at or below which a volume belongs on Hyphae pools
+    pub cold_threshold: f64,
+}
+
+/// Decaying count of recent accesses to a volume
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct VolumeHeat {
+    pub score: f64,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Volume whose pool tier differs from the tier its heat or pin calls for
+#[derive(Debug, Clone, PartialEq)]
+pub struct TierMove {
+    pub volume_id: Uuid,
+    pub current_tier: NodeTier,
+    pub target_tier: NodeTier,
+    pub heat: f64,
+    pub pinned: bool,
+}
+
+/// Limits on background data movement
+#[derive(Debug, Clone)]
+pub struct TieringConfig {
+    /// Bytes moved per second across all migrations; 0 disables the limit
+    pub max_bytes_per_second: u64,
+    /// Volumes migrated in a single pass
+    pub max_migrations_per_pass: usize,
+    /// How long a replica may take to answer one migration request
+    pub request_timeout: Duration,
+    /// Coding replicated volumes are converted to when demoted to Hyphae
+    /// pools, if it reserves less capacity; `None` keeps their replicas
+    pub cold_erasure_coding: Option<ErasureCoding>,
+}
+
+/// Completed move of a volume to another pool
+#[derive(Debug, Clone, PartialEq)]
+pub struct TierMigration {
+    pub volume_id: Uuid,
+    pub from_pool: String,
+    pub to_pool: String,
+    pub target_tier: NodeTier,
+    pub bytes_moved: u64,
+}
+
+/// Outcome of a tiering pass
+#[derive(Debug, Default)]
+pub struct TieringReport {
+    pub migrated: Vec<TierMigration>,
+    pub failed: Vec<(Uuid, StorageError)>,
+    /// Volumes with no pool of their target tier able to take them
+    pub deferred: Vec<Uuid>,
+}
+
+/// Moves volumes between pools of different tiers
+///
+/// Each pass asks the manager which volumes sit on the wrong tier, picks a
+/// pool of the target tier that still satisfies the volume's classification,
+/// copies the data there at a throttled rate and then releases the old copies.
+/// Replicated volumes demoted to Hyphae pools are converted to
+/// `cold_erasure_coding` when it fits; otherwise, and on promotion, a volume
+/// keeps its replica count or erasure coding.
+pub struct TieringController {
+    config: TieringConfig,
+    limiter: ByteRateLimiter,
+}
+
+impl Default for TierPolicy {
+    fn default() -> Self {
+        Self {
+            half_life: Duration::from_secs(24 * 60 * 60),
+            hot_threshold: 100.0,
+            cold_threshold: 1.0,
+        }
+    }
+}
+
+impl Default for TieringConfig {
+    fn default() -> Self {
+        Self {
+            max_bytes_per_second: 20 * 1024 * 1024,
+            max_migrations_per_pass: 4,
+            request_timeout: Duration::from_secs(30),
+            cold_erasure_coding: ErasureCoding::new(4, 2).ok(),
+        }
+    }
+}
+
+impl VolumeHeat {
+    /// Heat remaining at `at` under a half-life
+    pub fn decayed(&self, half_life: Duration, at: DateTime<Utc>) -> f64 {
+        let elapsed = (at - self.updated_at).to_std().unwrap_or_default();
+        if half_life.is_zero() {
+            return 0.0;
+        }
+        self.score * 0.5f64.powf(elapsed.as_secs_f64() / half_life.as_secs_f64())
+    }
+}
+
+impl TierPolicy {
+    /// Tier a volume with the given heat belongs on
+    pub fn tier_for(&self, heat: f64) -> NodeTier {
+        if heat >= self.hot_threshold {
+            NodeTier::Sclerotia
+        } else if heat <= self.cold_threshold {
+            NodeTier::Hyphae
+        } else {
+            NodeTier::Rhizomorph
+        }
+    }
+}
+
+impl TrustAwareStorageManager {
+    /// Replace the thresholds used to place volumes on tiers
+    pub fn set_tier_policy(&mut self, policy: TierPolicy) {
+        self.tier_policy = policy;
+    }
+
+    /// Record a read or write of a volume
+    pub fn record_access(&mut self, volume_id: &Uuid) {
+        self.record_access_at(volume_id, Utc::now());
+    }
+
+    /// Record an access at a given time
+    pub fn record_access_at(&mut self, volume_id: &Uuid, at: DateTime<Utc>) {
+        self.add_heat(volume_id, 1, at);
+    }
+
+    /// Record the reads and writes drained from `VolumeIo::take_accesses`
+    pub fn record_accesses(&mut self, accesses: &HashMap<Uuid, u64>) {
+        let at = Utc::now();
+        for (volume_id, count) in accesses {
+            self.add_heat(volume_id, *count, at);
+        }
+    }
+
+    fn add_heat(&mut self, volume_id: &Uuid, accesses: u64, at: DateTime<Utc>) {
+        if !self.allocations.contains_key(volume_id) {
+            return;
+        }
+        let half_life = self.tier_policy.half_life;
+        let heat = self.access_heat.entry(*volume_id).or_insert(VolumeHeat {
+            score: 0.0,
+            updated_at: at,
+        });
+        heat.score = heat.decayed(half_life, at) + accesses as f64;
+        heat.updated_at = at;
+    }
+
+    /// A volume's heat at a given time
+    pub fn volume_heat(&self, volume_id: &Uuid, at: DateTime<Utc>) -> f64 {
+        self.access_heat
+            .get(volume_id)
+            .map(|heat| heat.decayed(self.tier_policy.half_life, at))
+            .unwrap_or(0.0)
+    }
+
+    /// Keep a volume on a tier regardless of its heat
+    pub fn pin_volume(&mut self, volume_id: &Uuid, tier: NodeTier) -> Result<(), StorageError> {
+        if !self.allocations.contains_key(volume_id) {
+            return Err(StorageError::VolumeNotFound(*volume_id));
+        }
+        self.tier_pins.insert(*volume_id, tier);
+        Ok(())
+    }
+
+    /// Return a pinned volume to heat-based placement
+    pub fn unpin_volume(&mut self, volume_id: &Uuid) -> Option<NodeTier> {
+        self.tier_pins.remove(volume_id)
+    }
+
+    /// Volumes on the wrong tier, pinned volumes first, then promotions by heat, then demotions
+    ///
+    /// Snapshots stay with the chunks they share and are never moved.
+    pub fn tier_moves(&self, at: DateTime<Utc>) -> Vec<TierMove> {
+        let mut moves: Vec<TierMove> = self
+            .allocations
+            .values()
+            .filter(|allocation| !self.snapshots.contains_key(&allocation.volume_id))
+            .filter_map(|allocation| {
+                let current_tier = self.storage_pools.get(&allocation.pool_id)?.tier;
+                let heat = self.volume_heat(&allocation.volume_id, at);
+                let pin = self.tier_pins.get(&allocation.volume_id).copied();
+                let target_tier = pin.unwrap_or_else(|| self.tier_policy.tier_for(heat));
+
+                (target_tier != current_tier).then_some(TierMove {
+                    volume_id: allocation.volume_id,
+                    current_tier,
+                    target_tier,
+                    heat,
+                    pinned: pin.is_some(),
+                })
+            })
+            .collect();
+
+        moves.sort_by(|a, b| {
+            let promote_a = a.target_tier < a.current_tier;
+            let promote_b = b.target_tier < b.current_tier;
+            b.pinned
+                .cmp(&a.pinned)
+                .then(promote_b.cmp(&promote_a))
+                .then(if promote_a { b.heat.total_cmp(&a.heat) } else { a.heat.total_cmp(&b.heat) })
+                .then(a.volume_id.cmp(&b.volume_id))
+        });
+        moves
+    }
+
+    /// Best-fitting pool of a tier for a volume, other than the one it is in
+    fn select_tier_pool(&self, volume_id: &Uuid, tier: NodeTier) -> Option<StoragePool> {
+        let allocation = self.allocations.get(volume_id)?;
+        let requirements = TrustRequirements::for_classification(&allocation.data_classification);
+
+        self.storage_pools
+            .values()
+            .filter(|pool| pool.tier == tier && pool.pool_id != allocation.pool_id)
+            .filter(|pool| pool.trust_level >= requirements.minimum_trust_score)
+            .filter(|pool| pool.reservable_capacity(&self.overcommit_policy) >= allocation.reserved_capacity)
+            .min_by(|a, b| {
+                a.reservable_capacity(&self.overcommit_policy)
+                    .cmp(&b.reservable_capacity(&self.overcommit_policy))
+                    .then(a.pool_id.cmp(&b.pool_id))
+            })
+            .cloned()
+    }
+}
+
+impl TieringController {
+    pub fn new(config: TieringConfig) -> Self {
+        let limiter = ByteRateLimiter::new(config.max_bytes_per_second);
+        Self { config, limiter }
+    }
+
+    /// Run one tiering pass
+    pub async fn run_once<T: ReplicaTransport>(&mut self, manager: &mut TrustAwareStorageManager, transport: &T) -> TieringReport {
+        let mut report = TieringReport::default();
+
+        for tier_move in manager.tier_moves(Utc::now()).into_iter().take(self.config.max_migrations_per_pass) {
+            let Some(pool) = manager.select_tier_pool(&tier_move.volume_id, tier_move.target_tier) else {
+                report.deferred.push(tier_move.volume_id);
+                continue;
+            };
+            match self.migrate_volume(manager, transport, &tier_move.volume_id, &pool).await {
+                Ok(migration) => report.migrated.push(migration),
+                Err(e) => {
+                    tracing::warn!("Moving volume {} to pool {} failed: {}", tier_move.volume_id, pool.pool_id, e);
+                    report.failed.push((tier_move.volume_id, e));
+                },
+            }
+        }
+        report
+    }
+
+    /// Copy a volume into `pool` and release its old copies
+    ///
+    /// Writes to the old copies are fenced for the whole move, so none can
+    /// land after the copy is taken; writers get `ReadOnlyVolume` and retry
+    /// with the new plan. A volume is only moved once every old copy is
+    /// fenced, and the fence is lifted again if the move fails. The new plan
+    /// is published only once the copy is complete, so no writer reaches the
+    /// new nodes before they hold the volume.
+    async fn migrate_volume<T: ReplicaTransport>(
+        &mut self,
+        manager: &mut TrustAwareStorageManager,
+        transport: &T,
+        volume_id: &Uuid,
+        pool: &StoragePool,
+    ) -> Result<TierMigration, StorageError> {
+        let allocation = manager.allocation(volume_id).cloned().ok_or(StorageError::VolumeNotFound(*volume_id))?;
+        let old_plan = manager
+            .replication_manager
+            .active_plan(volume_id)
+            .cloned()
+            .ok_or(StorageError::VolumeNotFound(*volume_id))?;
+
+        let request = StorageRequest {
+            volume_id: *volume_id,
+            size_bytes: allocation.allocated_size,
+            data_classification: allocation.data_classification,
+            replication_requirements: ReplicationRequirements {
+                replica_count: 1 + allocation.replica_nodes.len(),
+                consistency_level: ConsistencyLevel::Strong,
+                geographic_distribution: old_plan.distinct_zones,
+                replication_strategy: old_plan.replication_strategy,
+                erasure_coding: allocation.erasure_coding,
+            },
+            service_id: allocation.service_id.clone(),
+            network_id: allocation.network_id.clone(),
+        };
+        let (new_plan, coding) = self.plan_move(manager, request, allocation.reserved_capacity, pool).await?;
+        let new_plan = ReplicationPlan {
+            shared_with: old_plan.shared_with.clone(),
+            ..new_plan
+        };
+        // Planning records the new plan; the old one stays in force until the copy is complete
+        manager.replication_manager.insert_plan(old_plan.clone());
+
+        let old_nodes = old_plan.nodes();
+        self.set_fence(transport, &old_nodes, *volume_id, true).await?;
+
+        // Nodes the volume moves onto may carry a fence from an earlier move,
+        // which would refuse the copy's fragment writes
+        let added_nodes: Vec<Uuid> = new_plan.nodes().into_iter().filter(|node_id| !old_nodes.contains(node_id)).collect();
+        if let Err(e) = self.set_fence(transport, &added_nodes, *volume_id, false).await {
+            tracing::debug!("Stale fence on volume {} was not lifted on every new node: {}", volume_id, e);
+        }
+
+        let old_copies: HashSet<(Uuid, Option<usize>)> = old_plan.copies(allocation.erasure_coding.is_some()).into_iter().collect();
+        let new_copies: HashSet<(Uuid, Option<usize>)> = new_plan.copies(coding.is_some()).into_iter().collect();
+
+        let copied = match (allocation.erasure_coding, coding) {
+            // Fragments move as stored, so encrypted volumes stay encrypted
+            (Some(old_coding), Some(new_coding)) => {
+                match load_fragments(transport, &old_plan, &old_coding, self.config.request_timeout).await {
+                    Ok(data) => self.store_coded(transport, &new_plan, &new_coding, &data).await,
+                    Err(e) => Err(e),
+                }
+            },
+            // Demotion converts the newest replica into fragments; a never-written volume has nothing to move
+            (None, Some(new_coding)) => match read_newest(transport, &old_nodes, *volume_id, self.config.request_timeout).await {
+                Ok(Some(contents)) => self.store_coded(transport, &new_plan, &new_coding, &contents.data).await,
+                Ok(None) => Ok(0),
+                Err(e) => Err(e),
+            },
+            (_, None) => self.copy_replicas(transport, &old_plan, &new_plan).await,
+        };
+
+        let bytes_moved = match copied {
+            Ok(bytes_moved) => bytes_moved,
+            Err(e) => {
+                // Leave the volume where it was and drop any partial copies
+                for (node_id, fragment) in new_copies.difference(&old_copies) {
+                    let request = ReplicaRequest::DeleteVolume { volume_id: *volume_id, fragment: *fragment };
+                    let _ = send_with_timeout(transport, *node_id, request, self.config.request_timeout).await;
+                }
+                let _ = self.set_fence(transport, &old_nodes, *volume_id, false).await;
+                return Err(e);
+            },
+        };
+        manager.replication_manager.insert_plan(new_plan.clone());
+
+        for (node_id, fragment) in old_copies.difference(&new_copies) {
+            let request = ReplicaRequest::DeleteVolume { volume_id: *volume_id, fragment: *fragment };
+            if let Err(e) = send_with_timeout(transport, *node_id, request, self.config.request_timeout).await {
+                tracing::warn!("Old copy of volume {} on {} was not removed: {}", volume_id, node_id, e);
+            }
+        }
+        // Old nodes stay fenced against writers still holding the old plan;
+        // nodes kept from the old plan are opened again under the new one
+        if let Err(e) = self.set_fence(transport, &new_plan.nodes(), *volume_id, false).await {
+            tracing::warn!("Fence on volume {} was not lifted on every new node: {}", volume_id, e);
+        }
+
+        let reserved_capacity = match coding {
+            Some(coding) if allocation.erasure_coding.is_none() => {
+                coding.reserved_capacity(allocation.allocated_size).unwrap_or(allocation.reserved_capacity)
+            },
+            _ => allocation.reserved_capacity,
+        };
+        if let Some(old_pool) = manager.storage_pools.get_mut(&allocation.pool_id) {
+            old_pool.used_capacity = old_pool.used_capacity.saturating_sub(allocation.reserved_capacity);
+        }
+        if let Some(new_pool) = manager.storage_pools.get_mut(&pool.pool_id) {
+            new_pool.used_capacity += reserved_capacity;
+        }
+        if let Some(moved) = manager.allocations.get_mut(volume_id) {
+            moved.pool_id = pool.pool_id.clone();
+            moved.primary_node = new_plan.primary_node;
+            moved.replica_nodes = new_plan.replica_nodes.clone();
+            moved.erasure_coding = coding;
+            moved.reserved_capacity = reserved_capacity;
+        }
+
+        tracing::info!("Moved volume {} from pool {} to {}", volume_id, allocation.pool_id, pool.pool_id);
+        Ok(TierMigration {
+            volume_id: *volume_id,
+            from_pool: allocation.pool_id,
+            to_pool: pool.pool_id.clone(),
+            target_tier: pool.tier,
+            bytes_moved,
+        })
+    }
+
+    /// Place a volume in `pool`, converting a replicated volume to erasure
+    /// coding on demotion when that reserves less capacity
+    ///
+    /// Returns the new plan and the coding the volume has under it.
+    async fn plan_move(
+        &self,
+        manager: &mut TrustAwareStorageManager,
+        request: StorageRequest,
+        reserved_capacity: u64,
+        pool: &StoragePool,
+    ) -> Result<(ReplicationPlan, Option<ErasureCoding>), StorageError> {
+        let minimum_trust_score = TrustRequirements::for_classification(&request.data_classification).minimum_trust_score;
+        let conversion = self
+            .config
+            .cold_erasure_coding
+            .filter(|_| pool.tier == NodeTier::Hyphae && request.replication_requirements.erasure_coding.is_none())
+            .filter(|coding| coding.reserved_capacity(request.size_bytes).is_some_and(|coded| coded < reserved_capacity));
+
+        if let Some(coding) = conversion {
+            let coded = StorageRequest {
+                replication_requirements: ReplicationRequirements {
+                    erasure_coding: Some(coding),
+                    ..request.replication_requirements.clone()
+                },
+                ..request.clone()
+            };
+            match manager
+                .replication_manager
+                .create_replication_plan(&coded, pool, &manager.trust_evaluator, minimum_trust_score)
+                .await
+            {
+                Ok(plan) => return Ok((plan, Some(coding))),
+                Err(e) => tracing::debug!("Volume {} keeps its replicas in pool {}: {}", request.volume_id, pool.pool_id, e),
+            }
+        }
+
+        let plan = manager
+            .replication_manager
+            .create_replication_plan(&request, pool, &manager.trust_evaluator, minimum_trust_score)
+            .await?;
+        Ok((plan, request.replication_requirements.erasure_coding))
+    }
+
+    /// Fence or unfence a volume on every node, failing if any node does not confirm
+    async fn set_fence<T: ReplicaTransport>(
+        &self,
+        transport: &T,
+        nodes: &[Uuid],
+        volume_id: Uuid,
+        fenced: bool,
+    ) -> Result<(), StorageError> {
+        let mut result = Ok(());
+        for node_id in nodes {
+            let request = ReplicaRequest::Fence { volume_id, fenced };
+            if let Err(e) = send_with_timeout(transport, *node_id, request, self.config.request_timeout).await {
+                result = Err(e);
+            }
+        }
+        if fenced && result.is_err() {
+            for node_id in nodes {
+                let request = ReplicaRequest::Fence { volume_id, fenced: false };
+                let _ = send_with_timeout(transport, *node_id, request, self.config.request_timeout).await;
+            }
+        }
+        result
+    }
+
+    /// Write stored volume contents as fragments of a new plan, throttled
+    async fn store_coded<T: ReplicaTransport>(
+        &mut self,
+        transport: &T,
+        plan: &ReplicationPlan,
+        coding: &ErasureCoding,
+        data: &[u8],
+    ) -> Result<u64, StorageError> {
+        let bytes = coding.reserved_capacity(data.len() as u64).unwrap_or(u64::MAX);
+        self.limiter.acquire(bytes).await;
+        store_fragments(transport, plan, coding, data, self.config.request_timeout).await?;
+        Ok(bytes)
+    }
+
+    /// Copy the newest contents of a replicated volume to every node of a new plan
+    ///
+    /// The old copies are fenced, so the newest one read here is final. A
+    /// volume that was never written has nothing to copy.
+    async fn copy_replicas<T: ReplicaTransport>(
+        &mut self,
+        transport: &T,
+        old_plan: &ReplicationPlan,
+        new_plan: &ReplicationPlan,
+    ) -> Result<u64, StorageError> {
+        let Some(contents) = read_newest(transport, &old_plan.nodes(), old_plan.volume_id, self.config.request_timeout).await? else {
+            return Ok(0);
+        };
+
+        let targets = new_plan.nodes();
+        let mut acknowledged = 0;
+        for target in &targets {
+            self.limiter.acquire(contents.data.len() as u64).await;
+            let request = ReplicaRequest::Restore(contents.clone());
+            if send_with_timeout(transport, *target, request, self.config.request_timeout).await.is_ok() {
+                acknowledged += 1;
+            }
+        }
+
+        if acknowledged < targets.len() {
+            return Err(StorageError::ReplicationFailed {
+                acknowledged,
+                required: targets.len(),
+            });
+        }
+        Ok(contents.data.len() as u64 * targets.len() as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{add_pool, copies, network_transport, pool, read, request, write};
+    use crate::{
+        ClientSession, DataClassification, InMemoryTransport, ReplicaResponse, VersionedWrite, VolumeIo, VolumeIoConfig, VolumeKeyEnvelope,
+    };
+    use std::sync::Arc;
+
+    fn tier_pool(pool_id: &str, tier: NodeTier) -> StoragePool {
+        StoragePool {
+            tier,
+            ..pool(pool_id, 0.8, 10_000, Vec::new())
+        }
+    }
+
+    async fn allocate(manager: &mut TrustAwareStorageManager, transport: &InMemoryTransport, data: &[u8]) -> Uuid {
+        let allocation = manager.allocate_storage(request(1000, DataClassification::Sensitive)).await.unwrap();
+        write(transport, &copies(&allocation), allocation.primary_node, allocation.volume_id, data);
+        allocation.volume_id
+    }
+
+    #[test]
+    fn test_heat_decays_by_half_life() {
+        let policy = TierPolicy::default();
+        let start = Utc::now();
+        let heat = VolumeHeat { score: 4.0, updated_at: start };
+        assert!((heat.decayed(policy.half_life, start + chrono::Duration::days(1)) - 2.0).abs() < 1e-9);
+        assert_eq!(policy.tier_for(150.0), NodeTier::Sclerotia);
+        assert_eq!(policy.tier_for(2.0), NodeTier::Rhizomorph);
+        assert_eq!(policy.tier_for(0.5), NodeTier::Hyphae);
+    }
+
+    #[tokio::test]
+    async fn test_hot_volume_promoted_and_pin_overrides_heat() {
+        let mut manager = TrustAwareStorageManager::new();
+        let mut transport = network_transport();
+        add_pool(&mut manager, &mut transport, tier_pool("hdd", NodeTier::Hyphae), 2);
+        manager.set_tier_policy(TierPolicy {
+            hot_threshold: 3.0,
+            ..TierPolicy::default()
+        });
+
+        let hot = allocate(&mut manager, &transport, b"read all the time").await;
+        let pinned = allocate(&mut manager, &transport, b"latency sensitive").await;
+        let cold = allocate(&mut manager, &transport, b"rarely read").await;
+        for _ in 0..5 {
+            manager.record_access(&hot);
+        }
+        manager.pin_volume(&pinned, NodeTier::Sclerotia).unwrap();
+        let nvme = add_pool(&mut manager, &mut transport, tier_pool("nvme", NodeTier::Sclerotia), 2);
+
+        let moves = manager.tier_moves(Utc::now());
+        assert_eq!(moves.iter().map(|m| m.volume_id).collect::<Vec<_>>(), vec![pinned, hot]);
+
+        let mut controller = TieringController::new(TieringConfig::default());
+        let report = controller.run_once(&mut manager, &transport).await;
+        assert_eq!(report.migrated.len(), 2);
+        assert!(report.failed.is_empty() && report.deferred.is_empty());
+
+        let promoted = manager.allocation(&hot).unwrap();
+        assert_eq!(promoted.pool_id, "nvme");
+        assert!(nvme.contains(&promoted.primary_node));
+        assert_eq!(read(&transport, &promoted.primary_node, &hot).unwrap(), b"read all the time");
+        assert_eq!(manager.allocation(&pinned).unwrap().pool_id, "nvme");
+        assert_eq!(manager.allocation(&cold).unwrap().pool_id, "hdd");
+        assert_eq!(manager.storage_pool("nvme").unwrap().used_capacity, 4000);
+        assert_eq!(manager.storage_pool("hdd").unwrap().used_capacity, 2000);
+    }
+
+    #[tokio::test]
+    async fn test_cold_volume_demoted_once_a_pool_is_available() {
+        let mut manager = TrustAwareStorageManager::new();
+        let mut transport = network_transport();
+        let nvme = add_pool(&mut manager, &mut transport, tier_pool("nvme", NodeTier::Sclerotia), 2);
+        let volume_id = allocate(&mut manager, &transport, b"archived").await;
+        let mut controller = TieringController::new(TieringConfig::default());
+
+        // No Hyphae pool exists yet, so the volume waits where it is
+        let report = controller.run_once(&mut manager, &transport).await;
+        assert_eq!(report.deferred, vec![volume_id]);
+
+        let hdd = add_pool(&mut manager, &mut transport, tier_pool("hdd", NodeTier::Hyphae), 2);
+        let report = controller.run_once(&mut manager, &transport).await;
+        assert_eq!(report.migrated[0].bytes_moved, 2 * b"archived".len() as u64);
+
+        let demoted = manager.allocation(&volume_id).unwrap();
+        assert_eq!(demoted.pool_id, "hdd");
+        assert!(hdd.iter().all(|node_id| read(&transport, node_id, &volume_id).unwrap() == b"archived"));
+        assert!(nvme.iter().all(|node_id| read(&transport, node_id, &volume_id).is_none()));
+        assert!(manager.tier_moves(Utc::now()).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_untrusted_pool_never_receives_volume() {
+        let mut manager = TrustAwareStorageManager::new();
+        let mut transport = network_transport();
+        add_pool(&mut manager, &mut transport, tier_pool("nvme", NodeTier::Sclerotia), 2);
+        let volume_id = allocate(&mut manager, &transport, b"sensitive").await;
+        let hdd = add_pool(&mut manager, &mut transport, tier_pool("hdd", NodeTier::Hyphae), 2);
+        manager.storage_pools.get_mut("hdd").unwrap().trust_level = 0.3;
+        for node_id in hdd {
+            manager.trust_evaluator_mut().set_node_trust_score(node_id, 0.3);
+        }
+
+        let report = TieringController::new(TieringConfig::default()).run_once(&mut manager, &transport).await;
+        assert_eq!(report.deferred, vec![volume_id]);
+        assert_eq!(manager.allocation(&volume_id).unwrap().pool_id, "nvme");
+    }
+
+    #[tokio::test]
+    async fn test_volume_io_accesses_heat_volumes() {
+        let mut manager = TrustAwareStorageManager::new();
+        let mut transport = network_transport();
+        add_pool(&mut manager, &mut transport, tier_pool("hdd", NodeTier::Hyphae), 2);
+        let volume_id = allocate(&mut manager, &transport, b"busy").await;
+        let plan = manager.replication_manager().active_plan(&volume_id).unwrap().clone();
+
+        let mut io = VolumeIo::new(plan.primary_node, Arc::new(transport), VolumeIoConfig::default());
+        io.register_volume_key(VolumeKeyEnvelope::generate(volume_id, &[]).unwrap().1);
+        let mut session = ClientSession::default();
+        // The first write matches the version `allocate` stored; the second replaces it
+        for _ in 0..2 {
+            io.write(&plan, &ConsistencyLevel::Strong, b"busy".to_vec(), &mut session).await.unwrap();
+        }
+        io.read(&plan, &ConsistencyLevel::Strong, &mut session).await.unwrap();
+        manager.record_accesses(&io.take_accesses());
+        assert!((manager.volume_heat(&volume_id, Utc::now()) - 3.0).abs() < 1e-3);
+        assert!(io.take_accesses().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_migration_copies_newest_replica_and_fences_old_copies() {
+        let mut manager = TrustAwareStorageManager::new();
+        let mut transport = network_transport();
+        add_pool(&mut manager, &mut transport, tier_pool("hdd", NodeTier::Hyphae), 2);
+        let volume_id = allocate(&mut manager, &transport, b"stale").await;
+        let old = manager.allocation(&volume_id).unwrap().clone();
+        manager.pin_volume(&volume_id, NodeTier::Sclerotia).unwrap();
+        add_pool(&mut manager, &mut transport, tier_pool("nvme", NodeTier::Sclerotia), 2);
+
+        // Only the second replica has the latest write
+        let mut version = transport.node(&old.replica_nodes[0]).unwrap().read(&volume_id).unwrap().unwrap().version;
+        version.increment(old.replica_nodes[0]);
+        let newest = VersionedWrite {
+            volume_id,
+            version,
+            data: b"newest".to_vec(),
+        };
+        transport.node(&old.replica_nodes[0]).unwrap().apply(newest.clone()).unwrap();
+
+        let report = TieringController::new(TieringConfig::default()).run_once(&mut manager, &transport).await;
+        assert_eq!(report.migrated.len(), 1);
+        let moved = manager.allocation(&volume_id).unwrap().clone();
+        for node_id in std::iter::once(moved.primary_node).chain(moved.replica_nodes.iter().copied()) {
+            assert_eq!(read(&transport, &node_id, &volume_id).unwrap(), b"newest");
+            let write = transport.send(node_id, ReplicaRequest::Write(newest.clone())).await;
+            assert!(matches!(write, Ok(ReplicaResponse::Ack)));
+        }
+
+        // Writers still holding the old plan are refused by the old nodes
+        let stale = transport.send(old.primary_node, ReplicaRequest::Write(newest)).await;
+        assert!(matches!(stale, Err(StorageError::ReadOnlyVolume(id)) if id == volume_id));
+    }
+
+    #[tokio::test]
+    async fn test_failed_migration_keeps_the_old_plan_published() {
+        let mut manager = TrustAwareStorageManager::new();
+        let mut transport = network_transport();
+        add_pool(&mut manager, &mut transport, tier_pool("hdd", NodeTier::Hyphae), 2);
+        let volume_id = allocate(&mut manager, &transport, b"stays put").await;
+        let old_plan = manager.replication_manager().active_plan(&volume_id).unwrap().clone();
+        manager.pin_volume(&volume_id, NodeTier::Sclerotia).unwrap();
+        let nvme = add_pool(&mut manager, &mut transport, tier_pool("nvme", NodeTier::Sclerotia), 2);
+        transport.set_available(nvme[1], false);
+
+        let report = TieringController::new(TieringConfig::default()).run_once(&mut manager, &transport).await;
+        assert!(matches!(report.failed[..], [(id, StorageError::ReplicationFailed { .. })] if id == volume_id));
+        assert_eq!(manager.replication_manager().active_plan(&volume_id).unwrap().nodes(), old_plan.nodes());
+        assert!(read(&transport, &nvme[0], &volume_id).is_none());
+
+        // The old copies are writable again
+        let mut newest = transport.node(&old_plan.primary_node).unwrap().read(&volume_id).unwrap().unwrap();
+        newest.version.increment(old_plan.primary_node);
+        let write = transport.send(old_plan.primary_node, ReplicaRequest::Write(newest)).await;
+        assert!(matches!(write, Ok(ReplicaResponse::Ack)));
+
+        // A volume that was never written moves without copying anything
+        transport.set_available(nvme[1], true);
+        let empty = manager.allocate_storage(request(1000, DataClassification::Sensitive)).await.unwrap().volume_id;
+        manager.pin_volume(&empty, NodeTier::Sclerotia).unwrap();
+        let report = TieringController::new(TieringConfig::default()).run_once(&mut manager, &transport).await;
+        assert!(report.failed.is_empty());
+        assert!(report.migrated.iter().any(|migration| migration.volume_id == empty && migration.bytes_moved == 0));
+        assert_eq!(manager.allocation(&empty).unwrap().pool_id, "nvme");
+    }
+
+    #[tokio::test]
+    async fn test_demotion_converts_replicas_to_erasure_coding() {
+        let mut manager = TrustAwareStorageManager::new();
+        let mut transport = network_transport();
+        add_pool(&mut manager, &mut transport, tier_pool("nvme", NodeTier::Sclerotia), 2);
+        let volume_id = allocate(&mut manager, &transport, b"cold archive contents").await;
+        let hdd = add_pool(&mut manager, &mut transport, tier_pool("hdd", NodeTier::Hyphae), 3);
+
+        let coding = ErasureCoding::new(2, 1).unwrap();
+        let mut controller = TieringController::new(TieringConfig {
+            cold_erasure_coding: Some(coding),
+            ..TieringConfig::default()
+        });
+        let report = controller.run_once(&mut manager, &transport).await;
+        assert_eq!(report.migrated.len(), 1);
+
+        let demoted = manager.allocation(&volume_id).unwrap().clone();
+        assert_eq!(demoted.erasure_coding, Some(coding));
+        let reserved = coding.reserved_capacity(1000).unwrap();
+        assert!(reserved < 2000);
+        assert_eq!(demoted.reserved_capacity, reserved);
+        assert_eq!(manager.storage_pool("hdd").unwrap().used_capacity, reserved);
+        assert_eq!(manager.storage_pool("nvme").unwrap().used_capacity, 0);
+        assert!(hdd.iter().all(|node_id| read(&transport, node_id, &volume_id).is_none()));
+
+        let plan = manager.replication_manager().active_plan(&volume_id).unwrap().clone();
+        let data = load_fragments(&transport, &plan, &coding, Duration::from_secs(1)).await.unwrap();
+        assert_eq!(data, b"cold archive contents");
+    }
+}
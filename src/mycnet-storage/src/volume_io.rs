@@ -0,0 +1,1447 @@
+//! Replicated volume reads and writes for each consistency level
+
+use crate::{
+    AccessCredential, ChunkId, ChunkStore, ConsistencyLevel, IoOperation, LatencyHistogram, ReplicationPlan, StorageAccessor, StorageError,
+    VolumeAccess, VolumeCipher,
+};
+use ed25519_dalek::VerifyingKey;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::task::{JoinHandle, JoinSet};
+use uuid::Uuid;
+
+/// Per-node write counters used to order volume versions
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VersionVector(pub BTreeMap<Uuid, u64>);
+
+/// Volume contents tagged with the version that produced them
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VersionedWrite {
+    pub volume_id: Uuid,
+    pub version: VersionVector,
+    pub data: Vec<u8>,
+}
+
+/// Identifies one encoding of an erasure-coded volume's contents
+///
+/// Every write stores its fragments under a new generation, ordered by
+/// sequence and then writer, and readers only decode fragments of one generation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct FragmentGeneration {
+    pub sequence: u64,
+    pub writer: Uuid,
+}
+
+/// Message sent to a replica node
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ReplicaRequest {
+    Write(VersionedWrite),
+    Read { volume_id: Uuid },
+    /// Store a fragment next to any earlier generations of it
+    WriteFragment { volume_id: Uuid, index: usize, generation: FragmentGeneration, data: Vec<u8> },
+    ReadFragment { volume_id: Uuid, index: usize, generation: FragmentGeneration },
+    /// Generations of a fragment the node holds, oldest first
+    FragmentGenerations { volume_id: Uuid, index: usize },
+    /// Drop generations older than one that is now stored on every node
+    CommitFragment { volume_id: Uuid, index: usize, generation: FragmentGeneration },
+    /// Drop one generation of a fragment, e.g. after a failed write
+    DiscardFragment { volume_id: Uuid, index: usize, generation: FragmentGeneration },
+    /// Overwrite a copy regardless of version, used to replace corrupted data
+    Restore(VersionedWrite),
+    ReadChunk { id: ChunkId },
+    Digest { volume_id: Uuid },
+    /// Share a volume's chunks with a new volume; `fragment` selects an erasure-coded fragment
+    ///
+    /// With `version` set, the copy only happens if the source is at exactly
+    /// that version, so every node copies the same point in time.
+    CopyVolume { source: Uuid, target: Uuid, fragment: Option<usize>, version: Option<VersionVector> },
+    DeleteVolume { volume_id: Uuid, fragment: Option<usize> },
+    /// Refuse or accept writes to a volume, e.g. while it moves to other nodes
+    Fence { volume_id: Uuid, fenced: bool },
+    /// Record which services may use a volume
+    SetAccess { volume_id: Uuid, access: VolumeAccess },
+}
+
+/// Reply from a replica node
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ReplicaResponse {
+    Ack,
+    /// The write lost to the stored version and was not applied
+    Stale,
+    Data(Option<VersionedWrite>),
+    Fragment(Option<Vec<u8>>),
+    Generations(Vec<FragmentGeneration>),
+    Chunk(Option<Vec<u8>>),
+    Digest(Option<VolumeDigest>),
+}
+
+/// Version and content hash of a replica's copy of a volume
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VolumeDigest {
+    pub version: VersionVector,
+    pub digest: [u8; 32],
+}
+
+/// Carries replica requests to storage nodes, each with the credential it is sent for
+pub trait ReplicaTransport: Send + Sync + 'static {
+    /// Credential of whoever operates the transport: a node's network credential or a service's own
+    fn credential(&self) -> &AccessCredential;
+
+    /// Send a request on behalf of the holder of `credential`
+    fn send_as(
+        &self,
+        node_id: Uuid,
+        credential: &AccessCredential,
+        request: ReplicaRequest,
+    ) -> impl Future<Output = Result<ReplicaResponse, StorageError>> + Send;
+
+    /// Send a request with the transport's own credential
+    fn send(&self, node_id: Uuid, request: ReplicaRequest) -> impl Future<Output = Result<ReplicaResponse, StorageError>> + Send {
+        self.send_as(node_id, self.credential(), request)
+    }
+}
+
+/// Acknowledgements required before a strong write completes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StrongAck {
+    All,
+    Majority,
+}
+
+/// Tuning for the volume I/O coordinator
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VolumeIoConfig {
+    pub strong_ack: StrongAck,
+    pub request_timeout: Duration,
+}
+
+/// Who a client is and the versions it has observed
+///
+/// The accessor is checked against each plan before every read and write,
+/// and replicas check the session's credential again; the versions keep the
+/// client's reads causally consistent.
+#[derive(Debug, Clone, Default)]
+pub struct ClientSession {
+    /// `None` acts for the coordinating node, with the transport's credential
+    credential: Option<AccessCredential>,
+    seen: HashMap<Uuid, VersionVector>,
+}
+
+/// Outcome of a replicated write
+#[derive(Debug, Clone)]
+pub struct WriteReceipt {
+    pub version: VersionVector,
+    /// Nodes that had acknowledged when the write returned
+    pub acknowledged: Vec<Uuid>,
+}
+
+/// Replica-side handler applying versioned writes to a local chunk store
+///
+/// Version vectors are persisted in the chunk store's metadata next to the
+/// volume manifests, so a restarted replica keeps ordering writes correctly.
+/// Volumes are stored and replaced whole; there are no ranged reads or writes.
+pub struct ReplicaNode {
+    node_id: Uuid,
+    store: ChunkStore,
+    /// Key of the storage authority that signs access credentials
+    authority: VerifyingKey,
+    /// Serializes version checks with the writes they guard
+    write_lock: Mutex<()>,
+    /// Volumes whose writes are refused until the fence is lifted
+    fenced: Mutex<HashSet<Uuid>>,
+}
+
+/// In-process transport connecting replica nodes directly, for tests and single-host setups
+pub struct InMemoryTransport {
+    credential: AccessCredential,
+    nodes: HashMap<Uuid, Arc<ReplicaNode>>,
+    unavailable: Mutex<HashSet<Uuid>>,
+}
+
+/// Writes waiting for recovered nodes, optionally persisted in a chunk store
+#[derive(Default)]
+struct HintLog {
+    pending: Mutex<HashMap<Uuid, Vec<VersionedWrite>>>,
+    store: Option<ChunkStore>,
+}
+
+/// Coordinates replicated volume I/O over a transport
+///
+/// Every write replaces a volume's whole contents and every read returns
+/// them whole; offset and length addressing is left to the filesystem on
+/// top of the volume.
+pub struct VolumeIo<T: ReplicaTransport> {
+    node_id: Uuid,
+    transport: Arc<T>,
+    config: VolumeIoConfig,
+    hints: Arc<HintLog>,
+    latest: Mutex<HashMap<Uuid, VersionVector>>,
+    ciphers: HashMap<Uuid, VolumeCipher>,
+    latency: Mutex<BTreeMap<IoOperation, LatencyHistogram>>,
+    accesses: Mutex<HashMap<Uuid, u64>>,
+    /// Volume access last published to all of a volume's replicas
+    published: Mutex<HashMap<Uuid, VolumeAccess>>,
+    /// Replica writes still completing after their write returned
+    background: Mutex<Vec<JoinHandle<()>>>,
+}
+
+impl VersionVector {
+    /// Count one more write by a node
+    pub fn increment(&mut self, node_id: Uuid) {
+        *self.0.entry(node_id).or_insert(0) += 1;
+    }
+
+    /// Take the element-wise maximum with another vector
+    pub fn merge(&mut self, other: &VersionVector) {
+        for (node_id, counter) in &other.0 {
+            let entry = self.0.entry(*node_id).or_insert(0);
+            *entry = (*entry).max(*counter);
+        }
+    }
+
+    /// Causal order between two versions; `None` if they are concurrent
+    pub fn partial_cmp(&self, other: &VersionVector) -> Option<Ordering> {
+        let nodes: HashSet<&Uuid> = self.0.keys().chain(other.0.keys()).collect();
+        let (mut less, mut greater) = (false, false);
+
+        for node_id in nodes {
+            let ours = self.0.get(node_id).copied().unwrap_or(0);
+            let theirs = other.0.get(node_id).copied().unwrap_or(0);
+            match ours.cmp(&theirs) {
+                Ordering::Less => less = true,
+                Ordering::Greater => greater = true,
+                Ordering::Equal => {},
+            }
+        }
+
+        match (less, greater) {
+            (false, false) => Some(Ordering::Equal),
+            (true, false) => Some(Ordering::Less),
+            (false, true) => Some(Ordering::Greater),
+            (true, true) => None,
+        }
+    }
+
+    /// Whether this version includes every write in `other`
+    pub fn dominates(&self, other: &VersionVector) -> bool {
+        matches!(self.partial_cmp(other), Some(Ordering::Greater | Ordering::Equal))
+    }
+}
+
+impl VersionedWrite {
+    /// Whether this write should replace `current`
+    ///
+    /// Later versions win. Concurrent versions are resolved deterministically
+    /// by total counter and then content hash so every replica picks the same one.
+    pub fn supersedes(&self, current: &VersionedWrite) -> bool {
+        match self.version.partial_cmp(&current.version) {
+            Some(Ordering::Greater) => true,
+            Some(_) => false,
+            None => {
+                let weight = |write: &VersionedWrite| write.version.0.values().sum::<u64>();
+                weight(self)
+                    .cmp(&weight(current))
+                    .then_with(|| blake3::hash(&self.data).as_bytes().cmp(blake3::hash(&current.data).as_bytes()))
+                    == Ordering::Greater
+            },
+        }
+    }
+}
+
+impl Default for VolumeIoConfig {
+    fn default() -> Self {
+        Self {
+            strong_ack: StrongAck::All,
+            request_timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+impl ClientSession {
+    /// Session for the coordinating node itself, which acts for the network
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Session acting for the holder of a credential
+    ///
+    /// The credential is forwarded with every replica request, so replicas
+    /// refuse sessions whose credential the storage authority did not sign.
+    pub fn for_credential(credential: AccessCredential) -> Self {
+        Self {
+            credential: Some(credential),
+            seen: HashMap::new(),
+        }
+    }
+
+    pub fn accessor(&self) -> &StorageAccessor {
+        const NETWORK: StorageAccessor = StorageAccessor::Network;
+        self.credential.as_ref().map_or(&NETWORK, |credential| &credential.accessor)
+    }
+
+    /// Latest version of a volume this client has observed
+    pub fn seen(&self, volume_id: &Uuid) -> Option<&VersionVector> {
+        self.seen.get(volume_id)
+    }
+
+    fn observe(&mut self, volume_id: Uuid, version: &VersionVector) {
+        self.seen.entry(volume_id).or_default().merge(version);
+    }
+}
+
+impl ReplicaNode {
+    /// Replica accepting requests with credentials signed by `authority`
+    pub fn new(node_id: Uuid, store: ChunkStore, authority: VerifyingKey) -> Self {
+        Self {
+            node_id,
+            store,
+            authority,
+            write_lock: Mutex::new(()),
+            fenced: Mutex::new(HashSet::new()),
+        }
+    }
+
+    pub fn node_id(&self) -> Uuid {
+        self.node_id
+    }
+
+    /// Get the local chunk store
+    pub fn store(&self) -> &ChunkStore {
+        &self.store
+    }
+
+    /// Handle a request sent for the holder of `credential`
+    ///
+    /// The credential must be signed by the storage authority. The network may
+    /// send any request. Services may only read and write volumes whose
+    /// published access admits them; volumes without published access are
+    /// treated as network storage.
+    pub fn handle(&self, credential: &AccessCredential, request: ReplicaRequest) -> Result<ReplicaResponse, StorageError> {
+        credential.verify(&self.authority)?;
+        if credential.accessor != StorageAccessor::Network {
+            self.authorize(&credential.accessor, &request)?;
+        }
+
+        match request {
+            ReplicaRequest::Write(write) => {
+                self.check_fence(&write.volume_id)?;
+                if self.apply(write)? {
+                    Ok(ReplicaResponse::Ack)
+                } else {
+                    Ok(ReplicaResponse::Stale)
+                }
+            },
+            ReplicaRequest::Read { volume_id } => Ok(ReplicaResponse::Data(self.read(&volume_id)?)),
+            ReplicaRequest::WriteFragment { volume_id, index, generation, data } => {
+                self.check_fence(&volume_id)?;
+                self.write_fragment(&volume_id, index, generation, &data)?;
+                Ok(ReplicaResponse::Ack)
+            },
+            ReplicaRequest::ReadFragment { volume_id, index, generation } => {
+                Ok(ReplicaResponse::Fragment(self.read_fragment(&volume_id, index, generation)?))
+            },
+            ReplicaRequest::FragmentGenerations { volume_id, index } => {
+                Ok(ReplicaResponse::Generations(self.fragment_generations(&volume_id, index)?))
+            },
+            ReplicaRequest::CommitFragment { volume_id, index, generation } => {
+                self.commit_fragment(&volume_id, index, generation)?;
+                Ok(ReplicaResponse::Ack)
+            },
+            ReplicaRequest::DiscardFragment { volume_id, index, generation } => {
+                self.discard_fragment(&volume_id, index, generation)?;
+                Ok(ReplicaResponse::Ack)
+            },
+            ReplicaRequest::Restore(write) => {
+                self.restore(write)?;
+                Ok(ReplicaResponse::Ack)
+            },
+            ReplicaRequest::ReadChunk { id } => {
+                // Only verified chunks are served so corruption never spreads
+                let chunk = self.store.raw_chunk(&id)?.filter(|data| ChunkId::of(data) == id);
+                Ok(ReplicaResponse::Chunk(chunk))
+            },
+            ReplicaRequest::Digest { volume_id } => Ok(ReplicaResponse::Digest(self.read(&volume_id)?.map(|write| VolumeDigest {
+                version: write.version,
+                digest: *blake3::hash(&write.data).as_bytes(),
+            }))),
+            ReplicaRequest::CopyVolume { source, target, fragment, version } => {
+                self.copy(&source, target, fragment, version.as_ref())?;
+                Ok(ReplicaResponse::Ack)
+            },
+            ReplicaRequest::DeleteVolume { volume_id, fragment } => {
+                self.delete(&volume_id, fragment)?;
+                Ok(ReplicaResponse::Ack)
+            },
+            ReplicaRequest::Fence { volume_id, fenced } => {
+                self.set_fenced(volume_id, fenced);
+                Ok(ReplicaResponse::Ack)
+            },
+            ReplicaRequest::SetAccess { volume_id, access } => {
+                self.set_access(&volume_id, &access)?;
+                Ok(ReplicaResponse::Ack)
+            },
+        }
+    }
+
+    /// Check a service's request against the access published for its volume
+    fn authorize(&self, accessor: &StorageAccessor, request: &ReplicaRequest) -> Result<(), StorageError> {
+        let volume_id = match request {
+            ReplicaRequest::Write(write) => write.volume_id,
+            ReplicaRequest::Read { volume_id }
+            | ReplicaRequest::WriteFragment { volume_id, .. }
+            | ReplicaRequest::ReadFragment { volume_id, .. }
+            | ReplicaRequest::FragmentGenerations { volume_id, .. }
+            | ReplicaRequest::CommitFragment { volume_id, .. }
+            | ReplicaRequest::DiscardFragment { volume_id, .. } => *volume_id,
+            _ => return Err(StorageError::MaintenanceDenied(accessor.to_string())),
+        };
+        self.access(&volume_id)?.unwrap_or_default().authorize(&volume_id, accessor)
+    }
+
+    /// Access published for a volume, if any
+    pub fn access(&self, volume_id: &Uuid) -> Result<Option<VolumeAccess>, StorageError> {
+        match self.store.metadata(&access_key(volume_id))? {
+            Some(encoded) => Ok(Some(bincode::deserialize(&encoded)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Record which services may use a volume; kept until the volume is deleted
+    pub fn set_access(&self, volume_id: &Uuid, access: &VolumeAccess) -> Result<(), StorageError> {
+        self.store.put_metadata(&access_key(volume_id), &bincode::serialize(access)?)
+    }
+
+    /// Refuse coordinator writes to a volume, or accept them again
+    ///
+    /// Fenced writes fail with `ReadOnlyVolume`, so writers holding an
+    /// outdated plan notice the volume has moved. Restores are still applied.
+    /// Fences are kept in memory and lifted by a restart.
+    pub fn set_fenced(&self, volume_id: Uuid, fenced: bool) {
+        let mut fences = self.fenced.lock().unwrap();
+        if fenced {
+            fences.insert(volume_id);
+        } else {
+            fences.remove(&volume_id);
+        }
+    }
+
+    fn check_fence(&self, volume_id: &Uuid) -> Result<(), StorageError> {
+        if self.fenced.lock().unwrap().contains(volume_id) {
+            return Err(StorageError::ReadOnlyVolume(*volume_id));
+        }
+        Ok(())
+    }
+
+    /// Copy a volume or one of its fragments to a new volume without copying data
+    ///
+    /// A full copy fails with `VersionConflict` unless the source is at
+    /// `expected`, when given.
+    /// Fragments are copied with every generation the node holds.
+    pub fn copy(&self, source: &Uuid, target: Uuid, fragment: Option<usize>, expected: Option<&VersionVector>) -> Result<(), StorageError> {
+        let _guard = self.write_lock.lock().unwrap();
+        if let Some(index) = fragment {
+            let generations = self.fragment_generations(source, index)?;
+            if generations.is_empty() {
+                return Err(StorageError::VolumeNotFound(*source));
+            }
+            for generation in &generations {
+                self.store.copy_volume(&fragment_key(source, index, generation), fragment_key(&target, index, generation))?;
+            }
+            return self.set_fragment_generations(&target, index, &generations);
+        }
+
+        let version = self.version(source)?.ok_or(StorageError::VolumeNotFound(*source))?;
+        if expected.is_some_and(|expected| *expected != version) {
+            return Err(StorageError::VersionConflict(*source));
+        }
+        self.store.copy_volume(source, target)?;
+        self.set_version(&target, &version)
+    }
+
+    /// Drop a volume or every generation of a fragment; deleting one that is absent succeeds
+    pub fn delete(&self, volume_id: &Uuid, fragment: Option<usize>) -> Result<(), StorageError> {
+        let _guard = self.write_lock.lock().unwrap();
+        match fragment {
+            Some(index) => {
+                for generation in self.fragment_generations(volume_id, index)? {
+                    self.delete_key(&fragment_key(volume_id, index, &generation))?;
+                }
+                self.set_fragment_generations(volume_id, index, &[])
+            },
+            None => {
+                self.store.remove_metadata(&version_key(volume_id))?;
+                self.store.remove_metadata(&access_key(volume_id))?;
+                self.delete_key(volume_id)
+            },
+        }
+    }
+
+    fn delete_key(&self, key: &Uuid) -> Result<(), StorageError> {
+        match self.store.delete_volume(key) {
+            Ok(_) | Err(StorageError::VolumeNotFound(_)) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Store one generation of a fragment, keeping the generations already held
+    pub fn write_fragment(&self, volume_id: &Uuid, index: usize, generation: FragmentGeneration, data: &[u8]) -> Result<(), StorageError> {
+        let _guard = self.write_lock.lock().unwrap();
+        self.store.write_volume(fragment_key(volume_id, index, &generation), data)?;
+
+        let mut generations = self.fragment_generations(volume_id, index)?;
+        if let Err(position) = generations.binary_search(&generation) {
+            generations.insert(position, generation);
+        }
+        self.set_fragment_generations(volume_id, index, &generations)
+    }
+
+    /// One generation of a fragment, if the node holds it
+    pub fn read_fragment(&self, volume_id: &Uuid, index: usize, generation: FragmentGeneration) -> Result<Option<Vec<u8>>, StorageError> {
+        let _guard = self.write_lock.lock().unwrap();
+        if !self.fragment_generations(volume_id, index)?.contains(&generation) {
+            return Ok(None);
+        }
+        Ok(Some(self.store.read_volume(&fragment_key(volume_id, index, &generation))?))
+    }
+
+    /// Keep `generation` and anything newer, dropping the generations it replaces
+    pub fn commit_fragment(&self, volume_id: &Uuid, index: usize, generation: FragmentGeneration) -> Result<(), StorageError> {
+        let _guard = self.write_lock.lock().unwrap();
+        let (older, kept): (Vec<_>, Vec<_>) = self.fragment_generations(volume_id, index)?
+            .into_iter()
+            .partition(|held| *held < generation);
+        for held in &older {
+            self.delete_key(&fragment_key(volume_id, index, held))?;
+        }
+        self.set_fragment_generations(volume_id, index, &kept)
+    }
+
+    /// Drop a single generation of a fragment
+    pub fn discard_fragment(&self, volume_id: &Uuid, index: usize, generation: FragmentGeneration) -> Result<(), StorageError> {
+        let _guard = self.write_lock.lock().unwrap();
+        let mut generations = self.fragment_generations(volume_id, index)?;
+        generations.retain(|held| *held != generation);
+        self.delete_key(&fragment_key(volume_id, index, &generation))?;
+        self.set_fragment_generations(volume_id, index, &generations)
+    }
+
+    /// Generations of a fragment this node holds, oldest first
+    pub fn fragment_generations(&self, volume_id: &Uuid, index: usize) -> Result<Vec<FragmentGeneration>, StorageError> {
+        match self.store.metadata(&fragment_generations_key(volume_id, index))? {
+            Some(encoded) => Ok(bincode::deserialize(&encoded)?),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    fn set_fragment_generations(&self, volume_id: &Uuid, index: usize, generations: &[FragmentGeneration]) -> Result<(), StorageError> {
+        let key = fragment_generations_key(volume_id, index);
+        if generations.is_empty() {
+            return self.store.remove_metadata(&key);
+        }
+        self.store.put_metadata(&key, &bincode::serialize(generations)?)
+    }
+
+    /// Replace the stored copy, rewriting any corrupted chunks
+    ///
+    /// Copies at the same or a concurrent version are replaced, but a stored
+    /// version that is strictly newer is never rolled back.
+    pub fn restore(&self, write: VersionedWrite) -> Result<(), StorageError> {
+        let _guard = self.write_lock.lock().unwrap();
+        if let Some(version) = self.version(&write.volume_id)? {
+            if version.partial_cmp(&write.version) == Some(Ordering::Greater) {
+                return Err(StorageError::VersionConflict(write.volume_id));
+            }
+        }
+        let manifest = self.store.write_volume(write.volume_id, &write.data)?;
+
+        // Chunks already present are deduplicated, so damaged copies are replaced explicitly
+        let mut offset = 0;
+        for chunk in &manifest.chunks {
+            let piece = &write.data[offset..offset + chunk.length as usize];
+            if self.store.raw_chunk(&chunk.id)?.is_some_and(|data| data != piece) {
+                self.store.restore_chunk(&chunk.id, piece)?;
+            }
+            offset += chunk.length as usize;
+        }
+
+        self.set_version(&write.volume_id, &write.version)
+    }
+
+    /// Apply a write unless the stored version already supersedes it
+    ///
+    /// Returns whether the node holds the write afterwards; a repeat of the
+    /// stored version counts as held, anything it supersedes does not.
+    pub fn apply(&self, write: VersionedWrite) -> Result<bool, StorageError> {
+        let _guard = self.write_lock.lock().unwrap();
+
+        if let Some(version) = self.version(&write.volume_id)? {
+            if version == write.version {
+                return Ok(true);
+            }
+            let current = VersionedWrite {
+                volume_id: write.volume_id,
+                version,
+                data: self.store.read_volume(&write.volume_id)?,
+            };
+            if !write.supersedes(&current) {
+                return Ok(false);
+            }
+        }
+
+        self.store.write_volume(write.volume_id, &write.data)?;
+        self.set_version(&write.volume_id, &write.version)?;
+        Ok(true)
+    }
+
+    /// Current contents and version of a volume
+    pub fn read(&self, volume_id: &Uuid) -> Result<Option<VersionedWrite>, StorageError> {
+        let _guard = self.write_lock.lock().unwrap();
+        let version = match self.version(volume_id)? {
+            Some(version) => version,
+            None => return Ok(None),
+        };
+        Ok(Some(VersionedWrite {
+            volume_id: *volume_id,
+            version,
+            data: self.store.read_volume(volume_id)?,
+        }))
+    }
+
+    fn version(&self, volume_id: &Uuid) -> Result<Option<VersionVector>, StorageError> {
+        match self.store.metadata(&version_key(volume_id))? {
+            Some(encoded) => Ok(Some(bincode::deserialize(&encoded)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn set_version(&self, volume_id: &Uuid, version: &VersionVector) -> Result<(), StorageError> {
+        self.store.put_metadata(&version_key(volume_id), &bincode::serialize(version)?)
+    }
+}
+
+impl HintLog {
+    /// Load hints a previous coordinator left in the store
+    fn open(store: ChunkStore) -> Result<Self, StorageError> {
+        let mut pending = HashMap::new();
+        for (key, value) in store.metadata_with_prefix(HINT_PREFIX)? {
+            let node_id = Uuid::from_slice(&key[HINT_PREFIX.len()..])
+                .map_err(|e| StorageError::InvalidRequest(format!("Malformed hint key: {}", e)))?;
+            pending.insert(node_id, bincode::deserialize(&value)?);
+        }
+        Ok(Self {
+            pending: Mutex::new(pending),
+            store: Some(store),
+        })
+    }
+
+    /// Queue writes for a node, keeping only the newest write per volume
+    fn push(&self, node_id: Uuid, writes: Vec<VersionedWrite>) {
+        let mut pending = self.pending.lock().unwrap();
+        let queued = pending.entry(node_id).or_default();
+        for write in writes {
+            match queued.iter_mut().find(|queued| queued.volume_id == write.volume_id) {
+                Some(queued) if write.supersedes(queued) => *queued = write,
+                Some(_) => {},
+                None => queued.push(write),
+            }
+        }
+        self.persist(node_id, queued);
+    }
+
+    fn drain(&self) -> Vec<(Uuid, Vec<VersionedWrite>)> {
+        let drained: Vec<_> = self.pending.lock().unwrap().drain().collect();
+        for (node_id, _) in &drained {
+            self.persist(*node_id, &[]);
+        }
+        drained
+    }
+
+    fn len(&self) -> usize {
+        self.pending.lock().unwrap().values().map(Vec::len).sum()
+    }
+
+    /// Write a node's queue through to the store; failures only cost durability
+    fn persist(&self, node_id: Uuid, writes: &[VersionedWrite]) {
+        let Some(store) = &self.store else { return };
+        let key = [HINT_PREFIX, node_id.as_bytes().as_slice()].concat();
+        let result = if writes.is_empty() {
+            store.remove_metadata(&key)
+        } else {
+            bincode::serialize(writes)
+                .map_err(StorageError::from)
+                .and_then(|encoded| store.put_metadata(&key, &encoded))
+        };
+        if let Err(e) = result {
+            tracing::warn!("Cannot persist hints for {}: {}", node_id, e);
+        }
+    }
+}
+
+impl InMemoryTransport {
+    /// Transport sending with `credential` unless a request names another
+    pub fn new(credential: AccessCredential) -> Self {
+        Self {
+            credential,
+            nodes: HashMap::new(),
+            unavailable: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Connect a replica node
+    pub fn add_node(&mut self, node: Arc<ReplicaNode>) {
+        self.nodes.insert(node.node_id(), node);
+    }
+
+    /// Get a connected replica node
+    pub fn node(&self, node_id: &Uuid) -> Option<&Arc<ReplicaNode>> {
+        self.nodes.get(node_id)
+    }
+
+    /// Simulate a node going down or coming back
+    pub fn set_available(&self, node_id: Uuid, available: bool) {
+        let mut unavailable = self.unavailable.lock().unwrap();
+        if available {
+            unavailable.remove(&node_id);
+        } else {
+            unavailable.insert(node_id);
+        }
+    }
+}
+
+impl ReplicaTransport for InMemoryTransport {
+    fn credential(&self) -> &AccessCredential {
+        &self.credential
+    }
+
+    async fn send_as(&self, node_id: Uuid, credential: &AccessCredential, request: ReplicaRequest) -> Result<ReplicaResponse, StorageError> {
+        if self.unavailable.lock().unwrap().contains(&node_id) {
+            return Err(StorageError::ReplicaUnavailable(node_id));
+        }
+        let node = self.nodes.get(&node_id).ok_or(StorageError::UnknownNode(node_id))?;
+        node.handle(credential, request)
+    }
+}
+
+impl<T: ReplicaTransport> VolumeIo<T> {
+    /// Create a coordinator running on `node_id`
+    pub fn new(node_id: Uuid, transport: Arc<T>, config: VolumeIoConfig) -> Self {
+        Self {
+            node_id,
+            transport,
+            config,
+            hints: Arc::new(HintLog::default()),
+            latest: Mutex::new(HashMap::new()),
+            ciphers: HashMap::new(),
+            latency: Mutex::new(BTreeMap::new()),
+            accesses: Mutex::new(HashMap::new()),
+            published: Mutex::new(HashMap::new()),
+            background: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Keep hinted writes in a chunk store so they survive a restart
+    ///
+    /// Hints already in the store from an earlier run are loaded and delivered
+    /// by the next `deliver_hints`.
+    pub fn with_hint_store(mut self, store: ChunkStore) -> Result<Self, StorageError> {
+        self.hints = Arc::new(HintLog::open(store)?);
+        Ok(self)
+    }
+
+    /// Encrypt and decrypt a volume's contents with its unwrapped data key
+    pub fn register_volume_key(&mut self, cipher: VolumeCipher) {
+        self.ciphers.insert(cipher.volume_id(), cipher);
+    }
+
+    /// Use a source volume's data key for a clone or snapshot of it
+    ///
+    /// Clones share the source's encrypted chunks, so they keep its key and
+    /// the cipher stays bound to the source volume ID.
+    pub fn register_clone_key(&mut self, volume_id: Uuid, cipher: VolumeCipher) {
+        self.ciphers.insert(volume_id, cipher);
+    }
+
+    /// Forget a volume's data key
+    pub fn remove_volume_key(&mut self, volume_id: &Uuid) {
+        self.ciphers.remove(volume_id);
+    }
+
+    /// Write a volume's contents to the nodes in its plan
+    ///
+    /// - `Strong` waits for all replicas, or a majority under `StrongAck::Majority`.
+    /// - `Eventual` returns after the first acknowledgement.
+    /// - `Causal` behaves like `Eventual`; reads in the same session then only
+    ///   accept replicas that have caught up with it.
+    ///
+    /// At every level, copies not yet acknowledged when the write returns
+    /// complete in the background, and failed nodes get hinted handoff.
+    ///
+    /// The new version includes every version the coordinator or session has
+    /// seen, so it is ordered after them; read before writing to order a write
+    /// after versions produced by other coordinators.
+    ///
+    /// Data is encrypted here, before it leaves the writer, when a key is
+    /// registered for the volume. Plans that require encryption are refused
+    /// without one, and read-only plans (snapshots) are always refused, as
+    /// is any session whose accessor may not use the volume. Replicas check
+    /// the session's credential against the access published with `publish_access`.
+    pub async fn write(
+        &self,
+        plan: &ReplicationPlan,
+        level: &ConsistencyLevel,
+        data: Vec<u8>,
+        session: &mut ClientSession,
+    ) -> Result<WriteReceipt, StorageError> {
+        let started = Instant::now();
+        let result = self.replicate_write(plan, level, data, session).await;
+        self.observe_latency(IoOperation::Write, started.elapsed());
+        result
+    }
+
+    async fn replicate_write(
+        &self,
+        plan: &ReplicationPlan,
+        level: &ConsistencyLevel,
+        data: Vec<u8>,
+        session: &mut ClientSession,
+    ) -> Result<WriteReceipt, StorageError> {
+        let volume_id = plan.volume_id;
+        plan.authorize(session.accessor())?;
+        self.observe_access(volume_id);
+        if plan.read_only {
+            return Err(StorageError::ReadOnlyVolume(volume_id));
+        }
+        let data = match self.ciphers.get(&volume_id) {
+            Some(cipher) => cipher.encrypt(&data)?,
+            None if plan.encryption_required => return Err(StorageError::EncryptionRequired(volume_id)),
+            None => data,
+        };
+        let version = {
+            let mut latest = self.latest.lock().unwrap();
+            let version = latest.entry(volume_id).or_default();
+            if let Some(seen) = session.seen(&volume_id) {
+                version.merge(seen);
+            }
+            version.increment(self.node_id);
+            version.clone()
+        };
+        let write = VersionedWrite {
+            volume_id,
+            version: version.clone(),
+            data,
+        };
+
+        let nodes = plan.nodes();
+        let required = match level {
+            ConsistencyLevel::Strong => match self.config.strong_ack {
+                StrongAck::All => nodes.len(),
+                StrongAck::Majority => nodes.len() / 2 + 1,
+            },
+            ConsistencyLevel::Eventual | ConsistencyLevel::Causal => 1,
+        };
+        if session.credential.is_some() {
+            self.publish_access(plan).await;
+        }
+        let mut pending = JoinSet::new();
+        for node_id in nodes {
+            let transport = self.transport.clone();
+            let credential = session.credential.clone();
+            let request = ReplicaRequest::Write(write.clone());
+            let timeout = self.config.request_timeout;
+
+            pending.spawn(async move {
+                let result = send_as_with_timeout(transport.as_ref(), node_id, credential.as_ref(), request, timeout).await;
+                (node_id, result)
+            });
+        }
+
+        // Replicas holding a newer version answer `Stale` and count toward neither quorum nor hints
+        let mut acknowledged = Vec::new();
+        let mut failed = Vec::new();
+        while acknowledged.len() < required {
+            match pending.join_next().await {
+                Some(Ok((node_id, Ok(ReplicaResponse::Stale)))) => {
+                    tracing::debug!("Replica {} already holds a newer version of {}", node_id, volume_id)
+                },
+                Some(Ok((node_id, Ok(_)))) => acknowledged.push(node_id),
+                Some(Ok((node_id, Err(_)))) => failed.push(node_id),
+                Some(Err(e)) => tracing::warn!("Replica write task failed: {}", e),
+                None => break,
+            }
+        }
+
+        for node_id in &failed {
+            tracing::debug!("Storing hint for {} after failed write", node_id);
+            self.store_hint(*node_id, write.clone());
+        }
+        if !pending.is_empty() {
+            // Finish the fan-out in the background, hinting nodes that fail later
+            let hints = self.hints.clone();
+            let write = write.clone();
+            let handle = tokio::spawn(async move {
+                while let Some(joined) = pending.join_next().await {
+                    if let Ok((node_id, Err(_))) = joined {
+                        hints.push(node_id, vec![write.clone()]);
+                    }
+                }
+            });
+            let mut background = self.background.lock().unwrap();
+            background.retain(|handle| !handle.is_finished());
+            background.push(handle);
+        }
+
+        if acknowledged.len() < required {
+            return Err(StorageError::ReplicationFailed {
+                acknowledged: acknowledged.len(),
+                required,
+            });
+        }
+
+        session.observe(volume_id, &version);
+        Ok(WriteReceipt { version, acknowledged })
+    }
+
+    /// Read a volume's contents according to a consistency level
+    ///
+    /// - `Strong` reads the same number of replicas a strong write waits for
+    ///   and returns the newest version among them.
+    /// - `Eventual` returns the first replica that answers, primary first.
+    /// - `Causal` returns the first replica whose version includes everything
+    ///   the session has already seen.
+    pub async fn read(
+        &self,
+        plan: &ReplicationPlan,
+        level: &ConsistencyLevel,
+        session: &mut ClientSession,
+    ) -> Result<Option<VersionedWrite>, StorageError> {
+        let started = Instant::now();
+        let result = self.replicate_read(plan, level, session).await;
+        self.observe_latency(IoOperation::Read, started.elapsed());
+        result
+    }
+
+    async fn replicate_read(
+        &self,
+        plan: &ReplicationPlan,
+        level: &ConsistencyLevel,
+        session: &mut ClientSession,
+    ) -> Result<Option<VersionedWrite>, StorageError> {
+        let volume_id = plan.volume_id;
+        plan.authorize(session.accessor())?;
+        self.observe_access(volume_id);
+        if session.credential.is_some() {
+            self.publish_access(plan).await;
+        }
+        let credential = session.credential.clone();
+        let nodes = plan.nodes();
+
+        let result = match level {
+            ConsistencyLevel::Strong => {
+                let required = match self.config.strong_ack {
+                    StrongAck::All => 1,
+                    // Any majority overlaps the majority that acknowledged the last write
+                    StrongAck::Majority => nodes.len() / 2 + 1,
+                };
+                let mut newest: Option<VersionedWrite> = None;
+                let mut answered = 0;
+
+                for node_id in &nodes {
+                    if let Ok(value) = self.read_from(*node_id, credential.as_ref(), volume_id).await {
+                        answered += 1;
+                        if let Some(value) = value {
+                            if newest.as_ref().map(|current| value.supersedes(current)).unwrap_or(true) {
+                                newest = Some(value);
+                            }
+                        }
+                        if answered == required {
+                            break;
+                        }
+                    }
+                }
+                if answered < required {
+                    return Err(StorageError::ReplicationFailed {
+                        acknowledged: answered,
+                        required,
+                    });
+                }
+                newest
+            },
+            ConsistencyLevel::Eventual => {
+                let mut result = Err(StorageError::ReplicaUnavailable(plan.primary_node));
+                for node_id in &nodes {
+                    result = self.read_from(*node_id, credential.as_ref(), volume_id).await;
+                    if result.is_ok() {
+                        break;
+                    }
+                }
+                result?
+            },
+            ConsistencyLevel::Causal => {
+                let seen = session.seen(&volume_id).cloned().unwrap_or_default();
+                let mut found = None;
+
+                for node_id in &nodes {
+                    match self.read_from(*node_id, credential.as_ref(), volume_id).await {
+                        Ok(Some(value)) if value.version.dominates(&seen) => {
+                            found = Some(Some(value));
+                            break;
+                        },
+                        Ok(None) if seen.0.is_empty() => {
+                            found = Some(None);
+                            break;
+                        },
+                        _ => {},
+                    }
+                }
+                found.ok_or(StorageError::CausalReadUnavailable(volume_id))?
+            },
+        };
+
+        let mut result = result;
+        if let Some(value) = &mut result {
+            session.observe(volume_id, &value.version);
+            self.latest.lock().unwrap().entry(volume_id).or_default().merge(&value.version);
+
+            if let Some(cipher) = self.ciphers.get(&volume_id) {
+                value.data = cipher.decrypt(&value.data)?;
+            } else if plan.encryption_required {
+                return Err(StorageError::EncryptionRequired(volume_id));
+            }
+        }
+        Ok(result)
+    }
+
+    /// Tell a volume's replicas which services may use it
+    ///
+    /// Replicas refuse service credentials for volumes whose access they were
+    /// never told, so this runs before the first request a session makes for
+    /// a service. It only sends when the plan's owner or shares differ from
+    /// what every replica last received. Unreachable replicas are retried on
+    /// the next call.
+    pub async fn publish_access(&self, plan: &ReplicationPlan) {
+        let access = plan.access();
+        if self.published.lock().unwrap().get(&plan.volume_id) == Some(&access) {
+            return;
+        }
+
+        let mut pending = JoinSet::new();
+        for node_id in plan.nodes() {
+            let transport = self.transport.clone();
+            let request = ReplicaRequest::SetAccess {
+                volume_id: plan.volume_id,
+                access: access.clone(),
+            };
+            let timeout = self.config.request_timeout;
+            pending.spawn(async move { send_with_timeout(transport.as_ref(), node_id, request, timeout).await.is_ok() });
+        }
+        let mut complete = true;
+        while let Some(joined) = pending.join_next().await {
+            complete &= joined.unwrap_or(false);
+        }
+        if complete {
+            self.published.lock().unwrap().insert(plan.volume_id, access);
+        } else {
+            tracing::debug!("Access of volume {} did not reach every replica", plan.volume_id);
+        }
+    }
+
+    /// Latency of reads and writes since the last call, for `record_io_latency`
+    pub fn take_io_latency(&self) -> BTreeMap<IoOperation, LatencyHistogram> {
+        std::mem::take(&mut *self.latency.lock().unwrap())
+    }
+
+    fn observe_latency(&self, operation: IoOperation, latency: Duration) {
+        self.latency.lock().unwrap().entry(operation).or_default().observe(latency);
+    }
+
+    /// Reads and writes per volume since the last call, for `record_accesses`
+    pub fn take_accesses(&self) -> HashMap<Uuid, u64> {
+        std::mem::take(&mut *self.accesses.lock().unwrap())
+    }
+
+    fn observe_access(&self, volume_id: Uuid) {
+        *self.accesses.lock().unwrap().entry(volume_id).or_insert(0) += 1;
+    }
+
+    /// Wait until every replica write still running in the background has finished
+    pub async fn flush(&self) {
+        let handles = std::mem::take(&mut *self.background.lock().unwrap());
+        for handle in handles {
+            if let Err(e) = handle.await {
+                tracing::warn!("Background replica writes failed: {}", e);
+            }
+        }
+    }
+
+    /// Number of writes waiting to be handed off to recovered nodes
+    pub fn pending_hints(&self) -> usize {
+        self.hints.len()
+    }
+
+    /// Retry hinted writes; returns how many were delivered
+    pub async fn deliver_hints(&self) -> usize {
+        let hints = self.hints.drain();
+        let mut delivered = 0;
+
+        for (node_id, writes) in hints {
+            let mut remaining = Vec::new();
+            for write in writes {
+                let request = ReplicaRequest::Write(write.clone());
+                match send_with_timeout(self.transport.as_ref(), node_id, request, self.config.request_timeout).await {
+                    Ok(ReplicaResponse::Stale) => {
+                        tracing::debug!("Dropped hint for {} superseded on {}", write.volume_id, node_id)
+                    },
+                    Ok(_) => delivered += 1,
+                    // The volume moved away from this node, so the hint is obsolete
+                    Err(StorageError::ReadOnlyVolume(_)) => {
+                        tracing::debug!("Dropped hint for fenced volume {} on {}", write.volume_id, node_id)
+                    },
+                    Err(_) => remaining.push(write),
+                }
+            }
+            if !remaining.is_empty() {
+                self.hints.push(node_id, remaining);
+            }
+        }
+
+        if delivered > 0 {
+            tracing::info!("Delivered {} hinted writes", delivered);
+        }
+        delivered
+    }
+
+    fn store_hint(&self, node_id: Uuid, write: VersionedWrite) {
+        self.hints.push(node_id, vec![write]);
+    }
+
+    async fn read_from(&self, node_id: Uuid, credential: Option<&AccessCredential>, volume_id: Uuid) -> Result<Option<VersionedWrite>, StorageError> {
+        let request = ReplicaRequest::Read { volume_id };
+        match send_as_with_timeout(self.transport.as_ref(), node_id, credential, request, self.config.request_timeout).await? {
+            ReplicaResponse::Data(value) => Ok(value),
+            _ => Err(StorageError::InvalidRequest(format!("Unexpected reply to read from {}", node_id))),
+        }
+    }
+}
+
+/// Metadata prefix for hinted writes queued for one node
+const HINT_PREFIX: &[u8] = b"hint:";
+
+/// Metadata key holding the version of a replica's copy of a volume
+fn version_key(volume_id: &Uuid) -> Vec<u8> {
+    [b"version:".as_slice(), volume_id.as_bytes().as_slice()].concat()
+}
+
+/// Metadata key holding the access published for a volume
+fn access_key(volume_id: &Uuid) -> Vec<u8> {
+    [b"access:".as_slice(), volume_id.as_bytes().as_slice()].concat()
+}
+
+/// Metadata key listing the generations of a fragment a replica holds
+fn fragment_generations_key(volume_id: &Uuid, index: usize) -> Vec<u8> {
+    [b"fragments:".as_slice(), volume_id.as_bytes().as_slice(), &(index as u64).to_be_bytes()].concat()
+}
+
+/// Key under which a replica stores one generation of an erasure-coded fragment of a volume
+pub(crate) fn fragment_key(volume_id: &Uuid, index: usize, generation: &FragmentGeneration) -> Uuid {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(b"mycnet-storage-fragment");
+    hasher.update(volume_id.as_bytes());
+    hasher.update(&(index as u64).to_be_bytes());
+    hasher.update(&generation.sequence.to_be_bytes());
+    hasher.update(generation.writer.as_bytes());
+
+    let mut key = [0u8; 16];
+    key.copy_from_slice(&hasher.finalize().as_bytes()[..16]);
+    Uuid::from_bytes(key)
+}
+
+/// Newest copy of a replicated volume among `nodes`
+///
+/// Replies are compared with `supersedes`, so the result is never older than
+/// any copy that answered. Nodes that fail or time out are skipped; `None`
+/// means the nodes that answered hold no copy yet, and an error that none answered.
+pub(crate) async fn read_newest<T: ReplicaTransport>(
+    transport: &T,
+    nodes: &[Uuid],
+    volume_id: Uuid,
+    timeout: Duration,
+) -> Result<Option<VersionedWrite>, StorageError> {
+    let mut newest: Option<VersionedWrite> = None;
+    let mut answered = false;
+    for node_id in nodes {
+        let request = ReplicaRequest::Read { volume_id };
+        if let Ok(ReplicaResponse::Data(value)) = send_with_timeout(transport, *node_id, request, timeout).await {
+            answered = true;
+            if let Some(write) = value {
+                if newest.as_ref().is_none_or(|current| write.supersedes(current)) {
+                    newest = Some(write);
+                }
+            }
+        }
+    }
+    if !answered {
+        return Err(StorageError::NoHealthyReplica(volume_id));
+    }
+    Ok(newest)
+}
+
+pub(crate) async fn send_with_timeout<T: ReplicaTransport>(
+    transport: &T,
+    node_id: Uuid,
+    request: ReplicaRequest,
+    timeout: Duration,
+) -> Result<ReplicaResponse, StorageError> {
+    send_as_with_timeout(transport, node_id, None, request, timeout).await
+}
+
+/// Send for the holder of `credential`, or with the transport's own credential without one
+pub(crate) async fn send_as_with_timeout<T: ReplicaTransport>(
+    transport: &T,
+    node_id: Uuid,
+    credential: Option<&AccessCredential>,
+    request: ReplicaRequest,
+    timeout: Duration,
+) -> Result<ReplicaResponse, StorageError> {
+    let credential = credential.unwrap_or(transport.credential());
+    tokio::time::timeout(timeout, transport.send_as(node_id, credential, request))
+        .await
+        .map_err(|_| StorageError::ReplicaUnavailable(node_id))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{authority, network_transport, plan, replica_nodes};
+    use crate::{ReplicationStrategy, VolumeKeyEnvelope};
+
+    fn cluster(size: usize) -> (Arc<InMemoryTransport>, ReplicationPlan) {
+        let mut transport = network_transport();
+        let nodes = replica_nodes(&mut transport, size);
+        (Arc::new(transport), plan(&nodes, ReplicationStrategy::HierarchyAware))
+    }
+
+    #[test]
+    fn test_version_vector_ordering() {
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+        let mut first = VersionVector::default();
+        first.increment(a);
+        let mut second = first.clone();
+        second.increment(b);
+        let mut concurrent = first.clone();
+        concurrent.increment(a);
+
+        assert!(second.dominates(&first));
+        assert!(!first.dominates(&second));
+        assert_eq!(second.partial_cmp(&concurrent), None);
+
+        concurrent.merge(&second);
+        assert!(concurrent.dominates(&second));
+    }
+
+    #[test]
+    fn test_restore_never_rolls_back_a_newer_copy() {
+        let mut transport = network_transport();
+        let node_id = replica_nodes(&mut transport, 1)[0];
+        let node = transport.node(&node_id).unwrap();
+        let volume_id = Uuid::new_v4();
+        let (old, new) = (VersionVector(BTreeMap::from([(node_id, 1)])), VersionVector(BTreeMap::from([(node_id, 2)])));
+
+        node.apply(VersionedWrite { volume_id, version: new.clone(), data: b"newer".to_vec() }).unwrap();
+        let result = node.restore(VersionedWrite { volume_id, version: old, data: b"older".to_vec() });
+        assert!(matches!(result, Err(StorageError::VersionConflict(id)) if id == volume_id));
+        assert_eq!(node.read(&volume_id).unwrap().unwrap().data, b"newer");
+
+        // The same version is rewritten, which is how scrubbing repairs damaged copies
+        node.restore(VersionedWrite { volume_id, version: new, data: b"repaired".to_vec() }).unwrap();
+        assert_eq!(node.read(&volume_id).unwrap().unwrap().data, b"repaired");
+    }
+
+    /// Open the store at `path` once sled has released the lock held by a store that was just dropped
+    async fn open_store(path: &std::path::Path) -> ChunkStore {
+        for _ in 0..100 {
+            if let Ok(store) = ChunkStore::open(path) {
+                return store;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        ChunkStore::open(path).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_versions_and_hints_survive_restart() {
+        let path = std::env::temp_dir().join(format!("mycnet-volume-io-{}", Uuid::new_v4()));
+        let (node_id, volume_id) = (Uuid::new_v4(), Uuid::new_v4());
+        let (old, new) = (VersionVector(BTreeMap::from([(node_id, 1)])), VersionVector(BTreeMap::from([(node_id, 2)])));
+
+        {
+            let replica = ReplicaNode::new(node_id, open_store(&path).await, authority().verifying_key());
+            replica.apply(VersionedWrite { volume_id, version: new.clone(), data: b"new".to_vec() }).unwrap();
+            replica.store().flush().unwrap();
+        }
+        let replica = ReplicaNode::new(node_id, open_store(&path).await, authority().verifying_key());
+        // The reopened replica still knows the stored version and ignores the stale write
+        replica.apply(VersionedWrite { volume_id, version: old, data: b"old".to_vec() }).unwrap();
+        let stored = replica.read(&volume_id).unwrap().unwrap();
+        assert_eq!((stored.version, stored.data), (new, b"new".to_vec()));
+        drop(replica);
+        std::fs::remove_dir_all(&path).unwrap();
+
+        let (transport, plan) = cluster(2);
+        transport.set_available(plan.replica_nodes[0], false);
+        {
+            let io = VolumeIo::new(Uuid::new_v4(), transport.clone(), VolumeIoConfig::default())
+                .with_hint_store(open_store(&path).await)
+                .unwrap();
+            io.write(&plan, &ConsistencyLevel::Strong, b"v1".to_vec(), &mut ClientSession::new()).await.unwrap_err();
+            io.write(&plan, &ConsistencyLevel::Eventual, b"v1".to_vec(), &mut ClientSession::new()).await.unwrap();
+            io.flush().await;
+            // The eventual write supersedes the hint left by the failed strong write
+            assert_eq!(io.pending_hints(), 1);
+        }
+
+        transport.set_available(plan.replica_nodes[0], true);
+        let io = VolumeIo::new(Uuid::new_v4(), transport.clone(), VolumeIoConfig::default())
+            .with_hint_store(open_store(&path).await)
+            .unwrap();
+        assert_eq!(io.pending_hints(), 1);
+        assert_eq!(io.deliver_hints().await, 1);
+        assert_eq!(transport.node(&plan.replica_nodes[0]).unwrap().read(&plan.volume_id).unwrap().unwrap().data, b"v1");
+        drop(io);
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_strong_write_requires_all_replicas() {
+        let (transport, plan) = cluster(3);
+        let io = VolumeIo::new(Uuid::new_v4(), transport.clone(), VolumeIoConfig::default());
+        let mut session = ClientSession::new();
+
+        let receipt = io.write(&plan, &ConsistencyLevel::Strong, b"v1".to_vec(), &mut session).await.unwrap();
+        assert_eq!(receipt.acknowledged.len(), 3);
+        for node_id in plan.nodes() {
+            let stored = transport.node(&node_id).unwrap().read(&plan.volume_id).unwrap().unwrap();
+            assert_eq!(stored.data, b"v1");
+        }
+
+        transport.set_available(plan.replica_nodes[1], false);
+        let err = io.write(&plan, &ConsistencyLevel::Strong, b"v2".to_vec(), &mut session).await.unwrap_err();
+        assert!(matches!(err, StorageError::ReplicationFailed { acknowledged: 2, required: 3 }));
+
+        let majority = VolumeIo::new(Uuid::new_v4(), transport.clone(), VolumeIoConfig {
+            strong_ack: StrongAck::Majority,
+            ..VolumeIoConfig::default()
+        });
+        // Reading first orders the write after the partially applied v2
+        majority.read(&plan, &ConsistencyLevel::Strong, &mut session).await.unwrap();
+        majority.write(&plan, &ConsistencyLevel::Strong, b"v3".to_vec(), &mut session).await.unwrap();
+        let read = majority.read(&plan, &ConsistencyLevel::Strong, &mut session).await.unwrap().unwrap();
+        assert_eq!(read.data, b"v3");
+
+        // The replica outside the majority is hinted rather than silently skipped
+        majority.flush().await;
+        assert_eq!(majority.pending_hints(), 1);
+        transport.set_available(plan.replica_nodes[1], true);
+        assert_eq!(majority.deliver_hints().await, 1);
+        assert_eq!(transport.node(&plan.replica_nodes[1]).unwrap().read(&plan.volume_id).unwrap().unwrap().data, b"v3");
+    }
+
+    #[tokio::test]
+    async fn test_stale_replicas_do_not_count_toward_quorum() {
+        let (transport, plan) = cluster(3);
+        let io = VolumeIo::new(Uuid::new_v4(), transport.clone(), VolumeIoConfig {
+            strong_ack: StrongAck::Majority,
+            ..VolumeIoConfig::default()
+        });
+
+        // Two replicas hold a version this coordinator has never seen and that wins over its write
+        let newer = VersionedWrite {
+            volume_id: plan.volume_id,
+            version: VersionVector(BTreeMap::from([(Uuid::new_v4(), 5)])),
+            data: b"newer".to_vec(),
+        };
+        for node_id in &plan.replica_nodes {
+            assert!(transport.node(node_id).unwrap().apply(newer.clone()).unwrap());
+            let repeat = transport.send(*node_id, ReplicaRequest::Write(newer.clone())).await;
+            assert!(matches!(repeat, Ok(ReplicaResponse::Ack)));
+        }
+
+        let err = io.write(&plan, &ConsistencyLevel::Strong, b"older".to_vec(), &mut ClientSession::new()).await.unwrap_err();
+        assert!(matches!(err, StorageError::ReplicationFailed { acknowledged: 1, required: 2 }));
+        io.flush().await;
+        assert_eq!(io.pending_hints(), 0);
+        assert_eq!(transport.node(&plan.replica_nodes[0]).unwrap().read(&plan.volume_id).unwrap().unwrap().data, b"newer");
+    }
+
+    #[tokio::test]
+    async fn test_eventual_write_uses_hinted_handoff() {
+        let (transport, plan) = cluster(3);
+        let io = VolumeIo::new(Uuid::new_v4(), transport.clone(), VolumeIoConfig::default());
+        let mut session = ClientSession::new();
+        let offline = plan.replica_nodes[0];
+
+        transport.set_available(offline, false);
+        io.write(&plan, &ConsistencyLevel::Eventual, b"data".to_vec(), &mut session).await.unwrap();
+
+        // Let the background fan-out finish
+        io.flush().await;
+        assert_eq!(io.pending_hints(), 1);
+        assert!(transport.node(&offline).unwrap().read(&plan.volume_id).unwrap().is_none());
+
+        assert_eq!(io.deliver_hints().await, 0);
+        transport.set_available(offline, true);
+        assert_eq!(io.deliver_hints().await, 1);
+        assert_eq!(transport.node(&offline).unwrap().read(&plan.volume_id).unwrap().unwrap().data, b"data");
+    }
+
+    #[tokio::test]
+    async fn test_causal_reads_skip_stale_replicas() {
+        let (transport, plan) = cluster(2);
+        let io = VolumeIo::new(Uuid::new_v4(), transport.clone(), VolumeIoConfig::default());
+        let mut writer = ClientSession::new();
+        io.write(&plan, &ConsistencyLevel::Strong, b"old".to_vec(), &mut writer).await.unwrap();
+
+        // The new version only reaches the replica, leaving the primary stale
+        transport.set_available(plan.primary_node, false);
+        io.write(&plan, &ConsistencyLevel::Causal, b"new".to_vec(), &mut writer).await.unwrap();
+        transport.set_available(plan.primary_node, true);
+
+        let read = io.read(&plan, &ConsistencyLevel::Causal, &mut writer).await.unwrap().unwrap();
+        assert_eq!(read.data, b"new");
+
+        // A fresh session has no causal dependencies and may see the stale primary
+        let eventual = io.read(&plan, &ConsistencyLevel::Eventual, &mut ClientSession::new()).await.unwrap().unwrap();
+        assert_eq!(eventual.data, b"old");
+
+        transport.set_available(plan.replica_nodes[0], false);
+        assert!(matches!(
+            io.read(&plan, &ConsistencyLevel::Causal, &mut writer).await,
+            Err(StorageError::CausalReadUnavailable(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_encrypted_volume_never_stored_in_plaintext() {
+        let (transport, mut plan) = cluster(2);
+        plan.encryption_required = true;
+        let mut io = VolumeIo::new(Uuid::new_v4(), transport.clone(), VolumeIoConfig::default());
+        let mut session = ClientSession::new();
+        let secret = b"sensitive volume contents".to_vec();
+
+        let err = io.write(&plan, &ConsistencyLevel::Strong, secret.clone(), &mut session).await.unwrap_err();
+        assert!(matches!(err, StorageError::EncryptionRequired(_)));
+
+        let (_, cipher) = VolumeKeyEnvelope::generate(plan.volume_id, &[]).unwrap();
+        io.register_volume_key(cipher);
+        io.write(&plan, &ConsistencyLevel::Strong, secret.clone(), &mut session).await.unwrap();
+
+        let stored = transport.node(&plan.primary_node).unwrap().read(&plan.volume_id).unwrap().unwrap();
+        assert!(!stored.data.windows(secret.len()).any(|window| window == secret.as_slice()));
+
+        let read = io.read(&plan, &ConsistencyLevel::Strong, &mut session).await.unwrap().unwrap();
+        assert_eq!(read.data, secret);
+    }
+}